@@ -1,10 +1,15 @@
 use keyring::Entry;
 use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
 use uuid::Uuid;
 
+use crate::error::{AppError, Context, ErrorCode, Result};
+
 const SERVICE_NAME: &str = "rw.ibimina.staff-admin";
 const CREDENTIALS_KEY: &str = "auth_credentials";
 const DEVICE_ID_KEY: &str = "device_id";
+/// Supabase Auth `getUser`, behind the API gateway
+const STAFF_USER_URL: &str = "https://api.ibimina.rw/auth/v1/user";
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SecureCredentials {
@@ -15,55 +20,72 @@ pub struct SecureCredentials {
 
 /// Retrieve stored auth tokens from OS keychain
 #[tauri::command]
-pub async fn get_secure_credentials() -> Result<Option<SecureCredentials>, String> {
+pub async fn get_secure_credentials() -> Result<Option<SecureCredentials>> {
     let entry = Entry::new(SERVICE_NAME, CREDENTIALS_KEY)
-        .map_err(|e| format!("Failed to access keychain: {}", e))?;
+        .context("Failed to access keychain")?;
 
     match entry.get_password() {
         Ok(json_str) => {
             let credentials: SecureCredentials = serde_json::from_str(&json_str)
-                .map_err(|e| format!("Failed to deserialize credentials: {}", e))?;
+                .context("Failed to deserialize credentials")?;
             Ok(Some(credentials))
         }
         Err(keyring::Error::NoEntry) => Ok(None),
-        Err(e) => Err(format!("Failed to retrieve credentials: {}", e)),
+        Err(e) => Err(e).context("Failed to retrieve credentials"),
     }
 }
 
 /// Store auth tokens securely in OS keychain
+///
+/// The new token is verified with the auth server straight away so hardware
+/// actions gated on the staff role keep working if the network drops later.
 #[tauri::command]
-pub async fn set_secure_credentials(credentials: SecureCredentials) -> Result<(), String> {
+pub async fn set_secure_credentials(
+    session: tauri::State<'_, StaffSession>,
+    credentials: SecureCredentials,
+) -> Result<()> {
     let entry = Entry::new(SERVICE_NAME, CREDENTIALS_KEY)
-        .map_err(|e| format!("Failed to access keychain: {}", e))?;
+        .context("Failed to access keychain")?;
 
     let json_str = serde_json::to_string(&credentials)
-        .map_err(|e| format!("Failed to serialize credentials: {}", e))?;
+        .context("Failed to serialize credentials")?;
 
     entry
         .set_password(&json_str)
-        .map_err(|e| format!("Failed to store credentials: {}", e))?;
+        .context("Failed to store credentials")?;
+
+    let verified = match verify_session(&credentials.access_token).await {
+        Ok(verified) => Some(verified),
+        Err(e) => {
+            eprintln!("Failed to verify sign-in: {}", e);
+            None
+        }
+    };
+    *session.verified.write()? = verified;
 
     Ok(())
 }
 
 /// Clear stored credentials from OS keychain
 #[tauri::command]
-pub async fn delete_secure_credentials() -> Result<(), String> {
+pub async fn delete_secure_credentials(session: tauri::State<'_, StaffSession>) -> Result<()> {
+    *session.verified.write()? = None;
+
     let entry = Entry::new(SERVICE_NAME, CREDENTIALS_KEY)
-        .map_err(|e| format!("Failed to access keychain: {}", e))?;
+        .context("Failed to access keychain")?;
 
     match entry.delete_password() {
         Ok(()) => Ok(()),
         Err(keyring::Error::NoEntry) => Ok(()), // Already deleted
-        Err(e) => Err(format!("Failed to delete credentials: {}", e)),
+        Err(e) => Err(e).context("Failed to delete credentials"),
     }
 }
 
 /// Generate or retrieve unique device identifier
 #[tauri::command]
-pub async fn get_device_id() -> Result<String, String> {
+pub async fn get_device_id() -> Result<String> {
     let entry = Entry::new(SERVICE_NAME, DEVICE_ID_KEY)
-        .map_err(|e| format!("Failed to access keychain: {}", e))?;
+        .context("Failed to access keychain")?;
 
     match entry.get_password() {
         Ok(device_id) => Ok(device_id),
@@ -72,11 +94,136 @@ pub async fn get_device_id() -> Result<String, String> {
             let device_id = Uuid::new_v4().to_string();
             entry
                 .set_password(&device_id)
-                .map_err(|e| format!("Failed to store device ID: {}", e))?;
+                .context("Failed to store device ID")?;
             Ok(device_id)
         }
-        Err(e) => Err(format!("Failed to retrieve device ID: {}", e)),
+        Err(e) => Err(e).context("Failed to retrieve device ID"),
+    }
+}
+
+/// Staff identity confirmed by the auth server
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct StaffIdentity {
+    pub user_id: String,
+    pub role: Option<String>,
+}
+
+/// Access token the auth server accepted, with the user it belongs to
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifiedSession {
+    access_token: String,
+    expires_at: i64,
+    identity: StaffIdentity,
+}
+
+impl VerifiedSession {
+    /// Session from the auth server's `getUser` response for `access_token`
+    ///
+    /// The user id and role come from the response, never from the token's
+    /// own claims, which anyone with access to the keychain could rewrite.
+    fn from_user(access_token: &str, user: &serde_json::Value) -> Result<Self> {
+        let invalid = || {
+            AppError::from_code(ErrorCode::InvalidResponse).with_detail(STAFF_USER_URL)
+        };
+        let expires_at = token_expiry(access_token)
+            .ok_or_else(|| AppError::from_code(ErrorCode::NotSignedIn))?;
+
+        Ok(Self {
+            access_token: access_token.to_string(),
+            expires_at,
+            identity: StaffIdentity {
+                user_id: user["id"].as_str().ok_or_else(invalid)?.to_string(),
+                role: user["app_metadata"]["role"].as_str().map(str::to_string),
+            },
+        })
+    }
+
+    /// The identity, if `access_token` is the verified token and has not expired
+    fn identity_for(&self, access_token: &str, now: i64) -> Option<&StaffIdentity> {
+        (self.access_token == access_token && now < self.expires_at).then_some(&self.identity)
+    }
+}
+
+/// Managed state caching the session verified at sign-in
+#[derive(Clone, Default)]
+pub struct StaffSession {
+    pub verified: Arc<RwLock<Option<VerifiedSession>>>,
+}
+
+/// The `exp` claim of a JWT, in seconds since the epoch
+///
+/// The signature is not checked; only use this for a token the auth server
+/// has accepted.
+fn token_expiry(access_token: &str) -> Option<i64> {
+    use base64::{engine::general_purpose, Engine as _};
+
+    let claims = access_token.split('.').nth(1)?;
+    let claims = general_purpose::URL_SAFE_NO_PAD
+        .decode(claims.trim_end_matches('='))
+        .ok()?;
+    let claims: serde_json::Value = serde_json::from_slice(&claims).ok()?;
+    claims["exp"].as_i64()
+}
+
+/// Ask the auth server who `access_token` belongs to
+async fn verify_session(access_token: &str) -> Result<VerifiedSession> {
+    let client = reqwest::Client::builder()
+        .user_agent("SACCO+ Staff Admin")
+        .timeout(std::time::Duration::from_secs(30))
+        .build()
+        .context("Failed to create HTTP client")?;
+
+    let response = client
+        .get(STAFF_USER_URL)
+        .bearer_auth(access_token)
+        .send()
+        .await
+        .context("Failed to verify sign-in")?;
+    match response.status() {
+        reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => {
+            return Err(AppError::from_code(ErrorCode::NotSignedIn));
+        }
+        status if !status.is_success() => {
+            return Err(AppError::http_status(status).with_detail(STAFF_USER_URL));
+        }
+        _ => {}
     }
+
+    let user: serde_json::Value = response.json().await.context("Failed to parse response")?;
+    VerifiedSession::from_user(access_token, &user)
+}
+
+/// Identity of the signed-in staff member, if any
+///
+/// Uses the session verified at sign-in while its token is still the stored
+/// one and has not expired, and asks the auth server again otherwise.
+pub async fn current_identity(session: &StaffSession) -> Result<Option<StaffIdentity>> {
+    let Some(credentials) = get_secure_credentials().await? else {
+        return Ok(None);
+    };
+
+    let now = chrono::Utc::now().timestamp();
+    if let Some(verified) = session.verified.read()?.as_ref() {
+        if let Some(identity) = verified.identity_for(&credentials.access_token, now) {
+            return Ok(Some(identity.clone()));
+        }
+    }
+
+    let verified = match verify_session(&credentials.access_token).await {
+        Ok(verified) => verified,
+        Err(e) if e.code == ErrorCode::NotSignedIn => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let identity = verified.identity_for(&credentials.access_token, now).cloned();
+    *session.verified.write()? = Some(verified);
+    Ok(identity)
+}
+
+/// Stored credentials, or `NOT_SIGNED_IN` when there are none
+pub async fn require_credentials() -> Result<SecureCredentials> {
+    get_secure_credentials()
+        .await?
+        .ok_or_else(|| AppError::from_code(ErrorCode::NotSignedIn))
 }
 
 #[cfg(test)]
@@ -99,4 +246,81 @@ mod tests {
             }
         }
     }
+
+    fn token(claims: serde_json::Value) -> String {
+        use base64::{engine::general_purpose, Engine as _};
+
+        format!(
+            "eyJhbGciOiJIUzI1NiJ9.{}.signature",
+            general_purpose::URL_SAFE_NO_PAD.encode(claims.to_string())
+        )
+    }
+
+    fn staff_user() -> serde_json::Value {
+        serde_json::json!({
+            "id": "9b2f6c1e-0d4a-4f7e-8a3b-5c6d7e8f9a0b",
+            "app_metadata": { "role": "SACCO_STAFF" }
+        })
+    }
+
+    #[test]
+    fn test_verified_session() {
+        let access_token = token(serde_json::json!({
+            "sub": "9b2f6c1e-0d4a-4f7e-8a3b-5c6d7e8f9a0b",
+            "exp": 1_800_000_000,
+            "app_metadata": { "role": "SACCO_STAFF" }
+        }));
+        let session = VerifiedSession::from_user(&access_token, &staff_user()).unwrap();
+
+        assert_eq!(
+            session.identity_for(&access_token, 1_799_999_999),
+            Some(&StaffIdentity {
+                user_id: "9b2f6c1e-0d4a-4f7e-8a3b-5c6d7e8f9a0b".to_string(),
+                role: Some("SACCO_STAFF".to_string()),
+            })
+        );
+        assert!(VerifiedSession::from_user("not-a-jwt", &staff_user()).is_err());
+        assert!(VerifiedSession::from_user(&access_token, &serde_json::json!({})).is_err());
+    }
+
+    #[test]
+    fn test_expired_token() {
+        let access_token = token(serde_json::json!({
+            "sub": "9b2f6c1e-0d4a-4f7e-8a3b-5c6d7e8f9a0b",
+            "exp": 1_800_000_000,
+        }));
+        let session = VerifiedSession::from_user(&access_token, &staff_user()).unwrap();
+
+        assert_eq!(session.identity_for(&access_token, 1_800_000_000), None);
+        assert_eq!(session.identity_for(&access_token, 1_900_000_000), None);
+
+        let no_expiry = token(serde_json::json!({ "sub": "9b2f6c1e" }));
+        assert!(VerifiedSession::from_user(&no_expiry, &staff_user()).is_err());
+    }
+
+    #[test]
+    fn test_tampered_token() {
+        let claims = |role: &str| {
+            serde_json::json!({
+                "sub": "9b2f6c1e-0d4a-4f7e-8a3b-5c6d7e8f9a0b",
+                "exp": 1_800_000_000,
+                "app_metadata": { "role": role }
+            })
+        };
+        let access_token = token(claims("SACCO_STAFF"));
+        let session = VerifiedSession::from_user(&access_token, &staff_user()).unwrap();
+
+        // A token edited to claim another role is not the one the server accepted
+        let tampered = token(claims("SACCO_MANAGER"));
+        assert_eq!(session.identity_for(&tampered, 1_700_000_000), None);
+
+        // Even when verified, the role is the server's, not the token's
+        let session = VerifiedSession::from_user(&tampered, &staff_user()).unwrap();
+        assert_eq!(
+            session
+                .identity_for(&tampered, 1_700_000_000)
+                .and_then(|i| i.role.as_deref()),
+            Some("SACCO_STAFF")
+        );
+    }
 }
@@ -0,0 +1,216 @@
+// Camera and image barcode decoding for branches without a hardware scanner
+
+use base64::{engine::general_purpose, Engine as _};
+use rxing::{BarcodeFormat, RXingResult};
+use serde::{Deserialize, Serialize};
+
+use super::hardware::ScanResult;
+use crate::error::{AppError, Context, ErrorCode, Result};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameFormat {
+    /// 4 bytes per pixel, as returned by `CanvasRenderingContext2D.getImageData`
+    Rgba,
+    Rgb,
+    /// 1 byte per pixel greyscale
+    Luma,
+}
+
+/// Raw frame grabbed from the webview's camera stream
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CameraFrame {
+    pub width: u32,
+    pub height: u32,
+    pub format: FrameFormat,
+    /// Pixel data, base64 encoded
+    pub data: String,
+}
+
+impl FrameFormat {
+    fn bytes_per_pixel(self) -> usize {
+        match self {
+            FrameFormat::Rgba => 4,
+            FrameFormat::Rgb => 3,
+            FrameFormat::Luma => 1,
+        }
+    }
+}
+
+/// Convert a raw frame to 8-bit luminance (ITU-R BT.601 weights)
+fn frame_to_luma(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    format: FrameFormat,
+) -> Result<Vec<u8>> {
+    let bpp = format.bytes_per_pixel();
+    let expected = width as usize * height as usize * bpp;
+    if width == 0 || height == 0 || pixels.len() != expected {
+        return Err(AppError::invalid_input(format!(
+            "Frame is {} bytes, expected {} for {}x{} {:?}",
+            pixels.len(),
+            expected,
+            width,
+            height,
+            format
+        )));
+    }
+
+    if format == FrameFormat::Luma {
+        return Ok(pixels.to_vec());
+    }
+
+    Ok(pixels
+        .chunks_exact(bpp)
+        .map(|px| ((px[0] as u32 * 299 + px[1] as u32 * 587 + px[2] as u32 * 114) / 1000) as u8)
+        .collect())
+}
+
+/// Decode every QR code and 1D barcode visible in a greyscale image
+pub fn decode_luma(luma: Vec<u8>, width: u32, height: u32) -> Vec<(String, String)> {
+    // rxing reports "not found" as an error; treat it as an empty result
+    let results = rxing::helpers::detect_multiple_in_luma(luma, width, height).unwrap_or_default();
+
+    let mut decoded: Vec<(String, String)> = Vec::new();
+    for result in results {
+        let entry = (scan_type_name(&result), result.getText().to_string());
+        if !decoded.contains(&entry) {
+            decoded.push(entry);
+        }
+    }
+    decoded
+}
+
+/// Decode an encoded image (PNG or JPEG)
+pub fn decode_image_bytes(bytes: &[u8]) -> Result<Vec<(String, String)>> {
+    let image = image::load_from_memory(bytes)
+        .map_err(|e| AppError::new(ErrorCode::DecodeFailed, "Failed to read image").with_detail(e))?
+        .into_luma8();
+    let (width, height) = image.dimensions();
+    Ok(decode_luma(image.into_raw(), width, height))
+}
+
+/// Format names match those reported by the HID scanner path
+fn scan_type_name(result: &RXingResult) -> String {
+    match result.getBarcodeFormat() {
+        BarcodeFormat::QR_CODE => "QR_CODE",
+        BarcodeFormat::CODE_128 => "CODE128",
+        BarcodeFormat::CODE_39 => "CODE39",
+        BarcodeFormat::CODE_93 => "CODE93",
+        BarcodeFormat::CODABAR => "CODABAR",
+        BarcodeFormat::EAN_13 => "EAN13",
+        BarcodeFormat::EAN_8 => "EAN8",
+        BarcodeFormat::UPC_A => "UPCA",
+        BarcodeFormat::UPC_E => "UPCE",
+        BarcodeFormat::ITF => "ITF",
+        other => return format!("{:?}", other),
+    }
+    .to_string()
+}
+
+fn to_scan_results(
+    app_handle: &tauri::AppHandle,
+    decoded: Vec<(String, String)>,
+) -> Vec<ScanResult> {
+    decoded
+        .into_iter()
+        .map(|(scan_type, data)| ScanResult::new(app_handle, data, &scan_type))
+        .collect()
+}
+
+/// Decode QR codes and barcodes from an image file (PNG/JPEG)
+#[tauri::command]
+pub async fn decode_barcode_image(
+    app_handle: tauri::AppHandle,
+    path: String,
+) -> Result<Vec<ScanResult>> {
+    let bytes = tokio::fs::read(&path)
+        .await
+        .context(format!("Failed to read {}", path))?;
+
+    let decoded = tauri::async_runtime::spawn_blocking(move || decode_image_bytes(&bytes))
+        .await
+        .context("Decoder task failed")??;
+
+    Ok(to_scan_results(&app_handle, decoded))
+}
+
+/// Decode QR codes and barcodes from a camera frame captured in the webview
+///
+/// Returns an empty list when nothing readable is in frame, so the frontend
+/// can keep feeding frames until something decodes.
+#[tauri::command]
+pub async fn decode_camera_frame(
+    app_handle: tauri::AppHandle,
+    frame: CameraFrame,
+) -> Result<Vec<ScanResult>> {
+    let pixels = general_purpose::STANDARD
+        .decode(&frame.data)
+        .context("Camera frame is not valid base64")?;
+
+    let luma = frame_to_luma(&pixels, frame.width, frame.height, frame.format)?;
+
+    let decoded =
+        tauri::async_runtime::spawn_blocking(move || decode_luma(luma, frame.width, frame.height))
+            .await
+            .context("Decoder task failed")?;
+
+    Ok(to_scan_results(&app_handle, decoded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> Vec<u8> {
+        let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+        std::fs::read(&path).unwrap_or_else(|e| panic!("{}: {}", path, e))
+    }
+
+    #[test]
+    fn test_decode_member_card_qr() {
+        let decoded = decode_image_bytes(&fixture("member_card_qr.png")).unwrap();
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(decoded[0].0, "QR_CODE");
+
+        let (card, _) = super::super::cards::decode_card_payload(&decoded[0].1).unwrap();
+        assert_eq!(card.member_ref, "RWA.NYA.GAS.TWIZ.001");
+    }
+
+    #[test]
+    fn test_decode_momo_qr() {
+        let decoded = decode_image_bytes(&fixture("momo_pay_qr.png")).unwrap();
+        assert_eq!(
+            decoded,
+            vec![("QR_CODE".to_string(), "*182*8*1*048321*5000#".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_decode_code128() {
+        let decoded = decode_image_bytes(&fixture("receipt_code128.png")).unwrap();
+        assert_eq!(
+            decoded,
+            vec![("CODE128".to_string(), "RCP-000123".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_decode_rgba_frame() {
+        let image = image::load_from_memory(&fixture("momo_pay_qr.png"))
+            .unwrap()
+            .into_rgba8();
+        let (width, height) = image.dimensions();
+
+        let luma = frame_to_luma(image.as_raw(), width, height, FrameFormat::Rgba).unwrap();
+        let decoded = decode_luma(luma, width, height);
+        assert_eq!(decoded[0].1, "*182*8*1*048321*5000#");
+    }
+
+    #[test]
+    fn test_blank_frame_and_bad_size() {
+        assert!(decode_luma(vec![255; 64 * 64], 64, 64).is_empty());
+        assert!(frame_to_luma(&[0; 10], 2, 2, FrameFormat::Rgba).is_err());
+    }
+}
@@ -0,0 +1,218 @@
+// Per-SACCO receipt logos
+//
+// The logo named by `saccos.logo_url` is downloaded once and kept in the app
+// data dir so receipts and PDF reports still carry it offline. Dithered
+// bitmaps are cached in memory per paper width.
+
+use chrono::{DateTime, Utc};
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tauri::Manager;
+use uuid::Uuid;
+
+use crate::error::{AppError, Context, ErrorCode, Result};
+use crate::escpos::raster::{self, Bitmap};
+use crate::escpos::PaperWidth;
+
+const LOGO_DIR: &str = "logos";
+/// Tallest logo printed, in dots (about 20mm at 203 dpi)
+const MAX_LOGO_HEIGHT: usize = 160;
+const MAX_LOGO_BYTES: usize = 2 * 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CachedLogo {
+    pub sacco_id: Uuid,
+    pub logo_url: String,
+    pub fetched_at: DateTime<Utc>,
+}
+
+/// Dithered logos, keyed by SACCO and paper width
+#[derive(Clone, Default)]
+pub struct LogoCache {
+    pub bitmaps: Arc<Mutex<HashMap<(Uuid, PaperWidth), Bitmap>>>,
+}
+
+fn logo_dir(app_handle: &tauri::AppHandle) -> Result<PathBuf> {
+    let dir = app_handle
+        .path()
+        .app_data_dir()
+        .context("Failed to resolve app data dir")?;
+    Ok(dir.join(LOGO_DIR))
+}
+
+fn image_path(dir: &Path, sacco_id: Uuid) -> PathBuf {
+    dir.join(format!("{}.img", sacco_id))
+}
+
+fn meta_path(dir: &Path, sacco_id: Uuid) -> PathBuf {
+    dir.join(format!("{}.json", sacco_id))
+}
+
+fn read_meta(dir: &Path, sacco_id: Uuid) -> Option<CachedLogo> {
+    let json = std::fs::read_to_string(meta_path(dir, sacco_id)).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+fn store_logo(dir: &Path, meta: &CachedLogo, bytes: &[u8]) -> Result<()> {
+    std::fs::create_dir_all(dir).context("Failed to create logo cache")?;
+
+    // Image first, then metadata, so metadata never points at a missing image
+    let tmp_path = image_path(dir, meta.sacco_id).with_extension("img.tmp");
+    std::fs::write(&tmp_path, bytes).context("Failed to write logo")?;
+    std::fs::rename(&tmp_path, image_path(dir, meta.sacco_id)).context("Failed to write logo")?;
+
+    let json = serde_json::to_string(meta).context("Failed to serialize logo metadata")?;
+    std::fs::write(meta_path(dir, meta.sacco_id), json).context("Failed to write logo")
+}
+
+fn load_image(dir: &Path, sacco_id: Uuid) -> Result<Option<DynamicImage>> {
+    let bytes = match std::fs::read(image_path(dir, sacco_id)) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).context("Failed to read logo"),
+    };
+
+    let image = image::load_from_memory(&bytes).map_err(|e| {
+        AppError::new(ErrorCode::DecodeFailed, "Cached logo is not an image").with_detail(e)
+    })?;
+    Ok(Some(image))
+}
+
+fn load_bitmap(dir: &Path, sacco_id: Uuid, paper: PaperWidth) -> Result<Option<Bitmap>> {
+    Ok(load_image(dir, sacco_id)?
+        .map(|image| raster::from_image(&image, paper.dots(), MAX_LOGO_HEIGHT)))
+}
+
+/// The SACCO's cached logo in full colour, for PDF documents
+pub fn logo_image(app_handle: &tauri::AppHandle, sacco_id: Uuid) -> Result<Option<DynamicImage>> {
+    load_image(&logo_dir(app_handle)?, sacco_id)
+}
+
+/// The SACCO's logo ready to print, or `None` if none has been cached
+pub fn receipt_logo(
+    app_handle: &tauri::AppHandle,
+    sacco_id: Uuid,
+    paper: PaperWidth,
+) -> Result<Option<Bitmap>> {
+    let cache = app_handle.state::<LogoCache>();
+    if let Some(bitmap) = cache.bitmaps.lock()?.get(&(sacco_id, paper)) {
+        return Ok(Some(bitmap.clone()));
+    }
+
+    let bitmap = load_bitmap(&logo_dir(app_handle)?, sacco_id, paper)?;
+    if let Some(bitmap) = &bitmap {
+        cache
+            .bitmaps
+            .lock()?
+            .insert((sacco_id, paper), bitmap.clone());
+    }
+    Ok(bitmap)
+}
+
+/// Download and cache a SACCO's logo for printing on receipts
+///
+/// Does nothing if the same URL is already cached.
+#[tauri::command]
+pub async fn cache_sacco_logo(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, LogoCache>,
+    sacco_id: Uuid,
+    logo_url: String,
+) -> Result<CachedLogo> {
+    let dir = logo_dir(&app_handle)?;
+    if let Some(meta) = read_meta(&dir, sacco_id) {
+        if meta.logo_url == logo_url && image_path(&dir, sacco_id).exists() {
+            return Ok(meta);
+        }
+    }
+
+    let client = reqwest::Client::builder()
+        .user_agent("SACCO+ Staff Admin")
+        .timeout(std::time::Duration::from_secs(30))
+        .build()
+        .context("Failed to create HTTP client")?;
+
+    let response = client
+        .get(&logo_url)
+        .send()
+        .await
+        .context("Failed to download logo")?;
+    if !response.status().is_success() {
+        return Err(AppError::http_status(response.status()).with_detail(logo_url));
+    }
+
+    let bytes = read_logo(response).await?;
+    image::load_from_memory(&bytes).map_err(|e| {
+        AppError::new(ErrorCode::DecodeFailed, "Logo is not a PNG or JPEG image").with_detail(e)
+    })?;
+
+    let meta = CachedLogo {
+        sacco_id,
+        logo_url,
+        fetched_at: Utc::now(),
+    };
+    store_logo(&dir, &meta, &bytes)?;
+    state.bitmaps.lock()?.retain(|(id, _), _| *id != sacco_id);
+
+    Ok(meta)
+}
+
+/// Read a logo download, stopping as soon as it is larger than `MAX_LOGO_BYTES`
+async fn read_logo(mut response: reqwest::Response) -> Result<Vec<u8>> {
+    let too_large = || {
+        AppError::invalid_input("Logo image is too large")
+            .with_detail(format!("limit is {} bytes", MAX_LOGO_BYTES))
+    };
+    if response
+        .content_length()
+        .is_some_and(|len| len > MAX_LOGO_BYTES as u64)
+    {
+        return Err(too_large());
+    }
+
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk().await.context("Failed to download logo")? {
+        if bytes.len() + chunk.len() > MAX_LOGO_BYTES {
+            return Err(too_large());
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_and_load_logo() {
+        let dir = std::env::temp_dir().join(format!("logo-test-{}", Uuid::new_v4()));
+        let sacco_id = Uuid::new_v4();
+        assert!(load_bitmap(&dir, sacco_id, PaperWidth::Mm58)
+            .unwrap()
+            .is_none());
+
+        let mut png = Vec::new();
+        image::DynamicImage::ImageLuma8(image::GrayImage::new(600, 300))
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+
+        let meta = CachedLogo {
+            sacco_id,
+            logo_url: "https://cdn.ibimina.rw/logos/twizigamire.png".to_string(),
+            fetched_at: Utc::now(),
+        };
+        store_logo(&dir, &meta, &png).unwrap();
+
+        assert_eq!(read_meta(&dir, sacco_id).unwrap().logo_url, meta.logo_url);
+        let bitmap = load_bitmap(&dir, sacco_id, PaperWidth::Mm58)
+            .unwrap()
+            .unwrap();
+        assert_eq!((bitmap.width, bitmap.height), (320, 160));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,560 @@
+// Signed member card payloads (QR codes and NFC card data)
+//
+// A card payload is `IBC1:` followed by the unpadded base64url encoding of
+// the binary card body and its 64-byte Ed25519 signature. Each SACCO signs its
+// cards with its own key; tellers verify offline against a cached public key
+// set and revocation list synced from the backend.
+
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey, SIGNATURE_LENGTH};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use tauri::Manager;
+use uuid::Uuid;
+
+use crate::error::{AppError, Context, Result};
+
+pub const CARD_PAYLOAD_PREFIX: &str = "IBC1:";
+const CARD_PAYLOAD_VERSION: u8 = 1;
+const TRUST_STORE_FILE: &str = "card_trust_store.json";
+const CARD_KEYS_URL: &str = "https://api.ibimina.rw/member-cards/keys";
+const CARD_REVOCATIONS_URL: &str = "https://api.ibimina.rw/member-cards/revocations";
+
+/// Fields carried by a member card, covered by the SACCO signature
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MemberCard {
+    pub key_id: String,
+    pub sacco_id: Uuid,
+    pub member_ref: String,
+    pub issued_on: NaiveDate,
+    pub card_serial: u32,
+}
+
+/// Public key provisioned for a SACCO's card signing
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CardPublicKey {
+    pub key_id: String,
+    pub sacco_id: Uuid,
+    /// Raw 32-byte Ed25519 public key, base64 encoded
+    pub public_key: String,
+    pub valid_from: Option<NaiveDate>,
+    pub valid_until: Option<NaiveDate>,
+}
+
+/// Card revoked by the backend (lost, stolen, replaced)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RevokedCard {
+    pub sacco_id: Uuid,
+    pub card_serial: u32,
+    pub revoked_at: DateTime<Utc>,
+    pub reason: Option<String>,
+}
+
+/// Locally cached keys and revocations, persisted in the app data dir
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct CardTrustStore {
+    pub keys: Vec<CardPublicKey>,
+    pub revocations: Vec<RevokedCard>,
+    pub keys_synced_at: Option<DateTime<Utc>>,
+    pub revocations_synced_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CardStatus {
+    /// Signature checks out and the card is not revoked
+    Valid,
+    /// Signature checks out but the card is on the revocation list
+    Revoked,
+    /// Signature does not match the SACCO key, or the key does not cover the card
+    Forged,
+    /// No cached key with this id; sync the trust store and scan again
+    UnknownKey,
+    /// The cached key with this id is not a valid Ed25519 public key
+    InvalidKey,
+    /// Not a member card payload, or a corrupted one
+    Malformed,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CardVerification {
+    pub status: CardStatus,
+    pub card: Option<MemberCard>,
+    pub detail: Option<String>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub trust_store_synced_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TrustStoreSummary {
+    pub key_count: usize,
+    pub revocation_count: usize,
+    pub keys_synced_at: Option<DateTime<Utc>>,
+    pub revocations_synced_at: Option<DateTime<Utc>>,
+}
+
+/// Managed state holding the trust store loaded from disk
+#[derive(Clone, Default)]
+pub struct CardTrustState {
+    pub store: Arc<RwLock<Option<CardTrustStore>>>,
+}
+
+impl MemberCard {
+    /// Binary body covered by the signature; fails if the key id is longer
+    /// than the 255 bytes its length prefix can hold
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let key_id = self.key_id.as_bytes();
+        let key_len = u8::try_from(key_id.len())
+            .map_err(|_| format!("Key id is {} bytes, at most 255 fit", key_id.len()))?;
+        let mut body = Vec::with_capacity(32 + key_id.len() + self.member_ref.len());
+        body.push(CARD_PAYLOAD_VERSION);
+        body.push(key_len);
+        body.extend_from_slice(key_id);
+        body.extend_from_slice(self.sacco_id.as_bytes());
+        body.extend_from_slice(&self.issued_on.num_days_from_ce().to_be_bytes());
+        body.extend_from_slice(&self.card_serial.to_be_bytes());
+        body.extend_from_slice(self.member_ref.as_bytes());
+        Ok(body)
+    }
+
+    pub fn from_bytes(body: &[u8]) -> Result<Self, String> {
+        let (&version, rest) = body.split_first().ok_or("Empty card body")?;
+        if version != CARD_PAYLOAD_VERSION {
+            return Err(format!("Unsupported card version {}", version));
+        }
+
+        let (&key_len, rest) = rest.split_first().ok_or("Truncated card body")?;
+        let key_len = key_len as usize;
+        if rest.len() < key_len + 16 + 4 + 4 {
+            return Err("Truncated card body".to_string());
+        }
+
+        let (key_id, rest) = rest.split_at(key_len);
+        let (sacco_id, rest) = rest.split_at(16);
+        let (days, rest) = rest.split_at(4);
+        let (serial, member_ref) = rest.split_at(4);
+
+        let days = i32::from_be_bytes(days.try_into().expect("4-byte slice"));
+
+        Ok(Self {
+            key_id: String::from_utf8(key_id.to_vec()).map_err(|_| "Invalid key id")?,
+            sacco_id: Uuid::from_slice(sacco_id).map_err(|e| format!("Invalid SACCO id: {}", e))?,
+            member_ref: String::from_utf8(member_ref.to_vec())
+                .map_err(|_| "Invalid member reference")?,
+            issued_on: NaiveDate::from_num_days_from_ce_opt(days).ok_or("Invalid issue date")?,
+            card_serial: u32::from_be_bytes(serial.try_into().expect("4-byte slice")),
+        })
+    }
+}
+
+/// Encode a signed card as printed on QR codes
+pub fn encode_card_payload(
+    card: &MemberCard,
+    signature: &[u8; SIGNATURE_LENGTH],
+) -> Result<String, String> {
+    let mut bytes = card.to_bytes()?;
+    bytes.extend_from_slice(signature);
+    Ok(format!(
+        "{}{}",
+        CARD_PAYLOAD_PREFIX,
+        general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+    ))
+}
+
+/// Split a scanned payload into the card and its signature
+pub fn decode_card_payload(payload: &str) -> Result<(MemberCard, Signature), String> {
+    let encoded = payload
+        .trim()
+        .strip_prefix(CARD_PAYLOAD_PREFIX)
+        .ok_or("Not a member card payload")?;
+
+    let bytes = general_purpose::URL_SAFE_NO_PAD
+        .decode(encoded)
+        .map_err(|e| format!("Base64 decode error: {}", e))?;
+
+    if bytes.len() <= SIGNATURE_LENGTH {
+        return Err("Truncated card payload".to_string());
+    }
+
+    let (body, signature) = bytes.split_at(bytes.len() - SIGNATURE_LENGTH);
+    let signature =
+        Signature::from_slice(signature).map_err(|e| format!("Bad signature: {}", e))?;
+
+    Ok((MemberCard::from_bytes(body)?, signature))
+}
+
+pub fn is_card_payload(data: &str) -> bool {
+    data.trim().starts_with(CARD_PAYLOAD_PREFIX)
+}
+
+impl CardTrustStore {
+    /// Verify a scanned payload against the cached keys and revocations
+    pub fn verify(&self, payload: &str) -> CardVerification {
+        let mut result = CardVerification {
+            status: CardStatus::Malformed,
+            card: None,
+            detail: None,
+            revoked_at: None,
+            trust_store_synced_at: self.revocations_synced_at,
+        };
+
+        let (card, signature) = match decode_card_payload(payload) {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                result.detail = Some(e);
+                return result;
+            }
+        };
+
+        let (status, detail) = self.check_signature(&card, &signature);
+        result.status = status;
+        result.detail = detail;
+
+        if status == CardStatus::Valid {
+            if let Some(revoked) = self
+                .revocations
+                .iter()
+                .find(|r| r.sacco_id == card.sacco_id && r.card_serial == card.card_serial)
+            {
+                result.status = CardStatus::Revoked;
+                result.revoked_at = Some(revoked.revoked_at);
+                result.detail = revoked.reason.clone();
+            }
+        }
+
+        result.card = Some(card);
+        result
+    }
+
+    fn check_signature(
+        &self,
+        card: &MemberCard,
+        signature: &Signature,
+    ) -> (CardStatus, Option<String>) {
+        let Some(key) = self.keys.iter().find(|k| k.key_id == card.key_id) else {
+            return (
+                CardStatus::UnknownKey,
+                Some(format!("No cached key '{}'", card.key_id)),
+            );
+        };
+
+        if key.sacco_id != card.sacco_id {
+            return (
+                CardStatus::Forged,
+                Some("Signing key belongs to another SACCO".to_string()),
+            );
+        }
+
+        let outside_window = key.valid_from.is_some_and(|from| card.issued_on < from)
+            || key.valid_until.is_some_and(|until| card.issued_on > until);
+        if outside_window {
+            return (
+                CardStatus::Forged,
+                Some("Card issue date is outside the key validity period".to_string()),
+            );
+        }
+
+        let verifying_key = match parse_public_key(&key.public_key) {
+            Ok(k) => k,
+            Err(e) => return (CardStatus::InvalidKey, Some(e)),
+        };
+        let body = match card.to_bytes() {
+            Ok(body) => body,
+            Err(e) => return (CardStatus::Malformed, Some(e)),
+        };
+
+        match verifying_key.verify(&body, signature) {
+            Ok(()) => (CardStatus::Valid, None),
+            Err(_) => (
+                CardStatus::Forged,
+                Some("Signature verification failed".to_string()),
+            ),
+        }
+    }
+
+    pub fn summary(&self) -> TrustStoreSummary {
+        TrustStoreSummary {
+            key_count: self.keys.len(),
+            revocation_count: self.revocations.len(),
+            keys_synced_at: self.keys_synced_at,
+            revocations_synced_at: self.revocations_synced_at,
+        }
+    }
+}
+
+fn parse_public_key(encoded: &str) -> Result<VerifyingKey, String> {
+    let bytes = general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(|e| format!("Invalid public key encoding: {}", e))?;
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| "Public key must be 32 bytes".to_string())?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| format!("Invalid public key: {}", e))
+}
+
+fn trust_store_path(app_handle: &tauri::AppHandle) -> Result<PathBuf> {
+    let dir = app_handle
+        .path()
+        .app_data_dir()
+        .context("Failed to resolve app data dir")?;
+    Ok(dir.join(TRUST_STORE_FILE))
+}
+
+fn load_trust_store(path: &PathBuf) -> Result<CardTrustStore> {
+    match std::fs::read_to_string(path) {
+        Ok(json) => serde_json::from_str(&json)
+            .context("Failed to parse card trust store"),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(CardTrustStore::default()),
+        Err(e) => Err(e).context("Failed to read card trust store"),
+    }
+}
+
+fn save_trust_store(path: &PathBuf, store: &CardTrustStore) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .context("Failed to create app data dir")?;
+    }
+
+    let json = serde_json::to_string(store)
+        .context("Failed to serialize card trust store")?;
+
+    // Write then rename so a crash never leaves a half-written store
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::write(&tmp_path, json)
+        .context("Failed to write card trust store")?;
+    std::fs::rename(&tmp_path, path).context("Failed to write card trust store")
+}
+
+/// Return the cached trust store, loading it from disk on first use
+fn cached_store(
+    app_handle: &tauri::AppHandle,
+    state: &CardTrustState,
+) -> Result<CardTrustStore> {
+    if let Some(store) = state.store.read()?.as_ref() {
+        return Ok(store.clone());
+    }
+
+    let store = load_trust_store(&trust_store_path(app_handle)?)?;
+    *state.store.write()? = Some(store.clone());
+    Ok(store)
+}
+
+/// Verify a scanned member card offline
+pub fn verify_card_payload(
+    app_handle: &tauri::AppHandle,
+    payload: &str,
+) -> Result<CardVerification> {
+    let state = app_handle.state::<CardTrustState>();
+    Ok(cached_store(app_handle, &state)?.verify(payload))
+}
+
+/// Verify a member card QR/NFC payload against the cached SACCO keys
+#[tauri::command]
+pub async fn verify_member_card(
+    app_handle: tauri::AppHandle,
+    payload: String,
+) -> Result<CardVerification> {
+    verify_card_payload(&app_handle, &payload)
+}
+
+/// Report what the cached trust store contains and when it was last synced
+#[tauri::command]
+pub async fn get_card_trust_store_status(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, CardTrustState>,
+) -> Result<TrustStoreSummary> {
+    Ok(cached_store(&app_handle, &state)?.summary())
+}
+
+/// Refresh public keys and revocations from the backend
+///
+/// On failure the previously cached store is kept so offline verification
+/// keeps working.
+#[tauri::command]
+pub async fn sync_card_trust_store(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, CardTrustState>,
+) -> Result<TrustStoreSummary> {
+    let credentials = super::auth::require_credentials().await?;
+
+    let client = reqwest::Client::builder()
+        .user_agent("SACCO+ Staff Admin")
+        .timeout(std::time::Duration::from_secs(30))
+        .build()
+        .context("Failed to create HTTP client")?;
+
+    let keys: Vec<CardPublicKey> =
+        fetch_json(&client, CARD_KEYS_URL, &credentials.access_token).await?;
+    let revocations: Vec<RevokedCard> =
+        fetch_json(&client, CARD_REVOCATIONS_URL, &credentials.access_token).await?;
+
+    let now = Utc::now();
+    let store = CardTrustStore {
+        keys,
+        revocations,
+        keys_synced_at: Some(now),
+        revocations_synced_at: Some(now),
+    };
+
+    save_trust_store(&trust_store_path(&app_handle)?, &store)?;
+    let summary = store.summary();
+    *state.store.write()? = Some(store);
+
+    Ok(summary)
+}
+
+async fn fetch_json<T: serde::de::DeserializeOwned>(
+    client: &reqwest::Client,
+    url: &str,
+    access_token: &str,
+) -> Result<T> {
+    let response = client
+        .get(url)
+        .bearer_auth(access_token)
+        .send()
+        .await
+        .context("Failed to sync member card keys")?;
+
+    if !response.status().is_success() {
+        return Err(AppError::http_status(response.status()).with_detail(url));
+    }
+
+    response
+        .json()
+        .await
+        .context("Failed to parse response")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    fn sacco_id() -> Uuid {
+        Uuid::parse_str("6f1c2a5e-8d1b-4c3f-9a7e-2b5d8c0e4f11").unwrap()
+    }
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_bytes(&[7u8; 32])
+    }
+
+    fn card() -> MemberCard {
+        MemberCard {
+            key_id: "kgl-2025".to_string(),
+            sacco_id: sacco_id(),
+            member_ref: "RWA.NYA.GAS.TWIZ.001".to_string(),
+            issued_on: NaiveDate::from_ymd_opt(2025, 3, 14).unwrap(),
+            card_serial: 4021,
+        }
+    }
+
+    fn sign(card: &MemberCard, key: &SigningKey) -> String {
+        let body = card.to_bytes().unwrap();
+        encode_card_payload(card, &key.sign(&body).to_bytes()).unwrap()
+    }
+
+    fn store() -> CardTrustStore {
+        CardTrustStore {
+            keys: vec![CardPublicKey {
+                key_id: "kgl-2025".to_string(),
+                sacco_id: sacco_id(),
+                public_key: general_purpose::STANDARD
+                    .encode(signing_key().verifying_key().to_bytes()),
+                valid_from: NaiveDate::from_ymd_opt(2025, 1, 1),
+                valid_until: None,
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_payload_roundtrip() {
+        let payload = sign(&card(), &signing_key());
+        assert!(is_card_payload(&payload));
+
+        let (decoded, _) = decode_card_payload(&payload).unwrap();
+        assert_eq!(decoded, card());
+    }
+
+    #[test]
+    fn test_valid_card() {
+        let result = store().verify(&sign(&card(), &signing_key()));
+        assert_eq!(result.status, CardStatus::Valid);
+        assert_eq!(result.card, Some(card()));
+    }
+
+    #[test]
+    fn test_tampered_card_is_forged() {
+        let payload = sign(&card(), &signing_key());
+        let (_, signature) = decode_card_payload(&payload).unwrap();
+
+        let mut cloned = card();
+        cloned.member_ref = "RWA.NYA.GAS.TWIZ.002".to_string();
+        let tampered = encode_card_payload(&cloned, &signature.to_bytes()).unwrap();
+
+        assert_eq!(store().verify(&tampered).status, CardStatus::Forged);
+    }
+
+    #[test]
+    fn test_card_signed_with_other_key_is_forged() {
+        let payload = sign(&card(), &SigningKey::from_bytes(&[9u8; 32]));
+        assert_eq!(store().verify(&payload).status, CardStatus::Forged);
+    }
+
+    #[test]
+    fn test_card_issued_before_key_is_forged() {
+        let mut early = card();
+        early.issued_on = NaiveDate::from_ymd_opt(2024, 12, 31).unwrap();
+        let payload = sign(&early, &signing_key());
+        assert_eq!(store().verify(&payload).status, CardStatus::Forged);
+    }
+
+    #[test]
+    fn test_revoked_card() {
+        let mut store = store();
+        store.revocations.push(RevokedCard {
+            sacco_id: sacco_id(),
+            card_serial: 4021,
+            revoked_at: Utc::now(),
+            reason: Some("Reported lost".to_string()),
+        });
+
+        let result = store.verify(&sign(&card(), &signing_key()));
+        assert_eq!(result.status, CardStatus::Revoked);
+        assert_eq!(result.detail.as_deref(), Some("Reported lost"));
+    }
+
+    #[test]
+    fn test_unknown_key_and_malformed() {
+        let payload = sign(&card(), &signing_key());
+        assert_eq!(
+            CardTrustStore::default().verify(&payload).status,
+            CardStatus::UnknownKey
+        );
+        assert_eq!(
+            store().verify("04:12:34:56:78:90:AB").status,
+            CardStatus::Malformed
+        );
+        assert_eq!(store().verify("IBC1:AAAA").status, CardStatus::Malformed);
+    }
+
+    #[test]
+    fn test_invalid_cached_key() {
+        let mut store = store();
+        store.keys[0].public_key = general_purpose::STANDARD.encode([1u8; 16]);
+        let result = store.verify(&sign(&card(), &signing_key()));
+        assert_eq!(result.status, CardStatus::InvalidKey);
+    }
+
+    #[test]
+    fn test_long_key_id_is_rejected() {
+        let mut long = card();
+        long.key_id = "k".repeat(256);
+        assert!(long.to_bytes().is_err());
+        long.key_id.pop();
+        let body = long.to_bytes().unwrap();
+        assert_eq!(MemberCard::from_bytes(&body).unwrap(), long);
+    }
+}
@@ -3,6 +3,8 @@
 use serde::{Deserialize, Serialize};
 use base64::{Engine as _, engine::general_purpose};
 
+use crate::error::{Context, Result};
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct EncryptRequest {
     pub data: String,
@@ -16,7 +18,7 @@ pub struct DecryptRequest {
 /// Encrypt data using simple XOR cipher with rotating key
 /// In production, use a proper encryption library like ring or sodiumoxide
 #[tauri::command]
-pub async fn encrypt_data(data: EncryptRequest) -> Result<String, String> {
+pub async fn encrypt_data(data: EncryptRequest) -> Result<String> {
     // Simple XOR encryption for demonstration
     // In production, use AES-256-GCM or similar
     let key = get_encryption_key().await?;
@@ -26,23 +28,23 @@ pub async fn encrypt_data(data: EncryptRequest) -> Result<String, String> {
 
 /// Decrypt data
 #[tauri::command]
-pub async fn decrypt_data(data: DecryptRequest) -> Result<String, String> {
+pub async fn decrypt_data(data: DecryptRequest) -> Result<String> {
     let key = get_encryption_key().await?;
     let encrypted = general_purpose::STANDARD
         .decode(data.data)
-        .map_err(|e| format!("Base64 decode error: {}", e))?;
+        .context("Encrypted data is corrupted")?;
     
     let decrypted = xor_encrypt(&encrypted, &key);
     String::from_utf8(decrypted)
-        .map_err(|e| format!("UTF-8 decode error: {}", e))
+        .context("Encrypted data is corrupted")
 }
 
 /// Get or generate encryption key from keyring
-async fn get_encryption_key() -> Result<Vec<u8>, String> {
+async fn get_encryption_key() -> Result<Vec<u8>> {
     use keyring::Entry;
     
     let entry = Entry::new("ibimina-staff-admin", "encryption-key")
-        .map_err(|e| format!("Keyring error: {}", e))?;
+        .context("Failed to access keychain")?;
     
     match entry.get_password() {
         Ok(password) => Ok(password.into_bytes()),
@@ -51,7 +53,7 @@ async fn get_encryption_key() -> Result<Vec<u8>, String> {
             let key = generate_random_key(32);
             let key_str = general_purpose::STANDARD.encode(&key);
             entry.set_password(&key_str)
-                .map_err(|e| format!("Failed to save key: {}", e))?;
+                .context("Failed to save key")?;
             Ok(key)
         }
     }
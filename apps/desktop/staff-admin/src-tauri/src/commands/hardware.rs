@@ -1,12 +1,192 @@
 use serde::{Deserialize, Serialize};
 use std::sync::{Arc, Mutex};
-use tauri::{Emitter, WebviewWindow};
+use tauri::{Emitter, Manager, WebviewWindow};
+
+use super::cards::{self, CardVerification};
+use crate::error::{AppError, ErrorCode, Result};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ScanResult {
     pub data: String,
     pub scan_type: String,
     pub timestamp: i64,
+    pub payload: ScanPayload,
+}
+
+/// Typed interpretation of the scanned data
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ScanPayload {
+    /// Signed member card, with its offline verification result
+    MemberCard(CardVerification),
+    /// MoMo Pay merchant QR code (USSD or EMVCo merchant-presented format)
+    MomoPayment(MomoPayment),
+    /// Anything we don't recognise; use `data` as-is
+    Text,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MomoPayment {
+    pub merchant_code: String,
+    pub merchant_name: Option<String>,
+    /// Amount in whole francs, when the QR code fixes one
+    pub amount: Option<u64>,
+    pub currency: String,
+}
+
+impl ScanResult {
+    /// Build a scan result, verifying member card payloads on the way
+    pub fn new(app_handle: &tauri::AppHandle, data: String, scan_type: &str) -> Self {
+        let payload = if cards::is_card_payload(&data) {
+            match cards::verify_card_payload(app_handle, &data) {
+                Ok(verification) => ScanPayload::MemberCard(verification),
+                Err(e) => {
+                    eprintln!("Failed to verify member card: {}", e);
+                    ScanPayload::Text
+                }
+            }
+        } else if let Some(payment) = parse_momo_payment(&data) {
+            ScanPayload::MomoPayment(payment)
+        } else {
+            ScanPayload::Text
+        };
+
+        Self {
+            data,
+            scan_type: scan_type.to_string(),
+            timestamp: chrono::Utc::now().timestamp(),
+            payload,
+        }
+    }
+}
+
+const MOMO_PAY_USSD_PREFIX: &str = "*182*8*1*";
+
+/// Parse a MoMo Pay QR code
+///
+/// Accepts the USSD form printed on merchant stickers
+/// (`*182*8*1*<merchant code>[*<amount>]#`, optionally as a `tel:` URI) and
+/// EMVCo merchant-presented payloads.
+pub fn parse_momo_payment(data: &str) -> Option<MomoPayment> {
+    let data = data.trim();
+
+    if data.starts_with("000201") {
+        return parse_emvco_payment(data);
+    }
+
+    let ussd = data
+        .strip_prefix("tel:")
+        .unwrap_or(data)
+        .replace("%23", "#");
+    let fields = ussd.strip_prefix(MOMO_PAY_USSD_PREFIX)?.strip_suffix('#')?;
+
+    let mut parts = fields.split('*');
+    let merchant_code = parts.next().filter(|c| is_digits(c))?.to_string();
+    let amount = match parts.next() {
+        Some(amount) => Some(parse_amount(amount)?),
+        None => None,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+
+    Some(MomoPayment {
+        merchant_code,
+        merchant_name: None,
+        amount,
+        currency: "RWF".to_string(),
+    })
+}
+
+fn parse_emvco_payment(data: &str) -> Option<MomoPayment> {
+    let fields = parse_emvco_tlv(data)?;
+    let field = |id: &str| fields.iter().find(|(tag, _, _)| *tag == id).map(|(_, v, _)| *v);
+
+    // The CRC (tag 63) is the last field and covers everything up to and
+    // including its own tag and length
+    let (_, crc, offset) = fields
+        .last()
+        .filter(|(tag, crc, _)| *tag == "63" && crc.len() == 4)?;
+    if fields.iter().filter(|(tag, _, _)| *tag == "63").count() != 1 {
+        return None;
+    }
+    let covered = data.get(..offset + 4)?;
+    if !crc.eq_ignore_ascii_case(&format!("{:04X}", crc16_ccitt(covered.as_bytes()))) {
+        return None;
+    }
+
+    // Merchant account templates live in tags 26-51; the merchant id is sub-tag 01
+    let merchant_code = fields
+        .iter()
+        .filter(|(tag, _, _)| matches!(tag.parse::<u8>(), Ok(26..=51)))
+        .find_map(|(_, template, _)| {
+            parse_emvco_tlv(template)?
+                .into_iter()
+                .find(|(tag, _, _)| *tag == "01")
+                .map(|(_, v, _)| v.to_string())
+        })?;
+
+    let currency = match field("53") {
+        Some("646") | None => "RWF".to_string(),
+        Some(other) => other.to_string(),
+    };
+
+    let amount = match field("54") {
+        Some(amount) => Some(parse_amount(amount)?),
+        None => None,
+    };
+
+    Some(MomoPayment {
+        merchant_code,
+        merchant_name: field("59").map(str::to_string),
+        amount,
+        currency,
+    })
+}
+
+/// Split EMVCo TLV data into `(tag, value, byte offset of the tag)`
+fn parse_emvco_tlv(data: &str) -> Option<Vec<(&str, &str, usize)>> {
+    let mut fields = Vec::new();
+    let mut offset = 0;
+
+    while offset < data.len() {
+        let rest = &data[offset..];
+        let tag = rest.get(..2)?;
+        let len: usize = rest.get(2..4)?.parse().ok()?;
+        let value = rest.get(4..4 + len)?;
+        fields.push((tag, value, offset));
+        offset += 4 + len;
+    }
+
+    Some(fields)
+}
+
+fn crc16_ccitt(bytes: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for &byte in bytes {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Whole-franc amount; RWF has no minor unit so "5000.00" is accepted but "5000.50" is not
+fn parse_amount(amount: &str) -> Option<u64> {
+    let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    if !is_digits(whole) || !fraction.chars().all(|c| c == '0') {
+        return None;
+    }
+    whole.parse().ok()
+}
+
+fn is_digits(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_digit())
 }
 
 #[derive(Clone)]
@@ -24,7 +204,7 @@ impl Default for ScannerState {
 
 /// Check if HID barcode scanner is available
 #[tauri::command]
-pub async fn is_scanner_available() -> Result<bool, String> {
+pub async fn is_scanner_available() -> Result<bool> {
     #[cfg(target_os = "windows")]
     {
         // Check for HID devices on Windows
@@ -50,14 +230,11 @@ pub async fn is_scanner_available() -> Result<bool, String> {
 pub async fn start_barcode_scan(
     window: WebviewWindow,
     state: tauri::State<'_, ScannerState>,
-) -> Result<(), String> {
-    let mut is_scanning = state
-        .is_scanning
-        .lock()
-        .map_err(|e| format!("Lock error: {}", e))?;
+) -> Result<()> {
+    let mut is_scanning = state.is_scanning.lock()?;
 
     if *is_scanning {
-        return Err("Scanner already active".to_string());
+        return Err(AppError::new(ErrorCode::DeviceBusy, "Scanner already active"));
     }
 
     *is_scanning = true;
@@ -73,11 +250,11 @@ pub async fn start_barcode_scan(
         // Example: emit scan event when barcode is detected
         let _result = window.emit(
             "barcode-scanned",
-            ScanResult {
-                data: "EXAMPLE-BARCODE-123".to_string(),
-                scan_type: "CODE128".to_string(),
-                timestamp: chrono::Utc::now().timestamp(),
-            },
+            ScanResult::new(
+                window.app_handle(),
+                "EXAMPLE-BARCODE-123".to_string(),
+                "CODE128",
+            ),
         );
 
         // Reset scanning state when done
@@ -91,11 +268,8 @@ pub async fn start_barcode_scan(
 
 /// Stop barcode scanning
 #[tauri::command]
-pub async fn stop_barcode_scan(state: tauri::State<'_, ScannerState>) -> Result<(), String> {
-    let mut is_scanning = state
-        .is_scanning
-        .lock()
-        .map_err(|e| format!("Lock error: {}", e))?;
+pub async fn stop_barcode_scan(state: tauri::State<'_, ScannerState>) -> Result<()> {
+    let mut is_scanning = state.is_scanning.lock()?;
 
     *is_scanning = false;
     Ok(())
@@ -103,7 +277,7 @@ pub async fn stop_barcode_scan(state: tauri::State<'_, ScannerState>) -> Result<
 
 /// Check if NFC reader is available
 #[tauri::command]
-pub async fn is_nfc_available() -> Result<bool, String> {
+pub async fn is_nfc_available() -> Result<bool> {
     #[cfg(target_os = "windows")]
     {
         // Check for NFC readers via Windows.Devices.SmartCards
@@ -131,7 +305,7 @@ pub async fn is_nfc_available() -> Result<bool, String> {
 
 /// Start NFC reading
 #[tauri::command]
-pub async fn start_nfc_reading(window: WebviewWindow) -> Result<(), String> {
+pub async fn start_nfc_reading(window: WebviewWindow) -> Result<()> {
     // Emit event when NFC tag is detected
     // This is a simplified implementation
     let _result = window.emit(
@@ -148,14 +322,14 @@ pub async fn start_nfc_reading(window: WebviewWindow) -> Result<(), String> {
 
 /// Stop NFC reading
 #[tauri::command]
-pub async fn stop_nfc_reading() -> Result<(), String> {
+pub async fn stop_nfc_reading() -> Result<()> {
     // Stop NFC reader
     Ok(())
 }
 
 /// Check if biometric authentication is available
 #[tauri::command]
-pub async fn is_biometrics_available() -> Result<bool, String> {
+pub async fn is_biometrics_available() -> Result<bool> {
     #[cfg(target_os = "windows")]
     {
         // Check for Windows Hello
@@ -176,20 +350,20 @@ pub async fn is_biometrics_available() -> Result<bool, String> {
 }
 
 #[cfg(target_os = "windows")]
-fn check_windows_hello() -> Result<bool, String> {
+fn check_windows_hello() -> Result<bool> {
     // Simplified - in production, check Windows.Security.Credentials.UI
     Ok(true)
 }
 
 #[cfg(target_os = "macos")]
-fn check_touch_id() -> Result<bool, String> {
+fn check_touch_id() -> Result<bool> {
     // Simplified - in production, check LocalAuthentication framework
     Ok(true)
 }
 
 /// Trigger biometric authentication
 #[tauri::command]
-pub async fn authenticate_biometrics(reason: String) -> Result<bool, String> {
+pub async fn authenticate_biometrics(reason: String) -> Result<bool> {
     #[cfg(target_os = "windows")]
     {
         authenticate_windows_hello(reason)
@@ -203,20 +377,111 @@ pub async fn authenticate_biometrics(reason: String) -> Result<bool, String> {
     #[cfg(target_os = "linux")]
     {
         let _ = reason; // Suppress unused warning
-        Err("Biometric authentication not supported on Linux".to_string())
+        Err(AppError::new(
+            ErrorCode::Unsupported,
+            "Biometric authentication not supported on Linux",
+        ))
     }
 }
 
 #[cfg(target_os = "windows")]
-fn authenticate_windows_hello(_reason: String) -> Result<bool, String> {
+fn authenticate_windows_hello(_reason: String) -> Result<bool> {
     // Simplified implementation
     // In production, use Windows.Security.Credentials.UI.UserConsentVerifier
     Ok(true)
 }
 
 #[cfg(target_os = "macos")]
-fn authenticate_touch_id(_reason: String) -> Result<bool, String> {
+fn authenticate_touch_id(_reason: String) -> Result<bool> {
     // Simplified implementation
     // In production, use LocalAuthentication framework via objc bindings
     Ok(true)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tlv(tag: &str, value: &str) -> String {
+        format!("{}{:02}{}", tag, value.len(), value)
+    }
+
+    fn emvco(fields: &[(&str, &str)]) -> String {
+        let merchant = tlv("00", "rw.momo") + &tlv("01", "048321");
+        let mut body = tlv("00", "01") + &tlv("01", "12") + &tlv("26", &merchant);
+        for (tag, value) in fields {
+            body += &tlv(tag, value);
+        }
+        let covered = format!("{}6304", body);
+        format!("{}{:04X}", covered, crc16_ccitt(covered.as_bytes()))
+    }
+
+    #[test]
+    fn test_momo_ussd_payment() {
+        assert_eq!(
+            parse_momo_payment("*182*8*1*048321#"),
+            Some(MomoPayment {
+                merchant_code: "048321".to_string(),
+                merchant_name: None,
+                amount: None,
+                currency: "RWF".to_string(),
+            })
+        );
+
+        let payment = parse_momo_payment("tel:*182*8*1*048321*5000%23").unwrap();
+        assert_eq!(payment.amount, Some(5000));
+
+        assert_eq!(parse_momo_payment("*182*1*1*0788123456#"), None);
+        assert_eq!(parse_momo_payment("*182*8*1*04A321#"), None);
+    }
+
+    #[test]
+    fn test_momo_emvco_payment() {
+        let payload = emvco(&[
+            ("53", "646"),
+            ("54", "500.00"),
+            ("58", "RW"),
+            ("59", "KIMIRONKO SHOP"),
+            ("60", "KIGALI"),
+        ]);
+        let payment = parse_momo_payment(&payload).unwrap();
+
+        assert_eq!(payment.merchant_code, "048321");
+        assert_eq!(payment.merchant_name.as_deref(), Some("KIMIRONKO SHOP"));
+        assert_eq!(payment.amount, Some(500));
+        assert_eq!(payment.currency, "RWF");
+    }
+
+    #[test]
+    fn test_momo_emvco_rejects_bad_crc() {
+        let mut payload = emvco(&[("53", "646")]);
+        payload.replace_range(payload.len() - 4.., "0000");
+        assert_eq!(parse_momo_payment(&payload), None);
+    }
+
+    #[test]
+    fn test_momo_emvco_non_ascii_merchant() {
+        let payload = emvco(&[("59", "CAFÉ NYAMIRAMBO"), ("60", "KIGALI")]);
+        let payment = parse_momo_payment(&payload).unwrap();
+        assert_eq!(payment.merchant_name.as_deref(), Some("CAFÉ NYAMIRAMBO"));
+
+        // A short CRC followed by a multibyte field must not panic
+        assert_eq!(parse_momo_payment("0002016301A5902é"), None);
+    }
+
+    #[test]
+    fn test_momo_emvco_rejects_misplaced_crc() {
+        // Missing CRC
+        let payload = emvco(&[("53", "646")]);
+        assert_eq!(parse_momo_payment(&payload[..payload.len() - 8]), None);
+
+        // CRC followed by another field
+        let payload = emvco(&[("53", "646")]) + &tlv("60", "KIGALI");
+        assert_eq!(parse_momo_payment(&payload), None);
+    }
+
+    #[test]
+    fn test_crc16_ccitt_check_value() {
+        assert_eq!(crc16_ccitt(b"123456789"), 0x29B1);
+    }
+}
@@ -0,0 +1,205 @@
+// Member ID labels and passbook/folder stickers
+//
+// Members are looked up by id and their labels printed as one ZPL or TSPL
+// job through the print queue, which sends it raw to the printer over the
+// network or the spooler.
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::print_jobs::{self, PrintDocument, PrintJob};
+use crate::error::{AppError, Context, Result};
+use crate::labels::{self, LabelDefinition, LabelLanguage};
+
+const MEMBERS_URL: &str = "https://api.ibimina.rw/members";
+/// Ids per members request, to keep the query string short
+const MEMBERS_PER_REQUEST: usize = 100;
+/// Labels per bulk job
+const MAX_LABELS: usize = 1000;
+
+/// Member fields a label can print; any other field the API returns (e.g.
+/// `phone`) is available to custom labels too
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MemberLabel {
+    pub id: Uuid,
+    pub member_ref: String,
+    pub full_name: String,
+    pub sacco_name: String,
+    #[serde(default)]
+    pub group_name: Option<String>,
+    #[serde(flatten)]
+    pub fields: serde_json::Map<String, serde_json::Value>,
+}
+
+impl MemberLabel {
+    fn record(&self) -> Result<serde_json::Map<String, serde_json::Value>> {
+        match serde_json::to_value(self).context("Failed to serialize member")? {
+            serde_json::Value::Object(record) => Ok(record),
+            _ => Err(AppError::internal("Member did not serialize to an object")),
+        }
+    }
+}
+
+/// Render the labels for a bulk job
+pub fn label_bytes(
+    label: &LabelDefinition,
+    language: LabelLanguage,
+    members: &[MemberLabel],
+    copies: u32,
+) -> Result<Vec<u8>> {
+    let records = members
+        .iter()
+        .map(MemberLabel::record)
+        .collect::<Result<Vec<_>>>()?;
+    labels::render(language, label, &records, copies)
+}
+
+/// Look up members, keeping the order of `member_ids`
+async fn fetch_members(member_ids: &[Uuid]) -> Result<Vec<MemberLabel>> {
+    let credentials = super::auth::require_credentials().await?;
+
+    let client = reqwest::Client::builder()
+        .user_agent("SACCO+ Staff Admin")
+        .timeout(std::time::Duration::from_secs(30))
+        .build()
+        .context("Failed to create HTTP client")?;
+
+    let mut found = Vec::with_capacity(member_ids.len());
+    for chunk in member_ids.chunks(MEMBERS_PER_REQUEST) {
+        let ids = chunk
+            .iter()
+            .map(Uuid::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+        let response = client
+            .get(MEMBERS_URL)
+            .query(&[("ids", ids)])
+            .bearer_auth(&credentials.access_token)
+            .send()
+            .await
+            .context("Failed to fetch members")?;
+        if !response.status().is_success() {
+            return Err(AppError::http_status(response.status()).with_detail(MEMBERS_URL));
+        }
+        let members: Vec<MemberLabel> =
+            response.json().await.context("Failed to parse response")?;
+        found.extend(members);
+    }
+
+    order_members(member_ids, found)
+}
+
+fn order_members(member_ids: &[Uuid], found: Vec<MemberLabel>) -> Result<Vec<MemberLabel>> {
+    let mut missing = Vec::new();
+    let members = member_ids
+        .iter()
+        .filter_map(|id| {
+            let member = found.iter().find(|m| m.id == *id).cloned();
+            if member.is_none() {
+                missing.push(id.to_string());
+            }
+            member
+        })
+        .collect();
+
+    if !missing.is_empty() {
+        return Err(
+            AppError::invalid_input("Some members were not found").with_detail(missing.join(", "))
+        );
+    }
+    Ok(members)
+}
+
+/// The built-in 50 x 25 mm member ID label, as a starting point for editing
+#[tauri::command]
+pub async fn get_default_member_label() -> Result<LabelDefinition> {
+    Ok(labels::default_member_label())
+}
+
+/// Print one label per member, in the order given
+///
+/// Uses the built-in member ID label when `label` is not given. `copies`
+/// prints each member's label that many times, e.g. one for the passbook and
+/// one for the file folder.
+#[tauri::command]
+pub async fn print_member_labels(
+    app_handle: tauri::AppHandle,
+    printer_name: String,
+    member_ids: Vec<Uuid>,
+    language: LabelLanguage,
+    label: Option<LabelDefinition>,
+    copies: Option<u32>,
+) -> Result<PrintJob> {
+    if member_ids.is_empty() {
+        return Err(AppError::invalid_input("No members selected"));
+    }
+    if member_ids.len() > MAX_LABELS {
+        return Err(AppError::invalid_input(format!(
+            "At most {} labels can be printed at once",
+            MAX_LABELS
+        )));
+    }
+
+    let label = label.unwrap_or_else(labels::default_member_label);
+    let copies = copies.unwrap_or(1);
+    labels::check_copies(copies)?;
+    let members = fetch_members(&member_ids).await?;
+    // Fail before queuing if the label doesn't fit these members
+    label_bytes(&label, language, &members, copies)?;
+
+    print_jobs::submit(
+        &app_handle,
+        printer_name,
+        PrintDocument::Labels {
+            label,
+            language,
+            members,
+            copies,
+        },
+        None,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(member_ref: &str) -> MemberLabel {
+        serde_json::from_value(serde_json::json!({
+            "id": Uuid::new_v4(),
+            "member_ref": member_ref,
+            "full_name": "Hélène Uwase",
+            "sacco_name": "Twizigamire SACCO",
+            "phone": "+250788000111",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_member_labels_in_requested_order() {
+        let (first, second) = (member("TWZ-00421"), member("TWZ-00422"));
+        let ids = [second.id, first.id];
+        let members = order_members(&ids, vec![first.clone(), second.clone()]).unwrap();
+        assert_eq!(members[0].member_ref, "TWZ-00422");
+
+        let error = order_members(&[first.id, Uuid::nil()], vec![first.clone()]).unwrap_err();
+        assert_eq!(
+            error.detail.as_deref(),
+            Some(Uuid::nil().to_string().as_str())
+        );
+
+        // Extra API fields can be used on custom labels
+        let label = LabelDefinition {
+            elements: vec![labels::LabelElement::Text {
+                x_mm: 2.0,
+                y_mm: 2.0,
+                text: "{{ phone }}".to_string(),
+                height_mm: 3.0,
+            }],
+            ..labels::default_member_label()
+        };
+        let zpl = label_bytes(&label, LabelLanguage::Zpl, &members, 1).unwrap();
+        let zpl = String::from_utf8(zpl).unwrap();
+        assert_eq!(zpl.matches("^FD+250788000111^FS").count(), 2);
+    }
+}
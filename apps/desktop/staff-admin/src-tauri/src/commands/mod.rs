@@ -1,5 +1,15 @@
 pub mod auth;
+pub mod barcode;
+pub mod branding;
+pub mod cards;
 pub mod crypto;
 pub mod hardware;
+pub mod labels;
+pub mod peripherals;
 pub mod print;
+pub mod print_jobs;
+pub mod receipt_numbers;
+pub mod receipt_templates;
+pub mod reports;
+pub mod serial;
 pub mod updates;
@@ -0,0 +1,332 @@
+// Teller counter peripherals: ESC/POS cash drawer and VFD customer display
+
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use tauri::Manager;
+
+use super::auth;
+use super::serial::SerialDeviceProfile;
+use crate::error::{AppError, Context, ErrorCode, Result};
+use crate::printers;
+
+const AUDIT_LOG_FILE: &str = "peripheral_audit.jsonl";
+
+/// Roles allowed to open the cash drawer
+const DRAWER_ROLES: &[&str] = &[
+    "SYSTEM_ADMIN",
+    "SACCO_MANAGER",
+    "SACCO_STAFF",
+    "MFI_MANAGER",
+    "MFI_STAFF",
+];
+
+/// Drawer connector pin on the printer's DK port
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DrawerPin {
+    #[default]
+    Pin2,
+    Pin5,
+}
+
+fn default_pulse_on_ms() -> u16 {
+    100
+}
+
+fn default_pulse_off_ms() -> u16 {
+    200
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DrawerKickOptions {
+    /// Receipt printer the drawer is wired to
+    pub printer_name: String,
+    #[serde(default)]
+    pub pin: DrawerPin,
+    #[serde(default = "default_pulse_on_ms")]
+    pub pulse_on_ms: u16,
+    #[serde(default = "default_pulse_off_ms")]
+    pub pulse_off_ms: u16,
+}
+
+/// Where the customer display is connected
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DisplayTarget {
+    /// Display on its own serial port
+    Serial { profile: SerialDeviceProfile },
+    /// Display daisy-chained behind the receipt printer
+    Printer { printer_name: String },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DisplayMessage {
+    AmountReceived {
+        amount: String,
+        member_name: Option<String>,
+    },
+    ThankYou,
+    Text {
+        line1: String,
+        line2: String,
+    },
+    Clear,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct AuditEntry {
+    timestamp: String,
+    action: String,
+    device: String,
+    user_id: Option<String>,
+    role: Option<String>,
+    allowed: bool,
+    reason: Option<String>,
+    error: Option<String>,
+}
+
+/// ESC p m t1 t2: pulse the drawer solenoid; times are in 2 ms units
+pub fn drawer_kick_bytes(pin: DrawerPin, pulse_on_ms: u16, pulse_off_ms: u16) -> Vec<u8> {
+    let pin = match pin {
+        DrawerPin::Pin2 => 0x00,
+        DrawerPin::Pin5 => 0x01,
+    };
+    let units = |ms: u16| (ms / 2).clamp(1, 255) as u8;
+    vec![0x1B, 0x70, pin, units(pulse_on_ms), units(pulse_off_ms)]
+}
+
+/// Standard 2x20 VFD line width
+const DISPLAY_WIDTH: usize = 20;
+
+impl DisplayMessage {
+    fn lines(&self) -> (String, String) {
+        match self {
+            DisplayMessage::AmountReceived {
+                amount,
+                member_name,
+            } => (
+                two_column("RECEIVED", amount),
+                member_name.clone().unwrap_or_default(),
+            ),
+            DisplayMessage::ThankYou => (center("THANK YOU"), center("MURAKOZE")),
+            DisplayMessage::Text { line1, line2 } => (line1.clone(), line2.clone()),
+            DisplayMessage::Clear => (String::new(), String::new()),
+        }
+    }
+
+    /// Epson-compatible display commands for this message
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let (line1, line2) = self.lines();
+        let mut bytes = vec![0x1B, 0x40, 0x0C]; // ESC @, CLR
+        bytes.extend(fit_line(&line1));
+        bytes.extend_from_slice(&[0x1F, 0x24, 0x01, 0x02]); // US $ 1 2: column 1, row 2
+        bytes.extend(fit_line(&line2));
+        bytes
+    }
+}
+
+fn two_column(label: &str, value: &str) -> String {
+    let gap = DISPLAY_WIDTH.saturating_sub(label.chars().count() + value.chars().count());
+    format!("{}{}{}", label, " ".repeat(gap.max(1)), value)
+}
+
+fn center(text: &str) -> String {
+    let pad = DISPLAY_WIDTH.saturating_sub(text.chars().count()) / 2;
+    format!("{}{}", " ".repeat(pad), text)
+}
+
+/// Pad or cut a line to the display width, folding accents the VFD can't show
+fn fit_line(text: &str) -> Vec<u8> {
+    let mut line: Vec<u8> = text
+        .chars()
+        .map(fold_to_ascii)
+        .take(DISPLAY_WIDTH)
+        .collect();
+    line.resize(DISPLAY_WIDTH, b' ');
+    line
+}
+
+fn fold_to_ascii(c: char) -> u8 {
+    match c {
+        'à' | 'á' | 'â' | 'ä' => b'a',
+        'À' | 'Á' | 'Â' | 'Ä' => b'A',
+        'è' | 'é' | 'ê' | 'ë' => b'e',
+        'È' | 'É' | 'Ê' | 'Ë' => b'E',
+        'ì' | 'í' | 'î' | 'ï' => b'i',
+        'Î' | 'Ï' => b'I',
+        'ò' | 'ó' | 'ô' | 'ö' => b'o',
+        'Ô' | 'Ö' => b'O',
+        'ù' | 'ú' | 'û' | 'ü' => b'u',
+        'Ù' | 'Û' | 'Ü' => b'U',
+        'ç' => b'c',
+        'Ç' => b'C',
+        c if c.is_ascii() && !c.is_ascii_control() => c as u8,
+        _ => b'?',
+    }
+}
+
+/// Wrap display bytes for a display connected through the printer
+fn printer_pass_through(display_bytes: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![0x1B, 0x3D, 0x02]; // ESC = 2: select display
+    bytes.extend_from_slice(display_bytes);
+    bytes.extend_from_slice(&[0x1B, 0x3D, 0x01]); // ESC = 1: back to printer
+    bytes
+}
+
+fn append_audit_entry(app_handle: &tauri::AppHandle, entry: &AuditEntry) -> Result<()> {
+    let dir = app_handle
+        .path()
+        .app_data_dir()
+        .context("Failed to resolve app data dir")?;
+    std::fs::create_dir_all(&dir).context("Failed to create app data dir")?;
+
+    let line = serde_json::to_string(entry)
+        .context("Failed to serialize audit entry")?;
+
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(AUDIT_LOG_FILE))
+        .context("Failed to open audit log")?;
+    writeln!(file, "{}", line).context("Failed to write audit log")
+}
+
+/// Open the cash drawer wired to a receipt printer
+///
+/// Only cash-handling roles may open the drawer. Every attempt, allowed or
+/// not, is appended to the local peripheral audit log.
+#[tauri::command]
+pub async fn open_cash_drawer(
+    app_handle: tauri::AppHandle,
+    options: DrawerKickOptions,
+    reason: Option<String>,
+) -> Result<()> {
+    let identity = auth::current_identity(&app_handle.state::<auth::StaffSession>()).await?;
+    let role = identity.as_ref().and_then(|i| i.role.clone());
+    let allowed = role
+        .as_deref()
+        .is_some_and(|role| DRAWER_ROLES.contains(&role));
+
+    let mut entry = AuditEntry {
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        action: "open_cash_drawer".to_string(),
+        device: options.printer_name.clone(),
+        user_id: identity.map(|i| i.user_id),
+        role,
+        allowed,
+        reason,
+        error: None,
+    };
+
+    if !allowed {
+        let error = AppError::new(
+            ErrorCode::PermissionDenied,
+            format!(
+                "Role {} is not allowed to open the cash drawer",
+                entry.role.as_deref().unwrap_or("(none)")
+            ),
+        );
+        entry.error = Some(error.message.clone());
+        if let Err(e) = append_audit_entry(&app_handle, &entry) {
+            eprintln!("Failed to record drawer audit entry: {}", e);
+        }
+        return Err(error);
+    }
+
+    // Record the attempt before kicking, so the drawer never opens unlogged
+    append_audit_entry(&app_handle, &entry)?;
+
+    let result = printers::send(
+        &app_handle,
+        &options.printer_name,
+        drawer_kick_bytes(options.pin, options.pulse_on_ms, options.pulse_off_ms),
+    )
+    .await
+    .map(|_| ());
+
+    if let Err(e) = &result {
+        entry.timestamp = chrono::Utc::now().to_rfc3339();
+        entry.error = Some(e.to_string());
+        if let Err(e) = append_audit_entry(&app_handle, &entry) {
+            eprintln!("Failed to record drawer audit entry: {}", e);
+        }
+    }
+
+    result
+}
+
+/// Show a message on the customer-facing pole display
+#[tauri::command]
+pub async fn show_customer_display(
+    app_handle: tauri::AppHandle,
+    target: DisplayTarget,
+    message: DisplayMessage,
+) -> Result<()> {
+    let bytes = message.to_bytes();
+
+    match target {
+        DisplayTarget::Serial { profile } => tauri::async_runtime::spawn_blocking(move || {
+            let mut port = profile.open()?;
+            port.write_all(&bytes)
+                .and_then(|_| port.flush())
+                .context("Failed to write to display")
+        })
+        .await
+        .context("Display task failed")?,
+        DisplayTarget::Printer { printer_name } => {
+            printers::send(&app_handle, &printer_name, printer_pass_through(&bytes))
+                .await
+                .map(|_| ())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drawer_kick_bytes() {
+        assert_eq!(
+            drawer_kick_bytes(DrawerPin::Pin2, 100, 200),
+            vec![0x1B, 0x70, 0x00, 50, 100]
+        );
+        assert_eq!(
+            drawer_kick_bytes(DrawerPin::Pin5, 0, 1000),
+            vec![0x1B, 0x70, 0x01, 1, 255]
+        );
+    }
+
+    #[test]
+    fn test_amount_received_display() {
+        let message = DisplayMessage::AmountReceived {
+            amount: "RWF 15,000".to_string(),
+            member_name: Some("Hélène Uwase".to_string()),
+        };
+
+        let mut expected = vec![0x1B, 0x40, 0x0C];
+        expected.extend_from_slice(b"RECEIVED  RWF 15,000");
+        expected.extend_from_slice(&[0x1F, 0x24, 0x01, 0x02]);
+        expected.extend_from_slice(b"Helene Uwase        ");
+        assert_eq!(message.to_bytes(), expected);
+    }
+
+    #[test]
+    fn test_long_lines_are_cut() {
+        let message = DisplayMessage::Text {
+            line1: "Twizigamire Savings Group".to_string(),
+            line2: String::new(),
+        };
+        let bytes = message.to_bytes();
+        assert_eq!(&bytes[3..23], b"Twizigamire Savings ");
+        assert_eq!(bytes.len(), 3 + 20 + 4 + 20);
+    }
+
+    #[test]
+    fn test_printer_pass_through() {
+        let bytes = printer_pass_through(&DisplayMessage::Clear.to_bytes());
+        assert_eq!(&bytes[..3], &[0x1B, 0x3D, 0x02]);
+        assert_eq!(&bytes[bytes.len() - 3..], &[0x1B, 0x3D, 0x01]);
+    }
+}
@@ -1,11 +1,41 @@
+use base64::{engine::general_purpose, Engine as _};
 use serde::{Deserialize, Serialize};
 use std::process::Command;
+use tauri::Manager;
+use uuid::Uuid;
+
+use super::branding;
+use super::print_jobs::{self, PrintDocument, PrintJob};
+use super::receipt_numbers;
+use super::receipt_templates;
+
+use crate::error::{AppError, Context, ErrorCode, Result};
+use crate::escpos::emulator;
+use crate::escpos::raster::Bitmap;
+use crate::escpos::template::Template;
+use crate::escpos::PrinterProfile;
+use crate::pdf::{self, Orientation, PageMargins, PageSetup, PaperSize};
+use crate::printers::status::{
+    self, PrinterCondition, PrinterMonitor, PrinterState, PrinterStatus,
+};
+use crate::printers::{self, NetworkPrinter, PrinterBackend};
+
+const DEFAULT_VERIFY_URL: &str = "https://app.ibimina.rw/verify/receipt";
+const DEFAULT_DOCUMENT_TITLE: &str = "SACCO+ document";
+/// Spooler job names; the content itself never touches the disk
+const RECEIPT_JOB_NAME: &str = "SACCO+ receipt";
+const MAX_COPIES: u32 = 99;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PrinterInfo {
     pub name: String,
     pub is_default: bool,
-    pub status: String,
+    pub status: PrinterState,
+    /// Paper, cover and connection problems the printer last reported
+    #[serde(default)]
+    pub conditions: Vec<PrinterCondition>,
+    #[serde(default)]
+    pub backend: PrinterBackend,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -14,6 +44,72 @@ pub struct ReceiptData {
     pub items: Vec<ReceiptItem>,
     pub total: String,
     pub footer: String,
+    /// Payment to encode in a QR code members can scan to verify it later
+    #[serde(default)]
+    pub verification: Option<ReceiptVerification>,
+    /// Printed as a Code128 barcode at the bottom of the receipt
+    #[serde(default)]
+    pub receipt_number: Option<String>,
+    /// SACCO whose cached logo is printed at the top
+    #[serde(default)]
+    pub sacco_id: Option<Uuid>,
+    /// Reprint of an earlier receipt; printed with a DUPLICATE banner
+    #[serde(default)]
+    pub duplicate: bool,
+    /// Extra values for the SACCO's receipt template, such as raw amounts
+    /// and ISO dates to format with `rwf` or `datetime`
+    #[serde(default)]
+    pub fields: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ReceiptData {
+    /// Values a receipt template can use: the receipt's own fields, its
+    /// extra `fields`, and `has_logo`, `verification_url` and `printed_at`
+    pub fn template_data(&self, has_logo: bool) -> Result<serde_json::Value> {
+        let mut data = serde_json::to_value(self).context("Failed to serialize receipt")?;
+        let map = data
+            .as_object_mut()
+            .expect("receipt serializes to an object");
+        for (key, value) in &self.fields {
+            map.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+        map.insert("has_logo".to_string(), has_logo.into());
+        if let Some(verification) = &self.verification {
+            map.insert(
+                "verification_url".to_string(),
+                verification.qr_data()?.into(),
+            );
+        }
+        map.insert(
+            "printed_at".to_string(),
+            chrono::Utc::now().to_rfc3339().into(),
+        );
+        Ok(data)
+    }
+}
+
+fn default_verify_url() -> String {
+    DEFAULT_VERIFY_URL.to_string()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReceiptVerification {
+    pub payment_id: String,
+    pub reference_token: String,
+    #[serde(default = "default_verify_url")]
+    pub url: String,
+}
+
+impl ReceiptVerification {
+    /// Verification link with the payment id and token as query parameters
+    pub fn qr_data(&self) -> Result<String> {
+        let url = reqwest::Url::parse_with_params(
+            &self.url,
+            &[("p", &self.payment_id), ("t", &self.reference_token)],
+        )
+        .map_err(|e| AppError::invalid_input("Invalid verification URL").with_detail(e))?;
+        Ok(url.to_string())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -22,9 +118,178 @@ pub struct ReceiptItem {
     pub value: String,
 }
 
-/// List available printers on the system
+/// A receipt as the printer would print it
+#[derive(Debug, Serialize, Clone)]
+pub struct ReceiptPreview {
+    /// PNG image, one pixel per printer dot, base64 encoded
+    pub png: String,
+    pub width: u32,
+    pub height: u32,
+    /// Printed text, one line per printed line
+    pub text: String,
+}
+
+fn default_copies() -> u32 {
+    1
+}
+
+fn default_scale() -> f32 {
+    100.0
+}
+
+/// Options chosen in the print dialog (camelCase, as PrintDialog sends them)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentOptions {
+    #[serde(default = "default_copies")]
+    pub copies: u32,
+    #[serde(default)]
+    pub color: bool,
+    #[serde(default)]
+    pub duplex: bool,
+    #[serde(default)]
+    pub paper_size: PaperSize,
+    #[serde(default)]
+    pub orientation: Orientation,
+    /// Content scale in percent
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+    /// In millimetres
+    #[serde(default)]
+    pub margins: PageMargins,
+    /// Pages to print, e.g. "1-3, 5"; all when empty
+    #[serde(default)]
+    pub page_range: Option<String>,
+}
+
+impl DocumentOptions {
+    pub fn validate(&self) -> Result<()> {
+        if !(1..=MAX_COPIES).contains(&self.copies) {
+            return Err(AppError::invalid_input(format!(
+                "Copies must be between 1 and {}",
+                MAX_COPIES
+            )));
+        }
+        if !(10.0..=400.0).contains(&self.scale) {
+            return Err(AppError::invalid_input(
+                "Scale must be between 10% and 400%",
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn page_setup(&self) -> PageSetup {
+        PageSetup {
+            paper: self.paper_size,
+            orientation: self.orientation,
+            margins: self.margins,
+            scale: self.scale / 100.0,
+        }
+    }
+
+    /// IPP/CUPS `sides`; landscape pages flip on the short edge
+    pub fn sides(&self) -> &'static str {
+        match (self.duplex, self.orientation) {
+            (false, _) => "one-sided",
+            (true, Orientation::Portrait) => "two-sided-long-edge",
+            (true, Orientation::Landscape) => "two-sided-short-edge",
+        }
+    }
+
+    /// IPP/CUPS `print-color-mode`
+    pub fn color_mode(&self) -> &'static str {
+        if self.color {
+            "color"
+        } else {
+            "monochrome"
+        }
+    }
+
+    /// Lay out sanitized HTML and render the selected pages as a PDF
+    pub fn render_pdf(&self, content: &str, title: &str) -> Result<Vec<u8>> {
+        self.validate()?;
+        let pages = pdf::html_pages(content, &self.page_setup());
+        let pages = pdf::select_pages(pages, self.page_range.as_deref())?;
+        pdf::render(&pages, title)
+    }
+}
+
+/// List system printers together with manually configured network printers,
+/// with the status the printer monitor last saw
 #[tauri::command]
-pub async fn get_printers() -> Result<Vec<PrinterInfo>, String> {
+pub async fn get_printers(app_handle: tauri::AppHandle) -> Result<Vec<PrinterInfo>> {
+    let mut printers = list_printers(&app_handle).await?;
+
+    let monitor = app_handle.state::<PrinterMonitor>();
+    for printer in &mut printers {
+        if let Some(status) = monitor.get(&printer.name) {
+            printer.status = status.state;
+            printer.conditions = status.conditions;
+        }
+    }
+    Ok(printers)
+}
+
+/// Ask a printer for its status now, rather than waiting for the monitor
+#[tauri::command]
+pub async fn get_printer_status(
+    app_handle: tauri::AppHandle,
+    printer_name: String,
+) -> Result<PrinterStatus> {
+    let status = status::query(&app_handle, &printer_name).await?;
+    status::record(&app_handle, &status)?;
+    Ok(status)
+}
+
+/// System and network printers, with the status the spooler lists
+pub async fn list_printers(app_handle: &tauri::AppHandle) -> Result<Vec<PrinterInfo>> {
+    let network = printers::load_network_printers(app_handle)?;
+
+    let mut printers = match tauri::async_runtime::spawn_blocking(get_system_printers).await? {
+        Ok(printers) => printers,
+        // Still offer the network printers when there is no spooler (e.g. no CUPS)
+        Err(e) if !network.is_empty() => {
+            eprintln!("Failed to list system printers: {}", e);
+            Vec::new()
+        }
+        Err(e) => return Err(e),
+    };
+
+    for printer in network {
+        printers.retain(|p| p.name != printer.name);
+        printers.push(PrinterInfo {
+            backend: printer.backend(),
+            name: printer.name,
+            is_default: false,
+            status: PrinterState::Unknown,
+            conditions: Vec::new(),
+        });
+    }
+
+    Ok(printers)
+}
+
+/// Network printers configured in this app
+#[tauri::command]
+pub async fn get_network_printers(app_handle: tauri::AppHandle) -> Result<Vec<NetworkPrinter>> {
+    printers::load_network_printers(&app_handle)
+}
+
+/// Add or update a raw TCP (port 9100) or IPP printer
+#[tauri::command]
+pub async fn save_network_printer(
+    app_handle: tauri::AppHandle,
+    printer: NetworkPrinter,
+) -> Result<()> {
+    printers::save_network_printer(&app_handle, printer)
+}
+
+#[tauri::command]
+pub async fn remove_network_printer(app_handle: tauri::AppHandle, name: String) -> Result<()> {
+    printers::remove_network_printer(&app_handle, &name)
+}
+
+fn get_system_printers() -> Result<Vec<PrinterInfo>> {
     #[cfg(target_os = "windows")]
     {
         get_printers_windows()
@@ -42,14 +307,15 @@ pub async fn get_printers() -> Result<Vec<PrinterInfo>, String> {
 }
 
 #[cfg(target_os = "windows")]
-fn get_printers_windows() -> Result<Vec<PrinterInfo>, String> {
+fn get_printers_windows() -> Result<Vec<PrinterInfo>> {
     let output = Command::new("wmic")
         .args(["printer", "get", "name,default,status", "/format:csv"])
         .output()
-        .map_err(|e| format!("Failed to execute wmic: {}", e))?;
+        .context("Failed to execute wmic")?;
 
     if !output.status.success() {
-        return Err("Failed to query printers".to_string());
+        return Err(AppError::new(ErrorCode::DeviceError, "Failed to query printers")
+            .with_detail(String::from_utf8_lossy(&output.stderr).trim()));
     }
 
     let output_str = String::from_utf8_lossy(&output.stdout);
@@ -66,7 +332,9 @@ fn get_printers_windows() -> Result<Vec<PrinterInfo>, String> {
             printers.push(PrinterInfo {
                 name: parts[1].trim().to_string(),
                 is_default: parts[0].trim().eq_ignore_ascii_case("TRUE"),
-                status: parts[2].trim().to_string(),
+                status: wmic_state(parts[2]),
+                conditions: Vec::new(),
+                backend: PrinterBackend::System,
             });
         }
     }
@@ -74,15 +342,42 @@ fn get_printers_windows() -> Result<Vec<PrinterInfo>, String> {
     Ok(printers)
 }
 
+/// Win32_Printer Status property
+#[cfg(target_os = "windows")]
+fn wmic_state(status: &str) -> PrinterState {
+    match status.trim() {
+        "OK" => PrinterState::Ready,
+        "Degraded" | "Error" | "Pred Fail" | "NonRecover" | "Service" => PrinterState::Error,
+        "No Contact" | "Lost Comm" | "Stopping" => PrinterState::Offline,
+        "Starting" => PrinterState::Busy,
+        _ => PrinterState::Unknown,
+    }
+}
+
+/// State from an `lpstat -p` line such as "printer Counter is idle.  enabled since ..."
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn lpstat_state(line: &str) -> PrinterState {
+    if line.contains(" disabled ") {
+        PrinterState::Offline
+    } else if line.contains(" now printing ") {
+        PrinterState::Busy
+    } else if line.contains(" is idle") {
+        PrinterState::Ready
+    } else {
+        PrinterState::Unknown
+    }
+}
+
 #[cfg(target_os = "macos")]
-fn get_printers_macos() -> Result<Vec<PrinterInfo>, String> {
+fn get_printers_macos() -> Result<Vec<PrinterInfo>> {
     let output = Command::new("lpstat")
         .args(["-p", "-d"])
         .output()
-        .map_err(|e| format!("Failed to execute lpstat: {}", e))?;
+        .context("Failed to execute lpstat")?;
 
     if !output.status.success() {
-        return Err("Failed to query printers".to_string());
+        return Err(AppError::new(ErrorCode::DeviceError, "Failed to query printers")
+            .with_detail(String::from_utf8_lossy(&output.stderr).trim()));
     }
 
     let output_str = String::from_utf8_lossy(&output.stdout);
@@ -104,11 +399,9 @@ fn get_printers_macos() -> Result<Vec<PrinterInfo>, String> {
                 printers.push(PrinterInfo {
                     name: name.clone(),
                     is_default: name == default_printer,
-                    status: if line.contains("idle") {
-                        "Ready".to_string()
-                    } else {
-                        "Unknown".to_string()
-                    },
+                    status: lpstat_state(line),
+                    conditions: Vec::new(),
+                    backend: PrinterBackend::System,
                 });
             }
         }
@@ -118,14 +411,15 @@ fn get_printers_macos() -> Result<Vec<PrinterInfo>, String> {
 }
 
 #[cfg(target_os = "linux")]
-fn get_printers_linux() -> Result<Vec<PrinterInfo>, String> {
+fn get_printers_linux() -> Result<Vec<PrinterInfo>> {
     let output = Command::new("lpstat")
         .args(["-p", "-d"])
         .output()
-        .map_err(|e| format!("Failed to execute lpstat: {}", e))?;
+        .context("Failed to execute lpstat")?;
 
     if !output.status.success() {
-        return Err("Failed to query printers".to_string());
+        return Err(AppError::new(ErrorCode::DeviceError, "Failed to query printers")
+            .with_detail(String::from_utf8_lossy(&output.stderr).trim()));
     }
 
     let output_str = String::from_utf8_lossy(&output.stdout);
@@ -147,11 +441,9 @@ fn get_printers_linux() -> Result<Vec<PrinterInfo>, String> {
                 printers.push(PrinterInfo {
                     name: name.clone(),
                     is_default: name == default_printer,
-                    status: if line.contains("idle") {
-                        "Ready".to_string()
-                    } else {
-                        "Unknown".to_string()
-                    },
+                    status: lpstat_state(line),
+                    conditions: Vec::new(),
+                    backend: PrinterBackend::System,
                 });
             }
         }
@@ -163,18 +455,34 @@ fn get_printers_linux() -> Result<Vec<PrinterInfo>, String> {
 /// Print HTML content to selected printer
 #[tauri::command]
 pub async fn print_html(
+    app_handle: tauri::AppHandle,
     printer_name: String,
     html_content: String,
-) -> Result<(), String> {
-    // Create temporary HTML file
-    let temp_dir = std::env::temp_dir();
-    let file_path = temp_dir.join("print_temp.html");
-    
-    std::fs::write(&file_path, html_content)
-        .map_err(|e| format!("Failed to write temp file: {}", e))?;
+) -> Result<PrintJob> {
+    print_jobs::submit(
+        &app_handle,
+        printer_name,
+        PrintDocument::Html {
+            content: html_content,
+        },
+        None,
+    )
+}
 
+/// Hand an HTML document to the system print spooler, returning its job id
+pub fn send_html(printer_name: &str, html_content: &str) -> Result<Option<String>> {
     #[cfg(target_os = "windows")]
     {
+        // Edge needs a file; a private directory keeps concurrent jobs apart
+        // and is removed with everything in it when `dir` drops
+        let _ = printer_name;
+        let dir = tempfile::Builder::new()
+            .prefix("sacco-print-")
+            .tempdir()
+            .context("Failed to create temp dir")?;
+        let file_path = dir.path().join("document.html");
+        std::fs::write(&file_path, html_content).context("Failed to write temp file")?;
+
         // Use Microsoft Edge to print on Windows
         Command::new("msedge")
             .args([
@@ -184,73 +492,212 @@ pub async fn print_html(
                 &file_path.display().to_string(),
             ])
             .output()
-            .map_err(|e| format!("Failed to print: {}", e))?;
+            .context("Failed to print")
+            .map(|_| None)
     }
 
     #[cfg(any(target_os = "macos", target_os = "linux"))]
+    lp(
+        printer_name,
+        DEFAULT_DOCUMENT_TITLE,
+        &[],
+        html_content.as_bytes(),
+    )
+}
+
+/// Print an HTML document from the print dialog
+///
+/// The content is laid out as a PDF here, so no browser is needed, and sent
+/// with the dialog's copies, duplex and paper settings.
+#[tauri::command]
+pub async fn print_document(
+    app_handle: tauri::AppHandle,
+    printer_id: String,
+    content: String,
+    options: DocumentOptions,
+    title: Option<String>,
+) -> Result<PrintJob> {
+    options.validate()?;
+    print_jobs::submit(
+        &app_handle,
+        printer_id,
+        PrintDocument::Document {
+            content,
+            title,
+            options,
+        },
+        None,
+    )
+}
+
+/// Save an HTML document from the print dialog as a PDF file
+#[tauri::command]
+pub async fn export_to_pdf(
+    content: String,
+    file_path: String,
+    options: DocumentOptions,
+    title: Option<String>,
+) -> Result<()> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let title = title.as_deref().unwrap_or(DEFAULT_DOCUMENT_TITLE);
+        let pdf = options.render_pdf(&content, title)?;
+        std::fs::write(&file_path, pdf).context("Failed to save PDF")
+    })
+    .await?
+}
+
+/// Render a dialog document to PDF, for the print queue
+pub fn document_pdf(
+    content: &str,
+    title: Option<&str>,
+    options: &DocumentOptions,
+) -> Result<Vec<u8>> {
+    options.render_pdf(content, title.unwrap_or(DEFAULT_DOCUMENT_TITLE))
+}
+
+/// Hand a PDF to the system spooler with the dialog's job options
+pub fn send_pdf(
+    printer_name: &str,
+    pdf: &[u8],
+    options: &DocumentOptions,
+) -> Result<Option<String>> {
+    #[cfg(target_os = "windows")]
     {
-        Command::new("lp")
-            .args(["-d", &printer_name, &file_path.display().to_string()])
-            .output()
-            .map_err(|e| format!("Failed to print: {}", e))?;
+        let _ = (printer_name, pdf, options);
+        Err(AppError::new(
+            ErrorCode::Unsupported,
+            "Printing documents directly is not supported on Windows; export to PDF instead",
+        ))
     }
 
-    // Clean up temp file
-    let _ = std::fs::remove_file(&file_path);
-
-    Ok(())
+    #[cfg(any(target_os = "macos", target_os = "linux"))]
+    {
+        let copies = options.copies.to_string();
+        let sides = format!("sides={}", options.sides());
+        let media = format!("media={}", options.paper_size.media());
+        let color = format!("print-color-mode={}", options.color_mode());
+        lp(
+            printer_name,
+            DEFAULT_DOCUMENT_TITLE,
+            &["-n", &copies, "-o", &sides, "-o", &media, "-o", &color],
+            pdf,
+        )
+    }
 }
 
 /// Generate and print thermal receipt (ESC/POS compatible)
 #[tauri::command]
 pub async fn print_receipt(
+    app_handle: tauri::AppHandle,
     printer_name: String,
     receipt_data: ReceiptData,
-) -> Result<(), String> {
-    // Generate ESC/POS commands
-    let mut commands: Vec<u8> = Vec::new();
-
-    // Initialize printer
-    commands.extend_from_slice(&[0x1B, 0x40]); // ESC @
-
-    // Center align
-    commands.extend_from_slice(&[0x1B, 0x61, 0x01]); // ESC a 1
-
-    // Bold title
-    commands.extend_from_slice(&[0x1B, 0x45, 0x01]); // ESC E 1
-    commands.extend_from_slice(receipt_data.title.as_bytes());
-    commands.extend_from_slice(&[0x1B, 0x45, 0x00]); // ESC E 0
-    commands.extend_from_slice(b"\n\n");
-
-    // Left align for items
-    commands.extend_from_slice(&[0x1B, 0x61, 0x00]); // ESC a 0
-
-    // Print items
-    for item in receipt_data.items {
-        let line = format!("{}: {}\n", item.label, item.value);
-        commands.extend_from_slice(line.as_bytes());
+    profile: Option<PrinterProfile>,
+) -> Result<PrintJob> {
+    // Numbered once here so retries and reprints keep the same number
+    let job_id = Uuid::new_v4();
+    let mut receipt_data = receipt_data;
+    let mut issued = None;
+    if receipt_data.receipt_number.is_none() {
+        let reference = receipt_data
+            .verification
+            .as_ref()
+            .map(|v| v.payment_id.clone());
+        issued = receipt_numbers::assign(&app_handle, reference, job_id)?;
+        receipt_data.receipt_number = issued.clone();
     }
 
-    commands.extend_from_slice(b"\n");
-    commands.extend_from_slice(b"--------------------------------\n");
+    let job = print_jobs::submit_with_id(
+        &app_handle,
+        job_id,
+        printer_name,
+        PrintDocument::Receipt {
+            receipt: receipt_data,
+            profile: profile.unwrap_or_default(),
+        },
+        None,
+    );
+    // No job will ever print the number, so account for it now
+    if let (Err(e), Some(receipt_number)) = (&job, &issued) {
+        let reason = format!("Print job could not be queued: {}", e.message);
+        if let Err(e) = receipt_numbers::void(&app_handle, receipt_number, &reason) {
+            eprintln!("Failed to void receipt number {}: {}", receipt_number, e);
+        }
+    }
+    job
+}
 
-    // Bold total
-    commands.extend_from_slice(&[0x1B, 0x45, 0x01]); // ESC E 1
-    let total_line = format!("TOTAL: {}\n", receipt_data.total);
-    commands.extend_from_slice(total_line.as_bytes());
-    commands.extend_from_slice(&[0x1B, 0x45, 0x00]); // ESC E 0
+/// Render a receipt exactly as `print_receipt` would send it and run it
+/// through the ESC/POS emulator, so tellers can check it without paper
+#[tauri::command]
+pub async fn preview_receipt(
+    app_handle: tauri::AppHandle,
+    receipt_data: ReceiptData,
+    profile: Option<PrinterProfile>,
+) -> Result<ReceiptPreview> {
+    let profile = profile.unwrap_or_default();
+    let bytes = receipt_bytes(&app_handle, &receipt_data, &profile)?;
+    let printout = emulator::emulate(&bytes, profile.paper_width)?;
+
+    let (width, height) = printout.image.dimensions();
+    let text = printout.text();
+    Ok(ReceiptPreview {
+        png: general_purpose::STANDARD.encode(printout.png()?),
+        width,
+        height,
+        text,
+    })
+}
 
-    commands.extend_from_slice(b"\n");
+/// Render a receipt with the SACCO's cached logo, if there is one
+pub fn receipt_bytes(
+    app_handle: &tauri::AppHandle,
+    receipt_data: &ReceiptData,
+    profile: &PrinterProfile,
+) -> Result<Vec<u8>> {
+    // A missing or broken logo must never stop a receipt from printing
+    let logo = receipt_data.sacco_id.and_then(|sacco_id| {
+        branding::receipt_logo(app_handle, sacco_id, profile.paper_width)
+            .unwrap_or_else(|e| {
+                eprintln!("Failed to load receipt logo: {}", e);
+                None
+            })
+    });
+
+    // Likewise a SACCO template that can't be loaded falls back to the default
+    let template = receipt_data.sacco_id.and_then(|sacco_id| {
+        receipt_templates::receipt_template(app_handle, sacco_id).unwrap_or_else(|e| {
+            eprintln!("Failed to load receipt template: {}", e);
+            None
+        })
+    });
+    let template = template.as_deref().unwrap_or(Template::default_receipt());
+
+    render_template(template, receipt_data, profile.clone(), logo.as_ref())
+}
 
-    // Center align footer
-    commands.extend_from_slice(&[0x1B, 0x61, 0x01]); // ESC a 1
-    commands.extend_from_slice(receipt_data.footer.as_bytes());
-    commands.extend_from_slice(b"\n\n\n");
+/// Lay out a receipt for the given printer with the default template
+pub fn render_receipt(
+    receipt_data: &ReceiptData,
+    profile: PrinterProfile,
+    logo: Option<&Bitmap>,
+) -> Result<Vec<u8>> {
+    render_template(Template::default_receipt(), receipt_data, profile, logo)
+}
 
-    // Cut paper
-    commands.extend_from_slice(&[0x1D, 0x56, 0x00]); // GS V 0
+/// Lay out a receipt with a SACCO's template
+pub fn render_template(
+    template: &Template,
+    receipt_data: &ReceiptData,
+    profile: PrinterProfile,
+    logo: Option<&Bitmap>,
+) -> Result<Vec<u8>> {
+    let data = receipt_data.template_data(logo.is_some())?;
+    template.render(&data, profile, logo)
+}
 
-    // Write to printer (platform-specific)
+/// Send raw bytes (ESC/POS commands) straight to a printer queue, returning
+/// the spooler's job id when it reports one
+pub fn send_raw(printer_name: &str, data: &[u8]) -> Result<Option<String>> {
     #[cfg(target_os = "windows")]
     {
         // On Windows, write directly to printer port
@@ -261,26 +708,312 @@ pub async fn print_receipt(
         let mut file = OpenOptions::new()
             .write(true)
             .open(&printer_path)
-            .map_err(|e| format!("Failed to open printer: {}", e))?;
+            .map_err(|e| {
+                let code = match e.kind() {
+                    std::io::ErrorKind::NotFound => ErrorCode::PrinterNotFound,
+                    _ => ErrorCode::PrinterOffline,
+                };
+                AppError::new(code, format!("Failed to open printer {}", printer_name))
+                    .with_detail(e)
+            })?;
         
-        file.write_all(&commands)
-            .map_err(|e| format!("Failed to write to printer: {}", e))?;
+        file.write_all(data)
+            .map_err(|e| AppError::new(ErrorCode::PrintFailed, "Failed to write to printer").with_detail(e))?;
+
+        Ok(None)
     }
 
     #[cfg(any(target_os = "macos", target_os = "linux"))]
-    {
-        // On Unix, use lp command
-        let temp_file = std::env::temp_dir().join("receipt.bin");
-        std::fs::write(&temp_file, &commands)
-            .map_err(|e| format!("Failed to write receipt: {}", e))?;
+    lp(printer_name, RECEIPT_JOB_NAME, &["-o", "raw"], data)
+}
 
-        Command::new("lp")
-            .args(["-d", &printer_name, "-o", "raw", &temp_file.display().to_string()])
-            .output()
-            .map_err(|e| format!("Failed to print receipt: {}", e))?;
+/// Submit a document to `lp` on stdin, checking its exit status
+///
+/// Piping avoids temp files that concurrent jobs could overwrite and other
+/// local users could read.
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn lp(printer_name: &str, title: &str, options: &[&str], data: &[u8]) -> Result<Option<String>> {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = Command::new("lp")
+        .args(["-d", printer_name, "-t", title])
+        .args(options)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| AppError::new(ErrorCode::PrintFailed, "Failed to run lp").with_detail(e))?;
+
+    // lp reports why it stopped reading on stderr, so check that first
+    let written = child
+        .stdin
+        .take()
+        .map_or(Ok(()), |mut stdin| stdin.write_all(data));
+    let output = child
+        .wait_with_output()
+        .map_err(|e| AppError::new(ErrorCode::PrintFailed, "Failed to run lp").with_detail(e))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let code = if stderr.contains("does not exist") {
+            ErrorCode::PrinterNotFound
+        } else {
+            ErrorCode::PrintFailed
+        };
+        return Err(AppError::new(code, format!("Failed to print to {}", printer_name))
+            .with_detail(stderr.trim()));
+    }
+
+    written.map_err(|e| {
+        AppError::new(
+            ErrorCode::PrintFailed,
+            format!("Failed to print to {}", printer_name),
+        )
+        .with_detail(e)
+    })?;
+
+    Ok(parse_lp_job_id(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// CUPS job id from lp's "request id is Counter-42 (1 file(s))"
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn parse_lp_job_id(stdout: &str) -> Option<String> {
+    stdout
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("request id is "))
+        .and_then(|rest| rest.split_whitespace().next())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::escpos::PaperWidth;
+
+    #[test]
+    fn test_render_receipt_58mm() {
+        let receipt = ReceiptData {
+            title: "Twizigamire SACCO".to_string(),
+            items: vec![
+                ReceiptItem {
+                    label: "Member".to_string(),
+                    value: "Uwase Aline".to_string(),
+                },
+                ReceiptItem {
+                    label: "Monthly savings contribution".to_string(),
+                    value: "RWF 15,000".to_string(),
+                },
+            ],
+            total: "RWF 15,000".to_string(),
+            footer: "Murakoze".to_string(),
+            verification: None,
+            receipt_number: None,
+            sacco_id: None,
+            duplicate: false,
+            fields: Default::default(),
+        };
+
+        let mut expected = vec![0x1B, 0x40, 0x1B, 0x74, 19, 0x1B, 0x61, 0x01, 0x1B, 0x45, 0x01];
+        expected.extend_from_slice(b"Twizigamire SACCO\n");
+        expected.extend_from_slice(&[0x1B, 0x45, 0x00, 0x1B, 0x64, 0x01, 0x1B, 0x61, 0x00]);
+        expected.extend_from_slice(b"Member               Uwase Aline\n");
+        expected.extend_from_slice(b"Monthly savings contribution\n");
+        expected.extend_from_slice(b"                      RWF 15,000\n");
+        expected.extend_from_slice(b"--------------------------------\n");
+        expected.extend_from_slice(&[0x1B, 0x45, 0x01]);
+        expected.extend_from_slice(b"TOTAL                 RWF 15,000\n");
+        expected.extend_from_slice(&[0x1B, 0x45, 0x00, 0x1B, 0x64, 0x01, 0x1B, 0x61, 0x01]);
+        expected.extend_from_slice(b"Murakoze\n");
+        expected.extend_from_slice(&[0x1B, 0x64, 0x03, 0x1D, 0x56, 0x00]);
+
+        assert_eq!(
+            render_receipt(&receipt, PaperWidth::Mm58.into(), None).unwrap(),
+            expected
+        );
+    }
 
-        let _ = std::fs::remove_file(&temp_file);
+    #[test]
+    fn test_render_receipt_accented_names() {
+        let receipt = ReceiptData {
+            title: "Twizigamire SACCO".to_string(),
+            items: vec![
+                ReceiptItem {
+                    label: "Member".to_string(),
+                    value: "Hélène Uwase".to_string(),
+                },
+                ReceiptItem {
+                    label: "Group".to_string(),
+                    value: "Abakundakurya Cœur…".to_string(),
+                },
+            ],
+            total: "RWF 15,000".to_string(),
+            footer: String::new(),
+            verification: None,
+            receipt_number: None,
+            sacco_id: None,
+            duplicate: false,
+            fields: Default::default(),
+        };
+
+        let bytes = render_receipt(&receipt, PaperWidth::Mm58.into(), None).unwrap();
+        let contains = |line: &[u8]| bytes.windows(line.len()).any(|w| w == line);
+        assert!(contains(b"Member              H\x82l\x8ane Uwase\n"));
+        assert!(contains(b"Group     Abakundakurya Coeur...\n"));
     }
 
-    Ok(())
+    #[test]
+    fn test_logo_printed_first() {
+        let receipt = ReceiptData {
+            title: "Twizigamire SACCO".to_string(),
+            items: Vec::new(),
+            total: "RWF 0".to_string(),
+            footer: String::new(),
+            verification: None,
+            receipt_number: None,
+            sacco_id: None,
+            duplicate: false,
+            fields: Default::default(),
+        };
+        let logo = Bitmap::new(16, 4);
+
+        let bytes = render_receipt(&receipt, PrinterProfile::default(), Some(&logo)).unwrap();
+        assert_eq!(&bytes[5..8], &[0x1B, 0x61, 0x01]);
+        assert_eq!(&bytes[8..16], &[0x1D, 0x76, 0x30, 0x00, 2, 0, 4, 0]);
+    }
+
+    #[test]
+    fn test_duplicate_banner() {
+        let receipt = ReceiptData {
+            title: "Twizigamire SACCO".to_string(),
+            items: Vec::new(),
+            total: "RWF 0".to_string(),
+            footer: String::new(),
+            verification: None,
+            receipt_number: None,
+            sacco_id: None,
+            duplicate: true,
+            fields: Default::default(),
+        };
+
+        let bytes = render_receipt(&receipt, PaperWidth::Mm58.into(), None).unwrap();
+        let banner = [b"\x1bE\x01\x1dB\x01".as_slice(), b" DUPLICATE \n"].concat();
+        assert!(bytes.windows(banner.len()).any(|w| w == banner));
+    }
+
+    #[test]
+    fn test_printed_receipt_scans() {
+        let verification = ReceiptVerification {
+            payment_id: "8f14e45f".to_string(),
+            reference_token: "TWZ-7F3K9Q".to_string(),
+            url: default_verify_url(),
+        };
+        let receipt = ReceiptData {
+            title: "Twizigamire SACCO".to_string(),
+            items: vec![ReceiptItem {
+                label: "Member".to_string(),
+                value: "Hélène Uwase".to_string(),
+            }],
+            total: "RWF 15,000".to_string(),
+            footer: "Murakoze".to_string(),
+            verification: Some(verification.clone()),
+            receipt_number: Some("TWZ-000123".to_string()),
+            sacco_id: None,
+            duplicate: true,
+            fields: Default::default(),
+        };
+
+        for native_qr in [true, false] {
+            let profile = PrinterProfile {
+                native_qr,
+                ..PaperWidth::Mm58.into()
+            };
+            let bytes = render_receipt(&receipt, profile, None).unwrap();
+            let printout = emulator::emulate(&bytes, PaperWidth::Mm58).unwrap();
+
+            assert_eq!(&printout.lines[..2], ["Twizigamire SACCO", " DUPLICATE "]);
+            assert!(printout
+                .lines
+                .contains(&"Member              Hélène Uwase".to_string()));
+            assert_eq!(printout.cuts.len(), 1);
+
+            let (width, height) = printout.image.dimensions();
+            let decoded =
+                super::super::barcode::decode_luma(printout.image.into_raw(), width, height);
+            assert!(decoded.contains(&("QR_CODE".to_string(), verification.qr_data().unwrap())));
+            assert!(decoded.contains(&("CODE128".to_string(), "TWZ-000123".to_string())));
+        }
+    }
+
+    #[test]
+    #[cfg(any(target_os = "macos", target_os = "linux"))]
+    fn test_parse_lp_job_id() {
+        assert_eq!(
+            parse_lp_job_id("request id is Counter-42 (1 file(s))\n").as_deref(),
+            Some("Counter-42")
+        );
+        assert_eq!(parse_lp_job_id(""), None);
+    }
+
+    #[test]
+    #[cfg(any(target_os = "macos", target_os = "linux"))]
+    fn test_lpstat_state() {
+        assert_eq!(
+            lpstat_state("printer Counter is idle.  enabled since Mon 04 Mar 2024"),
+            PrinterState::Ready
+        );
+        assert_eq!(
+            lpstat_state("printer Counter now printing Counter-42.  enabled since Mon 04 Mar 2024"),
+            PrinterState::Busy
+        );
+        assert_eq!(
+            lpstat_state("printer Counter disabled since Mon 04 Mar 2024 -"),
+            PrinterState::Offline
+        );
+    }
+
+    #[test]
+    fn test_document_options_from_dialog() {
+        let options: DocumentOptions = serde_json::from_value(serde_json::json!({
+            "printerId": "Back office",
+            "copies": 2,
+            "color": false,
+            "duplex": true,
+            "paperSize": "A5",
+            "orientation": "landscape",
+            "scale": 100,
+            "margins": { "top": 10, "right": 10, "bottom": 10, "left": 10 },
+            "pageRange": "1"
+        }))
+        .unwrap();
+        assert_eq!(options.paper_size, PaperSize::A5);
+        assert_eq!(options.sides(), "two-sided-short-edge");
+        assert_eq!(options.color_mode(), "monochrome");
+
+        let content = "<h2>Daily report</h2>".to_string() + &"<p>Entry</p>".repeat(100);
+        let pdf = options.render_pdf(&content, "Daily report").unwrap();
+        assert!(pdf.starts_with(b"%PDF-"));
+
+        let too_many = DocumentOptions {
+            copies: 500,
+            ..options
+        };
+        assert_eq!(
+            too_many.render_pdf("<p>x</p>", "x").unwrap_err().code,
+            ErrorCode::InvalidInput
+        );
+    }
+
+    #[test]
+    fn test_verification_qr_data() {
+        let verification = ReceiptVerification {
+            payment_id: "8f14e45f".to_string(),
+            reference_token: "TWZ/2025 001".to_string(),
+            url: default_verify_url(),
+        };
+        assert_eq!(
+            verification.qr_data().unwrap(),
+            "https://app.ibimina.rw/verify/receipt?p=8f14e45f&t=TWZ%2F2025+001"
+        );
+    }
 }
@@ -0,0 +1,899 @@
+// Print job queue and history
+//
+// Every receipt and document is printed as a job with an id and a status
+// (queued -> sending -> spooled -> printed | failed | unconfirmed), so
+// failures reach the UI instead of disappearing into `lp`. Retryable failures
+// such as an offline printer are retried with backoff before the job fails.
+// Once a spooler or printer has accepted a job it is never sent again: if it
+// can't be followed to the end, it is left unconfirmed for staff to check.
+//
+// Jobs are kept on disk as a history from which a teller can reprint a
+// receipt, marked DUPLICATE. Only receipts keep their document there, in a
+// file only this user can read; other documents are kept in memory until
+// they print.
+//
+// Commands return a job as soon as it is queued; it is sent in the background
+// and its progress reported with `JOB_UPDATED_EVENT` and in `get_print_jobs`.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{Emitter, Manager};
+use uuid::Uuid;
+
+use super::labels::{self as label_commands, MemberLabel};
+use super::print::{self, DocumentOptions, ReceiptData};
+use super::receipt_numbers;
+use super::reports as report_commands;
+use crate::error::{AppError, Context, ErrorCode, Result};
+use crate::escpos::PrinterProfile;
+use crate::labels::{LabelDefinition, LabelLanguage};
+use crate::pdf::reports::{Branding, Report};
+use crate::printers;
+
+const JOBS_FILE: &str = "print_jobs.json";
+/// Oldest jobs beyond this are dropped from the history
+const MAX_HISTORY: usize = 500;
+/// Sends per run, including the first
+const MAX_ATTEMPTS: u32 = 3;
+/// Doubled after each failed attempt
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+const DEFAULT_HISTORY_LIMIT: usize = 50;
+
+/// Emitted with the job whenever its status changes
+pub const JOB_UPDATED_EVENT: &str = "print-job-updated";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Sending,
+    /// Accepted by the spooler or printer, waiting for it to print
+    Spooled,
+    Printed,
+    Failed,
+    /// Accepted, but whether it printed is unknown
+    Unconfirmed,
+}
+
+impl JobStatus {
+    pub fn is_finished(self) -> bool {
+        matches!(
+            self,
+            JobStatus::Printed | JobStatus::Failed | JobStatus::Unconfirmed
+        )
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PrintDocument {
+    Receipt {
+        receipt: ReceiptData,
+        profile: PrinterProfile,
+    },
+    Html {
+        content: String,
+    },
+    /// Print dialog document, laid out as a PDF
+    Document {
+        content: String,
+        title: Option<String>,
+        options: DocumentOptions,
+    },
+    /// Member statement or report, laid out as a PDF
+    Report {
+        report: Report,
+        branding: Branding,
+        options: DocumentOptions,
+    },
+    /// One ZPL or TSPL label per member
+    Labels {
+        label: LabelDefinition,
+        language: LabelLanguage,
+        members: Vec<MemberLabel>,
+        copies: u32,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PrintJob {
+    pub id: Uuid,
+    pub printer_name: String,
+    /// `None` once it is no longer needed; see [`PrintJob::prune`]
+    #[serde(default)]
+    pub document: Option<PrintDocument>,
+    pub status: JobStatus,
+    /// Sends so far, across automatic and manual retries
+    pub attempts: u32,
+    /// Job id assigned by CUPS, or by the printer for IPP
+    pub cups_job_id: Option<String>,
+    /// Last failure; kept while an automatic retry is pending
+    pub error: Option<AppError>,
+    /// Original job when this is a reprint
+    pub duplicate_of: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl PrintJob {
+    fn new(
+        id: Uuid,
+        printer_name: String,
+        document: PrintDocument,
+        duplicate_of: Option<Uuid>,
+    ) -> Self {
+        let now = Utc::now();
+        Self {
+            id,
+            printer_name,
+            document: Some(document),
+            status: JobStatus::Queued,
+            attempts: 0,
+            cups_job_id: None,
+            error: None,
+            duplicate_of,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    fn is_receipt(&self) -> bool {
+        matches!(self.document, Some(PrintDocument::Receipt { .. }))
+    }
+
+    /// Drop the document of a finished job nothing will send again: receipts
+    /// are kept for reprints and failed jobs for a retry
+    fn prune(&mut self) {
+        if matches!(self.status, JobStatus::Printed | JobStatus::Unconfirmed) && !self.is_receipt()
+        {
+            self.document = None;
+        }
+    }
+
+    /// Case-insensitive match on a receipt's title, number and line values
+    fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        let Some(document) = &self.document else {
+            return false;
+        };
+        match document {
+            PrintDocument::Receipt { receipt, .. } => std::iter::once(&receipt.title)
+                .chain(receipt.receipt_number.as_ref())
+                .chain(receipt.items.iter().map(|item| &item.value))
+                .any(|field| field.to_lowercase().contains(&query)),
+            PrintDocument::Report { report, .. } => [report.title().to_string(), report.subject()]
+                .iter()
+                .any(|field| field.to_lowercase().contains(&query)),
+            PrintDocument::Labels { members, .. } => members.iter().any(|member| {
+                [&member.full_name, &member.member_ref]
+                    .iter()
+                    .any(|field| field.to_lowercase().contains(&query))
+            }),
+            _ => false,
+        }
+    }
+}
+
+pub struct PrintQueue {
+    path: PathBuf,
+    jobs: Mutex<Vec<PrintJob>>,
+    /// Numbers each snapshot of `jobs`, taken under its lock
+    snapshots: AtomicU64,
+    /// Snapshot last written, so a slower write never replaces a newer one
+    saved: Mutex<u64>,
+}
+
+impl PrintQueue {
+    pub fn load(app_handle: &tauri::AppHandle) -> Result<Self> {
+        let dir = app_handle
+            .path()
+            .app_data_dir()
+            .context("Failed to resolve app data dir")?;
+        Ok(Self::open(dir.join(JOBS_FILE)))
+    }
+
+    /// Read the history, failing any job a previous run left unfinished
+    fn open(path: PathBuf) -> Self {
+        let mut jobs: Vec<PrintJob> = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                eprintln!("Discarding unreadable print history: {}", e);
+                Vec::new()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                eprintln!("Failed to read print history: {}", e);
+                Vec::new()
+            }
+        };
+
+        // Whether an interrupted job reached the printer is unknown; leave it
+        // to staff rather than risk printing a receipt twice
+        for job in &mut jobs {
+            let status = match job.status {
+                JobStatus::Queued | JobStatus::Sending => JobStatus::Failed,
+                JobStatus::Spooled => JobStatus::Unconfirmed,
+                _ => continue,
+            };
+            job.status = status;
+            job.error = Some(AppError::new(
+                ErrorCode::PrintFailed,
+                "Interrupted before the printer confirmed the job",
+            ));
+            job.updated_at = Utc::now();
+        }
+
+        Self {
+            path,
+            jobs: Mutex::new(jobs),
+            snapshots: AtomicU64::new(0),
+            saved: Mutex::new(0),
+        }
+    }
+
+    /// Serialize the history as it is stored, with only receipts' documents
+    ///
+    /// Called with `jobs` locked; the returned number orders the snapshots.
+    fn snapshot(&self, jobs: &mut [PrintJob]) -> Result<(u64, String)> {
+        let held: Vec<_> = jobs
+            .iter_mut()
+            .map(|job| {
+                if job.is_receipt() {
+                    None
+                } else {
+                    job.document.take()
+                }
+            })
+            .collect();
+        let json = serde_json::to_string(&*jobs);
+        for (job, document) in jobs.iter_mut().zip(held) {
+            if document.is_some() {
+                job.document = document;
+            }
+        }
+
+        let json = json.context("Failed to serialize print history")?;
+        Ok((self.snapshots.fetch_add(1, Ordering::SeqCst) + 1, json))
+    }
+
+    /// Write a snapshot, unless a newer one has already been written
+    fn persist(&self, (number, json): (u64, String)) -> Result<()> {
+        let mut saved = self.saved.lock()?;
+        if *saved > number {
+            return Ok(());
+        }
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).context("Failed to create app data dir")?;
+        }
+        let tmp_path = self.path.with_extension("json.tmp");
+        let _ = std::fs::remove_file(&tmp_path);
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options
+            .open(&tmp_path)
+            .and_then(|mut file| file.write_all(json.as_bytes()))
+            .context("Failed to save print history")?;
+        std::fs::rename(&tmp_path, &self.path).context("Failed to save print history")?;
+        *saved = number;
+        Ok(())
+    }
+
+    /// Add a job; if it can't be saved it is not added, and never runs
+    fn insert(&self, job: PrintJob) -> Result<()> {
+        let id = job.id;
+        let mut jobs = self.jobs.lock()?;
+        jobs.push(job);
+        // Drop the oldest finished jobs; unfinished ones are still being run
+        let mut excess = jobs.len().saturating_sub(MAX_HISTORY);
+        jobs.retain(|job| {
+            let evict = excess > 0 && job.status.is_finished();
+            if evict {
+                excess -= 1;
+            }
+            !evict
+        });
+        let snapshot = self.snapshot(&mut jobs);
+        drop(jobs);
+        let saved = snapshot.and_then(|snapshot| self.persist(snapshot));
+        if saved.is_err() {
+            self.jobs.lock()?.retain(|job| job.id != id);
+        }
+        saved
+    }
+
+    fn update(&self, id: Uuid, f: impl FnOnce(&mut PrintJob)) -> Result<PrintJob> {
+        let mut jobs = self.jobs.lock()?;
+        let job = jobs
+            .iter_mut()
+            .find(|job| job.id == id)
+            .ok_or_else(|| job_not_found(id))?;
+        f(job);
+        job.prune();
+        job.updated_at = Utc::now();
+        let job = job.clone();
+        let snapshot = self.snapshot(&mut jobs)?;
+        drop(jobs);
+        self.persist(snapshot)?;
+        Ok(job)
+    }
+
+    pub fn get(&self, id: Uuid) -> Result<PrintJob> {
+        self.jobs
+            .lock()?
+            .iter()
+            .find(|job| job.id == id)
+            .cloned()
+            .ok_or_else(|| job_not_found(id))
+    }
+
+    /// Most recent jobs first, optionally only receipts matching `search`
+    pub fn history(&self, limit: usize, search: Option<&str>) -> Result<Vec<PrintJob>> {
+        Ok(self
+            .jobs
+            .lock()?
+            .iter()
+            .rev()
+            .filter(|job| search.is_none_or(|query| job.matches(query)))
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    /// Send a job until it is accepted, fails for good, or runs out of
+    /// attempts, then `follow` it until it prints
+    ///
+    /// Only sending is retried. A job whose progress can't be followed after
+    /// it was accepted is left unconfirmed rather than sent again.
+    async fn run<F, Fut, G, GFut>(
+        &self,
+        id: Uuid,
+        send: F,
+        follow: G,
+        notify: impl Fn(&PrintJob),
+        retry_delay: Duration,
+    ) -> Result<PrintJob>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<printers::Accepted>>,
+        G: FnOnce(printers::TrackedJob) -> GFut,
+        GFut: Future<Output = Result<()>>,
+    {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let job = self.update(id, |job| {
+                job.status = JobStatus::Sending;
+                job.attempts += 1;
+            })?;
+            notify(&job);
+
+            match send().await {
+                Ok(accepted) => {
+                    let tracking = accepted.tracking;
+                    let job = self.update(id, |job| {
+                        job.status = match tracking {
+                            Some(_) => JobStatus::Spooled,
+                            None => JobStatus::Printed,
+                        };
+                        job.cups_job_id = accepted.job_id;
+                        job.error = None;
+                    })?;
+                    notify(&job);
+                    let Some(tracking) = tracking else {
+                        return Ok(job);
+                    };
+
+                    let result = follow(tracking).await;
+                    let job = self.update(id, |job| match &result {
+                        Ok(()) => job.status = JobStatus::Printed,
+                        Err(e) => {
+                            // Canceled or aborted by the spooler; anything
+                            // else leaves the job's fate unknown
+                            job.status = if e.code == ErrorCode::PrintFailed {
+                                JobStatus::Failed
+                            } else {
+                                JobStatus::Unconfirmed
+                            };
+                            job.error = Some(e.clone());
+                        }
+                    })?;
+                    notify(&job);
+                    return result.map(|()| job);
+                }
+                Err(e) if e.retryable && attempt < MAX_ATTEMPTS => {
+                    let job = self.update(id, |job| {
+                        job.status = JobStatus::Queued;
+                        job.error = Some(e);
+                    })?;
+                    notify(&job);
+                    tokio::time::sleep(retry_delay * 2u32.pow(attempt - 1)).await;
+                }
+                Err(e) => {
+                    let job = self.update(id, |job| {
+                        job.status = JobStatus::Failed;
+                        job.error = Some(e.clone());
+                    })?;
+                    notify(&job);
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+fn job_not_found(id: Uuid) -> AppError {
+    AppError::new(ErrorCode::InvalidInput, "Print job not found").with_detail(id)
+}
+
+fn document_not_kept() -> AppError {
+    AppError::invalid_input("This job's document is no longer kept; print it again")
+}
+
+fn notify(app_handle: &tauri::AppHandle, job: &PrintJob) {
+    let _ = app_handle.emit(JOB_UPDATED_EVENT, job);
+}
+
+/// Render and send a queued job
+async fn dispatch(
+    app_handle: &tauri::AppHandle,
+    queue: &PrintQueue,
+    job: PrintJob,
+) -> Result<PrintJob> {
+    let on_update = |job: &PrintJob| notify(app_handle, job);
+    let follow = printers::TrackedJob::wait;
+    let printer_name = job.printer_name.as_str();
+    let document = job.document.as_ref().ok_or_else(document_not_kept)?;
+
+    match document {
+        PrintDocument::Receipt { receipt, profile } => {
+            let send = move || async move {
+                let bytes = print::receipt_bytes(app_handle, receipt, profile)?;
+                printers::send(app_handle, printer_name, bytes).await
+            };
+            queue
+                .run(job.id, send, follow, on_update, RETRY_DELAY)
+                .await
+        }
+        PrintDocument::Html { content } => {
+            let send = move || async move {
+                printers::send_html(app_handle, printer_name, content.clone()).await
+            };
+            queue
+                .run(job.id, send, follow, on_update, RETRY_DELAY)
+                .await
+        }
+        PrintDocument::Document {
+            content,
+            title,
+            options,
+        } => {
+            let send = move || async move {
+                let (content, title, render_options) =
+                    (content.clone(), title.clone(), options.clone());
+                let pdf = tauri::async_runtime::spawn_blocking(move || {
+                    print::document_pdf(&content, title.as_deref(), &render_options)
+                })
+                .await??;
+                printers::send_pdf(app_handle, printer_name, pdf, options).await
+            };
+            queue
+                .run(job.id, send, follow, on_update, RETRY_DELAY)
+                .await
+        }
+        PrintDocument::Report {
+            report,
+            branding,
+            options,
+        } => {
+            let send = move || async move {
+                let (handle, report, branding, render_options) = (
+                    app_handle.clone(),
+                    report.clone(),
+                    branding.clone(),
+                    options.clone(),
+                );
+                let pdf = tauri::async_runtime::spawn_blocking(move || {
+                    report_commands::report_pdf(&handle, &report, &branding, &render_options)
+                })
+                .await??;
+                printers::send_pdf(app_handle, printer_name, pdf, options).await
+            };
+            queue
+                .run(job.id, send, follow, on_update, RETRY_DELAY)
+                .await
+        }
+        PrintDocument::Labels {
+            label,
+            language,
+            members,
+            copies,
+        } => {
+            let send = move || async move {
+                let bytes = label_commands::label_bytes(label, *language, members, *copies)?;
+                printers::send(app_handle, printer_name, bytes).await
+            };
+            queue
+                .run(job.id, send, follow, on_update, RETRY_DELAY)
+                .await
+        }
+    }
+}
+
+/// Send a queued job in the background
+fn spawn_dispatch(app_handle: &tauri::AppHandle, job: PrintJob) {
+    let app_handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        let queue = app_handle.state::<PrintQueue>();
+        // The failure is kept on the job and sent with its update
+        let id = job.id;
+        if let Err(e) = dispatch(&app_handle, &queue, job).await {
+            eprintln!("Print job failed: {}", e);
+        }
+        let recorded = queue
+            .get(id)
+            .and_then(|job| receipt_numbers::record_print(&app_handle, &job));
+        if let Err(e) = recorded {
+            eprintln!("Failed to record receipt print: {}", e);
+        }
+    });
+}
+
+/// Queue a document to print, returning the queued job right away
+pub fn submit(
+    app_handle: &tauri::AppHandle,
+    printer_name: String,
+    document: PrintDocument,
+    duplicate_of: Option<Uuid>,
+) -> Result<PrintJob> {
+    submit_with_id(
+        app_handle,
+        Uuid::new_v4(),
+        printer_name,
+        document,
+        duplicate_of,
+    )
+}
+
+/// `submit` with the job id chosen in advance, e.g. to record it with the
+/// receipt number printed on the document
+pub fn submit_with_id(
+    app_handle: &tauri::AppHandle,
+    id: Uuid,
+    printer_name: String,
+    document: PrintDocument,
+    duplicate_of: Option<Uuid>,
+) -> Result<PrintJob> {
+    let queue = app_handle.state::<PrintQueue>();
+    let job = PrintJob::new(id, printer_name, document, duplicate_of);
+    queue.insert(job.clone())?;
+    notify(app_handle, &job);
+
+    spawn_dispatch(app_handle, job.clone());
+    Ok(job)
+}
+
+/// Recent print jobs, newest first
+///
+/// `search` limits the list to receipts whose title, number or values
+/// (e.g. the member's name) contain it.
+#[tauri::command]
+pub async fn get_print_jobs(
+    state: tauri::State<'_, PrintQueue>,
+    limit: Option<usize>,
+    search: Option<String>,
+) -> Result<Vec<PrintJob>> {
+    state.history(limit.unwrap_or(DEFAULT_HISTORY_LIMIT), search.as_deref())
+}
+
+/// Queue a failed job again
+#[tauri::command]
+pub async fn retry_print_job(app_handle: tauri::AppHandle, job_id: Uuid) -> Result<PrintJob> {
+    let queue = app_handle.state::<PrintQueue>();
+    let job = queue.get(job_id)?;
+    if job.status != JobStatus::Failed {
+        return Err(AppError::invalid_input(
+            "Only failed print jobs can be retried",
+        ));
+    }
+    if job.document.is_none() {
+        return Err(document_not_kept());
+    }
+
+    let job = queue.update(job_id, |job| job.status = JobStatus::Queued)?;
+    notify(&app_handle, &job);
+    spawn_dispatch(&app_handle, job.clone());
+    Ok(job)
+}
+
+/// Print an earlier receipt again, marked DUPLICATE
+#[tauri::command]
+pub async fn reprint_receipt(
+    app_handle: tauri::AppHandle,
+    job_id: Uuid,
+    printer_name: Option<String>,
+) -> Result<PrintJob> {
+    let original = app_handle.state::<PrintQueue>().get(job_id)?;
+    let Some(PrintDocument::Receipt {
+        mut receipt,
+        profile,
+    }) = original.document
+    else {
+        return Err(AppError::invalid_input("Only receipts can be reprinted"));
+    };
+    receipt.duplicate = true;
+
+    submit(
+        &app_handle,
+        printer_name.unwrap_or(original.printer_name),
+        PrintDocument::Receipt { receipt, profile },
+        Some(original.duplicate_of.unwrap_or(original.id)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::print::ReceiptItem;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn receipt_job(member: &str) -> PrintJob {
+        PrintJob::new(
+            Uuid::new_v4(),
+            "Counter 1".to_string(),
+            PrintDocument::Receipt {
+                receipt: ReceiptData {
+                    title: "Twizigamire SACCO".to_string(),
+                    items: vec![ReceiptItem {
+                        label: "Member".to_string(),
+                        value: member.to_string(),
+                    }],
+                    total: "RWF 5,000".to_string(),
+                    footer: String::new(),
+                    verification: None,
+                    receipt_number: Some("TWZ-000123".to_string()),
+                    sacco_id: None,
+                    duplicate: false,
+                    fields: Default::default(),
+                },
+                profile: PrinterProfile::default(),
+            },
+            None,
+        )
+    }
+
+    /// Accepted by CUPS as job Counter-42
+    fn spooled() -> printers::Accepted {
+        printers::Accepted {
+            job_id: Some("Counter-42".to_string()),
+            tracking: Some(printers::TrackedJob {
+                printer_uri: "ipp://localhost:631/printers/Counter".to_string(),
+                certificate: None,
+                job_id: 42,
+            }),
+        }
+    }
+
+    fn temp_queue() -> (PrintQueue, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("print-jobs-test-{}", Uuid::new_v4()));
+        (PrintQueue::open(dir.join(JOBS_FILE)), dir)
+    }
+
+    #[tokio::test]
+    async fn test_retries_until_printed() {
+        let (queue, dir) = temp_queue();
+        let job = receipt_job("Jean Bosco");
+        queue.insert(job.clone()).unwrap();
+
+        let calls = AtomicU32::new(0);
+        let statuses = Mutex::new(Vec::new());
+        let send = || async {
+            if calls.fetch_add(1, Ordering::SeqCst) < 2 {
+                Err(AppError::from_code(ErrorCode::PrinterOffline))
+            } else {
+                Ok(spooled())
+            }
+        };
+
+        let printed = queue
+            .run(
+                job.id,
+                send,
+                |_| async { Ok(()) },
+                |job| statuses.lock().unwrap().push(job.status),
+                Duration::ZERO,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(printed.status, JobStatus::Printed);
+        assert_eq!(printed.attempts, 3);
+        assert_eq!(printed.cups_job_id.as_deref(), Some("Counter-42"));
+        assert!(printed.error.is_none());
+        assert_eq!(
+            *statuses.lock().unwrap(),
+            [
+                JobStatus::Sending,
+                JobStatus::Queued,
+                JobStatus::Sending,
+                JobStatus::Queued,
+                JobStatus::Sending,
+                JobStatus::Spooled,
+                JobStatus::Printed,
+            ]
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_accepted_job_is_never_resent() {
+        let (queue, dir) = temp_queue();
+        let job = receipt_job("Jean Bosco");
+        queue.insert(job.clone()).unwrap();
+
+        // CUPS took the job, then stopped answering
+        let calls = AtomicU32::new(0);
+        let send = || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Ok(spooled())
+        };
+        let follow = |_| async { Err(AppError::from_code(ErrorCode::PrintUnconfirmed)) };
+        let error = queue
+            .run(job.id, send, follow, |_| {}, Duration::ZERO)
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.code, ErrorCode::PrintUnconfirmed);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        let unconfirmed = queue.get(job.id).unwrap();
+        assert_eq!(unconfirmed.status, JobStatus::Unconfirmed);
+        assert_eq!(unconfirmed.cups_job_id.as_deref(), Some("Counter-42"));
+
+        // Canceled in the CUPS queue: the job did not print
+        let job = receipt_job("Hélène Uwase");
+        queue.insert(job.clone()).unwrap();
+        let follow = |_| async {
+            Err(AppError::new(
+                ErrorCode::PrintFailed,
+                "Print job 42 was Canceled",
+            ))
+        };
+        queue
+            .run(
+                job.id,
+                || async { Ok(spooled()) },
+                follow,
+                |_| {},
+                Duration::ZERO,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(queue.get(job.id).unwrap().status, JobStatus::Failed);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_permanent_failure_is_not_retried() {
+        let (queue, dir) = temp_queue();
+        let job = receipt_job("Jean Bosco");
+        queue.insert(job.clone()).unwrap();
+
+        let send = || async { Err(AppError::from_code(ErrorCode::PrinterNotFound)) };
+        let error = queue
+            .run(job.id, send, |_| async { Ok(()) }, |_| {}, Duration::ZERO)
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.code, ErrorCode::PrinterNotFound);
+        let failed = queue.get(job.id).unwrap();
+        assert_eq!(failed.status, JobStatus::Failed);
+        assert_eq!(failed.attempts, 1);
+        assert_eq!(failed.error.unwrap().code, ErrorCode::PrinterNotFound);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_history_survives_restart() {
+        let (queue, dir) = temp_queue();
+        let printed = receipt_job("Jean Bosco");
+        let interrupted = receipt_job("Hélène Uwase");
+        queue.insert(printed.clone()).unwrap();
+        queue.insert(interrupted.clone()).unwrap();
+        queue
+            .update(printed.id, |job| job.status = JobStatus::Printed)
+            .unwrap();
+        queue
+            .update(interrupted.id, |job| job.status = JobStatus::Sending)
+            .unwrap();
+
+        let reopened = PrintQueue::open(queue.path.clone());
+        assert_eq!(reopened.get(printed.id).unwrap().status, JobStatus::Printed);
+        assert_eq!(
+            reopened.get(interrupted.id).unwrap().status,
+            JobStatus::Failed
+        );
+
+        let found = reopened.history(10, Some("hélène")).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].id, interrupted.id);
+        assert_eq!(reopened.history(1, None).unwrap()[0].id, interrupted.id);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_history_stores_only_receipts() {
+        let (queue, dir) = temp_queue();
+        let receipt = receipt_job("Jean Bosco");
+        let statement = PrintJob::new(
+            Uuid::new_v4(),
+            "Back office".to_string(),
+            PrintDocument::Html {
+                content: "<p>Statement for Hélène Uwase</p>".to_string(),
+            },
+            None,
+        );
+        queue.insert(receipt.clone()).unwrap();
+        queue.insert(statement.clone()).unwrap();
+
+        // Still needed in memory until it prints, but never written out
+        assert!(queue.get(statement.id).unwrap().document.is_some());
+        let json = std::fs::read_to_string(&queue.path).unwrap();
+        assert!(json.contains("Jean Bosco"));
+        assert!(!json.contains("Hélène"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&queue.path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let printed = queue
+            .update(statement.id, |job| job.status = JobStatus::Printed)
+            .unwrap();
+        assert!(printed.document.is_none());
+        let reopened = PrintQueue::open(queue.path.clone());
+        assert!(reopened.get(receipt.id).unwrap().document.is_some());
+        assert!(reopened.get(statement.id).unwrap().document.is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_history_keeps_unfinished_jobs() {
+        let (queue, dir) = temp_queue();
+        let sending = receipt_job("Jean Bosco");
+        queue.insert(sending.clone()).unwrap();
+        queue
+            .update(sending.id, |job| job.status = JobStatus::Sending)
+            .unwrap();
+        let mut printed = Vec::new();
+        for _ in 0..MAX_HISTORY {
+            let mut job = receipt_job("Hélène Uwase");
+            job.status = JobStatus::Printed;
+            printed.push(job.id);
+            queue.insert(job).unwrap();
+        }
+
+        // The oldest printed job went instead of the one still sending
+        assert_eq!(queue.get(sending.id).unwrap().status, JobStatus::Sending);
+        assert!(queue.get(printed[0]).is_err());
+        assert!(queue.get(printed[1]).is_ok());
+        assert_eq!(queue.jobs.lock().unwrap().len(), MAX_HISTORY);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,946 @@
+// Sequential receipt numbers
+//
+// The server hands each device blocks of numbers from its branch's sequence,
+// so devices never collide and the branch's numbers have no gaps once every
+// device has reconciled. Within a block numbers are issued strictly in order.
+//
+// Every issued or voided number is appended to a ledger and flushed to disk
+// before the number is handed out, so a crash can't issue the same number
+// twice. A line cut short by a crash was never acknowledged and is dropped
+// on load.
+//
+// A number issued by `print_receipt` records its print job, and how that job
+// (or a reprint of it) ended is appended to the ledger too. Until a printed
+// entry follows, the number shows as print failed when reconciling, so a
+// crash mid-print can't hide it either.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::Manager;
+use uuid::Uuid;
+
+use super::print_jobs::{JobStatus, PrintJob};
+use crate::error::{AppError, Context, ErrorCode, Result};
+
+const NUMBERS_DIR: &str = "receipt_numbers";
+const BLOCKS_FILE: &str = "blocks.json";
+const LEDGER_FILE: &str = "ledger.jsonl";
+const RECEIPT_NUMBER_BLOCKS_URL: &str = "https://api.ibimina.rw/receipt-number-blocks";
+const DEFAULT_BLOCK_SIZE: u32 = 500;
+const MAX_BLOCK_SIZE: u32 = 10_000;
+/// Reserve the next block in the background once fewer numbers than this remain
+const REFILL_BELOW: u64 = 50;
+
+/// Numbers `start..=end` of a branch's sequence, reserved for this device
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct NumberBlock {
+    pub id: Uuid,
+    pub branch_id: Uuid,
+    /// Printed before the number, e.g. "KGL01"
+    pub prefix: String,
+    pub start: u64,
+    pub end: u64,
+    pub reserved_at: DateTime<Utc>,
+}
+
+impl NumberBlock {
+    pub fn format(&self, number: u64) -> String {
+        format!("{}-{:06}", self.prefix, number)
+    }
+
+    fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum LedgerEntry {
+    Issued {
+        block_id: Uuid,
+        number: u64,
+        /// What the number was printed on, e.g. the payment id
+        reference: Option<String>,
+        /// Print job of the receipt the number was printed on
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        print_job_id: Option<Uuid>,
+        at: DateTime<Utc>,
+    },
+    Voided {
+        block_id: Uuid,
+        number: u64,
+        reason: String,
+        at: DateTime<Utc>,
+    },
+    /// The number's receipt printed, on its own job or a reprint
+    Printed {
+        block_id: Uuid,
+        number: u64,
+        print_job_id: Uuid,
+        at: DateTime<Utc>,
+    },
+    /// The number's print job failed or was never confirmed
+    PrintFailed {
+        block_id: Uuid,
+        number: u64,
+        print_job_id: Uuid,
+        reason: String,
+        at: DateTime<Utc>,
+    },
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct IssuedNumber {
+    pub receipt_number: String,
+    pub block_id: Uuid,
+    pub number: u64,
+    pub print_job_id: Option<Uuid>,
+    pub issued_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct VoidedNumber {
+    pub receipt_number: String,
+    pub reason: String,
+    pub voided_at: DateTime<Utc>,
+}
+
+/// Issued number whose receipt never printed; retry or reprint the job, or
+/// void the number
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct PrintFailedNumber {
+    pub receipt_number: String,
+    pub print_job_id: Uuid,
+    pub issued_at: DateTime<Utc>,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct NumberRange {
+    pub first: String,
+    pub last: String,
+    pub count: u64,
+}
+
+/// Every number in a block is exactly one of issued, voided or unused
+#[derive(Debug, Serialize, Clone)]
+pub struct BlockReconciliation {
+    pub block: NumberBlock,
+    /// Issued and not voided, including `print_failed`
+    pub issued: u64,
+    pub print_failed: Vec<PrintFailedNumber>,
+    pub voided: Vec<VoidedNumber>,
+    /// Numbers not yet issued, always the end of the block
+    pub unused: Option<NumberRange>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ReconciliationReport {
+    pub generated_at: DateTime<Utc>,
+    pub blocks: Vec<BlockReconciliation>,
+    pub issued: u64,
+    pub print_failed: u64,
+    pub voided: u64,
+    pub unused: u64,
+}
+
+/// Blocks and ledger as stored on disk
+struct Ledger {
+    dir: PathBuf,
+    blocks: Vec<NumberBlock>,
+    /// Next number to issue, per block
+    next: HashMap<Uuid, u64>,
+    voided: HashMap<(Uuid, u64), VoidedNumber>,
+    /// Print job and issue time of numbers issued for a print job
+    print_jobs: HashMap<(Uuid, u64), (Uuid, DateTime<Utc>)>,
+    /// How the last print of a number issued for a print job ended: `None`
+    /// if it printed, otherwise why not
+    print_outcomes: HashMap<(Uuid, u64), Option<String>>,
+}
+
+/// Make a rename durable
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn sync_dir(dir: &Path) -> Result<()> {
+    File::open(dir)
+        .and_then(|d| d.sync_all())
+        .context("Failed to save receipt numbers")
+}
+
+/// Windows can't open directories and commits renames with the file
+#[cfg(target_os = "windows")]
+fn sync_dir(_dir: &Path) -> Result<()> {
+    Ok(())
+}
+
+impl Ledger {
+    fn open(dir: PathBuf) -> Result<Self> {
+        let blocks: Vec<NumberBlock> = match std::fs::read_to_string(dir.join(BLOCKS_FILE)) {
+            Ok(json) => {
+                serde_json::from_str(&json).context("Failed to read receipt number blocks")?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e).context("Failed to read receipt number blocks"),
+        };
+
+        let mut ledger = Self {
+            next: blocks.iter().map(|b| (b.id, b.start)).collect(),
+            blocks,
+            voided: HashMap::new(),
+            print_jobs: HashMap::new(),
+            print_outcomes: HashMap::new(),
+            dir,
+        };
+
+        let path = ledger.dir.join(LEDGER_FILE);
+        let contents = match std::fs::read(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(ledger),
+            Err(e) => return Err(e).context("Failed to read receipt number ledger"),
+        };
+
+        let mut complete = 0;
+        for (i, line) in contents.split_inclusive(|&b| b == b'\n').enumerate() {
+            if !line.ends_with(b"\n") {
+                // Torn final write; the number was never handed out
+                break;
+            }
+            let entry: LedgerEntry = serde_json::from_slice(line).map_err(|e| {
+                AppError::new(ErrorCode::StorageError, "Receipt number ledger is damaged")
+                    .with_detail(format!("line {}: {}", i + 1, e))
+            })?;
+            ledger.apply(entry)?;
+            complete += line.len();
+        }
+        if complete < contents.len() {
+            OpenOptions::new()
+                .write(true)
+                .open(&path)
+                .and_then(|f| f.set_len(complete as u64))
+                .context("Failed to repair receipt number ledger")?;
+        }
+
+        Ok(ledger)
+    }
+
+    fn block(&self, block_id: Uuid) -> Result<&NumberBlock> {
+        self.blocks
+            .iter()
+            .find(|b| b.id == block_id)
+            .ok_or_else(|| {
+                AppError::new(ErrorCode::StorageError, "Receipt number ledger is damaged")
+                    .with_detail(format!("unknown block {}", block_id))
+            })
+    }
+
+    fn apply(&mut self, entry: LedgerEntry) -> Result<()> {
+        match entry {
+            LedgerEntry::Issued {
+                block_id,
+                number,
+                print_job_id,
+                at,
+                ..
+            } => {
+                self.block(block_id)?;
+                self.next.insert(block_id, number + 1);
+                if let Some(print_job_id) = print_job_id {
+                    self.print_jobs
+                        .insert((block_id, number), (print_job_id, at));
+                }
+            }
+            LedgerEntry::Voided {
+                block_id,
+                number,
+                reason,
+                at,
+            } => {
+                let receipt_number = self.block(block_id)?.format(number);
+                self.voided.insert(
+                    (block_id, number),
+                    VoidedNumber {
+                        receipt_number,
+                        reason,
+                        voided_at: at,
+                    },
+                );
+            }
+            LedgerEntry::Printed {
+                block_id, number, ..
+            } => {
+                self.block(block_id)?;
+                self.print_outcomes.insert((block_id, number), None);
+            }
+            LedgerEntry::PrintFailed {
+                block_id,
+                number,
+                reason,
+                ..
+            } => {
+                self.block(block_id)?;
+                self.print_outcomes.insert((block_id, number), Some(reason));
+            }
+        }
+        Ok(())
+    }
+
+    /// Append an entry and flush it to disk before applying it
+    fn append(&mut self, entry: LedgerEntry) -> Result<()> {
+        std::fs::create_dir_all(&self.dir).context("Failed to save receipt numbers")?;
+        let mut line = serde_json::to_vec(&entry).context("Failed to save receipt numbers")?;
+        line.push(b'\n');
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(LEDGER_FILE))
+            .context("Failed to save receipt numbers")?;
+        file.write_all(&line)
+            .and_then(|_| file.sync_data())
+            .context("Failed to save receipt numbers")?;
+        self.apply(entry)
+    }
+
+    fn save_blocks(&self) -> Result<()> {
+        std::fs::create_dir_all(&self.dir).context("Failed to save receipt numbers")?;
+        let json =
+            serde_json::to_string_pretty(&self.blocks).context("Failed to save receipt numbers")?;
+        let path = self.dir.join(BLOCKS_FILE);
+        let tmp_path = path.with_extension("json.tmp");
+
+        let mut file = File::create(&tmp_path).context("Failed to save receipt numbers")?;
+        file.write_all(json.as_bytes())
+            .and_then(|_| file.sync_all())
+            .context("Failed to save receipt numbers")?;
+        std::fs::rename(&tmp_path, &path).context("Failed to save receipt numbers")?;
+        sync_dir(&self.dir)
+    }
+
+    /// Add a block the server reserved for `branch_id`
+    fn add_block(&mut self, branch_id: Uuid, block: NumberBlock) -> Result<()> {
+        if block.branch_id != branch_id {
+            return Err(AppError::new(
+                ErrorCode::InvalidResponse,
+                "Server reserved receipt numbers for another branch",
+            )
+            .with_detail(format!("requested {}, got {}", branch_id, block.branch_id)));
+        }
+        if block.start > block.end || block.prefix.trim().is_empty() {
+            return Err(AppError::new(
+                ErrorCode::InvalidResponse,
+                "Server reserved an invalid receipt number block",
+            )
+            .with_detail(format!("{}..={}", block.start, block.end)));
+        }
+        let overlaps = self.blocks.iter().any(|b| {
+            b.id == block.id
+                || (b.prefix == block.prefix && b.start <= block.end && block.start <= b.end)
+        });
+        if overlaps {
+            return Err(AppError::new(
+                ErrorCode::InvalidResponse,
+                "Server reserved receipt numbers this computer already has",
+            )
+            .with_detail(format!("{} {}..={}", block.prefix, block.start, block.end)));
+        }
+
+        self.next.insert(block.id, block.start);
+        self.blocks.push(block);
+        if let Err(e) = self.save_blocks() {
+            let block = self.blocks.pop().expect("just pushed");
+            self.next.remove(&block.id);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Branch of the most recently reserved block; older branches' blocks
+    /// are kept only for reconciliation
+    fn current_branch(&self) -> Option<Uuid> {
+        self.blocks.last().map(|b| b.branch_id)
+    }
+
+    fn remaining(&self) -> u64 {
+        let Some(branch_id) = self.current_branch() else {
+            return 0;
+        };
+        self.blocks
+            .iter()
+            .filter(|b| b.branch_id == branch_id)
+            .map(|b| (b.end + 1).saturating_sub(self.next[&b.id]))
+            .sum()
+    }
+
+    fn issue(
+        &mut self,
+        reference: Option<String>,
+        print_job_id: Option<Uuid>,
+    ) -> Result<IssuedNumber> {
+        let branch_id = self.current_branch();
+        let (block, number) = self
+            .blocks
+            .iter()
+            .filter(|b| Some(b.branch_id) == branch_id)
+            .map(|b| (b.clone(), self.next[&b.id]))
+            .find(|(b, next)| *next <= b.end)
+            .ok_or_else(|| {
+                AppError::from_code(ErrorCode::ReceiptNumbersExhausted)
+                    .with_detail("Connect to the internet to reserve more receipt numbers")
+            })?;
+
+        let issued_at = Utc::now();
+        self.append(LedgerEntry::Issued {
+            block_id: block.id,
+            number,
+            reference,
+            print_job_id,
+            at: issued_at,
+        })?;
+
+        Ok(IssuedNumber {
+            receipt_number: block.format(number),
+            block_id: block.id,
+            number,
+            print_job_id,
+            issued_at,
+        })
+    }
+
+    fn void(&mut self, receipt_number: &str, reason: &str) -> Result<()> {
+        let reason = reason.trim();
+        if reason.is_empty() {
+            return Err(AppError::invalid_input(
+                "A reason is required to void a receipt number",
+            ));
+        }
+
+        let (block_id, number) = self
+            .blocks
+            .iter()
+            .find_map(|b| {
+                let number: u64 = receipt_number
+                    .strip_prefix(&b.prefix)?
+                    .strip_prefix('-')?
+                    .parse()
+                    .ok()?;
+                // Only numbers already issued can be voided
+                (b.start <= number && number < self.next[&b.id]).then_some((b.id, number))
+            })
+            .ok_or_else(|| {
+                AppError::invalid_input("Receipt number was not issued on this computer")
+                    .with_detail(receipt_number)
+            })?;
+        if self.voided.contains_key(&(block_id, number)) {
+            return Err(AppError::invalid_input("Receipt number is already voided")
+                .with_detail(receipt_number));
+        }
+
+        self.append(LedgerEntry::Voided {
+            block_id,
+            number,
+            reason: reason.to_string(),
+            at: Utc::now(),
+        })
+    }
+
+    /// Record how a print job for an issued number ended; `job_id` is the
+    /// job the number was issued for, `print_job_id` that job or a reprint
+    fn record_print(
+        &mut self,
+        job_id: Uuid,
+        print_job_id: Uuid,
+        failure: Option<String>,
+    ) -> Result<()> {
+        let Some((block_id, number)) = self
+            .print_jobs
+            .iter()
+            .find(|(_, (id, _))| *id == job_id)
+            .map(|(key, _)| *key)
+        else {
+            // Printed without a number
+            return Ok(());
+        };
+
+        let at = Utc::now();
+        self.append(match failure {
+            None => LedgerEntry::Printed {
+                block_id,
+                number,
+                print_job_id,
+                at,
+            },
+            Some(reason) => LedgerEntry::PrintFailed {
+                block_id,
+                number,
+                print_job_id,
+                reason,
+                at,
+            },
+        })
+    }
+
+    /// Numbers issued for a print job count as print failed until a printed
+    /// entry is recorded for them
+    fn reconcile(&self) -> ReconciliationReport {
+        let blocks: Vec<_> = self
+            .blocks
+            .iter()
+            .map(|block| {
+                let next = self.next[&block.id];
+                let mut voided: Vec<_> = self
+                    .voided
+                    .iter()
+                    .filter(|((id, _), _)| *id == block.id)
+                    .map(|((_, number), v)| (*number, v.clone()))
+                    .collect();
+                voided.sort_by_key(|(number, _)| *number);
+
+                let mut print_failed: Vec<_> = self
+                    .print_jobs
+                    .iter()
+                    .filter(|((id, number), _)| {
+                        *id == block.id && !self.voided.contains_key(&(*id, *number))
+                    })
+                    .filter_map(|(key, (job_id, issued_at))| {
+                        let reason = match self.print_outcomes.get(key) {
+                            Some(None) => return None,
+                            Some(Some(reason)) => reason.clone(),
+                            None => "The printer has not confirmed the receipt".to_string(),
+                        };
+                        Some((
+                            key.1,
+                            PrintFailedNumber {
+                                receipt_number: block.format(key.1),
+                                print_job_id: *job_id,
+                                issued_at: *issued_at,
+                                reason,
+                            },
+                        ))
+                    })
+                    .collect();
+                print_failed.sort_by_key(|(number, _)| *number);
+
+                let unused = (next <= block.end).then(|| NumberRange {
+                    first: block.format(next),
+                    last: block.format(block.end),
+                    count: block.end - next + 1,
+                });
+                let unused_count = unused.as_ref().map_or(0, |r| r.count);
+                BlockReconciliation {
+                    issued: block.len() - voided.len() as u64 - unused_count,
+                    print_failed: print_failed.into_iter().map(|(_, p)| p).collect(),
+                    voided: voided.into_iter().map(|(_, v)| v).collect(),
+                    unused,
+                    block: block.clone(),
+                }
+            })
+            .collect();
+
+        ReconciliationReport {
+            generated_at: Utc::now(),
+            issued: blocks.iter().map(|b| b.issued).sum(),
+            print_failed: blocks.iter().map(|b| b.print_failed.len() as u64).sum(),
+            voided: blocks.iter().map(|b| b.voided.len() as u64).sum(),
+            unused: blocks
+                .iter()
+                .map(|b| b.unused.as_ref().map_or(0, |r| r.count))
+                .sum(),
+            blocks,
+        }
+    }
+}
+
+/// Receipt numbering for this device; the ledger is read on first use so a
+/// damaged ledger stops numbering, not the app
+#[derive(Default)]
+pub struct ReceiptNumbers {
+    ledger: Mutex<Option<Ledger>>,
+    refilling: Arc<AtomicBool>,
+}
+
+impl ReceiptNumbers {
+    fn with<T>(
+        &self,
+        app_handle: &tauri::AppHandle,
+        f: impl FnOnce(&mut Ledger) -> Result<T>,
+    ) -> Result<T> {
+        let mut ledger = self.ledger.lock()?;
+        if ledger.is_none() {
+            let dir = app_handle
+                .path()
+                .app_data_dir()
+                .context("Failed to resolve app data dir")?;
+            *ledger = Some(Ledger::open(dir.join(NUMBERS_DIR))?);
+        }
+        f(ledger.as_mut().expect("ledger was just loaded"))
+    }
+}
+
+/// Ask the server for the next block of the branch's numbers
+async fn reserve_block(branch_id: Uuid, size: u32) -> Result<NumberBlock> {
+    let credentials = super::auth::require_credentials().await?;
+    let device_id = super::auth::get_device_id().await?;
+
+    let client = reqwest::Client::builder()
+        .user_agent("SACCO+ Staff Admin")
+        .timeout(std::time::Duration::from_secs(30))
+        .build()
+        .context("Failed to create HTTP client")?;
+
+    let response = client
+        .post(RECEIPT_NUMBER_BLOCKS_URL)
+        .bearer_auth(&credentials.access_token)
+        .json(&serde_json::json!({
+            "device_id": device_id,
+            "branch_id": branch_id,
+            "size": size,
+        }))
+        .send()
+        .await
+        .context("Failed to reserve receipt numbers")?;
+    if !response.status().is_success() {
+        return Err(AppError::http_status(response.status()).with_detail(RECEIPT_NUMBER_BLOCKS_URL));
+    }
+    response.json().await.context("Failed to parse response")
+}
+
+/// Reserve another block in the background when numbers are running low
+fn refill_if_low(app_handle: &tauri::AppHandle) {
+    let state = app_handle.state::<ReceiptNumbers>();
+    let low = state
+        .with(app_handle, |ledger| {
+            Ok(ledger
+                .current_branch()
+                .filter(|_| ledger.remaining() < REFILL_BELOW))
+        })
+        .ok()
+        .flatten();
+    let Some(branch_id) = low else {
+        return;
+    };
+    if state.refilling.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let app_handle = app_handle.clone();
+    let refilling = Arc::clone(&state.refilling);
+    tauri::async_runtime::spawn(async move {
+        let result = match reserve_block(branch_id, DEFAULT_BLOCK_SIZE).await {
+            Ok(block) => app_handle
+                .state::<ReceiptNumbers>()
+                .with(&app_handle, |ledger| ledger.add_block(branch_id, block)),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to reserve receipt numbers: {}", e);
+        }
+        refilling.store(false, Ordering::SeqCst);
+    });
+}
+
+/// Issue the next receipt number for print job `print_job_id`, or `None` if
+/// this device has never reserved any (numbering is off)
+pub fn assign(
+    app_handle: &tauri::AppHandle,
+    reference: Option<String>,
+    print_job_id: Uuid,
+) -> Result<Option<String>> {
+    let issued = app_handle
+        .state::<ReceiptNumbers>()
+        .with(app_handle, |ledger| {
+            if ledger.blocks.is_empty() {
+                return Ok(None);
+            }
+            ledger.issue(reference, Some(print_job_id)).map(Some)
+        })?;
+    refill_if_low(app_handle);
+    Ok(issued.map(|issued| issued.receipt_number))
+}
+
+/// Void an issued number, e.g. one whose receipt could not be queued
+pub fn void(app_handle: &tauri::AppHandle, receipt_number: &str, reason: &str) -> Result<()> {
+    app_handle
+        .state::<ReceiptNumbers>()
+        .with(app_handle, |ledger| ledger.void(receipt_number, reason))
+}
+
+/// Record how a receipt's print job ended against the number printed on it
+///
+/// A failed reprint doesn't undo a receipt that already printed, so only the
+/// original job's failures are recorded.
+pub fn record_print(app_handle: &tauri::AppHandle, job: &PrintJob) -> Result<()> {
+    let failure = match job.status {
+        JobStatus::Printed => None,
+        JobStatus::Failed | JobStatus::Unconfirmed if job.duplicate_of.is_none() => Some(
+            job.error
+                .as_ref()
+                .map_or_else(|| "Printing failed".to_string(), |e| e.message.clone()),
+        ),
+        _ => return Ok(()),
+    };
+    let job_id = job.duplicate_of.unwrap_or(job.id);
+    app_handle
+        .state::<ReceiptNumbers>()
+        .with(app_handle, |ledger| {
+            ledger.record_print(job_id, job.id, failure)
+        })
+}
+
+/// Reserve a block of receipt numbers for a branch
+///
+/// Also used to move the device to another branch; numbers left in the old
+/// branch's blocks stay unused.
+#[tauri::command]
+pub async fn reserve_receipt_numbers(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, ReceiptNumbers>,
+    branch_id: Uuid,
+    size: Option<u32>,
+) -> Result<NumberBlock> {
+    let size = size.unwrap_or(DEFAULT_BLOCK_SIZE);
+    if !(1..=MAX_BLOCK_SIZE).contains(&size) {
+        return Err(AppError::invalid_input(format!(
+            "Block size must be between 1 and {}",
+            MAX_BLOCK_SIZE
+        )));
+    }
+
+    let block = reserve_block(branch_id, size).await?;
+    state.with(&app_handle, |ledger| {
+        ledger.add_block(branch_id, block.clone())
+    })?;
+    Ok(block)
+}
+
+/// Issue the next number for a receipt printed outside `print_receipt`
+#[tauri::command]
+pub async fn next_receipt_number(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, ReceiptNumbers>,
+    reference: Option<String>,
+) -> Result<IssuedNumber> {
+    let issued = state.with(&app_handle, |ledger| ledger.issue(reference, None))?;
+    refill_if_low(&app_handle);
+    Ok(issued)
+}
+
+/// Void an issued number, e.g. for a spoiled or cancelled receipt
+#[tauri::command]
+pub async fn void_receipt_number(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, ReceiptNumbers>,
+    receipt_number: String,
+    reason: String,
+) -> Result<()> {
+    state.with(&app_handle, |ledger| ledger.void(&receipt_number, &reason))
+}
+
+/// Issued, voided and unused numbers of every block this device reserved,
+/// with the issued numbers whose receipts are not known to have printed
+#[tauri::command]
+pub async fn get_receipt_number_report(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, ReceiptNumbers>,
+) -> Result<ReconciliationReport> {
+    state.with(&app_handle, |ledger| Ok(ledger.reconcile()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(prefix: &str, start: u64, end: u64) -> NumberBlock {
+        NumberBlock {
+            id: Uuid::new_v4(),
+            branch_id: Uuid::nil(),
+            prefix: prefix.to_string(),
+            start,
+            end,
+            reserved_at: Utc::now(),
+        }
+    }
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("receipt-numbers-test-{}", Uuid::new_v4()))
+    }
+
+    #[test]
+    fn test_numbers_are_sequential_across_restarts() {
+        let dir = temp_dir();
+        let mut ledger = Ledger::open(dir.clone()).unwrap();
+        assert_eq!(
+            ledger.issue(None, None).unwrap_err().code,
+            ErrorCode::ReceiptNumbersExhausted
+        );
+
+        ledger
+            .add_block(Uuid::nil(), block("KGL01", 101, 103))
+            .unwrap();
+        ledger
+            .add_block(Uuid::nil(), block("KGL01", 201, 202))
+            .unwrap();
+        assert!(ledger
+            .add_block(Uuid::nil(), block("KGL01", 202, 210))
+            .is_err());
+        // Reserved for another branch than the one asked for
+        let other_branch = NumberBlock {
+            branch_id: Uuid::new_v4(),
+            ..block("MUS02", 1, 100)
+        };
+        assert_eq!(
+            ledger
+                .add_block(Uuid::nil(), other_branch)
+                .unwrap_err()
+                .code,
+            ErrorCode::InvalidResponse
+        );
+
+        let numbers: Vec<_> = (0..2)
+            .map(|_| {
+                ledger
+                    .issue(Some("payment".to_string()), None)
+                    .unwrap()
+                    .receipt_number
+            })
+            .collect();
+        assert_eq!(numbers, ["KGL01-000101", "KGL01-000102"]);
+
+        // Reopened after the app was closed
+        let mut ledger = Ledger::open(dir.clone()).unwrap();
+        assert_eq!(ledger.remaining(), 3);
+        let numbers: Vec<_> = (0..3)
+            .map(|_| ledger.issue(None, None).unwrap().receipt_number)
+            .collect();
+        assert_eq!(numbers, ["KGL01-000103", "KGL01-000201", "KGL01-000202"]);
+        assert!(ledger.issue(None, None).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_torn_write_is_dropped() {
+        let dir = temp_dir();
+        let mut ledger = Ledger::open(dir.clone()).unwrap();
+        ledger
+            .add_block(Uuid::nil(), block("KGL01", 1, 10))
+            .unwrap();
+        ledger.issue(None, None).unwrap();
+
+        // Crash halfway through writing the second entry
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(dir.join(LEDGER_FILE))
+            .unwrap();
+        file.write_all(br#"{"event":"issued","block_id":"#).unwrap();
+        drop(file);
+
+        let mut ledger = Ledger::open(dir.clone()).unwrap();
+        assert_eq!(
+            ledger.issue(None, None).unwrap().receipt_number,
+            "KGL01-000002"
+        );
+        let mut ledger = Ledger::open(dir.clone()).unwrap();
+        assert_eq!(
+            ledger.issue(None, None).unwrap().receipt_number,
+            "KGL01-000003"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_void_and_reconcile() {
+        let dir = temp_dir();
+        let mut ledger = Ledger::open(dir.clone()).unwrap();
+        ledger
+            .add_block(Uuid::nil(), block("KGL01", 1, 10))
+            .unwrap();
+        for _ in 0..4 {
+            ledger.issue(None, None).unwrap();
+        }
+
+        assert!(ledger.void("KGL01-000002", " ").is_err());
+        ledger.void("KGL01-000002", "Printer jammed").unwrap();
+        assert!(ledger.void("KGL01-000002", "Again").is_err());
+        // Not issued yet
+        assert!(ledger.void("KGL01-000005", "Spoiled").is_err());
+        assert!(ledger.void("MUS02-000001", "Spoiled").is_err());
+
+        let report = Ledger::open(dir.clone()).unwrap().reconcile();
+        assert_eq!((report.issued, report.voided, report.unused), (3, 1, 6));
+        let block = &report.blocks[0];
+        assert_eq!(block.voided[0].receipt_number, "KGL01-000002");
+        assert_eq!(block.voided[0].reason, "Printer jammed");
+        assert_eq!(
+            block.unused,
+            Some(NumberRange {
+                first: "KGL01-000005".to_string(),
+                last: "KGL01-000010".to_string(),
+                count: 6,
+            })
+        );
+        assert_eq!(
+            block.issued + block.voided.len() as u64 + 6,
+            block.block.len()
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_print_failed_numbers() {
+        let dir = temp_dir();
+        let mut ledger = Ledger::open(dir.clone()).unwrap();
+        ledger
+            .add_block(Uuid::nil(), block("KGL01", 1, 10))
+            .unwrap();
+
+        let (printed, failed, voided, pending) = (
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+        );
+        ledger.issue(None, Some(printed)).unwrap();
+        let issued = ledger.issue(None, Some(failed)).unwrap();
+        assert_eq!(issued.print_job_id, Some(failed));
+        ledger.issue(None, Some(voided)).unwrap();
+        ledger.issue(None, None).unwrap();
+        ledger.issue(None, Some(pending)).unwrap();
+        ledger.void("KGL01-000003", "Paper jam").unwrap();
+        ledger.record_print(printed, printed, None).unwrap();
+        ledger
+            .record_print(failed, failed, Some("The printer is offline".to_string()))
+            .unwrap();
+        ledger
+            .record_print(voided, voided, Some("Paper jam".to_string()))
+            .unwrap();
+        // Not numbered
+        ledger
+            .record_print(Uuid::new_v4(), Uuid::new_v4(), None)
+            .unwrap();
+
+        // Read back from the ledger alone; the print history plays no part
+        let mut ledger = Ledger::open(dir.clone()).unwrap();
+        let report = ledger.reconcile();
+        assert_eq!((report.issued, report.print_failed), (4, 2));
+        let print_failed = &report.blocks[0].print_failed;
+        assert_eq!(print_failed[0].receipt_number, "KGL01-000002");
+        assert_eq!(print_failed[0].print_job_id, failed);
+        assert_eq!(print_failed[0].reason, "The printer is offline");
+        // Its job never finished, e.g. the app closed mid-print
+        assert_eq!(print_failed[1].receipt_number, "KGL01-000005");
+
+        // A reprint that printed settles the failed number
+        ledger.record_print(failed, Uuid::new_v4(), None).unwrap();
+        ledger.record_print(pending, pending, None).unwrap();
+        assert_eq!(ledger.reconcile().print_failed, 0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,347 @@
+// Per-SACCO receipt templates
+//
+// Templates are edited in the web console and synced down here, one file per
+// SACCO in the app data dir, so receipts keep their layout offline. Only
+// templates that parse are stored; a SACCO without one prints with the
+// built-in layout.
+
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tauri::Manager;
+use uuid::Uuid;
+
+use super::print::ReceiptData;
+use crate::error::{AppError, Context, Result};
+use crate::escpos::raster::Bitmap;
+use crate::escpos::template::{PreviewLine, Template, TemplateError, DEFAULT_TEMPLATE};
+use crate::escpos::{emulator, PrinterProfile};
+
+const TEMPLATES_DIR: &str = "receipt_templates";
+const RECEIPT_TEMPLATES_URL: &str = "https://api.ibimina.rw/receipt-templates";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReceiptTemplate {
+    pub sacco_id: Uuid,
+    pub source: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Outcome of syncing one SACCO's template; it was stored if `errors` is empty
+#[derive(Debug, Serialize, Clone)]
+pub struct TemplateSyncResult {
+    pub sacco_id: Uuid,
+    pub updated_at: DateTime<Utc>,
+    pub errors: Vec<TemplateError>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TemplatePreview {
+    /// The receipt as plain text, one line per printed line
+    pub text: String,
+    pub lines: Vec<PreviewLine>,
+    /// PNG image of the receipt, base64 encoded
+    pub png: String,
+}
+
+/// Parsed templates, keyed by SACCO; `None` records that a SACCO has none
+#[derive(Clone, Default)]
+pub struct TemplateCache {
+    pub templates: Arc<Mutex<HashMap<Uuid, Option<Arc<Template>>>>>,
+}
+
+fn templates_dir(app_handle: &tauri::AppHandle) -> Result<PathBuf> {
+    let dir = app_handle
+        .path()
+        .app_data_dir()
+        .context("Failed to resolve app data dir")?;
+    Ok(dir.join(TEMPLATES_DIR))
+}
+
+fn template_path(dir: &Path, sacco_id: Uuid) -> PathBuf {
+    dir.join(format!("{}.json", sacco_id))
+}
+
+fn read_template(dir: &Path, sacco_id: Uuid) -> Result<Option<ReceiptTemplate>> {
+    let json = match std::fs::read_to_string(template_path(dir, sacco_id)) {
+        Ok(json) => json,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).context("Failed to read receipt template"),
+    };
+    serde_json::from_str(&json)
+        .map(Some)
+        .context("Failed to parse receipt template")
+}
+
+fn store_template(dir: &Path, template: &ReceiptTemplate) -> Result<()> {
+    std::fs::create_dir_all(dir).context("Failed to create receipt template store")?;
+
+    let json = serde_json::to_string(template).context("Failed to serialize receipt template")?;
+    let path = template_path(dir, template.sacco_id);
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::write(&tmp_path, json).context("Failed to write receipt template")?;
+    std::fs::rename(&tmp_path, &path).context("Failed to write receipt template")
+}
+
+/// Delete stored templates for SACCOs not in `keep`
+fn prune_templates(dir: &Path, keep: &[Uuid]) -> Result<()> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).context("Failed to read receipt template store"),
+    };
+
+    for entry in entries {
+        let path = entry
+            .context("Failed to read receipt template store")?
+            .path();
+        let sacco_id = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| Uuid::parse_str(stem).ok());
+        if matches!(sacco_id, Some(id) if !keep.contains(&id)) {
+            std::fs::remove_file(&path).context("Failed to remove receipt template")?;
+        }
+    }
+    Ok(())
+}
+
+fn parse_template(template: &ReceiptTemplate) -> Result<Template> {
+    Template::parse(&template.source).map_err(|errors| {
+        let detail = errors
+            .iter()
+            .map(|e| format!("line {}: {}", e.line, e.message))
+            .collect::<Vec<_>>()
+            .join("; ");
+        AppError::invalid_input("Receipt template has errors").with_detail(detail)
+    })
+}
+
+/// The SACCO's receipt template, or `None` to use the built-in layout
+pub fn receipt_template(
+    app_handle: &tauri::AppHandle,
+    sacco_id: Uuid,
+) -> Result<Option<Arc<Template>>> {
+    let cache = app_handle.state::<TemplateCache>();
+    if let Some(template) = cache.templates.lock()?.get(&sacco_id) {
+        return Ok(template.clone());
+    }
+
+    let template = match read_template(&templates_dir(app_handle)?, sacco_id)? {
+        Some(stored) => Some(Arc::new(parse_template(&stored)?)),
+        None => None,
+    };
+    cache.templates.lock()?.insert(sacco_id, template.clone());
+    Ok(template)
+}
+
+/// Sample receipt used when previewing a template without real data
+fn sample_receipt() -> ReceiptData {
+    serde_json::from_value(serde_json::json!({
+        "title": "Twizigamire SACCO",
+        "items": [
+            { "label": "Member", "value": "Hélène Uwase" },
+            { "label": "Account", "value": "TWZ-00421" },
+            { "label": "Monthly savings contribution", "value": "RWF 15,000" },
+        ],
+        "total": "RWF 15,000",
+        "footer": "Murakoze",
+        "verification": {
+            "payment_id": "2f9c1e7a-5b1d-4c8e-9a3f-0d6e8b7c4a21",
+            "reference_token": "TWZ-7F3K9Q",
+        },
+        "receipt_number": "TWZ-000123",
+        "fields": {
+            "amount": 15000,
+            "paid_at": "2024-03-15T08:30:00Z",
+            "teller": "Jean Bosco",
+        },
+    }))
+    .expect("sample receipt is valid")
+}
+
+/// Fetch all receipt templates the signed-in user can see and store the
+/// valid ones
+///
+/// Templates with errors are reported and not stored, so the SACCO keeps
+/// printing with its previous template until the template is fixed.
+#[tauri::command]
+pub async fn sync_receipt_templates(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, TemplateCache>,
+) -> Result<Vec<TemplateSyncResult>> {
+    let credentials = super::auth::require_credentials().await?;
+
+    let client = reqwest::Client::builder()
+        .user_agent("SACCO+ Staff Admin")
+        .timeout(std::time::Duration::from_secs(30))
+        .build()
+        .context("Failed to create HTTP client")?;
+
+    let response = client
+        .get(RECEIPT_TEMPLATES_URL)
+        .bearer_auth(&credentials.access_token)
+        .send()
+        .await
+        .context("Failed to sync receipt templates")?;
+    if !response.status().is_success() {
+        return Err(AppError::http_status(response.status()).with_detail(RECEIPT_TEMPLATES_URL));
+    }
+    let templates: Vec<ReceiptTemplate> =
+        response.json().await.context("Failed to parse response")?;
+
+    let dir = templates_dir(&app_handle)?;
+    let mut results = Vec::with_capacity(templates.len());
+    for template in &templates {
+        let errors = match Template::parse(&template.source) {
+            Ok(_) => {
+                store_template(&dir, template)?;
+                Vec::new()
+            }
+            Err(errors) => errors,
+        };
+        results.push(TemplateSyncResult {
+            sacco_id: template.sacco_id,
+            updated_at: template.updated_at,
+            errors,
+        });
+    }
+
+    let synced: Vec<Uuid> = templates.iter().map(|t| t.sacco_id).collect();
+    prune_templates(&dir, &synced)?;
+    state.templates.lock()?.clear();
+
+    Ok(results)
+}
+
+/// The stored template for a SACCO, if it has one
+#[tauri::command]
+pub async fn get_receipt_template(
+    app_handle: tauri::AppHandle,
+    sacco_id: Uuid,
+) -> Result<Option<ReceiptTemplate>> {
+    read_template(&templates_dir(&app_handle)?, sacco_id)
+}
+
+/// The built-in receipt layout, as a starting point for editing
+#[tauri::command]
+pub async fn get_default_receipt_template() -> Result<String> {
+    Ok(DEFAULT_TEMPLATE.to_string())
+}
+
+/// Check a template, returning every error found (empty when valid)
+#[tauri::command]
+pub async fn validate_receipt_template(source: String) -> Result<Vec<TemplateError>> {
+    Ok(Template::parse(&source).err().unwrap_or_default())
+}
+
+/// Render a template as text and as a PNG image of the printed receipt
+///
+/// Uses a sample receipt when `receipt_data` is not given. The logo's place
+/// is marked with a frame rather than drawn.
+#[tauri::command]
+pub async fn preview_receipt_template(
+    source: String,
+    receipt_data: Option<ReceiptData>,
+    profile: Option<PrinterProfile>,
+) -> Result<TemplatePreview> {
+    render_preview(
+        &source,
+        &receipt_data.unwrap_or_else(sample_receipt),
+        profile.unwrap_or_default(),
+    )
+}
+
+/// A framed box printed where the SACCO's logo would go
+fn placeholder_logo() -> Bitmap {
+    let (width, height, border) = (240, 80, 3);
+    let mut bitmap = Bitmap::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let edge = x < border || y < border || x >= width - border || y >= height - border;
+            bitmap.set(x, y, edge);
+        }
+    }
+    bitmap
+}
+
+fn render_preview(
+    source: &str,
+    receipt_data: &ReceiptData,
+    profile: PrinterProfile,
+) -> Result<TemplatePreview> {
+    let template = parse_template(&ReceiptTemplate {
+        sacco_id: Uuid::nil(),
+        source: source.to_string(),
+        updated_at: Utc::now(),
+    })?;
+
+    let data = receipt_data.template_data(true)?;
+    let paper = profile.paper_width;
+    let lines = template.render_text(&data, paper)?;
+    let bytes = template.render(&data, profile, Some(&placeholder_logo()))?;
+    let png = emulator::emulate(&bytes, paper)?.png()?;
+    let text = lines
+        .iter()
+        .map(|line| line.text.as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Ok(TemplatePreview {
+        text,
+        lines,
+        png: general_purpose::STANDARD.encode(png),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::escpos::PaperWidth;
+
+    #[test]
+    fn test_store_and_prune_templates() {
+        let dir = std::env::temp_dir().join(format!("template-test-{}", Uuid::new_v4()));
+        let kept = Uuid::new_v4();
+        let dropped = Uuid::new_v4();
+        assert!(read_template(&dir, kept).unwrap().is_none());
+
+        for sacco_id in [kept, dropped] {
+            let template = ReceiptTemplate {
+                sacco_id,
+                source: DEFAULT_TEMPLATE.to_string(),
+                updated_at: Utc::now(),
+            };
+            store_template(&dir, &template).unwrap();
+        }
+        let stored = read_template(&dir, kept).unwrap().unwrap();
+        assert_eq!(stored.source, DEFAULT_TEMPLATE);
+        assert!(parse_template(&stored).is_ok());
+
+        prune_templates(&dir, &[kept]).unwrap();
+        assert!(read_template(&dir, kept).unwrap().is_some());
+        assert!(read_template(&dir, dropped).unwrap().is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_preview_sample_receipt() {
+        let preview =
+            render_preview(DEFAULT_TEMPLATE, &sample_receipt(), PaperWidth::Mm58.into()).unwrap();
+        assert!(preview.text.contains("Twizigamire SACCO"));
+        assert!(preview.text.contains("TWZ-000123"));
+        assert!(!preview.png.is_empty());
+
+        let error = render_preview(
+            "@each items\n{{ label }}\n",
+            &sample_receipt(),
+            PaperWidth::Mm58.into(),
+        )
+        .unwrap_err();
+        assert!(error.detail.unwrap().contains("line"));
+    }
+}
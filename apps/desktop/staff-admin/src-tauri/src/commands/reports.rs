@@ -0,0 +1,80 @@
+// Offline member statements and reports
+//
+// Rendered on the device from the frontend's cached rows (see `pdf::reports`)
+// and either saved as a PDF or printed through the job queue.
+
+use chrono::Local;
+use std::sync::Arc;
+
+use super::branding;
+use super::print::DocumentOptions;
+use super::print_jobs::{self, PrintDocument, PrintJob};
+use crate::error::{Context, Result};
+use crate::pdf::{self, layout::Picture, reports, reports::Branding, reports::Report};
+
+/// Lay out and render a report with the SACCO's cached logo
+pub fn report_pdf(
+    app_handle: &tauri::AppHandle,
+    report: &Report,
+    branding: &Branding,
+    options: &DocumentOptions,
+) -> Result<Vec<u8>> {
+    options.validate()?;
+
+    // A missing or broken logo must never stop a statement from printing
+    let logo = branding.sacco_id.and_then(|sacco_id| {
+        branding::logo_image(app_handle, sacco_id).unwrap_or_else(|e| {
+            eprintln!("Failed to load report logo: {}", e);
+            None
+        })
+    });
+    let logo = logo.map(|image| Arc::new(Picture::from_image(&image, reports::LOGO_PIXELS)));
+
+    let pages = reports::pages(
+        report,
+        branding,
+        logo,
+        &options.page_setup(),
+        Local::now().naive_local(),
+    );
+    let pages = pdf::select_pages(pages, options.page_range.as_deref())?;
+    pdf::render(&pages, report.title())
+}
+
+/// Save a member statement or report as a PDF file
+#[tauri::command]
+pub async fn export_report(
+    app_handle: tauri::AppHandle,
+    report: Report,
+    branding: Branding,
+    file_path: String,
+    options: DocumentOptions,
+) -> Result<()> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let pdf = report_pdf(&app_handle, &report, &branding, &options)?;
+        std::fs::write(&file_path, pdf).context("Failed to save PDF")
+    })
+    .await?
+}
+
+/// Print a member statement or report
+#[tauri::command]
+pub async fn print_report(
+    app_handle: tauri::AppHandle,
+    printer_id: String,
+    report: Report,
+    branding: Branding,
+    options: DocumentOptions,
+) -> Result<PrintJob> {
+    options.validate()?;
+    print_jobs::submit(
+        &app_handle,
+        printer_id,
+        PrintDocument::Report {
+            report,
+            branding,
+            options,
+        },
+        None,
+    )
+}
@@ -0,0 +1,500 @@
+// Serial (USB-CDC / RS-232) peripherals: barcode scanners, and the port
+// settings shared with cash drawers and customer displays
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{Emitter, Manager, WebviewWindow};
+
+use super::hardware::ScanResult;
+use crate::error::{AppError, Context, ErrorCode, Result};
+
+/// How often the read loop wakes up to check for a stop request
+const READ_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const MAX_FRAME_LEN: usize = 4096;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SerialPortEntry {
+    pub name: String,
+    /// "usb", "pci", "bluetooth" or "unknown"
+    pub port_type: String,
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    pub serial_number: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Parity {
+    #[default]
+    None,
+    Odd,
+    Even,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FlowControl {
+    #[default]
+    None,
+    Software,
+    Hardware,
+}
+
+/// Byte sequence that ends one scan
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Terminator {
+    #[default]
+    Cr,
+    Lf,
+    CrLf,
+    Tab,
+    /// ASCII ETX (0x03), used by some scanners in STX/ETX framing mode
+    Etx,
+    /// No terminator; a scan ends when the line goes idle
+    None,
+}
+
+impl Terminator {
+    fn bytes(self) -> &'static [u8] {
+        match self {
+            Terminator::Cr => b"\r",
+            Terminator::Lf => b"\n",
+            Terminator::CrLf => b"\r\n",
+            Terminator::Tab => b"\t",
+            Terminator::Etx => b"\x03",
+            Terminator::None => b"",
+        }
+    }
+}
+
+fn default_baud_rate() -> u32 {
+    9600
+}
+
+fn default_data_bits() -> u8 {
+    8
+}
+
+fn default_stop_bits() -> u8 {
+    1
+}
+
+fn default_idle_timeout_ms() -> u64 {
+    50
+}
+
+fn default_scan_type() -> String {
+    "SERIAL".to_string()
+}
+
+/// Per-device serial settings, stored by the frontend and passed in on start
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SerialDeviceProfile {
+    pub port: String,
+    #[serde(default = "default_baud_rate")]
+    pub baud_rate: u32,
+    #[serde(default = "default_data_bits")]
+    pub data_bits: u8,
+    #[serde(default)]
+    pub parity: Parity,
+    #[serde(default = "default_stop_bits")]
+    pub stop_bits: u8,
+    #[serde(default)]
+    pub flow_control: FlowControl,
+    #[serde(default)]
+    pub terminator: Terminator,
+    /// Flush a partial scan after this much silence on the line
+    #[serde(default = "default_idle_timeout_ms")]
+    pub idle_timeout_ms: u64,
+    /// Reported as `ScanResult.scan_type`, since serial scanners don't say
+    #[serde(default = "default_scan_type")]
+    pub scan_type: String,
+}
+
+/// A running serial read loop
+pub struct SerialReader {
+    stop: Arc<AtomicBool>,
+    /// Resolves once the port is closed, or failed to open; there from the
+    /// start so stopping always waits for it
+    closed: tokio::sync::oneshot::Receiver<()>,
+}
+
+/// Running serial read loops, keyed by port name
+#[derive(Clone, Default)]
+pub struct SerialState {
+    pub readers: Arc<Mutex<HashMap<String, SerialReader>>>,
+}
+
+impl SerialDeviceProfile {
+    pub fn open(&self) -> Result<Box<dyn serialport::SerialPort>> {
+        let data_bits = match self.data_bits {
+            5 => serialport::DataBits::Five,
+            6 => serialport::DataBits::Six,
+            7 => serialport::DataBits::Seven,
+            8 => serialport::DataBits::Eight,
+            n => {
+                return Err(AppError::invalid_input(format!(
+                    "Unsupported data bits: {}",
+                    n
+                )))
+            }
+        };
+
+        let stop_bits = match self.stop_bits {
+            1 => serialport::StopBits::One,
+            2 => serialport::StopBits::Two,
+            n => {
+                return Err(AppError::invalid_input(format!(
+                    "Unsupported stop bits: {}",
+                    n
+                )))
+            }
+        };
+
+        let parity = match self.parity {
+            Parity::None => serialport::Parity::None,
+            Parity::Odd => serialport::Parity::Odd,
+            Parity::Even => serialport::Parity::Even,
+        };
+
+        let flow_control = match self.flow_control {
+            FlowControl::None => serialport::FlowControl::None,
+            FlowControl::Software => serialport::FlowControl::Software,
+            FlowControl::Hardware => serialport::FlowControl::Hardware,
+        };
+
+        serialport::new(&self.port, self.baud_rate)
+            .data_bits(data_bits)
+            .stop_bits(stop_bits)
+            .parity(parity)
+            .flow_control(flow_control)
+            .timeout(READ_POLL_INTERVAL)
+            .open()
+            .context(format!("Failed to open {}", self.port))
+    }
+}
+
+/// Splits the incoming byte stream into scans
+struct FrameDecoder {
+    terminator: &'static [u8],
+    idle_timeout: Duration,
+    buffer: Vec<u8>,
+    last_byte_at: Option<Instant>,
+}
+
+impl FrameDecoder {
+    fn new(terminator: Terminator, idle_timeout: Duration) -> Self {
+        Self {
+            terminator: terminator.bytes(),
+            idle_timeout,
+            buffer: Vec::new(),
+            last_byte_at: None,
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8], now: Instant) -> Vec<String> {
+        let mut frames = Vec::new();
+        self.last_byte_at = Some(now);
+
+        for &byte in bytes {
+            // STX starts a fresh frame in STX/ETX mode
+            if byte == 0x02 && self.terminator == b"\x03" {
+                self.buffer.clear();
+                continue;
+            }
+
+            self.buffer.push(byte);
+            if !self.terminator.is_empty() && self.buffer.ends_with(self.terminator) {
+                self.buffer
+                    .truncate(self.buffer.len() - self.terminator.len());
+                frames.extend(self.take());
+            } else if self.buffer.len() >= MAX_FRAME_LEN {
+                // Line noise or a wrong terminator setting; don't grow forever
+                self.buffer.clear();
+            }
+        }
+
+        frames
+    }
+
+    /// Flush a partial scan once the line has been idle long enough
+    fn poll_idle(&mut self, now: Instant) -> Option<String> {
+        let last = self.last_byte_at?;
+        if self.buffer.is_empty() || now.duration_since(last) < self.idle_timeout {
+            return None;
+        }
+        self.take()
+    }
+
+    fn take(&mut self) -> Option<String> {
+        let data = String::from_utf8_lossy(&self.buffer).trim().to_string();
+        self.buffer.clear();
+        (!data.is_empty()).then_some(data)
+    }
+}
+
+/// Read scans until `stop` is set or the port fails
+///
+/// The reader must time out periodically (as serial ports opened by
+/// [`SerialDeviceProfile::open`] do) so the stop flag is noticed.
+fn run_read_loop<R: Read + ?Sized>(
+    reader: &mut R,
+    terminator: Terminator,
+    idle_timeout: Duration,
+    stop: &AtomicBool,
+    mut on_scan: impl FnMut(String),
+) -> Result<()> {
+    let mut decoder = FrameDecoder::new(terminator, idle_timeout);
+    let mut buf = [0u8; 256];
+
+    while !stop.load(Ordering::SeqCst) {
+        match reader.read(&mut buf) {
+            Ok(0) => return Err(AppError::new(ErrorCode::DeviceNotFound, "Serial port closed")),
+            Ok(n) => decoder
+                .push(&buf[..n], Instant::now())
+                .into_iter()
+                .for_each(&mut on_scan),
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {}
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => {
+                return Err(AppError::new(ErrorCode::DeviceError, "Serial read error").with_detail(e))
+            }
+        }
+
+        if let Some(data) = decoder.poll_idle(Instant::now()) {
+            on_scan(data);
+        }
+    }
+
+    Ok(())
+}
+
+/// List serial ports present on this machine
+#[tauri::command]
+pub async fn list_serial_ports() -> Result<Vec<SerialPortEntry>> {
+    let ports = serialport::available_ports()
+        .context("Failed to enumerate serial ports")?;
+
+    Ok(ports
+        .into_iter()
+        .map(|port| {
+            let mut entry = SerialPortEntry {
+                name: port.port_name,
+                port_type: "unknown".to_string(),
+                vid: None,
+                pid: None,
+                serial_number: None,
+                manufacturer: None,
+                product: None,
+            };
+
+            match port.port_type {
+                serialport::SerialPortType::UsbPort(usb) => {
+                    entry.port_type = "usb".to_string();
+                    entry.vid = Some(usb.vid);
+                    entry.pid = Some(usb.pid);
+                    entry.serial_number = usb.serial_number;
+                    entry.manufacturer = usb.manufacturer;
+                    entry.product = usb.product;
+                }
+                serialport::SerialPortType::PciPort => entry.port_type = "pci".to_string(),
+                serialport::SerialPortType::BluetoothPort => {
+                    entry.port_type = "bluetooth".to_string()
+                }
+                serialport::SerialPortType::Unknown => {}
+            }
+
+            entry
+        })
+        .collect())
+}
+
+/// Start reading scans from a serial scanner
+///
+/// Each scan is emitted as `barcode-scanned`, exactly like the HID scanner.
+/// A `serial-scanner-stopped` event carrying the port name is emitted if the
+/// port goes away.
+#[tauri::command]
+pub async fn start_serial_scanner(
+    window: WebviewWindow,
+    state: tauri::State<'_, SerialState>,
+    profile: SerialDeviceProfile,
+) -> Result<()> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let (closed_tx, closed) = tokio::sync::oneshot::channel();
+    {
+        let mut readers = state.readers.lock()?;
+
+        if readers.contains_key(&profile.port) {
+            return Err(AppError::new(
+                ErrorCode::DeviceBusy,
+                format!("Scanner on {} already active", profile.port),
+            ));
+        }
+        readers.insert(
+            profile.port.clone(),
+            SerialReader {
+                stop: stop.clone(),
+                closed,
+            },
+        );
+    }
+
+    let mut port = match profile.open() {
+        Ok(port) => port,
+        Err(e) => {
+            if let Ok(mut readers) = state.readers.lock() {
+                readers.remove(&profile.port);
+            }
+            return Err(e);
+        }
+    };
+
+    let readers = state.readers.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let app_handle = window.app_handle().clone();
+        let result = run_read_loop(
+            &mut port,
+            profile.terminator,
+            Duration::from_millis(profile.idle_timeout_ms),
+            &stop,
+            |data| {
+                let _ = window.emit(
+                    "barcode-scanned",
+                    ScanResult::new(&app_handle, data, &profile.scan_type),
+                );
+            },
+        );
+
+        drop(port);
+        let _ = closed_tx.send(());
+
+        if let Err(e) = result {
+            eprintln!("Serial scanner on {} stopped: {}", profile.port, e);
+            let _ = window.emit("serial-scanner-stopped", &profile.port);
+        }
+
+        // A stopped reader may already have been replaced by a new one
+        if let Ok(mut readers) = readers.lock() {
+            if readers
+                .get(&profile.port)
+                .is_some_and(|reader| Arc::ptr_eq(&reader.stop, &stop))
+            {
+                readers.remove(&profile.port);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Stop reading from a serial scanner
+///
+/// Returns once the port is closed, so the scanner can be started again
+/// straight away.
+#[tauri::command]
+pub async fn stop_serial_scanner(
+    state: tauri::State<'_, SerialState>,
+    port: String,
+) -> Result<()> {
+    let reader = state.readers.lock()?.remove(&port);
+
+    if let Some(reader) = reader {
+        reader.stop.store(true, Ordering::SeqCst);
+        // Dropped without sending if the port never opened
+        let _ = reader.closed.await;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_decoder_terminators() {
+        let now = Instant::now();
+        let mut decoder = FrameDecoder::new(Terminator::CrLf, Duration::from_millis(50));
+        assert!(decoder.push(b"RCP-0001\r", now).is_empty());
+        assert_eq!(
+            decoder.push(b"\nRCP-0002\r\n", now),
+            vec!["RCP-0001", "RCP-0002"]
+        );
+
+        let mut decoder = FrameDecoder::new(Terminator::Etx, Duration::from_millis(50));
+        assert_eq!(
+            decoder.push(b"noise\x02IBC1:abc\x03", now),
+            vec!["IBC1:abc"]
+        );
+    }
+
+    #[test]
+    fn test_frame_decoder_idle_flush() {
+        let start = Instant::now();
+        let mut decoder = FrameDecoder::new(Terminator::None, Duration::from_millis(50));
+        assert!(decoder.push(b"4006381333931", start).is_empty());
+        assert_eq!(decoder.poll_idle(start + Duration::from_millis(10)), None);
+        assert_eq!(
+            decoder.poll_idle(start + Duration::from_millis(60)),
+            Some("4006381333931".to_string())
+        );
+        assert_eq!(decoder.poll_idle(start + Duration::from_millis(120)), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_read_loop_over_pty() {
+        use serialport::{SerialPort, TTYPort};
+        use std::io::Write;
+
+        // The pty slave stands in for the scanner's port; we type into the master
+        let (mut master, slave) = TTYPort::pair().expect("Failed to open pty pair");
+        let profile = SerialDeviceProfile {
+            port: slave.name().expect("pty has a name"),
+            baud_rate: 9600,
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: 1,
+            flow_control: FlowControl::None,
+            terminator: Terminator::Cr,
+            idle_timeout_ms: 50,
+            scan_type: "CODE128".to_string(),
+        };
+        let mut port = profile.open().expect("Failed to open pty slave");
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_reader = stop.clone();
+        let reader = std::thread::spawn(move || {
+            let mut scans = Vec::new();
+            let result = run_read_loop(
+                &mut port,
+                profile.terminator,
+                Duration::from_millis(profile.idle_timeout_ms),
+                &stop_reader,
+                |data| {
+                    scans.push(data);
+                    if scans.len() == 2 {
+                        stop_reader.store(true, Ordering::SeqCst);
+                    }
+                },
+            );
+            (result, scans)
+        });
+
+        master.write_all(b"RCP-000123\r").unwrap();
+        master.write_all(b"*182*8*1*048321#\r").unwrap();
+        master.flush().unwrap();
+
+        let (result, scans) = reader.join().unwrap();
+        stop.store(true, Ordering::SeqCst);
+        assert_eq!(result, Ok(()));
+        assert_eq!(scans, vec!["RCP-000123", "*182*8*1*048321#"]);
+    }
+}
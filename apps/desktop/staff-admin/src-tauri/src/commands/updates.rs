@@ -1,15 +1,83 @@
+// Desktop update checks, downloads and installs
+//
+// Versions are compared by SemVer precedence, so a downgrade, a re-tag or a
+// build-metadata-only difference never prompts an update. The release a
+// device is offered comes from the signed update manifest (see
+// `crate::updater::manifest`). Installers must match the SHA-256 checksum and
+// minisign signature published with them before they are kept or run.
+//
+// Copies of a release on a folder or LAN mirror (see
+// `crate::updater::sources`) are checked alongside the release server, and
+// the newest release any of them offers wins. Installers in a folder are
+// copied rather than downloaded, with the same checks.
+
+use chrono::{DateTime, Utc};
 use futures::StreamExt;
+use minisign_verify::PublicKey;
+use semver::Version;
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use std::io::Write;
-use tauri::{Emitter, WebviewWindow};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{Emitter, Manager, WebviewWindow};
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+use crate::error::{AppError, Context, ErrorCode, Result};
+use crate::updater::health::{self, LaunchHealth, RollbackReport};
+pub use crate::updater::manifest::UpdateChannel;
+use crate::updater::manifest::{self, PlatformAsset, UpdateManifest};
+use crate::updater::sources;
+pub use crate::updater::sources::UpdateSource;
+use crate::updater::verify::{self, verification_failed};
+
+const MANIFEST_URL: &str = "https://releases.ibimina.rw/staff-admin/manifest.json";
+/// Detached minisign signature and SHA-256 checksum, published beside each
+/// installer asset
+const SIGNATURE_SUFFIX: &str = ".sig";
+const CHECKSUM_SUFFIX: &str = ".sha256";
+const PREFERENCES_FILE: &str = "update_preferences.json";
+/// In the health dir, when the newest manifest used was published
+const ACCEPTED_MANIFEST_FILE: &str = "accepted_manifest.json";
+/// Launch health and kept installers, under the app data dir
+const HEALTH_DIR: &str = "updates";
+/// Verified downloads, and private copies of installers about to run, under
+/// `HEALTH_DIR`
+const DOWNLOADS_DIR: &str = "downloads";
+const RUN_DIR: &str = "run";
+const ROLLBACK_REPORTS_URL: &str = "https://api.ibimina.rw/desktop-rollbacks";
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+/// Free space to leave beyond the download, for the installer to unpack
+const DISK_HEADROOM: u64 = 200_000_000;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UpdateInfo {
     pub current_version: String,
     pub latest_version: String,
     pub update_available: bool,
+    /// Running a version older than the minimum supported one; the update
+    /// can't be put off
+    #[serde(default)]
+    pub required: bool,
+    /// The device's channel, after any assignment in the manifest
+    #[serde(default)]
+    pub channel: UpdateChannel,
+    /// Running a newer version than the channel's latest release, e.g. a
+    /// beta build after switching back to stable
+    #[serde(default)]
+    pub ahead_of_latest: bool,
+    /// The latest version is a pre-release
+    #[serde(default)]
+    pub prerelease: bool,
     pub release_notes: String,
     pub download_url: String,
+    /// SHA-256 digest and size the manifest pins for the installer
+    #[serde(default)]
+    pub sha256: String,
+    #[serde(default)]
+    pub size: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -19,181 +87,1955 @@ pub struct DownloadProgress {
     pub percentage: f64,
 }
 
-/// Check for updates from GitHub releases
+/// Update settings chosen on this computer
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct UpdatePreferences {
+    /// Used unless the manifest assigns this device or its SACCO a channel
+    #[serde(default)]
+    pub channel: Option<UpdateChannel>,
+    /// SACCO this computer works for, for SACCO-wide channel assignments
+    #[serde(default)]
+    pub sacco_id: Option<Uuid>,
+    /// Copies of releases to check before the release server, e.g. a USB
+    /// drive or a mirror on the branch network
+    #[serde(default)]
+    pub sources: Vec<UpdateSource>,
+}
+
+fn preferences_path(app_handle: &tauri::AppHandle) -> Result<PathBuf> {
+    let dir = app_handle
+        .path()
+        .app_data_dir()
+        .context("Failed to resolve app data dir")?;
+    Ok(dir.join(PREFERENCES_FILE))
+}
+
+fn read_preferences(path: &Path) -> Result<UpdatePreferences> {
+    let json = match std::fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Default::default()),
+        Err(e) => return Err(e).context("Failed to read update preferences"),
+    };
+    serde_json::from_str(&json).context("Failed to parse update preferences")
+}
+
+fn write_preferences(path: &Path, preferences: &UpdatePreferences) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).context("Failed to create app data dir")?;
+    }
+    let json =
+        serde_json::to_string(preferences).context("Failed to serialize update preferences")?;
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::write(&tmp_path, json).context("Failed to write update preferences")?;
+    std::fs::rename(&tmp_path, path).context("Failed to write update preferences")
+}
+
+#[tauri::command]
+pub async fn get_update_preferences(app_handle: tauri::AppHandle) -> Result<UpdatePreferences> {
+    read_preferences(&preferences_path(&app_handle)?)
+}
+
+/// Save this computer's update channel and SACCO, used by every later check
+/// including the one at startup
+#[tauri::command]
+pub async fn set_update_preferences(
+    app_handle: tauri::AppHandle,
+    preferences: UpdatePreferences,
+) -> Result<()> {
+    write_preferences(&preferences_path(&app_handle)?, &preferences)
+}
+
+/// The newest manifest this computer has used
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct AcceptedManifest {
+    published_at: Option<DateTime<Utc>>,
+}
+
+fn read_accepted(dir: &Path) -> Result<Option<DateTime<Utc>>> {
+    let json = match std::fs::read_to_string(dir.join(ACCEPTED_MANIFEST_FILE)) {
+        Ok(json) => json,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).context("Failed to read accepted update manifest"),
+    };
+    let accepted: AcceptedManifest =
+        serde_json::from_str(&json).context("Failed to parse accepted update manifest")?;
+    Ok(accepted.published_at)
+}
+
+fn write_accepted(dir: &Path, published_at: DateTime<Utc>) -> Result<()> {
+    let json = serde_json::to_string(&AcceptedManifest {
+        published_at: Some(published_at),
+    })
+    .context("Failed to serialize accepted update manifest")?;
+    let path = dir.join(ACCEPTED_MANIFEST_FILE);
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::write(&tmp_path, json).context("Failed to write accepted update manifest")?;
+    std::fs::rename(&tmp_path, &path).context("Failed to write accepted update manifest")
+}
+
+/// Fetch the update manifest and check its signature, that it hasn't
+/// expired and that it isn't older than `newest_accepted`
+async fn fetch_manifest(
+    client: &reqwest::Client,
+    manifest_url: &str,
+    public_key: &PublicKey,
+    newest_accepted: Option<DateTime<Utc>>,
+) -> Result<UpdateManifest> {
+    let idle = DownloadLimits::default().idle;
+    let signature = fetch_text(
+        client,
+        &format!("{}{}", manifest_url, SIGNATURE_SUFFIX),
+        idle,
+    )
+    .await?;
+    let manifest = fetch_text(client, manifest_url, idle).await?;
+    verify::verify_signature(public_key, manifest.as_bytes(), &signature)?;
+    let manifest: UpdateManifest =
+        serde_json::from_str(&manifest).context("Failed to parse update manifest")?;
+    if manifest.is_expired(Utc::now()) {
+        return Err(verification_failed(format!(
+            "The update manifest expired at {}",
+            manifest.expires
+        )));
+    }
+    if let Some(newest) = newest_accepted.filter(|newest| manifest.published_at < *newest) {
+        return Err(verification_failed(format!(
+            "The update manifest was published at {}, before the one already used from {}",
+            manifest.published_at, newest
+        )));
+    }
+    Ok(manifest)
+}
+
+/// Check the update manifests for a newer release
+///
+/// `channel` overrides the channel saved in the update preferences; either is
+/// used only when a manifest doesn't assign this device or its SACCO one.
+/// The preferences' sources are checked first and the release server last;
+/// any that can't be reached, or only have a manifest older than one already
+/// used, are skipped as long as one can.
 #[tauri::command]
-pub async fn check_for_updates(app_handle: tauri::AppHandle) -> Result<UpdateInfo, String> {
-    let current_version = app_handle.package_info().version.to_string();
+pub async fn check_for_updates(
+    app_handle: tauri::AppHandle,
+    channel: Option<UpdateChannel>,
+) -> Result<UpdateInfo> {
+    let current = app_handle.package_info().version.clone();
+    let public_key = verify::updater_public_key(&app_handle)?;
+    let preferences = read_preferences(&preferences_path(&app_handle)?)?;
+    let device_id = super::auth::get_device_id().await?;
+    let dir = health_dir(&app_handle)?;
+    create_private_dir(&dir)?;
+    let accepted = read_accepted(&dir)?;
+    let mut newest = accepted;
 
-    // Fetch latest release from GitHub API
     let client = reqwest::Client::builder()
         .user_agent("SACCO+ Staff Admin")
+        .timeout(std::time::Duration::from_secs(30))
         .build()
-        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+        .context("Failed to create HTTP client")?;
 
-    let response = client
-        .get("https://api.github.com/repos/ikanisa/ibimina/releases/latest")
-        .send()
-        .await
-        .map_err(|e| format!("Failed to fetch releases: {}", e))?;
+    let mut offers = Vec::new();
+    let mut last_error = None;
+    let sources = preferences.sources.iter().map(Some).chain([None]);
+    for source in sources {
+        let offer = async {
+            let manifest_url = match source {
+                Some(source) => source.manifest_url()?,
+                None => MANIFEST_URL.to_string(),
+            };
+            let manifest = fetch_manifest(&client, &manifest_url, &public_key, accepted).await?;
+            let channel = manifest.channel_for(
+                &device_id,
+                preferences.sacco_id,
+                channel.or(preferences.channel),
+            );
+            let mut info = update_info(
+                &manifest,
+                channel,
+                &current,
+                &device_id,
+                &manifest::platform_key(),
+            )?;
+            if let Some(source) = source.filter(|_| !info.download_url.is_empty()) {
+                info.download_url = source.asset_url(&info.download_url)?;
+            }
+            Ok::<_, AppError>((info, manifest.published_at))
+        };
+        match offer.await {
+            Ok((info, published_at)) => {
+                newest = newest.max(Some(published_at));
+                offers.push(info);
+            }
+            Err(e) => {
+                match source {
+                    Some(source) => eprintln!("Update source {} unavailable: {}", source, e),
+                    None => eprintln!("Release server unavailable: {}", e),
+                }
+                last_error = Some(e);
+            }
+        }
+    }
 
-    if !response.status().is_success() {
-        return Err(format!("GitHub API returned status: {}", response.status()));
+    if let Some(published_at) = newest.filter(|_| newest > accepted) {
+        write_accepted(&dir, published_at)?;
     }
+    let info = best_offer(offers)
+        .ok_or_else(|| last_error.expect("the release server is always checked"))?;
+    app_handle.state::<UpdateDownload>().offer(&info)?;
+    Ok(info)
+}
 
-    let release: serde_json::Value = response
-        .json()
-        .await
-        .map_err(|e| format!("Failed to parse response: {}", e))?;
+/// The offer to show from several sources: an available update over none,
+/// then the newest release, then the earliest source, so a local copy wins
+/// over the release server for the same release
+fn best_offer(offers: Vec<UpdateInfo>) -> Option<UpdateInfo> {
+    let mut best: Option<(UpdateInfo, Version)> = None;
+    for info in offers {
+        let Ok(version) = Version::parse(&info.latest_version) else {
+            continue;
+        };
+        let better = best.as_ref().is_none_or(|(best, best_version)| {
+            info.update_available
+                .cmp(&best.update_available)
+                .then_with(|| version.cmp_precedence(best_version))
+                == Ordering::Greater
+        });
+        if better {
+            best = Some((info, version));
+        }
+    }
+    best.map(|(info, _)| info)
+}
 
-    let latest_version = release["tag_name"]
-        .as_str()
-        .unwrap_or("")
-        .trim_start_matches('v')
-        .to_string();
+/// What the manifest offers this device; a release still being rolled out
+/// is offered only once the rollout reaches the device, unless the running
+/// version is no longer supported
+fn update_info(
+    manifest: &UpdateManifest,
+    channel: UpdateChannel,
+    current: &Version,
+    device_id: &str,
+    platform: &str,
+) -> Result<UpdateInfo> {
+    let release = manifest.release_for(channel).ok_or_else(|| {
+        AppError::new(
+            ErrorCode::InvalidResponse,
+            "No releases found for this update channel",
+        )
+    })?;
+    let order = release.version.cmp_precedence(current);
+    let required = manifest.requires_update(current);
+    let asset = release.asset(platform);
+    let download_url = asset.map(|asset| asset.url.clone()).unwrap_or_default();
 
-    let release_notes = release["body"].as_str().unwrap_or("").to_string();
+    Ok(UpdateInfo {
+        current_version: current.to_string(),
+        latest_version: release.version.to_string(),
+        update_available: order == Ordering::Greater
+            && !download_url.is_empty()
+            && (required || release.rolled_out_to(device_id)),
+        required,
+        channel,
+        ahead_of_latest: order == Ordering::Less,
+        prerelease: !release.version.pre.is_empty(),
+        release_notes: release.notes.clone(),
+        download_url,
+        sha256: asset.map(|asset| asset.sha256.clone()).unwrap_or_default(),
+        size: asset.map_or(0, |asset| asset.size),
+    })
+}
 
-    // Find download URL for current platform
-    let empty_vec = vec![];
-    let assets = release["assets"].as_array().unwrap_or(&empty_vec);
-    let platform_asset = find_platform_asset(assets);
-    let download_url = platform_asset
-        .and_then(|a| a["browser_download_url"].as_str())
-        .unwrap_or("")
-        .to_string();
+/// Re-check a downloaded installer against the signature and checksum saved
+/// beside it; returns the bytes that were checked
+fn verify_downloaded(public_key: &PublicKey, installer_path: &Path) -> Result<Vec<u8>> {
+    let read_beside = |suffix: &str| {
+        let mut path = installer_path.as_os_str().to_owned();
+        path.push(suffix);
+        std::fs::read_to_string(path)
+            .map_err(|_| verification_failed("The installer was not downloaded by this app"))
+    };
+    let signature = read_beside(SIGNATURE_SUFFIX)?;
+    let checksum = read_beside(CHECKSUM_SUFFIX)?;
+    let installer = std::fs::read(installer_path).context("Failed to read installer")?;
+    verify::verify_installer(public_key, &installer, &signature, &checksum)?;
+    Ok(installer)
+}
 
-    let update_available = version_is_greater(&latest_version, &current_version);
+/// Create `dir` readable only by this user
+///
+/// On Windows the app data dir is already private to the user.
+fn create_private_dir(dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir).context("Failed to create update directory")?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))
+            .context("Failed to create update directory")?;
+    }
+    Ok(())
+}
 
-    Ok(UpdateInfo {
-        current_version,
-        latest_version,
-        update_available,
-        release_notes,
-        download_url,
+/// Verify an installer and write the verified bytes to a new file under
+/// `run_dir`, so what runs is exactly what was checked even if the original
+/// changes in between
+fn private_copy(public_key: &PublicKey, installer_path: &Path, run_dir: &Path) -> Result<PathBuf> {
+    let installer = verify_downloaded(public_key, installer_path)?;
+    let file_name = installer_path
+        .file_name()
+        .ok_or_else(|| AppError::invalid_input("Installer has no file name"))?;
+
+    create_private_dir(run_dir)?;
+    let dir = run_dir.join(Uuid::new_v4().to_string());
+    create_private_dir(&dir)?;
+    let path = dir.join(file_name);
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o700);
+    }
+    let mut file = options.open(&path).context("Failed to prepare installer")?;
+    file.write_all(&installer)
+        .context("Failed to prepare installer")?;
+    file.sync_all().context("Failed to prepare installer")?;
+    Ok(path)
+}
+
+/// Installer file name from its download URL, without any query string
+fn installer_file_name(download_url: &str) -> Result<String> {
+    if let Some(name) = sources::local_path(download_url)
+        .as_deref()
+        .and_then(Path::file_name)
+    {
+        return Ok(name.to_string_lossy().to_string());
+    }
+    let path = download_url.split(['?', '#']).next().unwrap_or("");
+    match path.rsplit('/').next() {
+        Some(name)
+            if !name.is_empty() && !name.contains(['\\', ':']) && name != "." && name != ".." =>
+        {
+            Ok(name.to_string())
+        }
+        _ => {
+            Err(AppError::invalid_input("Download URL has no file name").with_detail(download_url))
+        }
+    }
+}
+
+async fn fetch_text(client: &reqwest::Client, url: &str, idle: Duration) -> Result<String> {
+    if let Some(path) = sources::local_path(url) {
+        return match tokio::fs::read_to_string(&path).await {
+            Ok(text) => Ok(text),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(verification_failed(
+                format!("Not published: {}", path.display()),
+            )),
+            Err(e) => Err(e).context("Failed to read update file"),
+        };
+    }
+    let response = idle_timeout(idle, client.get(url).send())
+        .await?
+        .context("Download failed")?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(verification_failed(format!("Not published: {}", url)));
+    }
+    if !response.status().is_success() {
+        return Err(AppError::http_status(response.status()).with_detail(url));
+    }
+    idle_timeout(idle, response.text())
+        .await?
+        .context("Download error")
+}
+
+/// Time limits and retries for an installer download
+#[derive(Debug, Clone)]
+struct DownloadLimits {
+    /// The whole download, including retries
+    overall: Duration,
+    /// Longest wait for the next bytes from the server
+    idle: Duration,
+    attempts: u32,
+    retry_delay: Duration,
+}
+
+impl Default for DownloadLimits {
+    fn default() -> Self {
+        Self {
+            overall: Duration::from_secs(2 * 60 * 60),
+            idle: Duration::from_secs(60),
+            attempts: 5,
+            retry_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+async fn idle_timeout<T>(
+    idle: Duration,
+    future: impl std::future::Future<Output = T>,
+) -> Result<T> {
+    tokio::time::timeout(idle, future).await.map_err(|_| {
+        AppError::from_code(ErrorCode::NetworkTimeout)
+            .with_detail(format!("No data received for {}s", idle.as_secs()))
     })
 }
 
-fn version_is_greater(latest: &str, current: &str) -> bool {
-    // Simple version comparison (for production, use semver crate)
-    latest != current
+/// Limits progress events to one per `interval`, plus the final one
+struct ProgressThrottle {
+    interval: Duration,
+    last: Option<Instant>,
 }
 
-fn find_platform_asset(assets: &[serde_json::Value]) -> Option<&serde_json::Value> {
-    let platform_ext = if cfg!(target_os = "windows") {
-        ".msi"
-    } else if cfg!(target_os = "macos") {
-        ".dmg"
-    } else if cfg!(target_os = "linux") {
-        ".AppImage"
-    } else {
-        return None;
+impl ProgressThrottle {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last: None,
+        }
+    }
+
+    fn ready(&mut self, now: Instant, done: bool) -> bool {
+        let due = self
+            .last
+            .is_none_or(|last| now.duration_since(last) >= self.interval);
+        if due || done {
+            self.last = Some(now);
+        }
+        due || done
+    }
+}
+
+fn progress(downloaded: u64, total: u64) -> DownloadProgress {
+    DownloadProgress {
+        downloaded,
+        total,
+        percentage: if total > 0 {
+            (downloaded as f64 / total as f64) * 100.0
+        } else {
+            0.0
+        },
+    }
+}
+
+/// Free space on the volume holding `dir`
+#[cfg(unix)]
+fn available_space(dir: &Path) -> std::io::Result<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(dir.as_os_str().as_bytes())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    // SAFETY: statvfs is plain data, valid when zeroed; `path` is
+    // NUL-terminated and `stat` is a valid statvfs to fill
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // The field widths differ between platforms
+    #[allow(clippy::unnecessary_cast)]
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// Free space on the volume holding `dir`
+#[cfg(target_os = "windows")]
+fn available_space(dir: &Path) -> std::io::Result<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let path: Vec<u16> = dir.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut available = 0u64;
+    // SAFETY: `path` is NUL-terminated and `available` outlives the call
+    let ok = unsafe {
+        GetDiskFreeSpaceExW(
+            path.as_ptr(),
+            &mut available,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
     };
+    if ok == 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(available)
+}
 
-    assets
-        .iter()
-        .find(|asset| {
-            asset["name"]
-                .as_str()
-                .map(|name| name.ends_with(platform_ext))
-                .unwrap_or(false)
-        })
+/// Fail before writing if `needed` more bytes, plus some headroom for
+/// installing, won't fit in `dir`
+fn ensure_disk_space(dir: &Path, needed: u64) -> Result<()> {
+    let available = available_space(dir).context("Failed to check free disk space")?;
+    if available < needed.saturating_add(DISK_HEADROOM) {
+        return Err(AppError::new(
+            ErrorCode::StorageError,
+            "Not enough free disk space to download the update",
+        )
+        .with_detail(format!(
+            "{} MB needed, {} MB free in {}",
+            needed.saturating_add(DISK_HEADROOM) / 1_000_000,
+            available / 1_000_000,
+            dir.display()
+        )));
+    }
+    Ok(())
 }
 
-/// Download update with progress events
-#[tauri::command]
-pub async fn download_update(
-    window: WebviewWindow,
-    download_url: String,
-) -> Result<String, String> {
-    let client = reqwest::Client::new();
+/// First byte of a `Content-Range: bytes <start>-<end>/<size>` response
+fn content_range_start(headers: &reqwest::header::HeaderMap) -> Option<u64> {
+    headers
+        .get(reqwest::header::CONTENT_RANGE)?
+        .to_str()
+        .ok()?
+        .strip_prefix("bytes ")?
+        .split('-')
+        .next()?
+        .parse()
+        .ok()
+}
 
-    let response = client
-        .get(&download_url)
-        .send()
-        .await
-        .map_err(|e| format!("Download failed: {}", e))?;
+fn too_large(size: u64) -> AppError {
+    verification_failed(format!(
+        "The installer is larger than the {} bytes the manifest lists",
+        size
+    ))
+}
 
-    if !response.status().is_success() {
-        return Err(format!("Download failed with status: {}", response.status()));
+/// One attempt at completing `part_path`, resuming from its current length
+/// when the server supports ranges
+async fn download_part(
+    client: &reqwest::Client,
+    download_url: &str,
+    part_path: &Path,
+    size: u64,
+    idle: Duration,
+    throttle: &mut ProgressThrottle,
+    on_progress: &mut impl FnMut(DownloadProgress),
+) -> Result<()> {
+    let existing = std::fs::metadata(part_path).map(|m| m.len()).unwrap_or(0);
+
+    let mut request = client.get(download_url);
+    if existing > 0 {
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", existing));
+    }
+    let response = idle_timeout(idle, request.send())
+        .await?
+        .context("Download failed")?;
+
+    let status = response.status();
+    let resumed = status == reqwest::StatusCode::PARTIAL_CONTENT;
+    if status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE
+        || (resumed && content_range_start(response.headers()) != Some(existing))
+    {
+        // The partial file doesn't belong to this installer; start over
+        std::fs::remove_file(part_path).context("Failed to discard partial download")?;
+        return Err(AppError::from_code(ErrorCode::HttpError)
+            .with_detail("Partial download could not be resumed")
+            .with_retryable(true));
+    }
+    if !status.is_success() {
+        return Err(AppError::http_status(status).with_detail(download_url));
     }
 
-    let total_size = response.content_length().unwrap_or(0);
-    let mut downloaded: u64 = 0;
+    let mut downloaded = if resumed { existing } else { 0 };
+    let remaining = response.content_length().unwrap_or(0);
+    let total = if remaining > 0 {
+        downloaded + remaining
+    } else {
+        0
+    };
 
-    // Get temp directory for download
-    let temp_dir = std::env::temp_dir();
-    let filename = download_url.split('/').last().unwrap_or("update");
-    let file_path = temp_dir.join(filename);
+    let dir = part_path.parent().unwrap_or(Path::new("."));
+    ensure_disk_space(dir, remaining)?;
 
-    let mut file = std::fs::File::create(&file_path)
-        .map_err(|e| format!("Failed to create file: {}", e))?;
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(resumed)
+        .write(true)
+        .truncate(!resumed)
+        .open(part_path)
+        .context("Failed to create file")?;
 
     let mut stream = response.bytes_stream();
 
-    while let Some(chunk) = stream.next().await {
-        let chunk = chunk.map_err(|e| format!("Download error: {}", e))?;
-        file.write_all(&chunk)
-            .map_err(|e| format!("Write error: {}", e))?;
+    while let Some(chunk) = idle_timeout(idle, stream.next()).await? {
+        let chunk = chunk.map_err(|e| {
+            AppError::new(
+                ErrorCode::NetworkUnavailable,
+                "Connection lost during download",
+            )
+            .with_detail(e)
+        })?;
+        file.write_all(&chunk).context("Write error")?;
 
         downloaded += chunk.len() as u64;
+        if downloaded > size {
+            drop(file);
+            let _ = std::fs::remove_file(part_path);
+            return Err(too_large(size));
+        }
+        if throttle.ready(Instant::now(), false) {
+            on_progress(progress(downloaded, total));
+        }
+    }
 
-        // Emit progress event
-        let progress = DownloadProgress {
-            downloaded,
-            total: total_size,
-            percentage: if total_size > 0 {
-                (downloaded as f64 / total_size as f64) * 100.0
-            } else {
-                0.0
-            },
+    file.sync_all().context("Write error")?;
+    if total > 0 && downloaded < total {
+        return Err(AppError::new(
+            ErrorCode::NetworkUnavailable,
+            "Connection lost during download",
+        )
+        .with_detail(format!("{} of {} bytes", downloaded, total)));
+    }
+    if throttle.ready(Instant::now(), true) {
+        on_progress(progress(downloaded, downloaded.max(total)));
+    }
+    Ok(())
+}
+
+/// Copy an installer from a folder source into `part_path`
+///
+/// Local copies are quick, so this always starts over rather than resuming.
+async fn copy_part(
+    source: &Path,
+    part_path: &Path,
+    size: u64,
+    throttle: &mut ProgressThrottle,
+    on_progress: &mut impl FnMut(DownloadProgress),
+) -> Result<()> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mut reader = match tokio::fs::File::open(source).await {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(verification_failed(format!(
+                "Not published: {}",
+                source.display()
+            )))
+        }
+        Err(e) => return Err(e).context("Failed to read installer"),
+    };
+    let total = reader
+        .metadata()
+        .await
+        .context("Failed to read installer")?
+        .len();
+    if total > size {
+        return Err(too_large(size));
+    }
+    let dir = part_path.parent().unwrap_or(Path::new("."));
+    ensure_disk_space(dir, total)?;
+
+    let mut file = tokio::fs::File::create(part_path)
+        .await
+        .context("Failed to create file")?;
+    let mut buf = vec![0u8; 1 << 20];
+    let mut copied = 0;
+    loop {
+        let n = reader
+            .read(&mut buf)
+            .await
+            .context("Failed to read installer")?;
+        if n == 0 {
+            break;
+        }
+        file.write_all(&buf[..n]).await.context("Write error")?;
+        copied += n as u64;
+        if throttle.ready(Instant::now(), false) {
+            on_progress(progress(copied, total));
+        }
+    }
+
+    file.sync_all().await.context("Write error")?;
+    if throttle.ready(Instant::now(), true) {
+        on_progress(progress(copied, copied.max(total)));
+    }
+    Ok(())
+}
+
+/// Download an installer into `dir`, or copy it from a folder source's
+/// `file:` URL, and verify it
+///
+/// The installer's signature and checksum are published beside it as
+/// `<asset>.sig` and `<asset>.sha256`. Bytes are written to `<asset>.part`,
+/// which survives dropped connections, timeouts and cancellation so the next
+/// attempt resumes with a Range request. Nothing is left at the returned path
+/// unless it verifies; the signature and checksum are kept next to it so
+/// `install_update` can check it again.
+async fn fetch_installer(
+    client: &reqwest::Client,
+    asset: &PlatformAsset,
+    dir: &Path,
+    public_key: &PublicKey,
+    limits: &DownloadLimits,
+    cancel: &Notify,
+    mut on_progress: impl FnMut(DownloadProgress),
+) -> Result<PathBuf> {
+    let download_url = asset.url.as_str();
+    let file_name = installer_file_name(download_url)?;
+    std::fs::create_dir_all(dir).context("Failed to create update directory")?;
+    let file_path = dir.join(&file_name);
+    let part_path = dir.join(format!("{}.part", file_name));
+
+    let download = async {
+        let signature = fetch_text(
+            client,
+            &format!("{}{}", download_url, SIGNATURE_SUFFIX),
+            limits.idle,
+        )
+        .await?;
+        let checksum = fetch_text(
+            client,
+            &format!("{}{}", download_url, CHECKSUM_SUFFIX),
+            limits.idle,
+        )
+        .await?;
+
+        let mut throttle = ProgressThrottle::new(PROGRESS_INTERVAL);
+        if let Some(source) = sources::local_path(download_url) {
+            copy_part(
+                &source,
+                &part_path,
+                asset.size,
+                &mut throttle,
+                &mut on_progress,
+            )
+            .await?;
+            return Ok((signature, checksum));
+        }
+        let mut attempt = 1;
+        loop {
+            match download_part(
+                client,
+                download_url,
+                &part_path,
+                asset.size,
+                limits.idle,
+                &mut throttle,
+                &mut on_progress,
+            )
+            .await
+            {
+                Ok(()) => return Ok((signature, checksum)),
+                Err(e) if e.retryable && attempt < limits.attempts => {
+                    eprintln!("Update download attempt {} failed: {}", attempt, e);
+                    attempt += 1;
+                    tokio::time::sleep(limits.retry_delay).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    };
+
+    let (signature, checksum) = tokio::select! {
+        result = tokio::time::timeout(limits.overall, download) => result.map_err(|_| {
+            AppError::new(ErrorCode::NetworkTimeout, "The update took too long to download")
+                .with_detail(format!("Stopped after {} minutes", limits.overall.as_secs() / 60))
+        })??,
+        _ = cancel.notified() => return Err(AppError::from_code(ErrorCode::Cancelled)),
+    };
+
+    let installer = std::fs::read(&part_path).context("Failed to read installer")?;
+    let verified = verify::verify_pinned(&installer, &asset.sha256, asset.size)
+        .and_then(|()| verify::verify_installer(public_key, &installer, &signature, &checksum));
+    if let Err(e) = verified {
+        // Don't resume into a file that can never verify
+        let _ = std::fs::remove_file(&part_path);
+        return Err(e);
+    }
+
+    // Only verified bytes ever reach the disk under the installer's name
+    for (suffix, text) in [(SIGNATURE_SUFFIX, &signature), (CHECKSUM_SUFFIX, &checksum)] {
+        std::fs::write(dir.join(format!("{}{}", file_name, suffix)), text)
+            .context("Write error")?;
+    }
+    std::fs::rename(&part_path, &file_path).context("Failed to save installer")?;
+
+    Ok(file_path)
+}
+
+/// An update from a verified manifest and, once downloaded, its installer
+struct Offer {
+    version: Version,
+    asset: PlatformAsset,
+    installer: Option<PathBuf>,
+}
+
+/// The update offered by the last check, the only one `download_update`
+/// fetches, and the download in progress, if any, so it can be cancelled
+#[derive(Default)]
+pub struct UpdateDownload {
+    offer: Mutex<Option<Offer>>,
+    cancel: Mutex<Option<Arc<Notify>>>,
+}
+
+impl UpdateDownload {
+    /// Keep what a check found; an installer already downloaded for the
+    /// same release is kept with it
+    fn offer(&self, info: &UpdateInfo) -> Result<()> {
+        let mut offer = self.offer.lock()?;
+        if !info.update_available {
+            *offer = None;
+            return Ok(());
+        }
+        let version = Version::parse(&info.latest_version).map_err(|e| {
+            AppError::new(ErrorCode::InvalidResponse, "Invalid update version")
+                .with_detail(e.to_string())
+        })?;
+        let asset = PlatformAsset {
+            url: info.download_url.clone(),
+            sha256: info.sha256.clone(),
+            size: info.size,
         };
+        let installer = offer
+            .take()
+            .filter(|offer| offer.version == version && offer.asset == asset)
+            .and_then(|offer| offer.installer);
+        *offer = Some(Offer {
+            version,
+            asset,
+            installer,
+        });
+        Ok(())
+    }
+
+    /// The offered update, if it's newer than `current`; going back to an
+    /// earlier version is only possible through `rollback_update`
+    fn offered(&self, current: &Version) -> Result<(Version, PlatformAsset)> {
+        let offer = self.offer.lock()?;
+        let offer = offer
+            .as_ref()
+            .ok_or_else(|| AppError::invalid_input("Check for updates before downloading one"))?;
+        if offer.version.cmp_precedence(current) != Ordering::Greater {
+            return Err(AppError::invalid_input(
+                "The offered update is not newer than this version",
+            )
+            .with_detail(format!("{} offered, {} running", offer.version, current)));
+        }
+        Ok((offer.version.clone(), offer.asset.clone()))
+    }
+
+    fn downloaded(&self, version: &Version, installer: &Path) -> Result<()> {
+        if let Some(offer) = self.offer.lock()?.as_mut() {
+            if offer.version == *version {
+                offer.installer = Some(installer.to_path_buf());
+            }
+        }
+        Ok(())
+    }
+
+    /// The installer downloaded for the offered update, if it's newer than
+    /// `current`
+    fn installer(&self, current: &Version) -> Result<PathBuf> {
+        self.offered(current)?;
+        let offer = self.offer.lock()?;
+        offer
+            .as_ref()
+            .and_then(|offer| offer.installer.clone())
+            .ok_or_else(|| AppError::invalid_input("Download the update before installing it"))
+    }
 
-        let _ = window.emit("download-progress", progress);
+    fn start(&self) -> Result<Arc<Notify>> {
+        let mut cancel = self.cancel.lock()?;
+        if cancel.is_some() {
+            return Err(AppError::invalid_input("An update is already downloading"));
+        }
+        let notify = Arc::new(Notify::new());
+        *cancel = Some(notify.clone());
+        Ok(notify)
     }
 
-    Ok(file_path.to_string_lossy().to_string())
+    fn finish(&self) -> Result<()> {
+        self.cancel.lock()?.take();
+        Ok(())
+    }
 }
 
-/// Install update and restart app
+/// Download and verify the update offered by the last `check_for_updates`,
+/// with progress events at most every 100 ms
+///
+/// Fails if there's no offer or it isn't newer than the running version. A
+/// download interrupted by a dropped connection, a timeout or
+/// `cancel_update_download` picks up where it stopped next time. Fails with
+/// `UPDATE_VERIFICATION_FAILED` if the release has no signature or checksum,
+/// or the installer doesn't match them or the `sha256` and `size` from the
+/// manifest (see `UpdateInfo`), and with `CANCELLED` if cancelled.
 #[tauri::command]
-pub async fn install_update(installer_path: String) -> Result<(), String> {
+pub async fn download_update(
+    window: WebviewWindow,
+    state: tauri::State<'_, UpdateDownload>,
+) -> Result<String> {
+    let current = window.app_handle().package_info().version.clone();
+    let (version, asset) = state.offered(&current)?;
+    let public_key = verify::updater_public_key(window.app_handle())?;
+    let client = reqwest::Client::builder()
+        .user_agent("SACCO+ Staff Admin")
+        .connect_timeout(Duration::from_secs(30))
+        .build()
+        .context("Failed to create HTTP client")?;
+
+    let dir = health_dir(window.app_handle())?;
+    create_private_dir(&dir)?;
+    let dir = dir.join(DOWNLOADS_DIR);
+    create_private_dir(&dir)?;
+
+    let cancel = state.start()?;
+    let result = fetch_installer(
+        &client,
+        &asset,
+        &dir,
+        &public_key,
+        &DownloadLimits::default(),
+        &cancel,
+        |progress| {
+            let _ = window.emit("download-progress", progress);
+        },
+    )
+    .await;
+    state.finish()?;
+    let installer = result?;
+    state.downloaded(&version, &installer)?;
+
+    Ok(installer.to_string_lossy().to_string())
+}
+
+/// Stop the update download in progress, keeping what was downloaded so far
+///
+/// Returns false if nothing was downloading.
+#[tauri::command]
+pub async fn cancel_update_download(state: tauri::State<'_, UpdateDownload>) -> Result<bool> {
+    match state.cancel.lock()?.take() {
+        Some(cancel) => {
+            cancel.notify_one();
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Launch an installer; the app must exit for it to replace the running
+/// version
+fn run_installer(installer_path: &str) -> Result<()> {
     #[cfg(target_os = "windows")]
     {
         // Launch MSI installer
         std::process::Command::new("msiexec")
-            .args(["/i", &installer_path, "/qn", "/norestart"])
+            .args(["/i", installer_path, "/qn", "/norestart"])
             .spawn()
-            .map_err(|e| format!("Failed to launch installer: {}", e))?;
+            .context("Failed to launch installer")?;
     }
 
     #[cfg(target_os = "macos")]
     {
         // Mount DMG and copy app
         std::process::Command::new("hdiutil")
-            .args(["attach", &installer_path])
+            .args(["attach", installer_path])
             .spawn()
-            .map_err(|e| format!("Failed to mount DMG: {}", e))?;
+            .context("Failed to mount DMG")?;
     }
 
     #[cfg(target_os = "linux")]
     {
         // Make AppImage executable and run
         std::process::Command::new("chmod")
-            .args(["+x", &installer_path])
+            .args(["+x", installer_path])
             .output()
-            .map_err(|e| format!("Failed to make executable: {}", e))?;
+            .context("Failed to make executable")?;
 
-        std::process::Command::new(&installer_path)
+        std::process::Command::new(installer_path)
             .spawn()
-            .map_err(|e| format!("Failed to launch installer: {}", e))?;
+            .context("Failed to launch installer")?;
+    }
+
+    Ok(())
+}
+
+/// Install update and restart app
+///
+/// Only the installer `download_update` downloaded and verified for the
+/// offered update is run, and only if that update is newer than the running
+/// version; the file is verified again and run from a private copy of the
+/// checked bytes.
+#[tauri::command]
+pub async fn install_update(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, UpdateDownload>,
+    installer_path: String,
+) -> Result<()> {
+    let public_key = verify::updater_public_key(&app_handle)?;
+    let dir = health_dir(&app_handle)?;
+    let current = app_handle.package_info().version.clone();
+    let installer_path = PathBuf::from(installer_path);
+    if installer_path != state.installer(&current)? {
+        return Err(
+            verification_failed("The installer was not downloaded by this app")
+                .with_detail(installer_path.display().to_string()),
+        );
     }
+    let copy = private_copy(&public_key, &installer_path, &dir.join(RUN_DIR))?;
+
+    // Kept for rollbacks once the new version has started successfully
+    let mut launch_health = LaunchHealth::load(&dir)?;
+    launch_health.pending_installer = Some(installer_path);
+    launch_health.save(&dir)?;
+
+    run_installer(&copy.to_string_lossy())?;
 
     // Exit current app to allow update
     std::process::exit(0);
 }
 
+fn health_dir(app_handle: &tauri::AppHandle) -> Result<PathBuf> {
+    let dir = app_handle
+        .path()
+        .app_data_dir()
+        .context("Failed to resolve app data dir")?;
+    Ok(dir.join(HEALTH_DIR))
+}
+
+/// Reinstall `installer`, the kept installer for `target`, and exit
+fn roll_back(
+    app_handle: &tauri::AppHandle,
+    target: Version,
+    installer: &Path,
+    automatic: bool,
+) -> Result<()> {
+    let dir = health_dir(app_handle)?;
+    let current = app_handle.package_info().version.clone();
+    let public_key = verify::updater_public_key(app_handle)?;
+    let copy = private_copy(&public_key, installer, &dir.join(RUN_DIR))?;
+
+    let mut launch_health = LaunchHealth::load(&dir)?;
+    launch_health.rolling_back(RollbackReport {
+        from_version: current.clone(),
+        to_version: target,
+        failed_launches: launch_health.recent_failures(&current),
+        automatic,
+        rolled_back_at: Utc::now(),
+    });
+    launch_health.save(&dir)?;
+
+    run_installer(&copy.to_string_lossy())?;
+    std::process::exit(0);
+}
+
+/// Mark the start of this launch, called first thing at startup
+///
+/// Rolls back to the last good version, without asking, once this version has
+/// failed to start `MAX_FAILED_LAUNCHES` times in a row.
+pub fn record_launch(app_handle: &tauri::AppHandle) -> Result<()> {
+    let dir = health_dir(app_handle)?;
+    let current = app_handle.package_info().version.clone();
+    if let Some((target, installer)) = health::start_launch(&dir, &current)? {
+        eprintln!(
+            "Version {} keeps failing to start, rolling back to {}",
+            current, target
+        );
+        roll_back(app_handle, target, &installer, true)?;
+    }
+    Ok(())
+}
+
+async fn send_reports(app_handle: tauri::AppHandle) -> Result<()> {
+    let dir = health_dir(&app_handle)?;
+    let reports = LaunchHealth::load(&dir)?.unsent_reports;
+    if reports.is_empty() {
+        return Ok(());
+    }
+
+    let credentials = super::auth::require_credentials().await?;
+    let device_id = super::auth::get_device_id().await?;
+    let client = reqwest::Client::builder()
+        .user_agent("SACCO+ Staff Admin")
+        .timeout(std::time::Duration::from_secs(30))
+        .build()
+        .context("Failed to create HTTP client")?;
+
+    for report in &reports {
+        let response = client
+            .post(ROLLBACK_REPORTS_URL)
+            .bearer_auth(&credentials.access_token)
+            .json(&serde_json::json!({
+                "device_id": device_id,
+                "from_version": report.from_version.to_string(),
+                "to_version": report.to_version.to_string(),
+                "failed_launches": report.failed_launches,
+                "automatic": report.automatic,
+                "rolled_back_at": report.rolled_back_at,
+            }))
+            .send()
+            .await
+            .context("Failed to send rollback report")?;
+        if !response.status().is_success() {
+            return Err(AppError::http_status(response.status()).with_detail(ROLLBACK_REPORTS_URL));
+        }
+
+        let mut launch_health = LaunchHealth::load(&dir)?;
+        launch_health.unsent_reports.retain(|r| r != report);
+        launch_health.save(&dir)?;
+    }
+    Ok(())
+}
+
+/// Mark this launch as successful, once the main window has loaded
+///
+/// Keeps the installer that installed this version for later rollbacks, and
+/// sends reports of earlier rollbacks in the background; they stay queued
+/// until the app is online and signed in.
+pub async fn confirm_startup(app_handle: tauri::AppHandle) -> Result<()> {
+    let dir = health_dir(&app_handle)?;
+    let current = app_handle.package_info().version.clone();
+    health::confirm_launch(&dir, &current)?;
+    // Copies made to run the installer that got us here
+    match std::fs::remove_dir_all(dir.join(RUN_DIR)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            eprintln!("Failed to clean up installer copies: {}", e)
+        }
+        _ => {}
+    }
+
+    send_reports(app_handle).await
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RollbackStatus {
+    pub current_version: String,
+    /// Version a rollback would install, if its installer was kept
+    pub rollback_version: Option<String>,
+    /// Launches of this version in a row that failed before this one
+    pub failed_launches: u32,
+}
+
+/// The kept installer to roll back to, if any
+fn rollback_installer(
+    dir: &Path,
+    launch_health: &LaunchHealth,
+    current: &Version,
+) -> Option<(Version, PathBuf)> {
+    if !health::ROLLBACK_SUPPORTED {
+        return None;
+    }
+    let target = launch_health.rollback_target(current)?;
+    Some((target.clone(), health::kept_installer(dir, target)?))
+}
+
+#[tauri::command]
+pub async fn get_rollback_status(app_handle: tauri::AppHandle) -> Result<RollbackStatus> {
+    let dir = health_dir(&app_handle)?;
+    let current = app_handle.package_info().version.clone();
+    let launch_health = LaunchHealth::load(&dir)?;
+    let rollback = rollback_installer(&dir, &launch_health, &current);
+
+    Ok(RollbackStatus {
+        current_version: current.to_string(),
+        rollback_version: rollback.map(|(target, _)| target.to_string()),
+        failed_launches: launch_health.recent_failures(&current),
+    })
+}
+
+/// Reinstall the previous version and restart
+///
+/// Not available on macOS, where installing only mounts the disk image.
+#[tauri::command]
+pub async fn rollback_update(app_handle: tauri::AppHandle) -> Result<()> {
+    if !health::ROLLBACK_SUPPORTED {
+        return Err(AppError::from_code(ErrorCode::Unsupported));
+    }
+    let dir = health_dir(&app_handle)?;
+    let current = app_handle.package_info().version.clone();
+    let launch_health = LaunchHealth::load(&dir)?;
+    let (target, installer) = rollback_installer(&dir, &launch_health, &current)
+        .ok_or_else(|| AppError::invalid_input("No earlier version to roll back to"))?;
+    roll_back(&app_handle, target, &installer, false)
+}
+
 /// Get current app version
 #[tauri::command]
 pub fn get_current_version(app_handle: tauri::AppHandle) -> String {
     app_handle.package_info().version.to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::updater::verify::decode_public_key;
+    use crate::updater::verify::testing::{checksum, tauri_public_key, tauri_signature};
+    use ed25519_dalek::SigningKey;
+    use serde_json::json;
+    use sha2::{Digest, Sha256};
+    use std::collections::HashMap;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    /// How the release server misbehaves
+    #[derive(Clone, Copy, Default)]
+    struct Faults {
+        /// Drop the first installer download after this many bytes
+        cut_after: Option<usize>,
+        /// Send the installer's headers, then nothing
+        stall: bool,
+    }
+
+    /// Release server answering GETs from `files`, with Range support, and
+    /// 404 for anything else; returns its URL and the requests it received
+    fn release_server(
+        files: HashMap<String, Vec<u8>>,
+        faults: Faults,
+    ) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = requests.clone();
+        std::thread::spawn(move || {
+            let mut cut_after = faults.cut_after;
+            for socket in listener.incoming() {
+                let mut socket = socket.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let n = socket.read(&mut buf).unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+                let request = String::from_utf8_lossy(&request).to_string();
+                let path = request.split(' ').nth(1).unwrap_or("");
+                let request = request.to_lowercase();
+                seen.lock().unwrap().push(request.clone());
+                let Some(body) = files.get(path) else {
+                    let _ = socket.write_all(
+                        b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    );
+                    continue;
+                };
+
+                let start: usize = request
+                    .lines()
+                    .find_map(|l| l.strip_prefix("range: bytes="))
+                    .and_then(|r| r.trim().trim_end_matches('-').parse().ok())
+                    .unwrap_or(0);
+                let headers = if start > 0 {
+                    format!(
+                        "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\n",
+                        start,
+                        body.len() - 1,
+                        body.len()
+                    )
+                } else {
+                    "HTTP/1.1 200 OK\r\n".to_string()
+                };
+                let body = &body[start..];
+                let _ = socket.write_all(
+                    format!(
+                        "{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+                        headers,
+                        body.len()
+                    )
+                    .as_bytes(),
+                );
+
+                let installer =
+                    !path.ends_with(SIGNATURE_SUFFIX) && !path.ends_with(CHECKSUM_SUFFIX);
+                if installer && faults.stall {
+                    std::thread::sleep(Duration::from_secs(5));
+                } else if let Some(cut) = installer.then(|| cut_after.take()).flatten() {
+                    let _ = socket.write_all(&body[..cut]);
+                } else {
+                    let _ = socket.write_all(body);
+                }
+            }
+        });
+        (format!("http://127.0.0.1:{}", port), requests)
+    }
+
+    fn test_limits() -> DownloadLimits {
+        DownloadLimits {
+            overall: Duration::from_secs(10),
+            idle: Duration::from_millis(500),
+            attempts: 3,
+            retry_delay: Duration::from_millis(10),
+        }
+    }
+
+    fn signed_release(key: &SigningKey, installer: &[u8]) -> HashMap<String, Vec<u8>> {
+        HashMap::from([
+            ("/staff-admin.AppImage".to_string(), installer.to_vec()),
+            (
+                "/staff-admin.AppImage.sig".to_string(),
+                tauri_signature(key, installer).into_bytes(),
+            ),
+            (
+                "/staff-admin.AppImage.sha256".to_string(),
+                checksum(installer).into_bytes(),
+            ),
+        ])
+    }
+
+    /// The manifest's entry for `installer` served at `url`
+    fn pinned(url: String, installer: &[u8]) -> PlatformAsset {
+        PlatformAsset {
+            url,
+            sha256: hex::encode(Sha256::digest(installer)),
+            size: installer.len() as u64,
+        }
+    }
+
+    /// Fetch the installer the manifest pins as `listed`
+    async fn fetch(
+        files: HashMap<String, Vec<u8>>,
+        listed: &[u8],
+        dir: &Path,
+        public_key: &PublicKey,
+    ) -> Result<PathBuf> {
+        let (url, _) = release_server(files, Faults::default());
+        let asset = pinned(format!("{}/staff-admin.AppImage", url), listed);
+        let mut progress = Vec::new();
+        let result = fetch_installer(
+            &reqwest::Client::new(),
+            &asset,
+            dir,
+            public_key,
+            &test_limits(),
+            &Notify::new(),
+            |p| progress.push(p.downloaded),
+        )
+        .await;
+        if let Ok(path) = &result {
+            // The final event is always sent
+            assert_eq!(
+                progress.last(),
+                Some(&std::fs::metadata(path).unwrap().len())
+            );
+        }
+        result
+    }
+
+    #[tokio::test]
+    async fn test_fetch_verified_installer() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let public_key = decode_public_key(&tauri_public_key(&key)).unwrap();
+        let installer = b"#!/bin/sh\necho staff admin 1.4.0\n".to_vec();
+        let files = signed_release(&key, &installer);
+        let dir = std::env::temp_dir().join(format!("updates-{}", uuid::Uuid::new_v4()));
+
+        let path = fetch(files.clone(), &installer, &dir, &public_key)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), installer);
+        verify_downloaded(&public_key, &path).unwrap();
+
+        // What runs is a private copy of the bytes that were checked
+        let copy = private_copy(&public_key, &path, &dir.join(RUN_DIR)).unwrap();
+        assert_ne!(copy, path);
+        assert_eq!(std::fs::read(&copy).unwrap(), installer);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(copy.parent().unwrap())
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+
+        // Changed on disk after download
+        std::fs::write(&path, b"#!/bin/sh\nrm -rf ~\n").unwrap();
+        let error = verify_downloaded(&public_key, &path).unwrap_err();
+        assert_eq!(error.code, ErrorCode::UpdateVerificationFailed);
+        let error = private_copy(&public_key, &path, &dir.join(RUN_DIR)).unwrap_err();
+        assert_eq!(error.code, ErrorCode::UpdateVerificationFailed);
+
+        // A file that didn't come through download_update
+        let stray = dir.join("other.AppImage");
+        std::fs::write(&stray, &installer).unwrap();
+        let error = verify_downloaded(&public_key, &stray).unwrap_err();
+        assert_eq!(error.code, ErrorCode::UpdateVerificationFailed);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reject_unverified_installer() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let public_key = decode_public_key(&tauri_public_key(&key)).unwrap();
+        let installer = b"#!/bin/sh\necho staff admin 1.4.0\n".to_vec();
+        let tampered = b"#!/bin/sh\necho something else\n".to_vec();
+        let older = b"#!/bin/sh\necho staff admin 1.3.0\n".to_vec();
+        let dir = std::env::temp_dir().join(format!("updates-{}", uuid::Uuid::new_v4()));
+
+        let served = |body: &[u8], signature: String, checksum: Option<String>| {
+            let mut files = HashMap::from([
+                ("/staff-admin.AppImage".to_string(), body.to_vec()),
+                (
+                    "/staff-admin.AppImage.sig".to_string(),
+                    signature.into_bytes(),
+                ),
+            ]);
+            if let Some(checksum) = checksum {
+                files.insert(
+                    "/staff-admin.AppImage.sha256".to_string(),
+                    checksum.into_bytes(),
+                );
+            }
+            files
+        };
+
+        let cases = [
+            // Checksum and signature of the real installer, different bytes
+            served(
+                &tampered,
+                tauri_signature(&key, &installer),
+                Some(checksum(&installer)),
+            ),
+            // Checksum matches but the signature is from another key
+            served(
+                &tampered,
+                tauri_signature(&SigningKey::from_bytes(&[9u8; 32]), &tampered),
+                Some(checksum(&tampered)),
+            ),
+            // No checksum published
+            served(&installer, tauri_signature(&key, &installer), None),
+            // An older, validly signed release served under the new name
+            served(
+                &older,
+                tauri_signature(&key, &older),
+                Some(checksum(&older)),
+            ),
+        ];
+        for files in cases {
+            let error = fetch(files, &installer, &dir, &public_key)
+                .await
+                .unwrap_err();
+            assert_eq!(error.code, ErrorCode::UpdateVerificationFailed);
+            assert!(!dir.join("staff-admin.AppImage").exists());
+        }
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_resume_interrupted_download() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let public_key = decode_public_key(&tauri_public_key(&key)).unwrap();
+        let installer: Vec<u8> = (0..64 * 1024).map(|i| (i % 251) as u8).collect();
+        let dir = std::env::temp_dir().join(format!("updates-{}", uuid::Uuid::new_v4()));
+
+        let (url, requests) = release_server(
+            signed_release(&key, &installer),
+            Faults {
+                cut_after: Some(10_000),
+                ..Faults::default()
+            },
+        );
+        let path = fetch_installer(
+            &reqwest::Client::new(),
+            &pinned(format!("{}/staff-admin.AppImage", url), &installer),
+            &dir,
+            &public_key,
+            &test_limits(),
+            &Notify::new(),
+            |_| {},
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), installer);
+        assert!(!dir.join("staff-admin.AppImage.part").exists());
+        let requests = requests.lock().unwrap();
+        assert!(requests.last().unwrap().contains("range: bytes=10000-"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_stalled_and_cancelled_download() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let public_key = decode_public_key(&tauri_public_key(&key)).unwrap();
+        let installer = b"#!/bin/sh\necho staff admin 1.4.0\n".to_vec();
+        let dir = std::env::temp_dir().join(format!("updates-{}", uuid::Uuid::new_v4()));
+        let stall = Faults {
+            stall: true,
+            ..Faults::default()
+        };
+        let limits = DownloadLimits {
+            idle: Duration::from_millis(200),
+            attempts: 1,
+            ..test_limits()
+        };
+
+        let (url, _) = release_server(signed_release(&key, &installer), stall);
+        let asset = pinned(format!("{}/staff-admin.AppImage", url), &installer);
+        let started = Instant::now();
+        let error = fetch_installer(
+            &reqwest::Client::new(),
+            &asset,
+            &dir,
+            &public_key,
+            &limits,
+            &Notify::new(),
+            |_| {},
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.code, ErrorCode::NetworkTimeout);
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let (url, _) = release_server(signed_release(&key, &installer), stall);
+        let asset = pinned(format!("{}/staff-admin.AppImage", url), &installer);
+        let cancel = Notify::new();
+        cancel.notify_one();
+        let error = fetch_installer(
+            &reqwest::Client::new(),
+            &asset,
+            &dir,
+            &public_key,
+            &test_limits(),
+            &cancel,
+            |_| {},
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.code, ErrorCode::Cancelled);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_progress_throttle() {
+        let mut throttle = ProgressThrottle::new(PROGRESS_INTERVAL);
+        let start = Instant::now();
+        let sent = (0..100)
+            .filter(|i| throttle.ready(start + Duration::from_millis(i * 5), false))
+            .count();
+        // 500 ms of chunks every 5 ms
+        assert_eq!(sent, 5);
+        assert!(throttle.ready(start + Duration::from_millis(499), true));
+    }
+
+    #[test]
+    fn test_installer_file_name() {
+        assert_eq!(
+            installer_file_name("https://example.com/v1.4.0/staff-admin_1.4.0_x64.msi?x=1")
+                .unwrap(),
+            "staff-admin_1.4.0_x64.msi"
+        );
+        assert!(installer_file_name("https://example.com/releases/").is_err());
+        assert!(installer_file_name("https://example.com/..").is_err());
+    }
+
+    #[test]
+    fn test_version_precedence() {
+        let ordered = [
+            "1.0.0-alpha",
+            "1.0.0-alpha.1",
+            "1.0.0-alpha.beta",
+            "1.0.0-beta",
+            "1.0.0-beta.2",
+            "1.0.0-beta.11",
+            "1.0.0-rc.1",
+            "1.0.0",
+            "1.0.1",
+            "1.10.0",
+        ];
+        for pair in ordered.windows(2) {
+            let (a, b) = (
+                Version::parse(pair[0]).unwrap(),
+                Version::parse(pair[1]).unwrap(),
+            );
+            assert_eq!(
+                a.cmp_precedence(&b),
+                Ordering::Less,
+                "{} < {}",
+                pair[0],
+                pair[1]
+            );
+        }
+
+        // Build metadata doesn't count
+        let (a, b) = (
+            Version::parse("1.2.0+build.1").unwrap(),
+            Version::parse("1.2.0+build.2").unwrap(),
+        );
+        assert_eq!(a.cmp_precedence(&b), Ordering::Equal);
+    }
+
+    /// The stable release's installer in `manifest`
+    const STABLE_INSTALLER: &[u8] = b"MSI staff admin 1.3.0";
+
+    fn manifest(minimum_version: &str, rollout_percent: u8) -> UpdateManifest {
+        let stable = pinned(
+            "https://releases.ibimina.rw/staff-admin/1.3.0/staff-admin_1.3.0_x64.msi".to_string(),
+            STABLE_INSTALLER,
+        );
+        let beta = pinned(
+            "https://releases.ibimina.rw/staff-admin/1.4.0-beta.1/staff-admin_1.4.0-beta.1_x64.msi"
+                .to_string(),
+            b"MSI staff admin 1.4.0-beta.1",
+        );
+        serde_json::from_value(json!({
+            "published_at": "2026-10-01T08:00:00Z",
+            "expires": "2100-01-01T00:00:00Z",
+            "minimum_version": minimum_version,
+            "channels": {
+                "stable": {
+                    "version": "1.3.0",
+                    "notes": "Faster receipt printing",
+                    "rollout_percent": rollout_percent,
+                    "platforms": {
+                        "windows-x86_64": stable
+                    }
+                },
+                "beta": {
+                    "version": "1.4.0-beta.1",
+                    "platforms": {
+                        "windows-x86_64": beta
+                    }
+                }
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_update_info() {
+        let check = |manifest: &UpdateManifest, current: &str, device_id: &str| {
+            update_info(
+                manifest,
+                UpdateChannel::Stable,
+                &Version::parse(current).unwrap(),
+                device_id,
+                "windows-x86_64",
+            )
+            .unwrap()
+        };
+        let full = manifest("1.0.0", 100);
+
+        let older = check(&full, "1.2.9", "device-a");
+        assert!(older.update_available && !older.ahead_of_latest && !older.required);
+        assert_eq!(older.release_notes, "Faster receipt printing");
+
+        // A beta build is ahead of stable and must not be "updated" back
+        let beta = check(&full, "1.4.0-beta.1", "device-a");
+        assert!(!beta.update_available && beta.ahead_of_latest);
+
+        let same = check(&full, "1.3.0+ci.42", "device-a");
+        assert!(!same.update_available && !same.ahead_of_latest);
+
+        // No installer for this platform
+        let linux = update_info(
+            &full,
+            UpdateChannel::Stable,
+            &Version::parse("1.2.9").unwrap(),
+            "device-a",
+            "linux-x86_64",
+        )
+        .unwrap();
+        assert!(!linux.update_available);
+
+        let pre = update_info(
+            &full,
+            UpdateChannel::Beta,
+            &Version::parse("1.3.0").unwrap(),
+            "device-a",
+            "windows-x86_64",
+        )
+        .unwrap();
+        assert!(pre.update_available && pre.prerelease);
+
+        // Outside a 1% rollout, unless the running version is unsupported
+        let device = (0..)
+            .map(|i| format!("device-{}", i))
+            .find(|d| manifest::rollout_bucket(d, &Version::new(1, 3, 0)) > 0)
+            .unwrap();
+        let staged = manifest("1.0.0", 1);
+        assert!(!check(&staged, "1.2.9", &device).update_available);
+        let critical = manifest("1.3.0", 1);
+        let forced = check(&critical, "1.2.9", &device);
+        assert!(forced.update_available && forced.required);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_signed_manifest() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let public_key = decode_public_key(&tauri_public_key(&key)).unwrap();
+        let manifest = serde_json::to_vec(&manifest("1.0.0", 100)).unwrap();
+        let mut tampered = manifest.clone();
+        let at = tampered.iter().position(|&b| b == b'3').unwrap();
+        tampered[at] = b'9';
+
+        let files = HashMap::from([
+            ("/manifest.json".to_string(), manifest.clone()),
+            (
+                "/manifest.json.sig".to_string(),
+                tauri_signature(&key, &manifest).into_bytes(),
+            ),
+        ]);
+        let (url, _) = release_server(files, Faults::default());
+        let fetched = fetch_manifest(
+            &reqwest::Client::new(),
+            &format!("{}/manifest.json", url),
+            &public_key,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(fetched, self::manifest("1.0.0", 100));
+
+        // The same manifest again is fine, one older than the newest used isn't
+        let client = reqwest::Client::new();
+        let manifest_url = format!("{}/manifest.json", url);
+        let published_at = fetched.published_at;
+        let fetch = |newest_accepted| {
+            fetch_manifest(&client, &manifest_url, &public_key, Some(newest_accepted))
+        };
+        assert!(fetch(published_at).await.is_ok());
+        let error = fetch(published_at + chrono::Duration::hours(1))
+            .await
+            .unwrap_err();
+        assert_eq!(error.code, ErrorCode::UpdateVerificationFailed);
+
+        let mut expired = self::manifest("1.0.0", 100);
+        expired.expires = Utc::now() - chrono::Duration::days(1);
+        let expired = serde_json::to_vec(&expired).unwrap();
+        let files = HashMap::from([
+            ("/manifest.json".to_string(), expired.clone()),
+            (
+                "/manifest.json.sig".to_string(),
+                tauri_signature(&key, &expired).into_bytes(),
+            ),
+        ]);
+        let (url, _) = release_server(files, Faults::default());
+        let error = fetch_manifest(
+            &reqwest::Client::new(),
+            &format!("{}/manifest.json", url),
+            &public_key,
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.code, ErrorCode::UpdateVerificationFailed);
+
+        let files = HashMap::from([
+            ("/manifest.json".to_string(), tampered),
+            (
+                "/manifest.json.sig".to_string(),
+                tauri_signature(&key, &manifest).into_bytes(),
+            ),
+        ]);
+        let (url, _) = release_server(files, Faults::default());
+        let error = fetch_manifest(
+            &reqwest::Client::new(),
+            &format!("{}/manifest.json", url),
+            &public_key,
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.code, ErrorCode::UpdateVerificationFailed);
+    }
+
+    #[tokio::test]
+    async fn test_update_from_folder() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let public_key = decode_public_key(&tauri_public_key(&key)).unwrap();
+        let manifest = serde_json::to_vec(&manifest("1.0.0", 100)).unwrap();
+        let installer = STABLE_INSTALLER.to_vec();
+
+        // A copy of the release as IT would put it on a USB drive
+        let bundle = std::env::temp_dir().join(format!("update bundle {}", Uuid::new_v4()));
+        std::fs::create_dir_all(&bundle).unwrap();
+        let name = "staff-admin_1.3.0_x64.msi";
+        for (file, contents) in [
+            ("manifest.json".to_string(), manifest.clone()),
+            (
+                "manifest.json.sig".to_string(),
+                tauri_signature(&key, &manifest).into_bytes(),
+            ),
+            (name.to_string(), installer.clone()),
+            (
+                format!("{}.sig", name),
+                tauri_signature(&key, &installer).into_bytes(),
+            ),
+            (
+                format!("{}.sha256", name),
+                checksum(&installer).into_bytes(),
+            ),
+        ] {
+            std::fs::write(bundle.join(file), contents).unwrap();
+        }
+        let source = UpdateSource::Folder {
+            path: bundle.clone(),
+        };
+
+        let client = reqwest::Client::new();
+        let fetched = fetch_manifest(&client, &source.manifest_url().unwrap(), &public_key, None)
+            .await
+            .unwrap();
+        let info = update_info(
+            &fetched,
+            UpdateChannel::Stable,
+            &Version::new(1, 2, 0),
+            "device-a",
+            "windows-x86_64",
+        )
+        .unwrap();
+        let asset = PlatformAsset {
+            url: source.asset_url(&info.download_url).unwrap(),
+            sha256: info.sha256.clone(),
+            size: info.size,
+        };
+
+        let dir = std::env::temp_dir().join(format!("updates-{}", Uuid::new_v4()));
+        let mut progress = Vec::new();
+        let path = fetch_installer(
+            &client,
+            &asset,
+            &dir,
+            &public_key,
+            &test_limits(),
+            &Notify::new(),
+            |p| progress.push(p.downloaded),
+        )
+        .await
+        .unwrap();
+        assert_eq!(path.file_name().unwrap(), name);
+        assert_eq!(progress.last(), Some(&(installer.len() as u64)));
+        verify_downloaded(&public_key, &path).unwrap();
+
+        // Swapped on the drive: the copy is checked like a download
+        std::fs::write(bundle.join(name), b"MSI something else").unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let error = fetch_installer(
+            &client,
+            &asset,
+            &dir,
+            &public_key,
+            &test_limits(),
+            &Notify::new(),
+            |_| {},
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.code, ErrorCode::UpdateVerificationFailed);
+        assert!(!dir.join(name).exists());
+
+        std::fs::remove_dir_all(&bundle).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_best_offer() {
+        let offer = |version: &str, update_available: bool, download_url: &str| UpdateInfo {
+            current_version: "1.2.0".to_string(),
+            latest_version: version.to_string(),
+            update_available,
+            required: false,
+            channel: UpdateChannel::Stable,
+            ahead_of_latest: false,
+            prerelease: false,
+            release_notes: String::new(),
+            download_url: download_url.to_string(),
+            sha256: String::new(),
+            size: 0,
+        };
+        let usb = offer("1.3.0", true, "file:///media/usb/staff-admin_1.3.0_x64.msi");
+        let server = offer(
+            "1.3.0",
+            true,
+            "https://releases.ibimina.rw/staff-admin_1.3.0_x64.msi",
+        );
+        let newer = offer(
+            "1.3.1",
+            true,
+            "https://releases.ibimina.rw/staff-admin_1.3.1_x64.msi",
+        );
+
+        // The local copy wins for the same release, but not over a newer one
+        let best = best_offer(vec![usb.clone(), server.clone()]).unwrap();
+        assert_eq!(best.download_url, usb.download_url);
+        let best = best_offer(vec![usb.clone(), newer.clone()]).unwrap();
+        assert_eq!(best.download_url, newer.download_url);
+
+        // A newer release still rolling out elsewhere doesn't hide one that's here
+        let not_yet = offer(
+            "1.4.0",
+            false,
+            "https://releases.ibimina.rw/staff-admin_1.4.0_x64.msi",
+        );
+        let best = best_offer(vec![usb.clone(), not_yet]).unwrap();
+        assert_eq!(best.download_url, usb.download_url);
+        assert!(best_offer(Vec::new()).is_none());
+    }
+
+    #[test]
+    fn test_only_newer_offer_downloads() {
+        let current = Version::parse("1.2.0").unwrap();
+        let offer = |version: &str, update_available: bool| UpdateInfo {
+            current_version: current.to_string(),
+            latest_version: version.to_string(),
+            update_available,
+            required: false,
+            channel: UpdateChannel::Stable,
+            ahead_of_latest: false,
+            prerelease: false,
+            release_notes: String::new(),
+            download_url: format!(
+                "https://releases.ibimina.rw/staff-admin_{}_x64.msi",
+                version
+            ),
+            sha256: "ab".repeat(32),
+            size: 1024,
+        };
+        let state = UpdateDownload::default();
+        assert!(state.offered(&current).is_err());
+
+        state.offer(&offer("1.3.0", true)).unwrap();
+        let (version, asset) = state.offered(&current).unwrap();
+        assert_eq!(version, Version::parse("1.3.0").unwrap());
+        assert_eq!(asset.size, 1024);
+        // Nothing to install until it's downloaded
+        assert!(state.installer(&current).is_err());
+        let installer = PathBuf::from("/updates/downloads/staff-admin_1.3.0_x64.msi");
+        state.downloaded(&version, &installer).unwrap();
+        assert_eq!(state.installer(&current).unwrap(), installer);
+        // Once the update is running it can't be installed again
+        assert!(state.installer(&version).is_err());
+
+        // A later check finding nothing newer clears the offer
+        state.offer(&offer("1.2.0", false)).unwrap();
+        assert!(state.offered(&current).is_err());
+        assert!(state.installer(&current).is_err());
+
+        // An offer for an older version is refused even if the check
+        // claimed it was an update
+        state.offer(&offer("1.1.0", true)).unwrap();
+        let err = state.offered(&current).unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidInput);
+    }
+
+    #[test]
+    fn test_accepted_manifest() {
+        let dir = std::env::temp_dir().join(format!("updates-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        assert_eq!(read_accepted(&dir).unwrap(), None);
+
+        let published_at = "2026-10-01T08:00:00Z".parse().unwrap();
+        write_accepted(&dir, published_at).unwrap();
+        assert_eq!(read_accepted(&dir).unwrap(), Some(published_at));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_update_preferences() {
+        let path = std::env::temp_dir().join(format!("update-preferences-{}.json", Uuid::new_v4()));
+        assert_eq!(
+            read_preferences(&path).unwrap(),
+            UpdatePreferences::default()
+        );
+
+        let preferences = UpdatePreferences {
+            channel: Some(UpdateChannel::Pilot),
+            sacco_id: Some(Uuid::new_v4()),
+            sources: vec![UpdateSource::Folder {
+                path: PathBuf::from("/media/usb/staff-admin"),
+            }],
+        };
+        write_preferences(&path, &preferences).unwrap();
+        assert_eq!(read_preferences(&path).unwrap(), preferences);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,400 @@
+// Crate-wide error type returned by every Tauri command
+//
+// Serialized to the webview as
+// `{ code, category, retryable, message, detail }` so the frontend can branch
+// on `code` instead of matching message strings. `message` is safe to show to
+// staff; `detail` carries the underlying technical error for logs.
+
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+
+pub type Result<T, E = AppError> = std::result::Result<T, E>;
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    Auth,
+    Keychain,
+    Network,
+    Printer,
+    Device,
+    Storage,
+    Validation,
+    Internal,
+    /// Stopped at the user's request; not a failure to report
+    Cancelled,
+}
+
+/// Stable machine-readable error codes; never rename a variant
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    NotSignedIn,
+    PermissionDenied,
+    KeychainLocked,
+    KeychainUnavailable,
+    KeychainError,
+    NetworkTimeout,
+    NetworkUnavailable,
+    HttpError,
+    InvalidResponse,
+    /// Stopped at the user's request
+    Cancelled,
+    PrinterNotFound,
+    PrinterOffline,
+    PrintFailed,
+    /// The printer accepted a job but did not confirm that it printed
+    PrintUnconfirmed,
+    DeviceNotFound,
+    DeviceBusy,
+    DeviceError,
+    Unsupported,
+    FileNotFound,
+    StorageError,
+    /// Every reserved receipt number has been used
+    ReceiptNumbersExhausted,
+    InvalidInput,
+    /// An update's signature or checksum did not match
+    UpdateVerificationFailed,
+    DecodeFailed,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn category(self) -> ErrorCategory {
+        use ErrorCode::*;
+        match self {
+            NotSignedIn | PermissionDenied => ErrorCategory::Auth,
+            KeychainLocked | KeychainUnavailable | KeychainError => ErrorCategory::Keychain,
+            NetworkTimeout | NetworkUnavailable | HttpError | InvalidResponse => {
+                ErrorCategory::Network
+            }
+            Cancelled => ErrorCategory::Cancelled,
+            PrinterNotFound | PrinterOffline | PrintFailed | PrintUnconfirmed => {
+                ErrorCategory::Printer
+            }
+            DeviceNotFound | DeviceBusy | DeviceError | Unsupported => ErrorCategory::Device,
+            FileNotFound | StorageError | ReceiptNumbersExhausted => ErrorCategory::Storage,
+            InvalidInput | DecodeFailed | UpdateVerificationFailed => ErrorCategory::Validation,
+            Internal => ErrorCategory::Internal,
+        }
+    }
+
+    /// Whether trying the same operation again may succeed
+    pub fn default_retryable(self) -> bool {
+        use ErrorCode::*;
+        matches!(
+            self,
+            KeychainLocked
+                | NetworkTimeout
+                | NetworkUnavailable
+                | PrinterOffline
+                | PrintFailed
+                | DeviceBusy
+        )
+    }
+
+    pub fn default_message(self) -> &'static str {
+        use ErrorCode::*;
+        match self {
+            NotSignedIn => "Please sign in again",
+            PermissionDenied => "You are not allowed to do this",
+            KeychainLocked => "The system keychain is locked",
+            KeychainUnavailable => "The system keychain is not available",
+            KeychainError => "Could not access the system keychain",
+            NetworkTimeout => "The connection timed out",
+            NetworkUnavailable => "Could not reach the server",
+            HttpError => "The server returned an error",
+            InvalidResponse => "The server sent an unexpected response",
+            Cancelled => "Cancelled",
+            PrinterNotFound => "Printer not found",
+            PrinterOffline => "The printer is offline",
+            PrintFailed => "Printing failed",
+            PrintUnconfirmed => {
+                "The printer has not confirmed the job; check it before printing again"
+            }
+            DeviceNotFound => "Device not found",
+            DeviceBusy => "The device is busy",
+            DeviceError => "The device reported an error",
+            Unsupported => "Not supported on this computer",
+            FileNotFound => "File not found",
+            StorageError => "Could not read or write local data",
+            ReceiptNumbersExhausted => "No receipt numbers left on this computer",
+            InvalidInput => "Invalid input",
+            UpdateVerificationFailed => "The update could not be verified and was not installed",
+            DecodeFailed => "Could not read the data",
+            Internal => "Something went wrong",
+        }
+    }
+}
+
+/// Deserializable so errors can be stored with print jobs; `category` is
+/// derived from `code` and ignored on the way back in
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AppError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub detail: Option<String>,
+    pub retryable: bool,
+}
+
+impl AppError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            detail: None,
+            retryable: code.default_retryable(),
+        }
+    }
+
+    /// Error with the code's default user-facing message
+    pub fn from_code(code: ErrorCode) -> Self {
+        Self::new(code, code.default_message())
+    }
+
+    pub fn with_detail(mut self, detail: impl fmt::Display) -> Self {
+        self.detail = Some(detail.to_string());
+        self
+    }
+
+    pub fn with_retryable(mut self, retryable: bool) -> Self {
+        self.retryable = retryable;
+        self
+    }
+
+    pub fn category(&self) -> ErrorCategory {
+        self.code.category()
+    }
+
+    pub fn invalid_input(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::InvalidInput, message)
+    }
+
+    pub fn internal(detail: impl fmt::Display) -> Self {
+        Self::from_code(ErrorCode::Internal).with_detail(detail)
+    }
+
+    /// Error for a non-success HTTP response; 5xx and 429 are worth retrying
+    pub fn http_status(status: reqwest::StatusCode) -> Self {
+        Self::new(
+            ErrorCode::HttpError,
+            format!("The server returned an error ({})", status.as_u16()),
+        )
+        .with_retryable(status.is_server_error() || status.as_u16() == 429)
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.detail {
+            Some(detail) => write!(f, "{}: {}", self.message, detail),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("AppError", 5)?;
+        s.serialize_field("code", &self.code)?;
+        s.serialize_field("category", &self.category())?;
+        s.serialize_field("retryable", &self.retryable)?;
+        s.serialize_field("message", &self.message)?;
+        s.serialize_field("detail", &self.detail)?;
+        s.end()
+    }
+}
+
+/// Prefix the detail with what was being done, keeping the user-facing message
+pub trait Context<T> {
+    fn context(self, context: impl Into<String>) -> Result<T>;
+}
+
+impl<T, E: Into<AppError>> Context<T> for std::result::Result<T, E> {
+    fn context(self, context: impl Into<String>) -> Result<T> {
+        self.map_err(|e| {
+            let mut error = e.into();
+            let context = context.into();
+            error.detail = Some(match error.detail.take() {
+                Some(detail) => format!("{}: {}", context, detail),
+                None => context,
+            });
+            error
+        })
+    }
+}
+
+impl From<keyring::Error> for AppError {
+    fn from(e: keyring::Error) -> Self {
+        let code = match &e {
+            keyring::Error::NoStorageAccess(_) => ErrorCode::KeychainLocked,
+            keyring::Error::PlatformFailure(_) => ErrorCode::KeychainUnavailable,
+            _ => ErrorCode::KeychainError,
+        };
+        Self::from_code(code).with_detail(e)
+    }
+}
+
+impl From<reqwest::Error> for AppError {
+    fn from(e: reqwest::Error) -> Self {
+        if let Some(status) = e.status() {
+            return Self::http_status(status).with_detail(e);
+        }
+
+        let code = if e.is_timeout() {
+            ErrorCode::NetworkTimeout
+        } else if e.is_connect() || e.is_request() {
+            ErrorCode::NetworkUnavailable
+        } else if e.is_decode() || e.is_body() {
+            ErrorCode::InvalidResponse
+        } else {
+            ErrorCode::Internal
+        };
+        Self::from_code(code).with_detail(e)
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        use std::io::ErrorKind;
+        let code = match e.kind() {
+            ErrorKind::NotFound => ErrorCode::FileNotFound,
+            ErrorKind::PermissionDenied => ErrorCode::PermissionDenied,
+            ErrorKind::TimedOut => ErrorCode::NetworkTimeout,
+            _ => ErrorCode::StorageError,
+        };
+        Self::from_code(code).with_detail(e)
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(e: serde_json::Error) -> Self {
+        let code = if e.is_io() {
+            ErrorCode::StorageError
+        } else {
+            ErrorCode::DecodeFailed
+        };
+        Self::from_code(code).with_detail(e)
+    }
+}
+
+impl From<base64::DecodeError> for AppError {
+    fn from(e: base64::DecodeError) -> Self {
+        Self::from_code(ErrorCode::DecodeFailed).with_detail(e)
+    }
+}
+
+impl From<std::string::FromUtf8Error> for AppError {
+    fn from(e: std::string::FromUtf8Error) -> Self {
+        Self::from_code(ErrorCode::DecodeFailed).with_detail(e)
+    }
+}
+
+impl From<serialport::Error> for AppError {
+    fn from(e: serialport::Error) -> Self {
+        let code = match e.kind() {
+            serialport::ErrorKind::NoDevice => ErrorCode::DeviceNotFound,
+            serialport::ErrorKind::InvalidInput => ErrorCode::InvalidInput,
+            serialport::ErrorKind::Io(std::io::ErrorKind::PermissionDenied) => {
+                ErrorCode::PermissionDenied
+            }
+            serialport::ErrorKind::Io(std::io::ErrorKind::NotFound) => ErrorCode::DeviceNotFound,
+            _ => ErrorCode::DeviceError,
+        };
+        Self::from_code(code).with_detail(e)
+    }
+}
+
+impl From<tauri::Error> for AppError {
+    fn from(e: tauri::Error) -> Self {
+        Self::internal(e)
+    }
+}
+
+impl From<tokio::task::JoinError> for AppError {
+    fn from(e: tokio::task::JoinError) -> Self {
+        Self::internal(e)
+    }
+}
+
+impl<T> From<std::sync::PoisonError<T>> for AppError {
+    fn from(e: std::sync::PoisonError<T>) -> Self {
+        Self::internal(format!("Lock error: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialized_shape() {
+        let error = AppError::from_code(ErrorCode::PrinterOffline).with_detail("lp: paused");
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            serde_json::json!({
+                "code": "PRINTER_OFFLINE",
+                "category": "printer",
+                "retryable": true,
+                "message": "The printer is offline",
+                "detail": "lp: paused",
+            })
+        );
+
+        let round_trip: AppError =
+            serde_json::from_value(serde_json::to_value(&error).unwrap()).unwrap();
+        assert_eq!(round_trip, error);
+    }
+
+    #[test]
+    fn test_context_keeps_message_user_facing() {
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "no such file");
+        let error = Err::<(), _>(io)
+            .context("Failed to read card trust store")
+            .unwrap_err();
+
+        assert_eq!(error.code, ErrorCode::FileNotFound);
+        assert_eq!(error.message, "File not found");
+        assert_eq!(
+            error.detail.as_deref(),
+            Some("Failed to read card trust store: no such file")
+        );
+
+        let error = Err::<(), _>(AppError::invalid_input("Copies must be at least 1"))
+            .context("Failed to print labels")
+            .unwrap_err();
+        assert_eq!(error.message, "Copies must be at least 1");
+        assert_eq!(error.detail.as_deref(), Some("Failed to print labels"));
+    }
+
+    #[test]
+    fn test_io_timeout_and_cancel_codes() {
+        let timeout = AppError::from(std::io::Error::from(std::io::ErrorKind::TimedOut));
+        assert_eq!(timeout.code, ErrorCode::NetworkTimeout);
+        assert_eq!(timeout.category(), ErrorCategory::Network);
+        assert!(timeout.retryable);
+
+        let cancelled = AppError::from_code(ErrorCode::Cancelled);
+        assert_eq!(cancelled.category(), ErrorCategory::Cancelled);
+        assert!(!cancelled.retryable);
+    }
+
+    #[test]
+    fn test_keyring_mapping() {
+        let error = AppError::from(keyring::Error::NoStorageAccess("locked".into()));
+        assert_eq!(error.code, ErrorCode::KeychainLocked);
+        assert_eq!(error.category(), ErrorCategory::Keychain);
+        assert!(error.retryable);
+    }
+
+    #[test]
+    fn test_http_status_retryable() {
+        assert!(AppError::http_status(reqwest::StatusCode::BAD_GATEWAY).retryable);
+        assert!(AppError::http_status(reqwest::StatusCode::TOO_MANY_REQUESTS).retryable);
+        assert!(!AppError::http_status(reqwest::StatusCode::NOT_FOUND).retryable);
+    }
+}
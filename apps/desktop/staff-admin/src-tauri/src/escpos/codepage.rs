@@ -0,0 +1,250 @@
+// Single-byte code pages for receipt text
+//
+// Thermal printers don't understand UTF-8. Text is encoded in the printer's
+// selected code page, and characters the page lacks are transliterated
+// (e.g. "œ" -> "oe", curly quotes -> straight) before falling back to "?".
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CodePage {
+    /// US English; the power-on default of most printers
+    Cp437,
+    /// Multilingual Latin-1
+    Cp850,
+    /// CP850 with the euro sign
+    #[default]
+    Cp858,
+    /// Windows-1252
+    Wpc1252,
+}
+
+#[rustfmt::skip]
+const CP437_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{A0}',
+];
+
+#[rustfmt::skip]
+const CP850_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', 'ø', '£', 'Ø', '×', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '®', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', 'Á', 'Â', 'À', '©', '╣', '║', '╗', '╝', '¢', '¥', '┐',
+    '└', '┴', '┬', '├', '─', '┼', 'ã', 'Ã', '╚', '╔', '╩', '╦', '╠', '═', '╬', '¤',
+    'ð', 'Ð', 'Ê', 'Ë', 'È', 'ı', 'Í', 'Î', 'Ï', '┘', '┌', '█', '▄', '¦', 'Ì', '▀',
+    'Ó', 'ß', 'Ô', 'Ò', 'õ', 'Õ', 'µ', 'þ', 'Þ', 'Ú', 'Û', 'Ù', 'ý', 'Ý', '¯', '´',
+    '\u{AD}', '±', '‗', '¾', '¶', '§', '÷', '¸', '°', '¨', '·', '¹', '³', '²', '■', '\u{A0}',
+];
+
+/// Windows-1252 0x80-0x9F; 0xA0-0xFF match Latin-1
+#[rustfmt::skip]
+const WPC1252_C1: [Option<char>; 32] = [
+    Some('€'), None, Some('‚'), Some('ƒ'), Some('„'), Some('…'), Some('†'), Some('‡'),
+    Some('ˆ'), Some('‰'), Some('Š'), Some('‹'), Some('Œ'), None, Some('Ž'), None,
+    None, Some('‘'), Some('’'), Some('“'), Some('”'), Some('•'), Some('–'), Some('—'),
+    Some('˜'), Some('™'), Some('š'), Some('›'), Some('œ'), None, Some('ž'), Some('Ÿ'),
+];
+
+impl CodePage {
+    /// `n` for ESC t n (Epson numbering)
+    pub fn escpos_table(self) -> u8 {
+        match self {
+            CodePage::Cp437 => 0,
+            CodePage::Cp850 => 2,
+            CodePage::Cp858 => 19,
+            CodePage::Wpc1252 => 16,
+        }
+    }
+
+    /// Code page selected by ESC t `n`, if it is one of ours
+    pub fn from_escpos_table(n: u8) -> Option<Self> {
+        [
+            CodePage::Cp437,
+            CodePage::Cp850,
+            CodePage::Cp858,
+            CodePage::Wpc1252,
+        ]
+        .into_iter()
+        .find(|page| page.escpos_table() == n)
+    }
+
+    /// Character a byte prints as; unassigned bytes print as '?'
+    pub fn decode(self, b: u8) -> char {
+        if b < 0x80 {
+            return b as char;
+        }
+
+        let high = (b - 0x80) as usize;
+        match self {
+            CodePage::Cp437 => CP437_HIGH[high],
+            CodePage::Cp850 => CP850_HIGH[high],
+            CodePage::Cp858 if b == 0xD5 => '€',
+            CodePage::Cp858 => CP850_HIGH[high],
+            CodePage::Wpc1252 if b >= 0xA0 => b as char,
+            CodePage::Wpc1252 => WPC1252_C1[high].unwrap_or('?'),
+        }
+    }
+
+    /// Byte for a character, if this code page has it
+    pub fn encode_char(self, c: char) -> Option<u8> {
+        if c == '\n' || (' '..='~').contains(&c) {
+            return Some(c as u8);
+        }
+
+        let position = |table: &[char; 128]| table.iter().position(|&t| t == c);
+        match self {
+            CodePage::Cp437 => position(&CP437_HIGH).map(|i| 0x80 + i as u8),
+            CodePage::Cp850 => position(&CP850_HIGH).map(|i| 0x80 + i as u8),
+            // CP858 swaps the dotless i at 0xD5 for the euro sign
+            CodePage::Cp858 => match c {
+                '€' => Some(0xD5),
+                'ı' => None,
+                _ => position(&CP850_HIGH).map(|i| 0x80 + i as u8),
+            },
+            CodePage::Wpc1252 => match c as u32 {
+                0xA0..=0xFF => Some(c as u8),
+                _ => WPC1252_C1
+                    .iter()
+                    .position(|&t| t == Some(c))
+                    .map(|i| 0x80 + i as u8),
+            },
+        }
+    }
+
+    /// Text as it will print, with what the code page can't represent
+    /// transliterated; each character is one printed column
+    pub fn printable(self, text: &str) -> String {
+        let mut printable = String::with_capacity(text.len());
+        for c in text.chars() {
+            if self.encode_char(c).is_some() {
+                printable.push(c);
+                continue;
+            }
+            match transliterate(c) {
+                Some(replacement) => printable.extend(
+                    replacement
+                        .chars()
+                        .map(|r| if self.encode_char(r).is_some() { r } else { '?' }),
+                ),
+                None => printable.push('?'),
+            }
+        }
+        printable
+    }
+
+    /// Encode text, transliterating what the code page can't represent
+    pub fn encode(self, text: &str) -> Vec<u8> {
+        self.printable(text)
+            .chars()
+            .map(|c| self.encode_char(c).unwrap_or(b'?'))
+            .collect()
+    }
+}
+
+/// Closest plain-ASCII spelling of a character
+pub fn transliterate(c: char) -> Option<&'static str> {
+    Some(match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' => "a",
+        'À' | 'Á' | 'Â' | 'Ã' | 'Ä' | 'Å' | 'Ā' => "A",
+        'è' | 'é' | 'ê' | 'ë' | 'ē' => "e",
+        'È' | 'É' | 'Ê' | 'Ë' | 'Ē' => "E",
+        'ì' | 'í' | 'î' | 'ï' | 'ī' | 'ı' => "i",
+        'Ì' | 'Í' | 'Î' | 'Ï' | 'Ī' => "I",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' => "o",
+        'Ò' | 'Ó' | 'Ô' | 'Õ' | 'Ö' | 'Ø' | 'Ō' => "O",
+        'ù' | 'ú' | 'û' | 'ü' | 'ū' => "u",
+        'Ù' | 'Ú' | 'Û' | 'Ü' | 'Ū' => "U",
+        'ý' | 'ÿ' => "y",
+        'Ý' | 'Ÿ' => "Y",
+        'ç' => "c",
+        'Ç' => "C",
+        'ñ' => "n",
+        'Ñ' => "N",
+        'œ' => "oe",
+        'Œ' => "OE",
+        'æ' => "ae",
+        'Æ' => "AE",
+        'ß' => "ss",
+        '‘' | '’' | '‚' | '′' | 'ʼ' => "'",
+        '“' | '”' | '„' | '«' | '»' => "\"",
+        '–' | '—' | '‐' | '−' | '\u{AD}' => "-",
+        '…' => "...",
+        '•' | '·' => "*",
+        '€' => "EUR",
+        '\u{A0}' | '\u{202F}' | '\t' => " ",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_french_accents() {
+        let name = "Hélène Françoise Mukamériza";
+        assert_eq!(
+            CodePage::Cp850.encode(name),
+            b"H\x82l\x8ane Fran\x87oise Mukam\x82riza".to_vec()
+        );
+        assert_eq!(
+            CodePage::Wpc1252.encode(name),
+            b"H\xe9l\xe8ne Fran\xe7oise Mukam\xe9riza".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_euro_and_capitals() {
+        assert_eq!(CodePage::Cp858.encode("€ É"), vec![0xD5, b' ', 0x90]);
+        assert_eq!(CodePage::Cp850.encode("€"), b"EUR".to_vec());
+        assert_eq!(CodePage::Wpc1252.encode("€"), vec![0x80]);
+        // CP437 has no capital Ê
+        assert_eq!(CodePage::Cp437.encode("Ê"), b"E".to_vec());
+    }
+
+    #[test]
+    fn test_transliteration_fallback() {
+        assert_eq!(
+            CodePage::Cp858.encode("Mwalimu n’Imana — œuvre…"),
+            b"Mwalimu n'Imana - oeuvre...".to_vec()
+        );
+        assert_eq!(CodePage::Cp858.encode("日"), b"?".to_vec());
+        assert_eq!(CodePage::Cp858.printable("Cœur…"), "Coeur...");
+    }
+
+    #[test]
+    fn test_tables_round_trip() {
+        for (i, &c) in CP850_HIGH.iter().enumerate() {
+            assert_eq!(CodePage::Cp850.encode_char(c), Some(0x80 + i as u8));
+        }
+        for (i, &c) in CP437_HIGH.iter().enumerate() {
+            assert_eq!(CodePage::Cp437.encode_char(c), Some(0x80 + i as u8));
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        for page in [
+            CodePage::Cp437,
+            CodePage::Cp850,
+            CodePage::Cp858,
+            CodePage::Wpc1252,
+        ] {
+            assert_eq!(CodePage::from_escpos_table(page.escpos_table()), Some(page));
+            let name = "Hélène Françoise";
+            let decoded: String = page.encode(name).iter().map(|&b| page.decode(b)).collect();
+            assert_eq!(decoded, name);
+        }
+        assert_eq!(CodePage::Cp858.decode(0xD5), '€');
+        assert_eq!(CodePage::Wpc1252.decode(0x81), '?');
+        assert_eq!(CodePage::from_escpos_table(7), None);
+    }
+}
@@ -0,0 +1,625 @@
+// ESC/POS emulator
+//
+// Interprets the bytes we send to receipt printers and draws what a printer
+// would put on paper: Font A text with alignment, bold, underline, inverse and
+// magnification, GS v 0 rasters, native QR codes, Code128 barcodes and cuts.
+// It backs the receipt preview in the print dialog, and lets tests check
+// receipt output the way a printer would see it.
+//
+// Only the commands our document builders emit (plus the drawer kick and line
+// spacing) are understood; anything else is reported rather than guessed at.
+
+use image::GrayImage;
+use rxing::oned::{Code128Writer, OneDimensionalCodeWriter};
+
+use super::codepage::CodePage;
+use super::preview::{self, CellStyle, CELL_HEIGHT, CELL_WIDTH, INK, PAPER};
+use super::qr::{self, QrErrorCorrection};
+use super::raster::Bitmap;
+use super::{Align, PaperWidth};
+use crate::error::{AppError, Result};
+
+const ESC: u8 = 0x1B;
+const GS: u8 = 0x1D;
+const LF: u8 = 0x0A;
+const CR: u8 = 0x0D;
+
+/// ESC 2 line spacing, 1/6 inch at 203 dpi
+const DEFAULT_LINE_SPACING: u32 = 30;
+/// Paper around a cut mark, in dots
+const CUT_MARGIN: u32 = 8;
+
+/// What came out of the printer
+#[derive(Debug)]
+pub struct Printout {
+    pub image: GrayImage,
+    /// Each printed text line, decoded from the printer's code page
+    pub lines: Vec<String>,
+    /// Distance of each cut from the top of the paper, in dots
+    pub cuts: Vec<u32>,
+}
+
+impl Printout {
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    pub fn png(self) -> Result<Vec<u8>> {
+        preview::encode_png(self.image)
+    }
+}
+
+/// Run ESC/POS bytes through the emulator
+pub fn emulate(bytes: &[u8], paper: PaperWidth) -> Result<Printout> {
+    let mut printer = Printer::new(paper);
+    let mut reader = Reader { bytes, pos: 0 };
+
+    while let Some(b) = reader.next() {
+        match b {
+            LF => printer.print_line(),
+            CR => {}
+            ESC => printer.esc(&mut reader)?,
+            GS => printer.gs(&mut reader)?,
+            0x20.. => printer.put(b),
+            _ => return Err(reader.unsupported(&format!("0x{:02X}", b))),
+        }
+    }
+    // Printers hold an unterminated line until the next LF; show it anyway
+    if !printer.line.is_empty() {
+        printer.print_line();
+    }
+
+    Ok(printer.finish())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn next(&mut self) -> Option<u8> {
+        let b = self.bytes.get(self.pos).copied()?;
+        self.pos += 1;
+        Some(b)
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.pos + n > self.bytes.len() {
+            return Err(AppError::invalid_input("ESC/POS data ends mid-command")
+                .with_detail(format!("byte {}", self.pos)));
+        }
+        let taken = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<usize> {
+        let bytes = self.take(2)?;
+        Ok(bytes[0] as usize | (bytes[1] as usize) << 8)
+    }
+
+    fn unsupported(&self, command: &str) -> AppError {
+        AppError::invalid_input(format!("Unsupported ESC/POS command {}", command))
+            .with_detail(format!("byte {}", self.pos.saturating_sub(1)))
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Cell {
+    c: char,
+    style: CellStyle,
+    underline: u32,
+}
+
+impl Cell {
+    fn width(&self) -> u32 {
+        CELL_WIDTH * self.style.size.0 as u32
+    }
+
+    fn height(&self) -> u32 {
+        CELL_HEIGHT * self.style.size.1 as u32
+    }
+}
+
+/// Settings reset by ESC @
+struct State {
+    code_page: CodePage,
+    align: Align,
+    bold: bool,
+    underline: u32,
+    inverse: bool,
+    size: (u8, u8),
+    line_spacing: u32,
+    qr_module_size: u8,
+    qr_error_correction: QrErrorCorrection,
+    qr_data: Vec<u8>,
+    barcode_height: u32,
+    barcode_module: u32,
+    barcode_hri: u8,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            code_page: CodePage::Cp437,
+            align: Align::Left,
+            bold: false,
+            underline: 0,
+            inverse: false,
+            size: (1, 1),
+            line_spacing: DEFAULT_LINE_SPACING,
+            qr_module_size: 3,
+            qr_error_correction: QrErrorCorrection::Low,
+            qr_data: Vec::new(),
+            barcode_height: 162,
+            barcode_module: 3,
+            barcode_hri: 0,
+        }
+    }
+}
+
+struct Printer {
+    width: u32,
+    /// Paper printed so far, `width` bytes per row
+    paper: Vec<u8>,
+    lines: Vec<String>,
+    cuts: Vec<u32>,
+    state: State,
+    line: Vec<Cell>,
+    line_align: Align,
+}
+
+impl Printer {
+    fn new(paper: PaperWidth) -> Self {
+        Self {
+            width: paper.dots() as u32,
+            paper: Vec::new(),
+            lines: Vec::new(),
+            cuts: Vec::new(),
+            state: State::default(),
+            line: Vec::new(),
+            line_align: Align::Left,
+        }
+    }
+
+    fn height(&self) -> u32 {
+        (self.paper.len() / self.width as usize) as u32
+    }
+
+    fn finish(self) -> Printout {
+        let height = self.height().max(1);
+        let mut paper = self.paper;
+        paper.resize((self.width * height) as usize, PAPER);
+        Printout {
+            image: GrayImage::from_raw(self.width, height, paper).expect("paper is whole rows"),
+            lines: self.lines,
+            cuts: self.cuts,
+        }
+    }
+
+    fn feed(&mut self, dots: u32) {
+        let len = self.paper.len() + (self.width * dots) as usize;
+        self.paper.resize(len, PAPER);
+    }
+
+    /// A blank strip of paper to draw on before it is appended
+    fn strip(&self, height: u32) -> GrayImage {
+        GrayImage::from_pixel(self.width, height, image::Luma([PAPER]))
+    }
+
+    fn append(&mut self, strip: GrayImage) {
+        self.paper.extend_from_slice(strip.as_raw());
+    }
+
+    /// Left edge of something `width` dots wide under the given alignment
+    fn offset(&self, align: Align, width: u32) -> u32 {
+        let spare = self.width.saturating_sub(width);
+        match align {
+            Align::Left => 0,
+            Align::Center => spare / 2,
+            Align::Right => spare,
+        }
+    }
+
+    fn put(&mut self, b: u8) {
+        let cell = Cell {
+            c: self.state.code_page.decode(b),
+            style: CellStyle {
+                bold: self.state.bold,
+                inverse: self.state.inverse,
+                size: self.state.size,
+            },
+            underline: self.state.underline,
+        };
+
+        // Text that overflows the line wraps onto the next one
+        let used: u32 = self.line.iter().map(Cell::width).sum();
+        if used + cell.width() > self.width {
+            self.print_line();
+        }
+        if self.line.is_empty() {
+            self.line_align = self.state.align;
+        }
+        self.line.push(cell);
+    }
+
+    fn print_line(&mut self) {
+        let cells = std::mem::take(&mut self.line);
+        let text_height = cells.iter().map(Cell::height).max().unwrap_or(0);
+        let mut strip = self.strip(text_height.max(self.state.line_spacing));
+
+        let text_width = cells.iter().map(Cell::width).sum();
+        let mut x = self.offset(self.line_align, text_width);
+        for cell in &cells {
+            // Characters of different heights share a baseline
+            let top = text_height - cell.height();
+            preview::draw_char(&mut strip, x, top, cell.c, cell.style);
+            if cell.underline > 0 {
+                let color = if cell.style.inverse { PAPER } else { INK };
+                let thickness = cell.underline;
+                preview::fill(
+                    &mut strip,
+                    x,
+                    text_height - thickness,
+                    cell.width(),
+                    thickness,
+                    color,
+                );
+            }
+            x += cell.width();
+        }
+
+        self.lines.push(cells.iter().map(|cell| cell.c).collect());
+        self.append(strip);
+    }
+
+    fn flush(&mut self) {
+        if !self.line.is_empty() {
+            self.print_line();
+        }
+    }
+
+    /// Print a 1-bit image at the current alignment, scaled by whole dots
+    fn print_bitmap(&mut self, bitmap: &Bitmap, scale_x: u32, scale_y: u32) {
+        self.flush();
+        let width = bitmap.width as u32 * scale_x;
+        let mut strip = self.strip(bitmap.height as u32 * scale_y);
+        let left = self.offset(self.state.align, width);
+
+        for y in 0..bitmap.height {
+            for x in 0..bitmap.width {
+                if bitmap.get(x, y) {
+                    let (px, py) = (left + x as u32 * scale_x, y as u32 * scale_y);
+                    preview::fill(&mut strip, px, py, scale_x, scale_y, INK);
+                }
+            }
+        }
+        self.append(strip);
+    }
+
+    fn cut(&mut self) {
+        self.flush();
+        let mut strip = self.strip(CUT_MARGIN * 2 + 1);
+        for x in (0..self.width).step_by(8) {
+            preview::fill(&mut strip, x, CUT_MARGIN, 4, 1, INK);
+        }
+        self.cuts.push(self.height() + CUT_MARGIN);
+        self.append(strip);
+    }
+
+    fn esc(&mut self, reader: &mut Reader) -> Result<()> {
+        let command = reader.byte()?;
+        match command {
+            b'@' => {
+                self.state = State::default();
+                self.line.clear();
+            }
+            b't' => {
+                let n = reader.byte()?;
+                self.state.code_page = CodePage::from_escpos_table(n).unwrap_or(CodePage::Cp437);
+            }
+            b'a' => {
+                self.state.align = match reader.byte()? % 48 {
+                    1 => Align::Center,
+                    2 => Align::Right,
+                    _ => Align::Left,
+                };
+            }
+            b'E' => self.state.bold = reader.byte()? & 1 == 1,
+            b'-' => self.state.underline = (reader.byte()? % 48).min(2) as u32,
+            b'd' => {
+                let lines = reader.byte()? as u32;
+                self.flush();
+                self.feed(lines * self.state.line_spacing);
+            }
+            b'J' => {
+                let dots = reader.byte()? as u32;
+                self.flush();
+                self.feed(dots);
+            }
+            b'2' => self.state.line_spacing = DEFAULT_LINE_SPACING,
+            b'3' => self.state.line_spacing = reader.byte()? as u32,
+            // Cash drawer kick: ESC p m t1 t2
+            b'p' => {
+                reader.take(3)?;
+            }
+            _ => return Err(reader.unsupported(&format!("ESC {}", command as char))),
+        }
+        Ok(())
+    }
+
+    fn gs(&mut self, reader: &mut Reader) -> Result<()> {
+        let command = reader.byte()?;
+        match command {
+            b'!' => {
+                let n = reader.byte()?;
+                self.state.size = (((n >> 4) & 0x07) + 1, (n & 0x07) + 1);
+            }
+            b'B' => self.state.inverse = reader.byte()? & 1 == 1,
+            b'V' => {
+                // Function B (65, 66) feeds n dots before cutting
+                if reader.byte()? >= 65 {
+                    let dots = reader.byte()? as u32;
+                    self.flush();
+                    self.feed(dots);
+                }
+                self.cut();
+            }
+            b'v' => self.raster(reader)?,
+            b'(' => self.function(reader)?,
+            b'h' => self.state.barcode_height = reader.byte()? as u32,
+            b'w' => self.state.barcode_module = reader.byte()?.clamp(1, 6) as u32,
+            b'H' => self.state.barcode_hri = reader.byte()? % 48,
+            b'k' => self.barcode(reader)?,
+            _ => return Err(reader.unsupported(&format!("GS {}", command as char))),
+        }
+        Ok(())
+    }
+
+    /// GS v 0 m xL xH yL yH d1...dk
+    fn raster(&mut self, reader: &mut Reader) -> Result<()> {
+        if reader.byte()? != b'0' {
+            return Err(reader.unsupported("GS v"));
+        }
+        let mode = reader.byte()? % 48;
+        let row_bytes = reader.u16()?;
+        let rows = reader.u16()?;
+        let data = reader.take(row_bytes * rows)?;
+
+        let mut bitmap = Bitmap::new(row_bytes * 8, rows);
+        for y in 0..rows {
+            for x in 0..row_bytes * 8 {
+                if data[y * row_bytes + x / 8] & (0x80 >> (x % 8)) != 0 {
+                    bitmap.set(x, y, true);
+                }
+            }
+        }
+        // Modes 1-3 double the width, the height, or both
+        let scale_x = if mode & 1 == 1 { 2 } else { 1 };
+        let scale_y = if mode & 2 == 2 { 2 } else { 1 };
+        self.print_bitmap(&bitmap, scale_x, scale_y);
+        Ok(())
+    }
+
+    /// GS ( k pL pH cn fn [parameters]; only QR codes (cn 49) are drawn
+    fn function(&mut self, reader: &mut Reader) -> Result<()> {
+        if reader.byte()? != b'k' {
+            return Err(reader.unsupported("GS ("));
+        }
+        let len = reader.u16()?;
+        let parameters = reader.take(len)?;
+        let (cn, function, args) = match parameters {
+            [cn, function, args @ ..] => (*cn, *function, args),
+            _ => return Err(reader.unsupported("GS ( k")),
+        };
+        if cn != 0x31 {
+            return Ok(());
+        }
+
+        match (function, args) {
+            (0x43, [size]) => self.state.qr_module_size = (*size).clamp(1, 16),
+            (0x45, [level]) => {
+                self.state.qr_error_correction = match level {
+                    49 => QrErrorCorrection::Medium,
+                    50 => QrErrorCorrection::Quartile,
+                    51 => QrErrorCorrection::High,
+                    _ => QrErrorCorrection::Low,
+                };
+            }
+            (0x50, [_, data @ ..]) => self.state.qr_data = data.to_vec(),
+            (0x51, _) => {
+                let data = String::from_utf8_lossy(&self.state.qr_data).into_owned();
+                let bitmap = qr::bitmap(
+                    &data,
+                    self.state.qr_module_size,
+                    self.state.qr_error_correction,
+                    self.width as usize,
+                )?;
+                self.print_bitmap(&bitmap, 1, 1);
+            }
+            // Model selection and anything else doesn't change the drawing
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// GS k m n d1...dn; only Code128 (m 73) is supported
+    fn barcode(&mut self, reader: &mut Reader) -> Result<()> {
+        let symbology = reader.byte()?;
+        if symbology != 73 {
+            return Err(reader.unsupported(&format!("GS k {}", symbology)));
+        }
+        let len = reader.byte()? as usize;
+        let text = code128_text(reader.take(len)?);
+
+        let modules = Code128Writer
+            .encode_oned(&text)
+            .map_err(|e| AppError::invalid_input("Cannot encode barcode").with_detail(e))?;
+        let module = self.state.barcode_module;
+        let height = self.state.barcode_height;
+
+        self.flush();
+        if self.state.barcode_hri & 1 == 1 {
+            self.print_hri(&text);
+        }
+        let mut strip = self.strip(height);
+        let left = self.offset(self.state.align, modules.len() as u32 * module);
+        for (i, &bar) in modules.iter().enumerate() {
+            if bar {
+                preview::fill(&mut strip, left + i as u32 * module, 0, module, height, INK);
+            }
+        }
+        self.append(strip);
+        if self.state.barcode_hri & 2 == 2 {
+            self.print_hri(&text);
+        }
+        Ok(())
+    }
+
+    /// Human-readable barcode text, always Font A at normal size
+    fn print_hri(&mut self, text: &str) {
+        let mut strip = self.strip(CELL_HEIGHT);
+        let width = text.chars().count() as u32 * CELL_WIDTH;
+        let mut x = self.offset(self.state.align, width);
+        for c in text.chars() {
+            x += preview::draw_char(&mut strip, x, 0, c, CellStyle::default());
+        }
+        self.append(strip);
+    }
+}
+
+/// The text a Code128 payload encodes, dropping "{A"-style code set switches
+fn code128_text(payload: &[u8]) -> String {
+    let mut text = String::new();
+    let mut code_c = false;
+    let mut bytes = payload.iter();
+
+    while let Some(&b) = bytes.next() {
+        if b == b'{' {
+            match bytes.next() {
+                Some(b'{') => text.push('{'),
+                Some(b'C') => code_c = true,
+                Some(b'A' | b'B') => code_c = false,
+                // FNC and SHIFT codes print nothing
+                _ => {}
+            }
+        } else if code_c {
+            text.push_str(&format!("{:02}", b));
+        } else {
+            text.push(b as char);
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::barcode;
+    use crate::escpos::{Cut, EscPos, PrinterProfile, TextSize};
+
+    /// Dark dots in a rectangle of the printout
+    fn ink(image: &GrayImage, x: u32, y: u32, width: u32, height: u32) -> usize {
+        (y..y + height)
+            .flat_map(|py| (x..x + width).map(move |px| (px, py)))
+            .filter(|&(px, py)| image.get_pixel(px, py).0[0] < 0x80)
+            .count()
+    }
+
+    #[test]
+    fn test_text_layout() {
+        let mut doc = EscPos::new(PaperWidth::Mm58);
+        doc.align(Align::Center)
+            .bold(true)
+            .line("Hélène")
+            .bold(false)
+            .align(Align::Right)
+            .size(TextSize::DOUBLE)
+            .line("RWF 500")
+            .size(TextSize::NORMAL)
+            .align(Align::Left)
+            .inverse(true)
+            .line(" COPY ")
+            .inverse(false)
+            .feed(2)
+            .cut(Cut::Full);
+
+        let printout = emulate(&doc.build(), PaperWidth::Mm58).unwrap();
+        assert_eq!(printout.lines, vec!["Hélène", "RWF 500", " COPY "]);
+        assert_eq!(printout.image.width(), 384);
+
+        // 30 + 48 + 30 dots of text, two 30-dot feeds, then the cut mark
+        assert_eq!(printout.cuts, vec![168 + CUT_MARGIN]);
+        assert_eq!(printout.image.height(), 168 + CUT_MARGIN * 2 + 1);
+
+        let image = &printout.image;
+        // Six centred characters occupy dots 156-228
+        assert_eq!(ink(image, 0, 0, 156, 30), 0);
+        assert!(ink(image, 156, 0, 72, 24) > 0);
+        assert_eq!(ink(image, 228, 0, 156, 30), 0);
+        // Seven double-width characters end at the right edge
+        assert_eq!(ink(image, 0, 30, 384 - 168, 48), 0);
+        assert!(ink(image, 384 - 24, 30, 24, 48) > 0);
+        // Inverse text is black, spaces included
+        assert_eq!(ink(image, 0, 78, 12, 24), 12 * 24);
+        assert_eq!(ink(image, 72, 78, 12, 24), 0);
+    }
+
+    #[test]
+    fn test_codes_scan() {
+        let mut logo = Bitmap::new(64, 32);
+        for y in 0..32 {
+            for x in 0..64 {
+                logo.set(x, y, (x / 8 + y / 8) % 2 == 0);
+            }
+        }
+
+        for native_qr in [true, false] {
+            let profile = PrinterProfile {
+                native_qr,
+                ..PaperWidth::Mm80.into()
+            };
+            let mut doc = EscPos::new(profile);
+            doc.align(Align::Center).image(&logo);
+            doc.qr_code(
+                "https://app.ibimina.rw/verify/receipt?p=42",
+                6,
+                QrErrorCorrection::Medium,
+            )
+            .unwrap();
+            doc.line("").code128("TWZ-000123").unwrap();
+            doc.cut(Cut::Partial);
+
+            let printout = emulate(&doc.build(), PaperWidth::Mm80).unwrap();
+            // The logo is centred: 256 dots of margin on each side
+            assert_eq!(ink(&printout.image, 0, 0, 256, 32), 0);
+            assert_eq!(ink(&printout.image, 256, 0, 64, 32), 32 * 32);
+
+            let (width, height) = printout.image.dimensions();
+            let decoded = barcode::decode_luma(printout.image.into_raw(), width, height);
+            assert!(decoded.contains(&(
+                "QR_CODE".to_string(),
+                "https://app.ibimina.rw/verify/receipt?p=42".to_string()
+            )));
+            assert!(decoded.contains(&("CODE128".to_string(), "TWZ-000123".to_string())));
+        }
+    }
+
+    #[test]
+    fn test_code128_text() {
+        assert_eq!(code128_text(b"{BTWZ-{{1}"), "TWZ-{1}");
+        assert_eq!(code128_text(b"{C\x0c\x22{BA"), "1234A");
+    }
+
+    #[test]
+    fn test_unsupported_command() {
+        let error = emulate(&[ESC, b'@', ESC, b'Z'], PaperWidth::Mm58).unwrap_err();
+        assert_eq!(error.message, "Unsupported ESC/POS command ESC Z");
+        assert!(emulate(&[GS, b'v', b'0', 0, 1, 0, 2, 0, 0xFF], PaperWidth::Mm58).is_err());
+    }
+}
@@ -0,0 +1,524 @@
+// ESC/POS document builder for thermal receipt printers
+//
+// Layout is done in character columns for the selected paper width, so the
+// same document renders correctly on 58mm and 80mm printers. Commands follow
+// the Epson TM-series reference; the common Xprinter/Rongta clones accept the
+// same subset.
+
+pub mod codepage;
+pub mod emulator;
+pub mod preview;
+pub mod qr;
+pub mod raster;
+pub mod status;
+pub mod template;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AppError, Result};
+use codepage::CodePage;
+use qr::QrErrorCorrection;
+use raster::Bitmap;
+
+const ESC: u8 = 0x1B;
+const GS: u8 = 0x1D;
+const LF: u8 = 0x0A;
+
+/// Paper roll width, which fixes the number of Font A columns per line
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum PaperWidth {
+    #[serde(rename = "58mm")]
+    Mm58,
+    #[default]
+    #[serde(rename = "80mm")]
+    Mm80,
+}
+
+impl PaperWidth {
+    /// Font A (12x24) characters per line at normal size
+    pub fn columns(self) -> usize {
+        match self {
+            PaperWidth::Mm58 => 32,
+            PaperWidth::Mm80 => 48,
+        }
+    }
+
+    /// Printable width in dots at 203 dpi
+    pub fn dots(self) -> usize {
+        match self {
+            PaperWidth::Mm58 => 384,
+            PaperWidth::Mm80 => 576,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// What a particular receipt printer can do
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct PrinterProfile {
+    #[serde(default)]
+    pub paper_width: PaperWidth,
+    /// Supports GS ( k QR codes; when false QR codes are sent as raster images
+    #[serde(default = "default_true")]
+    pub native_qr: bool,
+    /// Code page text is encoded in; selected with ESC t at the start of each job
+    #[serde(default)]
+    pub code_page: CodePage,
+}
+
+impl Default for PrinterProfile {
+    fn default() -> Self {
+        Self {
+            paper_width: PaperWidth::default(),
+            native_qr: true,
+            code_page: CodePage::default(),
+        }
+    }
+}
+
+impl From<PaperWidth> for PrinterProfile {
+    fn from(paper_width: PaperWidth) -> Self {
+        Self {
+            paper_width,
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Underline {
+    None,
+    Single,
+    Double,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cut {
+    Full,
+    Partial,
+}
+
+/// Character magnification, 1-8 in each direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextSize {
+    pub width: u8,
+    pub height: u8,
+}
+
+impl TextSize {
+    pub const NORMAL: TextSize = TextSize {
+        width: 1,
+        height: 1,
+    };
+    pub const DOUBLE_HEIGHT: TextSize = TextSize {
+        width: 1,
+        height: 2,
+    };
+    pub const DOUBLE: TextSize = TextSize {
+        width: 2,
+        height: 2,
+    };
+}
+
+pub struct EscPos {
+    profile: PrinterProfile,
+    size: TextSize,
+    bytes: Vec<u8>,
+}
+
+impl EscPos {
+    /// Start a document; resets the printer and selects the profile's code page
+    pub fn new(profile: impl Into<PrinterProfile>) -> Self {
+        let profile = profile.into();
+        let bytes = vec![ESC, b'@', ESC, b't', profile.code_page.escpos_table()];
+        Self {
+            profile,
+            size: TextSize::NORMAL,
+            bytes,
+        }
+    }
+
+    pub fn profile(&self) -> &PrinterProfile {
+        &self.profile
+    }
+
+    /// Columns available at the current text width
+    pub fn columns(&self) -> usize {
+        (self.profile.paper_width.columns() / self.size.width as usize).max(1)
+    }
+
+    pub fn align(&mut self, align: Align) -> &mut Self {
+        let n = match align {
+            Align::Left => 0,
+            Align::Center => 1,
+            Align::Right => 2,
+        };
+        self.raw(&[ESC, b'a', n])
+    }
+
+    pub fn bold(&mut self, on: bool) -> &mut Self {
+        self.raw(&[ESC, b'E', on as u8])
+    }
+
+    pub fn underline(&mut self, underline: Underline) -> &mut Self {
+        let n = match underline {
+            Underline::None => 0,
+            Underline::Single => 1,
+            Underline::Double => 2,
+        };
+        self.raw(&[ESC, b'-', n])
+    }
+
+    /// White on black text
+    pub fn inverse(&mut self, on: bool) -> &mut Self {
+        self.raw(&[GS, b'B', on as u8])
+    }
+
+    pub fn size(&mut self, size: TextSize) -> &mut Self {
+        let width = size.width.clamp(1, 8);
+        let height = size.height.clamp(1, 8);
+        self.size = TextSize { width, height };
+        self.raw(&[GS, b'!', ((width - 1) << 4) | (height - 1)])
+    }
+
+    /// Text without a line break; no wrapping is applied
+    pub fn text(&mut self, text: &str) -> &mut Self {
+        let encoded = self.profile.code_page.encode(text);
+        self.raw(&encoded)
+    }
+
+    pub fn line(&mut self, text: &str) -> &mut Self {
+        self.text(text).raw(&[LF])
+    }
+
+    /// Word-wrap text to the current line width
+    pub fn wrapped(&mut self, text: &str) -> &mut Self {
+        let text = self.profile.code_page.printable(text);
+        for line in wrap(&text, self.columns()) {
+            self.line(&line);
+        }
+        self
+    }
+
+    /// Label on the left, value right-aligned on the same line
+    ///
+    /// A label too long to share a line with the value wraps, and the value
+    /// goes on the last label line if it fits there, otherwise on its own.
+    pub fn two_column(&mut self, label: &str, value: &str) -> &mut Self {
+        // Measure after transliteration, which can make text longer
+        let label = self.profile.code_page.printable(label);
+        let value = self.profile.code_page.printable(value);
+        let columns = self.columns();
+        for line in two_column_lines(&label, &value, columns) {
+            self.line(&line);
+        }
+        self
+    }
+
+    /// Full-width horizontal rule
+    pub fn rule(&mut self, c: char) -> &mut Self {
+        self.line(&c.to_string().repeat(self.columns()))
+    }
+
+    /// Print the buffer and feed `lines` lines
+    pub fn feed(&mut self, lines: u8) -> &mut Self {
+        self.raw(&[ESC, b'd', lines])
+    }
+
+    pub fn cut(&mut self, cut: Cut) -> &mut Self {
+        let m = match cut {
+            Cut::Full => 0,
+            Cut::Partial => 1,
+        };
+        self.raw(&[GS, b'V', m])
+    }
+
+    /// QR code, as a native symbol or a raster image depending on the printer
+    pub fn qr_code(
+        &mut self,
+        data: &str,
+        module_size: u8,
+        ec: QrErrorCorrection,
+    ) -> Result<&mut Self> {
+        let bytes = if self.profile.native_qr {
+            qr::native_bytes(data, module_size, ec)?
+        } else {
+            let bitmap = qr::bitmap(data, module_size, ec, self.profile.paper_width.dots())?;
+            raster::gs_v0(&bitmap)
+        };
+        Ok(self.raw(&bytes))
+    }
+
+    /// Code128 barcode with the human-readable text printed below
+    pub fn code128(&mut self, data: &str) -> Result<&mut Self> {
+        if data.is_empty() || !data.bytes().all(|b| (0x20..0x7F).contains(&b)) {
+            return Err(AppError::invalid_input(
+                "Barcode data must be printable ASCII",
+            ));
+        }
+
+        // Code set B; a literal '{' is escaped as "{{"
+        let mut payload = b"{B".to_vec();
+        for b in data.bytes() {
+            if b == b'{' {
+                payload.push(b'{');
+            }
+            payload.push(b);
+        }
+        if payload.len() > 255 {
+            return Err(AppError::invalid_input("Barcode data is too long"));
+        }
+
+        self.raw(&[GS, b'h', 80]) // height in dots
+            .raw(&[GS, b'w', 2]) // module width
+            .raw(&[GS, b'H', 2]) // HRI text below
+            .raw(&[GS, b'k', 73, payload.len() as u8])
+            .raw(&payload);
+        Ok(self)
+    }
+
+    /// Print a bitmap at its native size
+    pub fn image(&mut self, bitmap: &Bitmap) -> &mut Self {
+        self.raw(&raster::gs_v0(bitmap))
+    }
+
+    /// Append bytes as-is
+    pub fn raw(&mut self, bytes: &[u8]) -> &mut Self {
+        self.bytes.extend_from_slice(bytes);
+        self
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        self.bytes.clone()
+    }
+}
+
+/// Greedy word wrap; words longer than a line are split
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+
+    for paragraph in text.lines() {
+        let mut current = String::new();
+        let mut current_len = 0;
+
+        for word in paragraph.split_whitespace() {
+            let mut word: Vec<char> = word.chars().collect();
+
+            while word.len() > width {
+                if current_len > 0 {
+                    lines.push(std::mem::take(&mut current));
+                    current_len = 0;
+                }
+                lines.push(word.drain(..width).collect());
+            }
+            if word.is_empty() {
+                continue;
+            }
+
+            let needed = if current_len == 0 {
+                word.len()
+            } else {
+                current_len + 1 + word.len()
+            };
+            if needed > width {
+                lines.push(std::mem::take(&mut current));
+                current_len = 0;
+            }
+            if current_len > 0 {
+                current.push(' ');
+                current_len += 1;
+            }
+            current.extend(word.iter());
+            current_len += word.len();
+        }
+
+        if current_len > 0 || lines.is_empty() {
+            lines.push(current);
+        }
+    }
+
+    if lines.is_empty() {
+        lines.push(String::new());
+    }
+    lines
+}
+
+pub(crate) fn two_column_lines(label: &str, value: &str, columns: usize) -> Vec<String> {
+    let value_len = value.chars().count();
+    if value_len >= columns {
+        let mut lines = wrap(label, columns);
+        lines.extend(wrap(value, columns).into_iter().map(|l| right(&l, columns)));
+        return lines;
+    }
+
+    let mut lines = wrap(label, columns);
+    let last = lines.pop().unwrap_or_default();
+    let last_len = last.chars().count();
+
+    if last_len + 1 + value_len <= columns {
+        lines.push(format!(
+            "{}{}{}",
+            last,
+            " ".repeat(columns - last_len - value_len),
+            value
+        ));
+    } else {
+        lines.push(last);
+        lines.push(right(value, columns));
+    }
+    lines
+}
+
+fn right(text: &str, columns: usize) -> String {
+    let pad = columns.saturating_sub(text.chars().count());
+    format!("{}{}", " ".repeat(pad), text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_two_column_fits() {
+        let mut doc = EscPos::new(PaperWidth::Mm58);
+        doc.two_column("Deposit", "RWF 5,000");
+
+        let mut expected = vec![0x1B, 0x40, 0x1B, 0x74, 19];
+        expected.extend_from_slice(b"Deposit                RWF 5,000\n");
+        assert_eq!(doc.build(), expected);
+    }
+
+    #[test]
+    fn test_two_column_measures_transliterated_text() {
+        // "Cœur…" prints as "Coeur...", three columns wider than it looks
+        let mut doc = EscPos::new(PaperWidth::Mm58);
+        doc.two_column("Contribution Cœur…", "RWF 5,000");
+        doc.two_column("Twizigamire Abakundakurya Cœur…", "RWF 5,000");
+
+        let mut expected = vec![0x1B, 0x40, 0x1B, 0x74, 19];
+        expected.extend_from_slice(b"Contribution Coeur...  RWF 5,000\n");
+        expected.extend_from_slice(b"Twizigamire Abakundakurya\n");
+        expected.extend_from_slice(b"Coeur...               RWF 5,000\n");
+        assert_eq!(doc.build(), expected);
+    }
+
+    #[test]
+    fn test_two_column_wraps_long_label() {
+        assert_eq!(
+            two_column_lines("Monthly contribution for Twizigamire", "RWF 20,000", 32),
+            vec![
+                "Monthly contribution for".to_string(),
+                "Twizigamire           RWF 20,000".to_string(),
+            ]
+        );
+        assert_eq!(
+            two_column_lines("Loan repayment instalment", "RWF 125,000", 32),
+            vec![
+                "Loan repayment instalment".to_string(),
+                "                     RWF 125,000".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(
+            wrap("Murakoze cyane kubana natwe", 12),
+            vec!["Murakoze", "cyane kubana", "natwe"]
+        );
+        assert_eq!(wrap("ABCDEFGHIJ", 4), vec!["ABCD", "EFGH", "IJ"]);
+        assert_eq!(wrap("", 10), vec![""]);
+    }
+
+    #[test]
+    fn test_double_width_halves_columns() {
+        let mut doc = EscPos::new(PaperWidth::Mm80);
+        assert_eq!(doc.columns(), 48);
+        doc.size(TextSize::DOUBLE);
+        assert_eq!(doc.columns(), 24);
+        doc.rule('=');
+
+        let mut expected = vec![0x1B, 0x40, 0x1B, 0x74, 19, 0x1D, 0x21, 0x11];
+        expected.extend_from_slice(&[b'='; 24]);
+        expected.push(b'\n');
+        assert_eq!(doc.build(), expected);
+    }
+
+    #[test]
+    fn test_text_uses_profile_code_page() {
+        let profile = PrinterProfile {
+            code_page: CodePage::Wpc1252,
+            ..PrinterProfile::default()
+        };
+        let mut doc = EscPos::new(profile);
+        doc.line("Reçu");
+        assert_eq!(
+            doc.build(),
+            vec![0x1B, 0x40, 0x1B, 0x74, 16, b'R', b'e', 0xE7, b'u', b'\n']
+        );
+    }
+
+    #[test]
+    fn test_code128() {
+        let mut doc = EscPos::new(PaperWidth::Mm58);
+        doc.code128("RCP-{1}").unwrap();
+
+        let mut expected = vec![
+            0x1B, 0x40, 0x1B, 0x74, 19, 0x1D, 0x68, 80, 0x1D, 0x77, 2, 0x1D, 0x48, 2,
+        ];
+        expected.extend_from_slice(&[0x1D, 0x6B, 73, 10]);
+        expected.extend_from_slice(b"{BRCP-{{1}");
+        assert_eq!(doc.build(), expected);
+
+        assert!(EscPos::new(PaperWidth::Mm58).code128("Gisenyi–01").is_err());
+    }
+
+    #[test]
+    fn test_qr_fallback_uses_raster() {
+        let profile = PrinterProfile {
+            paper_width: PaperWidth::Mm58,
+            native_qr: false,
+            ..PrinterProfile::default()
+        };
+        let mut doc = EscPos::new(profile);
+        doc.qr_code("RCP-000123", 6, QrErrorCorrection::Medium)
+            .unwrap();
+        assert_eq!(&doc.build()[5..9], &[0x1D, 0x76, 0x30, 0x00]);
+    }
+
+    #[test]
+    fn test_style_commands() {
+        let mut doc = EscPos::new(PaperWidth::Mm80);
+        doc.align(Align::Center)
+            .bold(true)
+            .underline(Underline::Double)
+            .inverse(true)
+            .feed(3)
+            .cut(Cut::Partial);
+
+        assert_eq!(
+            doc.build(),
+            vec![
+                0x1B, 0x40, // ESC @
+                0x1B, 0x74, 19, // ESC t 19: CP858
+                0x1B, 0x61, 0x01, // ESC a 1
+                0x1B, 0x45, 0x01, // ESC E 1
+                0x1B, 0x2D, 0x02, // ESC - 2
+                0x1D, 0x42, 0x01, // GS B 1
+                0x1B, 0x64, 0x03, // ESC d 3
+                0x1D, 0x56, 0x01, // GS V 1
+            ]
+        );
+    }
+}
@@ -0,0 +1,132 @@
+// Drawing receipt previews
+//
+// Text is drawn in the printer's Font A grid, 12x24 dots per character at
+// normal size, one image pixel per printer dot. DejaVu Sans Mono is close
+// enough to the printer's built-in font for a teller to judge a layout before
+// printing it.
+
+use std::io::Cursor;
+use std::sync::OnceLock;
+
+use ab_glyph::{point, Font, FontRef, PxScale, ScaleFont};
+use image::{DynamicImage, GrayImage, ImageFormat, Luma};
+
+use crate::error::{AppError, Result};
+
+/// Font A cell width in dots
+pub const CELL_WIDTH: u32 = 12;
+/// Font A cell height in dots
+pub const CELL_HEIGHT: u32 = 24;
+
+pub const PAPER: u8 = 0xFF;
+pub const INK: u8 = 0x00;
+
+/// Font height that makes a DejaVu Sans Mono advance fill one 12-dot cell
+const FONT_HEIGHT: f32 = 23.0;
+
+static REGULAR_FONT: &[u8] = include_bytes!("../../fonts/DejaVuSansMono.ttf");
+static BOLD_FONT: &[u8] = include_bytes!("../../fonts/DejaVuSansMono-Bold.ttf");
+
+struct Fonts {
+    regular: FontRef<'static>,
+    bold: FontRef<'static>,
+}
+
+fn fonts() -> &'static Fonts {
+    static FONTS: OnceLock<Fonts> = OnceLock::new();
+    FONTS.get_or_init(|| Fonts {
+        regular: FontRef::try_from_slice(REGULAR_FONT).expect("bundled font is valid"),
+        bold: FontRef::try_from_slice(BOLD_FONT).expect("bundled font is valid"),
+    })
+}
+
+/// Style of a single character cell
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CellStyle {
+    pub bold: bool,
+    pub inverse: bool,
+    /// Character magnification, as (width, height)
+    pub size: (u8, u8),
+}
+
+impl CellStyle {
+    fn magnification(&self) -> (u32, u32) {
+        (self.size.0.max(1) as u32, self.size.1.max(1) as u32)
+    }
+}
+
+/// Draw one character with its cell's top-left corner at (`x`, `y`)
+///
+/// Returns the width of the cell in dots. Anything falling outside the canvas
+/// is clipped, as the printer would.
+pub fn draw_char(canvas: &mut GrayImage, x: u32, y: u32, c: char, style: CellStyle) -> u32 {
+    let (width_mag, height_mag) = style.magnification();
+    let cell_width = CELL_WIDTH * width_mag;
+    let cell_height = CELL_HEIGHT * height_mag;
+    let (background, foreground) = if style.inverse {
+        (INK, PAPER)
+    } else {
+        (PAPER, INK)
+    };
+
+    if style.inverse {
+        fill(canvas, x, y, cell_width, cell_height, background);
+    }
+    if c.is_whitespace() {
+        return cell_width;
+    }
+
+    let fonts = fonts();
+    let font = if style.bold {
+        &fonts.bold
+    } else {
+        &fonts.regular
+    };
+    let scale = PxScale {
+        x: FONT_HEIGHT * width_mag as f32,
+        y: FONT_HEIGHT * height_mag as f32,
+    };
+    let ascent = font.as_scaled(scale).ascent();
+    let glyph = font
+        .glyph_id(c)
+        .with_scale_and_position(scale, point(x as f32, y as f32 + ascent));
+
+    if let Some(outlined) = font.outline_glyph(glyph) {
+        let bounds = outlined.px_bounds();
+        outlined.draw(|gx, gy, coverage| {
+            let px = bounds.min.x as i64 + gx as i64;
+            let py = bounds.min.y as i64 + gy as i64;
+            if px < 0 || py < 0 || px >= canvas.width() as i64 || py >= canvas.height() as i64 {
+                return;
+            }
+            let pixel = canvas.get_pixel_mut(px as u32, py as u32);
+            pixel.0[0] = blend(pixel.0[0], foreground, coverage);
+        });
+    }
+    cell_width
+}
+
+/// Fill a rectangle, clipped to the canvas
+pub fn fill(canvas: &mut GrayImage, x: u32, y: u32, width: u32, height: u32, value: u8) {
+    let right = (x + width).min(canvas.width());
+    let bottom = (y + height).min(canvas.height());
+    for py in y..bottom {
+        for px in x..right {
+            canvas.put_pixel(px, py, Luma([value]));
+        }
+    }
+}
+
+fn blend(under: u8, over: u8, coverage: f32) -> u8 {
+    let coverage = coverage.clamp(0.0, 1.0);
+    (under as f32 * (1.0 - coverage) + over as f32 * coverage).round() as u8
+}
+
+/// Encode a canvas as PNG
+pub fn encode_png(canvas: GrayImage) -> Result<Vec<u8>> {
+    let mut png = Vec::new();
+    DynamicImage::ImageLuma8(canvas)
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|e| AppError::internal(format!("Failed to encode preview: {}", e)))?;
+    Ok(png)
+}
@@ -0,0 +1,138 @@
+// QR codes: native GS ( k symbols, or a raster image for printers without
+// QR support
+
+use qrcode::{Color, EcLevel, QrCode};
+use serde::{Deserialize, Serialize};
+
+use super::raster::Bitmap;
+use crate::error::{AppError, Result};
+
+/// Modules of white border required around a QR symbol
+const QUIET_ZONE: usize = 4;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum QrErrorCorrection {
+    Low,
+    #[default]
+    Medium,
+    Quartile,
+    High,
+}
+
+impl QrErrorCorrection {
+    fn ec_level(self) -> EcLevel {
+        match self {
+            QrErrorCorrection::Low => EcLevel::L,
+            QrErrorCorrection::Medium => EcLevel::M,
+            QrErrorCorrection::Quartile => EcLevel::Q,
+            QrErrorCorrection::High => EcLevel::H,
+        }
+    }
+}
+
+/// GS ( k function 165-181 sequence: model 2, size, EC level, store, print
+pub fn native_bytes(data: &str, module_size: u8, ec: QrErrorCorrection) -> Result<Vec<u8>> {
+    let data = data.as_bytes();
+    // pL pH count the cn, fn and m bytes as well as the data
+    let stored_len = data.len() + 3;
+    if stored_len > 7092 {
+        return Err(AppError::invalid_input("QR code data is too long"));
+    }
+
+    let ec = match ec {
+        QrErrorCorrection::Low => 48,
+        QrErrorCorrection::Medium => 49,
+        QrErrorCorrection::Quartile => 50,
+        QrErrorCorrection::High => 51,
+    };
+
+    let size = module_size.clamp(1, 16);
+    let (pl, ph) = ((stored_len & 0xFF) as u8, (stored_len >> 8) as u8);
+
+    let mut bytes = Vec::with_capacity(data.len() + 40);
+    bytes.extend_from_slice(&[0x1D, 0x28, 0x6B, 0x04, 0x00, 0x31, 0x41, 0x32, 0x00]); // model 2
+    bytes.extend_from_slice(&[0x1D, 0x28, 0x6B, 0x03, 0x00, 0x31, 0x43, size]);
+    bytes.extend_from_slice(&[0x1D, 0x28, 0x6B, 0x03, 0x00, 0x31, 0x45, ec]);
+    bytes.extend_from_slice(&[0x1D, 0x28, 0x6B, pl, ph, 0x31, 0x50, 0x30]); // store data
+    bytes.extend_from_slice(data);
+    bytes.extend_from_slice(&[0x1D, 0x28, 0x6B, 0x03, 0x00, 0x31, 0x51, 0x30]); // print
+    Ok(bytes)
+}
+
+/// Render a QR symbol as a bitmap no wider than `max_width` dots
+pub fn bitmap(
+    data: &str,
+    module_size: u8,
+    ec: QrErrorCorrection,
+    max_width: usize,
+) -> Result<Bitmap> {
+    let code = QrCode::with_error_correction_level(data, ec.ec_level())
+        .map_err(|e| AppError::invalid_input("Cannot encode QR code").with_detail(e))?;
+
+    let modules = code.width();
+    let total = modules + 2 * QUIET_ZONE;
+    let scale = (module_size as usize).min(max_width / total);
+    if scale == 0 {
+        return Err(AppError::invalid_input(
+            "QR code is too large for the paper",
+        ));
+    }
+
+    let colors = code.to_colors();
+    let mut bitmap = Bitmap::new(total * scale, total * scale);
+    for (i, color) in colors.iter().enumerate() {
+        if *color != Color::Dark {
+            continue;
+        }
+        let (mx, my) = (i % modules + QUIET_ZONE, i / modules + QUIET_ZONE);
+        for y in my * scale..(my + 1) * scale {
+            for x in mx * scale..(mx + 1) * scale {
+                bitmap.set(x, y, true);
+            }
+        }
+    }
+    Ok(bitmap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_native_bytes() {
+        let bytes = native_bytes("ABC", 6, QrErrorCorrection::Medium).unwrap();
+        assert_eq!(
+            bytes,
+            vec![
+                0x1D, 0x28, 0x6B, 0x04, 0x00, 0x31, 0x41, 0x32, 0x00, // model 2
+                0x1D, 0x28, 0x6B, 0x03, 0x00, 0x31, 0x43, 0x06, // size 6
+                0x1D, 0x28, 0x6B, 0x03, 0x00, 0x31, 0x45, 0x31, // EC M
+                0x1D, 0x28, 0x6B, 0x06, 0x00, 0x31, 0x50, 0x30, b'A', b'B', b'C', // store
+                0x1D, 0x28, 0x6B, 0x03, 0x00, 0x31, 0x51, 0x30, // print
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bitmap_decodes() {
+        let url = "https://app.ibimina.rw/verify/receipt?p=123&t=abc";
+        let bitmap = bitmap(url, 4, QrErrorCorrection::Medium, 384).unwrap();
+        assert!(bitmap.width <= 384);
+
+        let luma: Vec<u8> = (0..bitmap.height)
+            .flat_map(|y| (0..bitmap.width).map(move |x| (x, y)))
+            .map(|(x, y)| if bitmap.get(x, y) { 0 } else { 255 })
+            .collect();
+        let decoded =
+            crate::commands::barcode::decode_luma(luma, bitmap.width as u32, bitmap.height as u32);
+        assert_eq!(decoded, vec![("QR_CODE".to_string(), url.to_string())]);
+    }
+
+    #[test]
+    fn test_bitmap_shrinks_to_fit() {
+        let bitmap = bitmap("RCP-000123", 16, QrErrorCorrection::Low, 100).unwrap();
+        assert!(bitmap.width <= 100);
+        assert!(super::bitmap("RCP-000123", 4, QrErrorCorrection::Low, 10).is_err());
+    }
+}
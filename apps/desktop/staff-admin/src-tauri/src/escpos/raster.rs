@@ -0,0 +1,198 @@
+// 1-bit bitmaps and the GS v 0 raster bit image command
+
+use image::{imageops::FilterType, DynamicImage, GrayImage};
+
+/// Monochrome image; `true` pixels are printed black
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bitmap {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<bool>,
+}
+
+impl Bitmap {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![false; width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, black: bool) {
+        self.pixels[y * self.width + x] = black;
+    }
+
+    /// Bytes per row once packed 8 pixels to a byte
+    pub fn row_bytes(&self) -> usize {
+        self.width.div_ceil(8)
+    }
+
+    /// Pack rows MSB-first, padding each row to a whole byte
+    pub fn packed(&self) -> Vec<u8> {
+        let row_bytes = self.row_bytes();
+        let mut packed = vec![0u8; row_bytes * self.height];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if self.get(x, y) {
+                    packed[y * row_bytes + x / 8] |= 0x80 >> (x % 8);
+                }
+            }
+        }
+        packed
+    }
+}
+
+/// Scale an image down to fit `max_width` x `max_height` dots and dither it
+///
+/// Images are never scaled up, and transparent areas print as paper white.
+pub fn from_image(image: &DynamicImage, max_width: usize, max_height: usize) -> Bitmap {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let scale = (max_width as f64 / width as f64)
+        .min(max_height as f64 / height as f64)
+        .min(1.0);
+    let target_width = ((width as f64 * scale).round() as u32).max(1);
+    let target_height = ((height as f64 * scale).round() as u32).max(1);
+
+    let mut rgba = image.to_rgba8();
+    for pixel in rgba.pixels_mut() {
+        let alpha = pixel[3] as u32;
+        for channel in &mut pixel.0[..3] {
+            *channel = ((*channel as u32 * alpha + 255 * (255 - alpha)) / 255) as u8;
+        }
+        pixel[3] = 255;
+    }
+
+    let gray = DynamicImage::ImageRgba8(rgba)
+        .resize_exact(target_width, target_height, FilterType::Triangle)
+        .into_luma8();
+    dither(&gray)
+}
+
+/// Floyd-Steinberg error diffusion to 1 bit
+pub fn dither(gray: &GrayImage) -> Bitmap {
+    let (width, height) = (gray.width() as usize, gray.height() as usize);
+    let mut levels: Vec<f32> = gray.as_raw().iter().map(|&v| v as f32).collect();
+    let mut bitmap = Bitmap::new(width, height);
+
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            let old = levels[i];
+            let black = old < 128.0;
+            bitmap.set(x, y, black);
+
+            let error = old - if black { 0.0 } else { 255.0 };
+            if x + 1 < width {
+                levels[i + 1] += error * 7.0 / 16.0;
+            }
+            if y + 1 < height {
+                if x > 0 {
+                    levels[i + width - 1] += error * 3.0 / 16.0;
+                }
+                levels[i + width] += error * 5.0 / 16.0;
+                if x + 1 < width {
+                    levels[i + width + 1] += error / 16.0;
+                }
+            }
+        }
+    }
+    bitmap
+}
+
+/// Rows sent per GS v 0 command; many printers choke on larger blocks
+const MAX_BAND_HEIGHT: usize = 256;
+
+/// GS v 0 m xL xH yL yH d1...dk, split into bands; nothing for an empty bitmap
+pub fn gs_v0(bitmap: &Bitmap) -> Vec<u8> {
+    let row_bytes = bitmap.row_bytes();
+    if row_bytes == 0 || bitmap.height == 0 {
+        return Vec::new();
+    }
+    let packed = bitmap.packed();
+    let mut bytes = Vec::with_capacity(packed.len() + 8 * bitmap.height.div_ceil(MAX_BAND_HEIGHT));
+
+    for band in packed.chunks(row_bytes * MAX_BAND_HEIGHT) {
+        let rows = band.len() / row_bytes;
+        bytes.extend_from_slice(&[
+            0x1D,
+            b'v',
+            b'0',
+            0x00,
+            (row_bytes & 0xFF) as u8,
+            (row_bytes >> 8) as u8,
+            (rows & 0xFF) as u8,
+            (rows >> 8) as u8,
+        ]);
+        bytes.extend_from_slice(band);
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gs_v0_packing() {
+        let mut bitmap = Bitmap::new(10, 2);
+        bitmap.set(0, 0, true);
+        bitmap.set(9, 0, true);
+        bitmap.set(3, 1, true);
+
+        let bytes = gs_v0(&bitmap);
+        assert_eq!(&bytes[..8], &[0x1D, 0x76, 0x30, 0x00, 2, 0, 2, 0]); // 2 bytes x 2 rows
+        assert_eq!(&bytes[8..10], &[0b1000_0000, 0b0100_0000]);
+        assert_eq!(&bytes[10..], &[0b0001_0000, 0b0000_0000]);
+    }
+
+    #[test]
+    fn test_gs_v0_empty_bitmap() {
+        assert!(gs_v0(&Bitmap::new(0, 10)).is_empty());
+        assert!(gs_v0(&Bitmap::new(10, 0)).is_empty());
+    }
+
+    #[test]
+    fn test_dither_levels() {
+        let black_count = |level: u8| {
+            let bitmap = dither(&GrayImage::from_pixel(32, 32, image::Luma([level])));
+            (0..32)
+                .flat_map(|y| (0..32).map(move |x| (x, y)))
+                .filter(|&(x, y)| bitmap.get(x, y))
+                .count()
+        };
+
+        assert_eq!(black_count(255), 0);
+        assert_eq!(black_count(0), 1024);
+        let half = black_count(128);
+        assert!((480..=544).contains(&half), "{} of 1024 black", half);
+    }
+
+    #[test]
+    fn test_from_image_fits_and_flattens_alpha() {
+        let transparent = image::RgbaImage::from_pixel(800, 200, image::Rgba([0, 0, 0, 0]));
+        let bitmap = from_image(&DynamicImage::ImageRgba8(transparent), 384, 160);
+        assert_eq!((bitmap.width, bitmap.height), (384, 96));
+        assert!(bitmap.packed().iter().all(|&b| b == 0));
+
+        let small = DynamicImage::ImageLuma8(GrayImage::new(40, 20));
+        let bitmap = from_image(&small, 384, 160);
+        assert_eq!((bitmap.width, bitmap.height), (40, 20));
+    }
+
+    #[test]
+    fn test_tall_images_are_banded() {
+        let bitmap = Bitmap::new(8, 300);
+        let bytes = gs_v0(&bitmap);
+        assert_eq!(&bytes[..8], &[0x1D, 0x76, 0x30, 0x00, 1, 0, 0, 1]);
+        assert_eq!(
+            &bytes[8 + 256..8 + 256 + 8],
+            &[0x1D, 0x76, 0x30, 0x00, 1, 0, 44, 0]
+        );
+        assert_eq!(bytes.len(), 8 + 256 + 8 + 44);
+    }
+}
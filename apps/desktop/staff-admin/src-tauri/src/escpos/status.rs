@@ -0,0 +1,78 @@
+// Real-time status (DLE EOT n)
+//
+// The printer answers each request with one byte immediately, even while it
+// is offline or its buffer is full. Bits 1 and 4 of every reply are always
+// set and bits 0 and 7 always clear, which tells a status byte apart from
+// stray data.
+
+use crate::error::{AppError, ErrorCode, Result};
+
+const DLE: u8 = 0x10;
+const EOT: u8 = 0x04;
+
+/// DLE EOT functions asked, in order: printer, offline cause, roll paper sensor
+pub const REQUESTS: [u8; 3] = [1, 2, 4];
+
+/// DLE EOT n
+pub fn request(n: u8) -> [u8; 3] {
+    [DLE, EOT, n]
+}
+
+/// What the printer's sensors report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RealTimeStatus {
+    pub offline: bool,
+    pub cover_open: bool,
+    pub paper_out: bool,
+    pub paper_near_end: bool,
+    /// Cutter jam, head overheating or another error stopped printing
+    pub error: bool,
+}
+
+impl RealTimeStatus {
+    /// Decode the replies to [`REQUESTS`]
+    pub fn parse(printer: u8, offline_cause: u8, paper: u8) -> Result<Self> {
+        for b in [printer, offline_cause, paper] {
+            if b & 0x93 != 0x12 {
+                return Err(AppError::new(
+                    ErrorCode::InvalidResponse,
+                    "Printer sent an invalid status byte",
+                )
+                .with_detail(format!("0x{:02X}", b)));
+            }
+        }
+
+        Ok(Self {
+            offline: printer & 0x08 != 0,
+            cover_open: offline_cause & 0x04 != 0,
+            paper_out: offline_cause & 0x20 != 0 || paper & 0x60 != 0,
+            paper_near_end: paper & 0x0C != 0,
+            error: offline_cause & 0x40 != 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_status() {
+        assert_eq!(
+            RealTimeStatus::parse(0x12, 0x12, 0x12).unwrap(),
+            RealTimeStatus::default()
+        );
+
+        // Cover open: offline, with the cover bit in the offline cause
+        let status = RealTimeStatus::parse(0x1A, 0x16, 0x12).unwrap();
+        assert!(status.offline && status.cover_open && !status.paper_out);
+
+        // Roll nearly used up, then empty
+        let near_end = RealTimeStatus::parse(0x12, 0x12, 0x1E).unwrap();
+        assert!(near_end.paper_near_end && !near_end.paper_out);
+        let out = RealTimeStatus::parse(0x1A, 0x32, 0x7E).unwrap();
+        assert!(out.paper_out && out.offline);
+
+        assert!(RealTimeStatus::parse(b'O', 0x12, 0x12).is_err());
+    }
+}
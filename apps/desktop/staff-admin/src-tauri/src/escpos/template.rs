@@ -0,0 +1,965 @@
+// Receipt templates
+//
+// Each SACCO can edit its receipt layout as a small line-based template.
+// Plain lines are printed word-wrapped; lines starting with `@` are commands:
+//
+//   @align left|center|right   @bold on|off   @inverse on|off
+//   @size normal|tall|double   @line <text>   (printed as-is, not wrapped)
+//   @row <label> | <value>     @rule [char]   @feed [lines]
+//   @logo   @qr <text>   @barcode <text>   @cut [full|partial]
+//
+// Blocks run to a matching `@end`:
+//
+//   @if <name> ... [@else ...] @end    @unless <name> ... @end
+//   @each <list> ... @end              each element's fields are in scope
+//   @section <name> ... @end           the object's fields are in scope;
+//                                      skipped when it is missing
+//
+// `{{ name }}` inserts a value, with dotted paths into objects and optional
+// filters: `{{ amount | rwf }}`, `{{ paid_at | datetime }}`. Blank lines and
+// lines starting with `#` are ignored.
+
+use chrono::{DateTime, Local, NaiveDate};
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::OnceLock;
+
+use super::qr::QrErrorCorrection;
+use super::raster::Bitmap;
+use super::{two_column_lines, wrap, Align, Cut, EscPos, PaperWidth, PrinterProfile, TextSize};
+use crate::error::{AppError, Result};
+use crate::pdf::reports::format_amount;
+
+const QR_MODULE_SIZE: u8 = 6;
+
+/// Layout used when a SACCO has no template of its own
+pub const DEFAULT_TEMPLATE: &str = "\
+# Default SACCO+ receipt
+@align center
+@if has_logo
+@logo
+@feed 1
+@end
+@bold on
+{{ title }}
+@bold off
+@feed 1
+@if duplicate
+@bold on
+@inverse on
+@line \x20DUPLICATE\x20
+@inverse off
+@bold off
+@feed 1
+@end
+@align left
+@each items
+@row {{ label }} | {{ value }}
+@end
+@rule -
+@bold on
+@row TOTAL | {{ total }}
+@bold off
+@feed 1
+@align center
+{{ footer }}
+@if verification_url
+@feed 1
+@qr {{ verification_url }}
+@line Scan to verify this payment
+@end
+@if receipt_number
+@feed 1
+@barcode {{ receipt_number }}
+@end
+@feed 3
+@cut
+";
+
+/// A problem found while parsing a template, with its 1-based line
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct TemplateError {
+    pub line: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Filter {
+    Rwf,
+    Amount,
+    Date,
+    Time,
+    DateTime,
+    Upper,
+    Lower,
+}
+
+impl Filter {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "rwf" => Filter::Rwf,
+            "amount" => Filter::Amount,
+            "date" => Filter::Date,
+            "time" => Filter::Time,
+            "datetime" => Filter::DateTime,
+            "upper" => Filter::Upper,
+            "lower" => Filter::Lower,
+            _ => return None,
+        })
+    }
+
+    fn apply(self, value: Value) -> Value {
+        let text = match self {
+            Filter::Rwf => match number(&value) {
+                Some(amount) => format!("RWF {}", format_amount(amount)),
+                None => display(&value),
+            },
+            Filter::Amount => match number(&value) {
+                Some(amount) => format_amount(amount),
+                None => display(&value),
+            },
+            Filter::Date | Filter::Time | Filter::DateTime => {
+                let format = match self {
+                    Filter::Date => "%d/%m/%Y",
+                    Filter::Time => "%H:%M",
+                    _ => "%d/%m/%Y %H:%M",
+                };
+                let text = display(&value);
+                if let Ok(at) = DateTime::parse_from_rfc3339(&text) {
+                    at.with_timezone(&Local).format(format).to_string()
+                } else if let (Filter::Date, Ok(date)) =
+                    (self, NaiveDate::parse_from_str(&text, "%Y-%m-%d"))
+                {
+                    date.format(format).to_string()
+                } else {
+                    text
+                }
+            }
+            Filter::Upper => display(&value).to_uppercase(),
+            Filter::Lower => display(&value).to_lowercase(),
+        };
+        Value::String(text)
+    }
+}
+
+type Path = Vec<String>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Literal(String),
+    Value { path: Path, filters: Vec<Filter> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Wrapped(Vec<Part>),
+    Line(Vec<Part>),
+    Row(Vec<Part>, Vec<Part>),
+    Align(Align),
+    Bold(bool),
+    Inverse(bool),
+    Size(TextSize),
+    Rule(char),
+    Feed(u8),
+    Logo,
+    Qr(Vec<Part>),
+    Barcode(Vec<Part>),
+    Cut(Cut),
+    If {
+        path: Path,
+        negate: bool,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    Each {
+        path: Path,
+        body: Vec<Node>,
+    },
+    Section {
+        path: Path,
+        body: Vec<Node>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+enum BlockKind {
+    If { path: Path, negate: bool },
+    Each(Path),
+    Section(Path),
+}
+
+/// A block being parsed, waiting for its `@end`
+struct Frame {
+    kind: BlockKind,
+    line: usize,
+    nodes: Vec<Node>,
+    otherwise: Option<Vec<Node>>,
+}
+
+impl Frame {
+    fn push(&mut self, node: Node) {
+        self.otherwise
+            .as_mut()
+            .unwrap_or(&mut self.nodes)
+            .push(node);
+    }
+
+    fn close(self) -> Node {
+        match self.kind {
+            BlockKind::If { path, negate } => Node::If {
+                path,
+                negate,
+                then: self.nodes,
+                otherwise: self.otherwise.unwrap_or_default(),
+            },
+            BlockKind::Each(path) => Node::Each {
+                path,
+                body: self.nodes,
+            },
+            BlockKind::Section(path) => Node::Section {
+                path,
+                body: self.nodes,
+            },
+        }
+    }
+}
+
+struct Parser {
+    line: usize,
+    errors: Vec<TemplateError>,
+}
+
+impl Parser {
+    fn error(&mut self, message: impl Into<String>) {
+        self.errors.push(TemplateError {
+            line: self.line,
+            message: message.into(),
+        });
+    }
+
+    fn path(&mut self, text: &str) -> Path {
+        let text = text.trim();
+        let valid = !text.is_empty()
+            && text.split('.').all(|segment| {
+                !segment.is_empty()
+                    && segment
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_')
+            });
+        if !valid {
+            self.error(format!("Invalid name '{}'", text));
+        }
+        text.split('.').map(str::to_string).collect()
+    }
+
+    /// Literal text with `{{ name | filter }}` values
+    fn text(&mut self, mut text: &str) -> Vec<Part> {
+        let mut parts = Vec::new();
+        while let Some(start) = text.find("{{") {
+            if start > 0 {
+                parts.push(Part::Literal(text[..start].to_string()));
+            }
+            let Some(end) = text[start..].find("}}") else {
+                self.error("Unclosed {{");
+                return parts;
+            };
+            let mut pieces = text[start + 2..start + end].split('|');
+            let path = self.path(pieces.next().unwrap_or_default());
+            let mut filters = Vec::new();
+            for name in pieces.map(str::trim) {
+                match Filter::parse(name) {
+                    Some(filter) => filters.push(filter),
+                    None => self.error(format!("Unknown filter '{}'", name)),
+                }
+            }
+            parts.push(Part::Value { path, filters });
+            text = &text[start + end + 2..];
+        }
+        if !text.is_empty() {
+            parts.push(Part::Literal(text.to_string()));
+        }
+        parts
+    }
+
+    fn required<'a>(&mut self, command: &str, arg: &'a str) -> Option<&'a str> {
+        if arg.is_empty() {
+            self.error(format!("@{} needs a value", command));
+            None
+        } else {
+            Some(arg)
+        }
+    }
+
+    fn switch(&mut self, command: &str, arg: &str) -> Option<bool> {
+        match arg {
+            "on" => Some(true),
+            "off" => Some(false),
+            _ => {
+                self.error(format!("@{} takes on or off", command));
+                None
+            }
+        }
+    }
+
+    /// Parse one `@` command; blocks are handled by the caller
+    fn command(&mut self, name: &str, arg: &str, verbatim: &str) -> Option<Node> {
+        Some(match name {
+            "align" => Node::Align(match arg {
+                "left" => Align::Left,
+                "center" => Align::Center,
+                "right" => Align::Right,
+                _ => {
+                    self.error("@align takes left, center or right");
+                    return None;
+                }
+            }),
+            "bold" => Node::Bold(self.switch(name, arg)?),
+            "inverse" => Node::Inverse(self.switch(name, arg)?),
+            "size" => Node::Size(match arg {
+                "normal" => TextSize::NORMAL,
+                "tall" => TextSize::DOUBLE_HEIGHT,
+                "double" => TextSize::DOUBLE,
+                _ => {
+                    self.error("@size takes normal, tall or double");
+                    return None;
+                }
+            }),
+            "line" => Node::Line(self.text(verbatim)),
+            "row" => {
+                let Some((label, value)) = split_row(arg) else {
+                    self.error("@row needs a label and a value separated by |");
+                    return None;
+                };
+                Node::Row(self.text(label.trim()), self.text(value.trim()))
+            }
+            "rule" => {
+                let mut chars = arg.chars();
+                match (chars.next(), chars.next()) {
+                    (None, _) => Node::Rule('-'),
+                    (Some(c), None) => Node::Rule(c),
+                    _ => {
+                        self.error("@rule takes a single character");
+                        return None;
+                    }
+                }
+            }
+            "feed" => match arg {
+                "" => Node::Feed(1),
+                _ => match arg.parse() {
+                    Ok(lines) => Node::Feed(lines),
+                    Err(_) => {
+                        self.error("@feed takes a number of lines up to 255");
+                        return None;
+                    }
+                },
+            },
+            "logo" => Node::Logo,
+            "qr" => {
+                let data = self.required(name, arg)?;
+                Node::Qr(self.text(data))
+            }
+            "barcode" => {
+                let data = self.required(name, arg)?;
+                Node::Barcode(self.text(data))
+            }
+            "cut" => Node::Cut(match arg {
+                "" | "full" => Cut::Full,
+                "partial" => Cut::Partial,
+                _ => {
+                    self.error("@cut takes full or partial");
+                    return None;
+                }
+            }),
+            _ => {
+                self.error(format!("Unknown command @{}", name));
+                return None;
+            }
+        })
+    }
+}
+
+/// Split "label | value" at the first `|` outside `{{ }}`
+fn split_row(text: &str) -> Option<(&str, &str)> {
+    let mut depth = 0;
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i..].starts_with(b"{{") {
+            depth += 1;
+            i += 2;
+        } else if bytes[i..].starts_with(b"}}") && depth > 0 {
+            depth -= 1;
+            i += 2;
+        } else if bytes[i] == b'|' && depth == 0 {
+            return Some((&text[..i], &text[i + 1..]));
+        } else {
+            i += 1;
+        }
+    }
+    None
+}
+
+impl Template {
+    /// Parse a template, reporting every error found rather than the first
+    pub fn parse(source: &str) -> std::result::Result<Self, Vec<TemplateError>> {
+        let mut parser = Parser {
+            line: 0,
+            errors: Vec::new(),
+        };
+        let mut root = Vec::new();
+        let mut frames: Vec<Frame> = Vec::new();
+
+        for (index, raw) in source.lines().enumerate() {
+            parser.line = index + 1;
+            let trimmed = raw.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+
+            let Some(command) = trimmed.strip_prefix('@') else {
+                let node = Node::Wrapped(parser.text(trimmed));
+                match frames.last_mut() {
+                    Some(frame) => frame.push(node),
+                    None => root.push(node),
+                }
+                continue;
+            };
+
+            let name = command
+                .split(char::is_whitespace)
+                .next()
+                .unwrap_or_default();
+            let rest = &command[name.len()..];
+            // `@line` keeps its spacing; one separating space is dropped
+            let verbatim = raw.trim_start()[1 + name.len()..]
+                .strip_prefix([' ', '\t'])
+                .unwrap_or(rest);
+            let arg = rest.trim();
+
+            let kind = match name {
+                "if" | "unless" => parser.required(name, arg).map(|arg| BlockKind::If {
+                    path: parser.path(arg),
+                    negate: name == "unless",
+                }),
+                "each" => parser
+                    .required(name, arg)
+                    .map(|arg| BlockKind::Each(parser.path(arg))),
+                "section" => parser
+                    .required(name, arg)
+                    .map(|arg| BlockKind::Section(parser.path(arg))),
+                "else" => {
+                    match frames.last_mut() {
+                        Some(Frame {
+                            kind: BlockKind::If { .. },
+                            otherwise: otherwise @ None,
+                            ..
+                        }) => *otherwise = Some(Vec::new()),
+                        _ => parser.error("@else without a matching @if"),
+                    }
+                    continue;
+                }
+                "end" => {
+                    match frames.pop() {
+                        Some(frame) => {
+                            let node = frame.close();
+                            match frames.last_mut() {
+                                Some(parent) => parent.push(node),
+                                None => root.push(node),
+                            }
+                        }
+                        None => parser.error("@end without an open block"),
+                    }
+                    continue;
+                }
+                _ => {
+                    if let Some(node) = parser.command(name, arg, verbatim) {
+                        match frames.last_mut() {
+                            Some(frame) => frame.push(node),
+                            None => root.push(node),
+                        }
+                    }
+                    continue;
+                }
+            };
+
+            // A block with a bad argument is still opened so its @end matches
+            frames.push(Frame {
+                kind: kind.unwrap_or(BlockKind::Section(Vec::new())),
+                line: parser.line,
+                nodes: Vec::new(),
+                otherwise: None,
+            });
+        }
+
+        for frame in frames.iter().rev() {
+            parser.errors.push(TemplateError {
+                line: frame.line,
+                message: "Block is never closed with @end".to_string(),
+            });
+        }
+
+        if parser.errors.is_empty() {
+            Ok(Self { nodes: root })
+        } else {
+            Err(parser.errors)
+        }
+    }
+
+    /// The built-in layout
+    pub fn default_receipt() -> &'static Self {
+        static DEFAULT: OnceLock<Template> = OnceLock::new();
+        DEFAULT.get_or_init(|| Self::parse(DEFAULT_TEMPLATE).expect("default template is valid"))
+    }
+
+    /// Render to ESC/POS bytes; `logo` is printed where the template says @logo
+    pub fn render(
+        &self,
+        data: &Value,
+        profile: PrinterProfile,
+        logo: Option<&Bitmap>,
+    ) -> Result<Vec<u8>> {
+        let mut sink = EscPosSink {
+            doc: EscPos::new(profile),
+            logo,
+        };
+        run(&self.nodes, &mut vec![data], &mut sink)?;
+        Ok(sink.doc.build())
+    }
+
+    /// Render as plain text lines, for previews
+    pub fn render_text(&self, data: &Value, paper: PaperWidth) -> Result<Vec<PreviewLine>> {
+        let mut sink = TextSink {
+            paper,
+            align: Align::Left,
+            bold: false,
+            inverse: false,
+            size: TextSize::NORMAL,
+            lines: Vec::new(),
+        };
+        run(&self.nodes, &mut vec![data], &mut sink)?;
+        Ok(sink.lines)
+    }
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().replace(',', "").parse().ok(),
+        _ => None,
+    }
+}
+
+fn display(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Array(_) | Value::Object(_) => String::new(),
+    }
+}
+
+fn truthy(value: Option<&Value>) -> bool {
+    match value {
+        None | Some(Value::Null) => false,
+        Some(Value::Bool(b)) => *b,
+        Some(Value::Number(n)) => n.as_f64() != Some(0.0),
+        Some(Value::String(s)) => !s.is_empty(),
+        Some(Value::Array(items)) => !items.is_empty(),
+        Some(Value::Object(_)) => true,
+    }
+}
+
+/// Resolve a path in the innermost scope that has its first name
+fn lookup<'a>(scopes: &[&'a Value], path: &[String]) -> Option<&'a Value> {
+    scopes.iter().rev().find_map(|scope| {
+        path.iter().try_fold(*scope, |value, key| match value {
+            Value::Object(map) => map.get(key),
+            Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => None,
+        })
+    })
+}
+
+fn interpolate(parts: &[Part], scopes: &[&Value]) -> String {
+    parts
+        .iter()
+        .map(|part| match part {
+            Part::Literal(text) => text.clone(),
+            Part::Value { path, filters } => {
+                let value = lookup(scopes, path).cloned().unwrap_or(Value::Null);
+                display(&filters.iter().fold(value, |value, f| f.apply(value)))
+            }
+        })
+        .collect()
+}
+
+fn run(nodes: &[Node], scopes: &mut Vec<&Value>, sink: &mut dyn Sink) -> Result<()> {
+    for node in nodes {
+        match node {
+            Node::Wrapped(parts) => sink.wrapped(&interpolate(parts, scopes)),
+            Node::Line(parts) => sink.line(&interpolate(parts, scopes)),
+            Node::Row(label, value) => {
+                sink.row(&interpolate(label, scopes), &interpolate(value, scopes))
+            }
+            Node::Align(align) => sink.align(*align),
+            Node::Bold(on) => sink.bold(*on),
+            Node::Inverse(on) => sink.inverse(*on),
+            Node::Size(size) => sink.size(*size),
+            Node::Rule(c) => sink.rule(*c),
+            Node::Feed(lines) => sink.feed(*lines),
+            Node::Logo => sink.logo(),
+            Node::Qr(parts) => sink.qr(&interpolate(parts, scopes))?,
+            Node::Barcode(parts) => sink.barcode(&interpolate(parts, scopes))?,
+            Node::Cut(cut) => sink.cut(*cut),
+            Node::If {
+                path,
+                negate,
+                then,
+                otherwise,
+            } => {
+                let branch = if truthy(lookup(scopes, path)) != *negate {
+                    then
+                } else {
+                    otherwise
+                };
+                run(branch, scopes, sink)?;
+            }
+            Node::Each { path, body } => {
+                if let Some(Value::Array(items)) = lookup(scopes, path) {
+                    for item in items {
+                        scopes.push(item);
+                        let result = run(body, scopes, sink);
+                        scopes.pop();
+                        result?;
+                    }
+                }
+            }
+            Node::Section { path, body } => {
+                if let Some(value @ Value::Object(_)) = lookup(scopes, path) {
+                    scopes.push(value);
+                    let result = run(body, scopes, sink);
+                    scopes.pop();
+                    result?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Where a template's output goes: the printer, or a preview
+trait Sink {
+    fn align(&mut self, align: Align);
+    fn bold(&mut self, on: bool);
+    fn inverse(&mut self, on: bool);
+    fn size(&mut self, size: TextSize);
+    fn wrapped(&mut self, text: &str);
+    fn line(&mut self, text: &str);
+    fn row(&mut self, label: &str, value: &str);
+    fn rule(&mut self, c: char);
+    fn feed(&mut self, lines: u8);
+    fn logo(&mut self);
+    fn qr(&mut self, data: &str) -> Result<()>;
+    fn barcode(&mut self, data: &str) -> Result<()>;
+    fn cut(&mut self, cut: Cut);
+}
+
+struct EscPosSink<'a> {
+    doc: EscPos,
+    logo: Option<&'a Bitmap>,
+}
+
+impl Sink for EscPosSink<'_> {
+    fn align(&mut self, align: Align) {
+        self.doc.align(align);
+    }
+
+    fn bold(&mut self, on: bool) {
+        self.doc.bold(on);
+    }
+
+    fn inverse(&mut self, on: bool) {
+        self.doc.inverse(on);
+    }
+
+    fn size(&mut self, size: TextSize) {
+        self.doc.size(size);
+    }
+
+    fn wrapped(&mut self, text: &str) {
+        self.doc.wrapped(text);
+    }
+
+    fn line(&mut self, text: &str) {
+        self.doc.line(text);
+    }
+
+    fn row(&mut self, label: &str, value: &str) {
+        self.doc.two_column(label, value);
+    }
+
+    fn rule(&mut self, c: char) {
+        self.doc.rule(c);
+    }
+
+    fn feed(&mut self, lines: u8) {
+        self.doc.feed(lines);
+    }
+
+    fn logo(&mut self) {
+        if let Some(logo) = self.logo {
+            self.doc.image(logo);
+        }
+    }
+
+    fn qr(&mut self, data: &str) -> Result<()> {
+        if !data.is_empty() {
+            self.doc
+                .qr_code(data, QR_MODULE_SIZE, QrErrorCorrection::Medium)?;
+        }
+        Ok(())
+    }
+
+    fn barcode(&mut self, data: &str) -> Result<()> {
+        if !data.is_empty() {
+            self.doc.code128(data)?;
+        }
+        Ok(())
+    }
+
+    fn cut(&mut self, cut: Cut) {
+        self.doc.cut(cut);
+    }
+}
+
+/// One line of a text preview, already aligned within the paper width
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct PreviewLine {
+    pub text: String,
+    /// Leading spaces added to align the text
+    pub indent: usize,
+    pub bold: bool,
+    pub inverse: bool,
+    /// Character magnification, as (width, height)
+    pub size: (u8, u8),
+}
+
+struct TextSink {
+    paper: PaperWidth,
+    align: Align,
+    bold: bool,
+    inverse: bool,
+    size: TextSize,
+    lines: Vec<PreviewLine>,
+}
+
+impl TextSink {
+    fn columns(&self) -> usize {
+        (self.paper.columns() / self.size.width as usize).max(1)
+    }
+
+    fn push(&mut self, text: &str) {
+        let columns = self.columns();
+        let pad = columns.saturating_sub(text.chars().count());
+        let indent = match self.align {
+            _ if text.is_empty() => 0,
+            Align::Left => 0,
+            Align::Center => pad / 2,
+            Align::Right => pad,
+        };
+        self.lines.push(PreviewLine {
+            text: format!("{}{}", " ".repeat(indent), text),
+            indent,
+            bold: self.bold,
+            inverse: self.inverse,
+            size: (self.size.width, self.size.height),
+        });
+    }
+
+    /// A placeholder for something that is not text, centered
+    fn placeholder(&mut self, text: &str) {
+        let align = std::mem::replace(&mut self.align, Align::Center);
+        for line in wrap(text, self.columns()) {
+            self.push(&line);
+        }
+        self.align = align;
+    }
+}
+
+impl Sink for TextSink {
+    fn align(&mut self, align: Align) {
+        self.align = align;
+    }
+
+    fn bold(&mut self, on: bool) {
+        self.bold = on;
+    }
+
+    fn inverse(&mut self, on: bool) {
+        self.inverse = on;
+    }
+
+    fn size(&mut self, size: TextSize) {
+        self.size = size;
+    }
+
+    fn wrapped(&mut self, text: &str) {
+        for line in wrap(text, self.columns()) {
+            self.push(&line);
+        }
+    }
+
+    fn line(&mut self, text: &str) {
+        self.push(text);
+    }
+
+    fn row(&mut self, label: &str, value: &str) {
+        for line in two_column_lines(label, value, self.columns()) {
+            self.push(&line);
+        }
+    }
+
+    fn rule(&mut self, c: char) {
+        self.push(&c.to_string().repeat(self.columns()));
+    }
+
+    fn feed(&mut self, lines: u8) {
+        for _ in 0..lines {
+            self.push("");
+        }
+    }
+
+    fn logo(&mut self) {
+        self.placeholder("[ logo ]");
+    }
+
+    fn qr(&mut self, data: &str) -> Result<()> {
+        if !data.is_empty() {
+            self.placeholder("[ QR code ]");
+        }
+        Ok(())
+    }
+
+    fn barcode(&mut self, data: &str) -> Result<()> {
+        if !data.is_empty() {
+            if !data.bytes().all(|b| (0x20..0x7F).contains(&b)) {
+                return Err(AppError::invalid_input(
+                    "Barcode data must be printable ASCII",
+                ));
+            }
+            self.placeholder(&format!("||||| {} |||||", data));
+        }
+        Ok(())
+    }
+
+    fn cut(&mut self, _cut: Cut) {
+        let columns = self.columns();
+        self.lines.push(PreviewLine {
+            text: format!("{:-^width$}", " cut ", width = columns),
+            indent: 0,
+            bold: false,
+            inverse: false,
+            size: (1, 1),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn text(template: &str, data: Value) -> Vec<String> {
+        Template::parse(template)
+            .unwrap()
+            .render_text(&data, PaperWidth::Mm58)
+            .unwrap()
+            .into_iter()
+            .map(|line| line.text)
+            .collect()
+    }
+
+    #[test]
+    fn test_default_template_parses() {
+        Template::default_receipt();
+    }
+
+    #[test]
+    fn test_values_and_filters() {
+        let data = json!({
+            "member": { "name": "Uwase" },
+            "amount": 15000,
+            "paid_at": "2026-10-18",
+        });
+        assert_eq!(
+            text(
+                "{{ member.name | upper }} paid {{ amount | rwf }} on {{ paid_at | date }}",
+                data
+            ),
+            ["UWASE paid RWF 15,000 on", "18/10/2026"]
+        );
+    }
+
+    #[test]
+    fn test_blocks() {
+        let template = "\
+@each items
+@row {{ label }} | {{ value | amount }}
+@end
+@if penalty
+Penalty applied
+@else
+No penalty
+@end
+@unless member
+Walk-in
+@end
+@section member
+Member {{ code }} of {{ group }}
+@end";
+        let data = json!({
+            "group": "Abishyizehamwe",
+            "items": [
+                { "label": "Savings", "value": 5000 },
+                { "label": "Loan", "value": "12500" },
+            ],
+            "penalty": 0,
+            "member": { "code": "M-0012" },
+        });
+        assert_eq!(
+            text(template, data),
+            [
+                "Savings                    5,000",
+                "Loan                      12,500",
+                "No penalty",
+                // Outer names stay in scope inside a section
+                "Member M-0012 of Abishyizehamwe",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_alignment_and_cut() {
+        let lines = text("@align right\nMurakoze\n@line  DUPLICATE \n@cut", json!({}));
+        assert_eq!(lines[0], format!("{:>32}", "Murakoze"));
+        assert_eq!(lines[1], format!("{:>32}", " DUPLICATE "));
+        assert_eq!(lines[2], format!("{:-^32}", " cut "));
+    }
+
+    #[test]
+    fn test_parse_errors_are_all_reported() {
+        let errors = Template::parse(
+            "@align middle\n{{ total | euro }}\n@row no separator\n@else\n@if\n@each items\n{{ title",
+        )
+        .unwrap_err();
+        let lines: Vec<usize> = errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, [1, 2, 3, 4, 5, 7, 6, 5]);
+        assert_eq!(errors[1].message, "Unknown filter 'euro'");
+        assert_eq!(errors[6].message, "Block is never closed with @end");
+    }
+}
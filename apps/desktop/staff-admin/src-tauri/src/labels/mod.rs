@@ -0,0 +1,348 @@
+// Label printing in ZPL (Zebra) and TSPL (TSC, Xprinter and most cheap
+// desktop label printers)
+//
+// A label is defined once in millimetres and converted to dots for the
+// printer's resolution, so the same definition prints on 203 and 300 dpi
+// printers. Text and barcode data may use `{{ field }}` placeholders, filled
+// from each record when labels are printed in bulk.
+
+pub mod tspl;
+pub mod zpl;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AppError, Result};
+
+const MM_PER_INCH: f32 = 25.4;
+/// Most copies of each label in one job
+pub const MAX_COPIES: u32 = 99;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LabelLanguage {
+    Zpl,
+    Tspl,
+}
+
+fn default_dpi() -> u32 {
+    203
+}
+
+fn default_gap_mm() -> f32 {
+    2.0
+}
+
+fn default_text_height_mm() -> f32 {
+    3.0
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_module_mm() -> f32 {
+    0.25
+}
+
+fn default_qr_module_mm() -> f32 {
+    0.5
+}
+
+fn default_thickness_mm() -> f32 {
+    0.25
+}
+
+/// One item on a label; positions are the top-left corner in mm
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LabelElement {
+    Text {
+        x_mm: f32,
+        y_mm: f32,
+        text: String,
+        #[serde(default = "default_text_height_mm")]
+        height_mm: f32,
+    },
+    /// Code128 barcode
+    Barcode {
+        x_mm: f32,
+        y_mm: f32,
+        data: String,
+        height_mm: f32,
+        /// Narrow bar width
+        #[serde(default = "default_module_mm")]
+        module_mm: f32,
+        /// Print the data as text under the bars
+        #[serde(default = "default_true")]
+        show_text: bool,
+    },
+    Qr {
+        x_mm: f32,
+        y_mm: f32,
+        data: String,
+        #[serde(default = "default_qr_module_mm")]
+        module_mm: f32,
+    },
+    /// Rectangle outline; a thickness of half the smaller side fills it
+    Box {
+        x_mm: f32,
+        y_mm: f32,
+        width_mm: f32,
+        height_mm: f32,
+        #[serde(default = "default_thickness_mm")]
+        thickness_mm: f32,
+    },
+}
+
+impl LabelElement {
+    fn origin(&self) -> (f32, f32) {
+        match *self {
+            LabelElement::Text { x_mm, y_mm, .. }
+            | LabelElement::Barcode { x_mm, y_mm, .. }
+            | LabelElement::Qr { x_mm, y_mm, .. }
+            | LabelElement::Box { x_mm, y_mm, .. } => (x_mm, y_mm),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LabelDefinition {
+    pub width_mm: f32,
+    pub height_mm: f32,
+    /// Printer resolution in dots per inch
+    #[serde(default = "default_dpi")]
+    pub dpi: u32,
+    /// Gap between labels on the roll, for TSPL printers' label sensing
+    #[serde(default = "default_gap_mm")]
+    pub gap_mm: f32,
+    pub elements: Vec<LabelElement>,
+}
+
+impl LabelDefinition {
+    /// Convert a length in mm to printer dots
+    pub fn dots(&self, mm: f32) -> u32 {
+        (mm * self.dpi as f32 / MM_PER_INCH).round().max(0.0) as u32
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if !(self.width_mm > 0.0 && self.height_mm > 0.0) {
+            return Err(AppError::invalid_input("Label size must be positive"));
+        }
+        if !(100..=600).contains(&self.dpi) {
+            return Err(AppError::invalid_input(
+                "Printer resolution must be 100-600 dpi",
+            ));
+        }
+        if self.elements.is_empty() {
+            return Err(AppError::invalid_input("Label has nothing to print"));
+        }
+        for (i, element) in self.elements.iter().enumerate() {
+            let (x, y) = element.origin();
+            if !(0.0..self.width_mm).contains(&x) || !(0.0..self.height_mm).contains(&y) {
+                return Err(
+                    AppError::invalid_input("Label element is outside the label")
+                        .with_detail(format!("element {}", i + 1)),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy of the label with `{{ field }}` placeholders filled from `record`
+    pub fn fill(&self, record: &serde_json::Map<String, serde_json::Value>) -> Result<Self> {
+        let mut label = self.clone();
+        for element in &mut label.elements {
+            match element {
+                LabelElement::Text { text, .. } => *text = fill_fields(text, record)?,
+                LabelElement::Barcode { data, .. } => {
+                    *data = fill_fields(data, record)?;
+                    if !data.is_ascii() {
+                        return Err(AppError::invalid_input("Barcode data must be plain ASCII")
+                            .with_detail(data.clone()));
+                    }
+                }
+                LabelElement::Qr { data, .. } => *data = fill_fields(data, record)?,
+                LabelElement::Box { .. } => {}
+            }
+        }
+        Ok(label)
+    }
+}
+
+/// Replace `{{ field }}` placeholders; missing and null fields print as
+/// nothing, unknown fields are an error
+fn fill_fields(
+    template: &str,
+    record: &serde_json::Map<String, serde_json::Value>,
+) -> Result<String> {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after.find("}}").ok_or_else(|| {
+            AppError::invalid_input("Unclosed placeholder in label").with_detail(template)
+        })?;
+        let name = after[..end].trim();
+        match record.get(name) {
+            Some(serde_json::Value::String(s)) => output.push_str(s),
+            Some(serde_json::Value::Null) => {}
+            Some(value) => output.push_str(&value.to_string()),
+            None => return Err(AppError::invalid_input("Unknown label field").with_detail(name)),
+        }
+        rest = &after[end + 2..];
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Reject 0 copies and more than `MAX_COPIES`
+pub fn check_copies(copies: u32) -> Result<()> {
+    if !(1..=MAX_COPIES).contains(&copies) {
+        return Err(AppError::invalid_input(format!(
+            "Copies must be between 1 and {}",
+            MAX_COPIES
+        )));
+    }
+    Ok(())
+}
+
+/// Text with control characters as spaces
+///
+/// A line break in a member's name would otherwise end the command it is
+/// part of, and whatever follows would be read as a new command.
+fn without_controls(text: &str) -> impl Iterator<Item = char> + '_ {
+    text.chars().map(|c| if c.is_control() { ' ' } else { c })
+}
+
+/// Render one label per record as a single print job
+pub fn render(
+    language: LabelLanguage,
+    label: &LabelDefinition,
+    records: &[serde_json::Map<String, serde_json::Value>],
+    copies: u32,
+) -> Result<Vec<u8>> {
+    label.validate()?;
+    check_copies(copies)?;
+    if records.is_empty() {
+        return Err(AppError::invalid_input("No labels to print"));
+    }
+
+    let mut job = match language {
+        LabelLanguage::Zpl => String::new(),
+        LabelLanguage::Tspl => tspl::setup(label),
+    };
+    for record in records {
+        let filled = label.fill(record)?;
+        match language {
+            LabelLanguage::Zpl => job.push_str(&zpl::label(&filled, copies)),
+            LabelLanguage::Tspl => job.push_str(&tspl::label(&filled, copies)),
+        }
+    }
+    Ok(job.into_bytes())
+}
+
+/// 50 x 25 mm member ID label: SACCO, name and member number beside a QR code
+/// of the member number
+pub fn default_member_label() -> LabelDefinition {
+    let text = |y_mm: f32, text: &str, height_mm: f32| LabelElement::Text {
+        x_mm: 3.0,
+        y_mm,
+        text: text.to_string(),
+        height_mm,
+    };
+    LabelDefinition {
+        width_mm: 50.0,
+        height_mm: 25.0,
+        dpi: default_dpi(),
+        gap_mm: default_gap_mm(),
+        elements: vec![
+            text(3.0, "{{ sacco_name }}", 2.5),
+            text(8.0, "{{ full_name }}", 3.5),
+            text(14.0, "{{ member_ref }}", 3.0),
+            text(19.0, "{{ group_name }}", 2.5),
+            LabelElement::Qr {
+                x_mm: 34.0,
+                y_mm: 5.0,
+                data: "{{ member_ref }}".to_string(),
+                module_mm: default_qr_module_mm(),
+            },
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn record(value: serde_json::Value) -> serde_json::Map<String, serde_json::Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn test_fill_fields() {
+        let member = record(json!({
+            "full_name": "Hélène Uwase",
+            "member_ref": "TWZ-00421",
+            "group_name": null,
+            "shares": 12,
+        }));
+        assert_eq!(
+            fill_fields("{{full_name}} ({{ member_ref }})", &member).unwrap(),
+            "Hélène Uwase (TWZ-00421)"
+        );
+        assert_eq!(fill_fields("{{ group_name }}", &member).unwrap(), "");
+        assert_eq!(
+            fill_fields("{{ shares }} shares", &member).unwrap(),
+            "12 shares"
+        );
+        assert!(fill_fields("{{ phone }}", &member).is_err());
+        assert!(fill_fields("{{ full_name", &member).is_err());
+
+        let label = LabelDefinition {
+            elements: vec![LabelElement::Barcode {
+                x_mm: 2.0,
+                y_mm: 2.0,
+                data: "{{ full_name }}".to_string(),
+                height_mm: 8.0,
+                module_mm: 0.25,
+                show_text: true,
+            }],
+            ..default_member_label()
+        };
+        assert!(label.fill(&member).is_err());
+    }
+
+    #[test]
+    fn test_validate_label() {
+        let label = default_member_label();
+        assert!(label.validate().is_ok());
+        assert_eq!(label.dots(25.4), 203);
+        assert_eq!(
+            LabelDefinition {
+                dpi: 300,
+                ..label.clone()
+            }
+            .dots(50.0),
+            591
+        );
+
+        let outside = LabelDefinition {
+            elements: vec![LabelElement::Box {
+                x_mm: 60.0,
+                y_mm: 0.0,
+                width_mm: 5.0,
+                height_mm: 5.0,
+                thickness_mm: 0.25,
+            }],
+            ..label.clone()
+        };
+        assert!(outside.validate().is_err());
+
+        let members = [record(json!({ "member_ref": "TWZ-00421" }))];
+        assert!(render(LabelLanguage::Zpl, &label, &members, 0).is_err());
+        assert!(render(LabelLanguage::Tspl, &label, &members, MAX_COPIES + 1).is_err());
+        assert!(render(LabelLanguage::Zpl, &label, &[], 1).is_err());
+    }
+}
@@ -0,0 +1,142 @@
+// TSPL output
+//
+// Text uses the scalable font "0", sized in points. Quotes inside strings are
+// written as \["], the TSPL escape for a double quote, and control characters
+// as spaces so a line break can't start a new command.
+
+use super::{without_controls, LabelDefinition, LabelElement, MAX_COPIES, MM_PER_INCH};
+
+const PT_PER_INCH: f32 = 72.0;
+
+fn quoted(text: &str) -> String {
+    let text: String = without_controls(text).collect();
+    format!("\"{}\"", text.replace('"', "\\[\"]"))
+}
+
+/// Media setup, sent once at the start of the job
+pub fn setup(label: &LabelDefinition) -> String {
+    format!(
+        "SIZE {} mm,{} mm\r\nGAP {} mm,0 mm\r\nCODEPAGE UTF-8\r\n",
+        label.width_mm, label.height_mm, label.gap_mm
+    )
+}
+
+/// One label, printed `copies` times, at most `MAX_COPIES`
+pub fn label(label: &LabelDefinition, copies: u32) -> String {
+    let mut tspl = String::from("CLS\r\n");
+
+    for element in &label.elements {
+        match element {
+            LabelElement::Text {
+                x_mm,
+                y_mm,
+                text,
+                height_mm,
+            } => {
+                let points = (height_mm / MM_PER_INCH * PT_PER_INCH).round().max(1.0);
+                tspl.push_str(&format!(
+                    "TEXT {},{},\"0\",0,{},{},{}\r\n",
+                    label.dots(*x_mm),
+                    label.dots(*y_mm),
+                    points,
+                    points,
+                    quoted(text)
+                ));
+            }
+            LabelElement::Barcode {
+                x_mm,
+                y_mm,
+                data,
+                height_mm,
+                module_mm,
+                show_text,
+            } => {
+                let narrow = label.dots(*module_mm).clamp(1, 10);
+                tspl.push_str(&format!(
+                    "BARCODE {},{},\"128\",{},{},0,{},{},{}\r\n",
+                    label.dots(*x_mm),
+                    label.dots(*y_mm),
+                    label.dots(*height_mm).max(1),
+                    u8::from(*show_text),
+                    narrow,
+                    narrow,
+                    quoted(data)
+                ));
+            }
+            LabelElement::Qr {
+                x_mm,
+                y_mm,
+                data,
+                module_mm,
+            } => {
+                // Error correction M, automatic data mode
+                tspl.push_str(&format!(
+                    "QRCODE {},{},M,{},A,0,{}\r\n",
+                    label.dots(*x_mm),
+                    label.dots(*y_mm),
+                    label.dots(*module_mm).clamp(1, 10),
+                    quoted(data)
+                ));
+            }
+            LabelElement::Box {
+                x_mm,
+                y_mm,
+                width_mm,
+                height_mm,
+                thickness_mm,
+            } => {
+                tspl.push_str(&format!(
+                    "BOX {},{},{},{},{}\r\n",
+                    label.dots(*x_mm),
+                    label.dots(*y_mm),
+                    label.dots(x_mm + width_mm),
+                    label.dots(y_mm + height_mm),
+                    label.dots(*thickness_mm).max(1)
+                ));
+            }
+        }
+    }
+
+    tspl.push_str(&format!("PRINT 1,{}\r\n", copies.clamp(1, MAX_COPIES)));
+    tspl
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::labels::{default_member_label, render, LabelLanguage};
+
+    #[test]
+    fn test_member_labels_tspl() {
+        let members: Vec<_> = ["TWZ-00421", "TWZ-00422"]
+            .iter()
+            .map(|member_ref| {
+                serde_json::json!({
+                    "sacco_name": "Twizigamire SACCO",
+                    "full_name": "Jean \"Bosco\" Habimana",
+                    "member_ref": member_ref,
+                    "group_name": null,
+                })
+                .as_object()
+                .unwrap()
+                .clone()
+            })
+            .collect();
+        let job = render(LabelLanguage::Tspl, &default_member_label(), &members, 1).unwrap();
+        let job = String::from_utf8(job).unwrap();
+
+        // Media is set up once, then one CLS ... PRINT per member
+        assert!(job.starts_with("SIZE 50 mm,25 mm\r\nGAP 2 mm,0 mm\r\nCODEPAGE UTF-8\r\nCLS\r\n"));
+        assert_eq!(job.matches("SIZE").count(), 1);
+        assert_eq!(job.matches("PRINT 1,1\r\n").count(), 2);
+        assert!(job.contains("TEXT 24,64,\"0\",0,10,10,\"Jean \\[\"]Bosco\\[\"] Habimana\"\r\n"));
+        assert!(job.contains("QRCODE 272,40,M,4,A,0,\"TWZ-00422\"\r\n"));
+    }
+
+    #[test]
+    fn test_quoted_strips_control_characters() {
+        assert_eq!(
+            super::quoted("Uwase\"\r\nPRINT 1,999\r\n"),
+            "\"Uwase\\[\"]  PRINT 1,999  \""
+        );
+    }
+}
@@ -0,0 +1,156 @@
+// ZPL II output
+//
+// Field data goes through ^FH so '^' and '~' in a member's name can't be read
+// as commands, and control characters are printed as spaces; ^CI28 makes the
+// printer read it as UTF-8.
+
+use super::{without_controls, LabelDefinition, LabelElement, MAX_COPIES};
+
+/// Escape field data for ^FH, whose escape character is '_'
+fn field_data(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in without_controls(text) {
+        match c {
+            '_' => escaped.push_str("_5F"),
+            '^' => escaped.push_str("_5E"),
+            '~' => escaped.push_str("_7E"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// One label format, printed `copies` times, at most `MAX_COPIES`
+pub fn label(label: &LabelDefinition, copies: u32) -> String {
+    let mut zpl = String::new();
+    zpl.push_str("^XA\n^CI28\n");
+    zpl.push_str(&format!(
+        "^PW{}\n^LL{}\n",
+        label.dots(label.width_mm),
+        label.dots(label.height_mm)
+    ));
+
+    for element in &label.elements {
+        match element {
+            LabelElement::Text {
+                x_mm,
+                y_mm,
+                text,
+                height_mm,
+            } => {
+                let height = label.dots(*height_mm).max(1);
+                zpl.push_str(&format!(
+                    "^FO{},{}^A0N,{},{}^FH^FD{}^FS\n",
+                    label.dots(*x_mm),
+                    label.dots(*y_mm),
+                    height,
+                    height,
+                    field_data(text)
+                ));
+            }
+            LabelElement::Barcode {
+                x_mm,
+                y_mm,
+                data,
+                height_mm,
+                module_mm,
+                show_text,
+            } => {
+                let height = label.dots(*height_mm).max(1);
+                zpl.push_str(&format!(
+                    "^BY{},3,{}\n^FO{},{}^BCN,{},{},N,N^FH^FD{}^FS\n",
+                    label.dots(*module_mm).clamp(1, 10),
+                    height,
+                    label.dots(*x_mm),
+                    label.dots(*y_mm),
+                    height,
+                    if *show_text { "Y" } else { "N" },
+                    field_data(data)
+                ));
+            }
+            LabelElement::Qr {
+                x_mm,
+                y_mm,
+                data,
+                module_mm,
+            } => {
+                // Model 2, error correction M, automatic data mode
+                zpl.push_str(&format!(
+                    "^FO{},{}^BQN,2,{}^FH^FDMA,{}^FS\n",
+                    label.dots(*x_mm),
+                    label.dots(*y_mm),
+                    label.dots(*module_mm).clamp(1, 10),
+                    field_data(data)
+                ));
+            }
+            LabelElement::Box {
+                x_mm,
+                y_mm,
+                width_mm,
+                height_mm,
+                thickness_mm,
+            } => {
+                zpl.push_str(&format!(
+                    "^FO{},{}^GB{},{},{}^FS\n",
+                    label.dots(*x_mm),
+                    label.dots(*y_mm),
+                    label.dots(*width_mm),
+                    label.dots(*height_mm),
+                    label.dots(*thickness_mm).max(1)
+                ));
+            }
+        }
+    }
+
+    zpl.push_str(&format!("^PQ{}\n^XZ\n", copies.clamp(1, MAX_COPIES)));
+    zpl
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::labels::default_member_label;
+
+    #[test]
+    fn test_member_label_zpl() {
+        let member = serde_json::json!({
+            "sacco_name": "Twizigamire SACCO",
+            "full_name": "Hélène ^Uwase_",
+            "member_ref": "TWZ-00421",
+            "group_name": "Abishyizehamwe",
+        });
+        let filled = default_member_label()
+            .fill(member.as_object().unwrap())
+            .unwrap();
+        let zpl = label(&filled, 2);
+
+        assert!(zpl.starts_with("^XA\n^CI28\n^PW400\n^LL200\n"));
+        assert!(zpl.contains("^FO24,64^A0N,28,28^FH^FDHélène _5EUwase_5F^FS\n"));
+        assert!(zpl.contains("^FO272,40^BQN,2,4^FH^FDMA,TWZ-00421^FS\n"));
+        assert!(zpl.ends_with("^PQ2\n^XZ\n"));
+    }
+
+    #[test]
+    fn test_field_data_cannot_inject_commands() {
+        assert_eq!(
+            field_data("Uwase\r\n^XZ^XA~JA\x10"),
+            "Uwase  _5EXZ_5EXA_7EJA "
+        );
+
+        let filled = default_member_label()
+            .fill(
+                serde_json::json!({
+                    "sacco_name": "Twizigamire SACCO",
+                    "full_name": "Uwase\n^PQ999",
+                    "member_ref": "TWZ-00421",
+                    "group_name": null,
+                })
+                .as_object()
+                .unwrap(),
+            )
+            .unwrap();
+        let zpl = label(&filled, 0);
+        assert_eq!(zpl.matches("^PQ").count(), 1);
+        assert!(zpl.ends_with("^PQ1\n^XZ\n"));
+    }
+}
@@ -2,9 +2,20 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod commands;
+mod error;
+mod escpos;
+mod labels;
+mod pdf;
+mod printers;
 mod tray;
+mod updater;
 
-use commands::{auth, crypto, hardware, print, updates};
+use commands::{
+    auth, barcode, branding, cards, crypto, hardware, labels as label_commands, peripherals, print,
+    print_jobs, receipt_numbers, receipt_templates, reports, serial, updates,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tauri::webview::PageLoadEvent;
 use tauri::{Emitter, Manager};
 use tokio::sync::oneshot;
 
@@ -12,6 +23,11 @@ use tokio::sync::oneshot;
 pub fn run() {
     tauri::Builder::default()
         .setup(|app| {
+            // Count this launch; rolls back an update that keeps failing to start
+            if let Err(e) = updates::record_launch(app.handle()) {
+                eprintln!("Failed to record launch: {}", e);
+            }
+
             // Create system tray with full menu
             tray::create_tray(app.handle())?;
 
@@ -21,35 +37,75 @@ pub fn run() {
             
             // Store shutdown sender for graceful shutdown
             app.manage(shutdown_tx);
+
+            // Print job history, needed before any print command runs
+            app.manage(print_jobs::PrintQueue::load(app.handle())?);
             
             tauri::async_runtime::spawn(async move {
                 tray::start_background_sync(app_handle, shutdown_rx).await;
             });
 
-            // Check for updates on startup
+            // Watch printers for paper, cover and connection problems
             let app_handle = app.handle().clone();
-            tauri::async_runtime::spawn(async move {
-                // Wait a bit before checking
-                tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+            tauri::async_runtime::spawn(printers::status::run_monitor(app_handle));
 
-                match updates::check_for_updates(app_handle.clone()).await {
-                    Ok(update_info) => {
-                        if update_info.update_available {
-                            // Emit update-available event to frontend
-                            if let Some(window) = app_handle.get_webview_window("main") {
-                                let _ = window.emit("update-available", update_info);
+            // Check for updates on startup; builds without an update signing
+            // key have updates turned off
+            if updater::verify::updates_enabled(app.handle()) {
+                let app_handle = app.handle().clone();
+                tauri::async_runtime::spawn(async move {
+                    // Wait a bit before checking
+                    tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+
+                    match updates::check_for_updates(app_handle.clone(), None).await {
+                        Ok(update_info) => {
+                            if update_info.update_available {
+                                // Emit update-available event to frontend
+                                if let Some(window) = app_handle.get_webview_window("main") {
+                                    let _ = window.emit("update-available", update_info);
+                                }
                             }
                         }
+                        Err(e) => {
+                            eprintln!("Failed to check for updates: {}", e);
+                        }
                     }
-                    Err(e) => {
-                        eprintln!("Failed to check for updates: {}", e);
-                    }
-                }
-            });
+                });
+            } else {
+                eprintln!("Updates are turned off: no update signing key is configured");
+            }
 
             Ok(())
         })
+        .on_page_load({
+            // The first time the main window finishes loading, this version
+            // has started successfully; see `updates::record_launch`
+            let confirmed = AtomicBool::new(false);
+            move |webview, payload| {
+                if webview.label() != "main"
+                    || payload.event() != PageLoadEvent::Finished
+                    || confirmed.swap(true, Ordering::SeqCst)
+                {
+                    return;
+                }
+                let app_handle = webview.app_handle().clone();
+                tauri::async_runtime::spawn(async move {
+                    if let Err(e) = updates::confirm_startup(app_handle).await {
+                        eprintln!("Failed to confirm startup: {}", e);
+                    }
+                });
+            }
+        })
+        .manage(auth::StaffSession::default())
         .manage(hardware::ScannerState::default())
+        .manage(cards::CardTrustState::default())
+        .manage(serial::SerialState::default())
+        .manage(branding::LogoCache::default())
+        .manage(receipt_templates::TemplateCache::default())
+        .manage(receipt_numbers::ReceiptNumbers::default())
+        .manage(printers::PrinterLocks::default())
+        .manage(printers::status::PrinterMonitor::default())
+        .manage(updates::UpdateDownload::default())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_notification::init())
@@ -63,17 +119,52 @@ pub fn run() {
             auth::set_secure_credentials,
             auth::delete_secure_credentials,
             auth::get_device_id,
+            // Member card commands
+            cards::verify_member_card,
+            cards::sync_card_trust_store,
+            cards::get_card_trust_store_status,
             // Crypto commands
             crypto::encrypt_data,
             crypto::decrypt_data,
             // Print commands
             print::get_printers,
+            print::get_printer_status,
             print::print_html,
             print::print_receipt,
+            print::preview_receipt,
+            print::print_document,
+            print::export_to_pdf,
+            reports::export_report,
+            reports::print_report,
+            print::get_network_printers,
+            print::save_network_printer,
+            print::remove_network_printer,
+            print_jobs::get_print_jobs,
+            print_jobs::retry_print_job,
+            print_jobs::reprint_receipt,
+            receipt_numbers::reserve_receipt_numbers,
+            receipt_numbers::next_receipt_number,
+            receipt_numbers::void_receipt_number,
+            receipt_numbers::get_receipt_number_report,
+            branding::cache_sacco_logo,
+            receipt_templates::sync_receipt_templates,
+            receipt_templates::get_receipt_template,
+            receipt_templates::get_default_receipt_template,
+            receipt_templates::validate_receipt_template,
+            receipt_templates::preview_receipt_template,
+            label_commands::print_member_labels,
+            label_commands::get_default_member_label,
             // Hardware commands
             hardware::is_scanner_available,
             hardware::start_barcode_scan,
             hardware::stop_barcode_scan,
+            barcode::decode_barcode_image,
+            barcode::decode_camera_frame,
+            serial::list_serial_ports,
+            serial::start_serial_scanner,
+            serial::stop_serial_scanner,
+            peripherals::open_cash_drawer,
+            peripherals::show_customer_display,
             hardware::is_nfc_available,
             hardware::start_nfc_reading,
             hardware::stop_nfc_reading,
@@ -81,8 +172,13 @@ pub fn run() {
             hardware::authenticate_biometrics,
             // Update commands
             updates::check_for_updates,
+            updates::get_update_preferences,
+            updates::set_update_preferences,
             updates::download_update,
+            updates::cancel_update_download,
             updates::install_update,
+            updates::get_rollback_status,
+            updates::rollback_update,
             updates::get_current_version,
         ])
         .run(tauri::generate_context!())
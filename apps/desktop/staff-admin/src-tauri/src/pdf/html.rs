@@ -0,0 +1,458 @@
+// Parser for the HTML subset the print dialog allows
+//
+// PrintDialog sanitizes content with DOMPurify down to p, br, strong, em, u,
+// h1-h3, table/tr/td/th, div and span, with `class` and `style` attributes.
+// That is parsed here into paragraphs and tables for the PDF layout. Anything
+// else is treated as plain text so unexpected markup degrades instead of
+// failing; of `style`, only `text-align` is honoured.
+
+use super::layout::{Align, Run, TextStyle};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Paragraph(Paragraph),
+    Table(Table),
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Paragraph {
+    pub runs: Vec<Run>,
+    /// 1-3 for h1-h3
+    pub heading: Option<u8>,
+    pub align: Align,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Table {
+    pub rows: Vec<Vec<Cell>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Cell {
+    pub runs: Vec<Run>,
+    pub header: bool,
+    pub align: Align,
+}
+
+enum Token<'a> {
+    Text(&'a str),
+    Open { name: String, attrs: &'a str },
+    Close(String),
+}
+
+fn tokenize(html: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = html;
+
+    while !rest.is_empty() {
+        let Some(start) = rest.find('<') else {
+            tokens.push(Token::Text(rest));
+            break;
+        };
+        if start > 0 {
+            tokens.push(Token::Text(&rest[..start]));
+        }
+        rest = &rest[start..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+
+        let Some(end) = rest.find('>') else {
+            // A stray "<" is text
+            tokens.push(Token::Text(rest));
+            break;
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        if let Some(name) = tag.strip_prefix('/') {
+            tokens.push(Token::Close(name.trim().to_ascii_lowercase()));
+            continue;
+        }
+        let tag = tag.trim_end_matches('/');
+        let name_end = tag
+            .find(|c: char| c.is_ascii_whitespace())
+            .unwrap_or(tag.len());
+        let name = tag[..name_end].to_ascii_lowercase();
+        if name.is_empty() || name.starts_with('!') || name.starts_with('?') {
+            continue;
+        }
+        tokens.push(Token::Open {
+            name,
+            attrs: &tag[name_end..],
+        });
+    }
+
+    tokens
+}
+
+/// Value of `text-align` in a tag's style attribute
+fn text_align(attrs: &str) -> Option<Align> {
+    let lower = attrs.to_ascii_lowercase();
+    let style = lower.split("style=").nth(1)?;
+    let quote = style.chars().next()?;
+    let style = match quote {
+        '"' | '\'' => style[1..].split(quote).next()?,
+        _ => style.split_whitespace().next()?,
+    };
+
+    style.split(';').find_map(|declaration| {
+        let (property, value) = declaration.split_once(':')?;
+        if property.trim() != "text-align" {
+            return None;
+        }
+        match value.trim() {
+            "center" => Some(Align::Center),
+            "right" | "end" => Some(Align::Right),
+            "left" | "start" | "justify" => Some(Align::Left),
+            _ => None,
+        }
+    })
+}
+
+pub fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let decoded = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{A0}'),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .map(|hex| u32::from_str_radix(hex, 16))
+                    .or_else(|| entity.strip_prefix('#').map(str::parse))
+                    .and_then(|code| code.ok())
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, end))
+        });
+
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+#[derive(Default)]
+struct Parser {
+    blocks: Vec<Block>,
+    paragraph: Paragraph,
+    table: Option<Table>,
+    row: Option<Vec<Cell>>,
+    cell: Option<Cell>,
+    /// Tables opened inside a cell; their structure is flattened into text
+    nested_tables: usize,
+    bold: usize,
+    italic: usize,
+    underline: usize,
+    /// Alignment of each open block element
+    aligns: Vec<(String, Align)>,
+    /// Inside script/style, whose content is never shown
+    hidden: usize,
+}
+
+impl Parser {
+    fn style(&self) -> TextStyle {
+        TextStyle {
+            bold: self.bold > 0 || self.paragraph.heading.is_some(),
+            italic: self.italic > 0,
+            underline: self.underline > 0,
+        }
+    }
+
+    fn align(&self) -> Align {
+        self.aligns
+            .last()
+            .map(|(_, align)| *align)
+            .unwrap_or_default()
+    }
+
+    fn runs(&mut self) -> &mut Vec<Run> {
+        match &mut self.cell {
+            Some(cell) => &mut cell.runs,
+            None => &mut self.paragraph.runs,
+        }
+    }
+
+    fn text(&mut self, raw: &str) {
+        if self.hidden > 0 {
+            return;
+        }
+
+        // Collapse whitespace the way a browser does
+        let decoded = decode_entities(raw);
+        let mut text = String::with_capacity(decoded.len());
+        let mut last_space = self
+            .runs()
+            .last()
+            .is_none_or(|run| run.text.ends_with([' ', '\n']));
+        for c in decoded.chars() {
+            if c.is_whitespace() && c != '\u{A0}' {
+                if !last_space {
+                    text.push(' ');
+                }
+                last_space = true;
+            } else {
+                text.push(c);
+                last_space = false;
+            }
+        }
+        if text.is_empty() {
+            return;
+        }
+
+        let style = self.style();
+        let runs = self.runs();
+        match runs.last_mut() {
+            Some(run) if run.style == style => run.text.push_str(&text),
+            _ => runs.push(Run { text, style }),
+        }
+    }
+
+    fn line_break(&mut self) {
+        let style = self.style();
+        let runs = self.runs();
+        if let Some(run) = runs.last_mut() {
+            while run.text.ends_with(' ') {
+                run.text.pop();
+            }
+        }
+        runs.push(Run {
+            text: "\n".to_string(),
+            style,
+        });
+    }
+
+    /// End the current paragraph, or start a new line within a table cell
+    fn block_boundary(&mut self) {
+        if let Some(cell) = &self.cell {
+            if cell
+                .runs
+                .last()
+                .is_some_and(|run| !run.text.ends_with('\n'))
+            {
+                self.line_break();
+            }
+            return;
+        }
+
+        let mut paragraph = std::mem::take(&mut self.paragraph);
+        trim_runs(&mut paragraph.runs);
+        if !paragraph.runs.is_empty() {
+            paragraph.align = self.align();
+            self.blocks.push(Block::Paragraph(paragraph));
+        }
+    }
+
+    fn open(&mut self, name: &str, attrs: &str) {
+        match name {
+            "p" | "div" | "h1" | "h2" | "h3" => {
+                self.block_boundary();
+                let align = text_align(attrs).unwrap_or_else(|| self.align());
+                self.aligns.push((name.to_string(), align));
+                if let Some(level) = name.strip_prefix('h') {
+                    self.paragraph.heading = level.parse().ok();
+                }
+            }
+            "br" => self.line_break(),
+            "strong" | "b" => self.bold += 1,
+            "em" | "i" => self.italic += 1,
+            "u" => self.underline += 1,
+            "script" | "style" => self.hidden += 1,
+            "table" if self.cell.is_some() => self.nested_tables += 1,
+            "table" => {
+                self.block_boundary();
+                self.table = Some(Table::default());
+            }
+            "tr" if self.nested_tables > 0 => self.line_break(),
+            "tr" if self.table.is_some() => {
+                self.close("tr");
+                self.row = Some(Vec::new());
+            }
+            "td" | "th" if self.nested_tables > 0 => self.text(" "),
+            "td" | "th" if self.table.is_some() => {
+                self.close("td");
+                self.row.get_or_insert_with(Vec::new);
+                self.cell = Some(Cell {
+                    header: name == "th",
+                    align: text_align(attrs).unwrap_or_default(),
+                    runs: Vec::new(),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    fn close(&mut self, name: &str) {
+        match name {
+            "p" | "div" | "h1" | "h2" | "h3" => {
+                self.block_boundary();
+                if let Some(index) = self.aligns.iter().rposition(|(open, _)| open == name) {
+                    self.aligns.truncate(index);
+                }
+                self.paragraph.heading = None;
+            }
+            "strong" | "b" => self.bold = self.bold.saturating_sub(1),
+            "em" | "i" => self.italic = self.italic.saturating_sub(1),
+            "u" => self.underline = self.underline.saturating_sub(1),
+            "script" | "style" => self.hidden = self.hidden.saturating_sub(1),
+            "table" if self.nested_tables > 0 => self.nested_tables -= 1,
+            "table" => {
+                self.close("tr");
+                if let Some(table) = self.table.take() {
+                    if !table.rows.is_empty() {
+                        self.blocks.push(Block::Table(table));
+                    }
+                }
+            }
+            "tr" if self.nested_tables == 0 => {
+                self.close("td");
+                if let (Some(table), Some(row)) = (&mut self.table, self.row.take()) {
+                    if !row.is_empty() {
+                        table.rows.push(row);
+                    }
+                }
+            }
+            "td" | "th" if self.nested_tables == 0 => {
+                if let Some(mut cell) = self.cell.take() {
+                    trim_runs(&mut cell.runs);
+                    self.row.get_or_insert_with(Vec::new).push(cell);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Drop leading/trailing whitespace and line breaks
+fn trim_runs(runs: &mut Vec<Run>) {
+    while let Some(run) = runs.last_mut() {
+        let trimmed = run.text.trim_end_matches([' ', '\n']).len();
+        run.text.truncate(trimmed);
+        if !run.text.is_empty() {
+            break;
+        }
+        runs.pop();
+    }
+    while let Some(run) = runs.first_mut() {
+        run.text = run.text.trim_start_matches([' ', '\n']).to_string();
+        if !run.text.is_empty() {
+            break;
+        }
+        runs.remove(0);
+    }
+}
+
+pub fn parse(html: &str) -> Vec<Block> {
+    let mut parser = Parser::default();
+    for token in tokenize(html) {
+        match token {
+            Token::Text(text) => parser.text(text),
+            Token::Open { name, attrs } => parser.open(&name, attrs),
+            Token::Close(name) => parser.close(&name),
+        }
+    }
+    parser.close("table");
+    parser.block_boundary();
+    parser.blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(text: &str, bold: bool, italic: bool) -> Run {
+        Run {
+            text: text.to_string(),
+            style: TextStyle {
+                bold,
+                italic,
+                underline: false,
+            },
+        }
+    }
+
+    #[test]
+    fn test_paragraphs_and_inline_styles() {
+        let blocks = parse(
+            "<h1>Statement</h1>\n<p style=\"text-align: right\">Member:  <strong>Hélène\n Uwase</strong><br/>\
+             <em>Kigali</em> &amp; Musanze</p><script>alert(1)</script>",
+        );
+        assert_eq!(
+            blocks,
+            vec![
+                Block::Paragraph(Paragraph {
+                    runs: vec![run("Statement", true, false)],
+                    heading: Some(1),
+                    align: Align::Left,
+                }),
+                Block::Paragraph(Paragraph {
+                    runs: vec![
+                        run("Member: ", false, false),
+                        run("Hélène Uwase", true, false),
+                        run("\n", false, false),
+                        run("Kigali", false, true),
+                        run(" & Musanze", false, false),
+                    ],
+                    heading: None,
+                    align: Align::Right,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_table() {
+        let blocks = parse(
+            "<table><tr><th>Date</th><th style='text-align:right'>Amount</th></tr>\
+             <tr><td>2025-03-01</td><td>RWF&nbsp;5,000</td></tr>\
+             <tr><td><p>Loan</p><p>repayment</p></td></tr></table>",
+        );
+        let Block::Table(table) = &blocks[0] else {
+            panic!("expected a table, got {:?}", blocks);
+        };
+        assert_eq!(table.rows.len(), 3);
+        assert!(table.rows[0][0].header);
+        assert_eq!(table.rows[0][1].align, Align::Right);
+        assert_eq!(table.rows[1][1].runs[0].text, "RWF\u{A0}5,000");
+        assert_eq!(
+            table.rows[2][0]
+                .runs
+                .iter()
+                .map(|r| r.text.as_str())
+                .collect::<String>(),
+            "Loan\nrepayment"
+        );
+    }
+
+    #[test]
+    fn test_entities() {
+        assert_eq!(
+            decode_entities("&lt;b&gt; &#233;&#xE8; &copy; a&b"),
+            "<b> éè &copy; a&b"
+        );
+    }
+}
@@ -0,0 +1,627 @@
+// Flow layout: wraps styled text and tables onto fixed-size pages
+//
+// Positions are in points from the top-left corner of the page, with text
+// placed at its baseline. `render` flips them into PDF space.
+
+use std::sync::Arc;
+
+use super::html::{Block, Cell, Table};
+use super::text_width;
+
+/// Line height as a multiple of the font size
+const LINE_SPACING: f32 = 1.3;
+/// Baseline offset from the top of a line, as a multiple of the font size
+const BASELINE: f32 = 1.0;
+const RULE_WIDTH: f32 = 0.5;
+const HEADER_FILL: f32 = 0.9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TextStyle {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+impl TextStyle {
+    pub const BOLD: TextStyle = TextStyle {
+        bold: true,
+        italic: false,
+        underline: false,
+    };
+}
+
+/// Text in one style; "\n" forces a line break
+#[derive(Debug, Clone, PartialEq)]
+pub struct Run {
+    pub text: String,
+    pub style: TextStyle,
+}
+
+impl Run {
+    pub fn plain(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            style: TextStyle::default(),
+        }
+    }
+
+    pub fn bold(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            style: TextStyle::BOLD,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    Text {
+        x: f32,
+        y: f32,
+        size: f32,
+        style: TextStyle,
+        text: String,
+    },
+    Line {
+        from: (f32, f32),
+        to: (f32, f32),
+        width: f32,
+    },
+    /// Filled rectangle; `gray` runs from 0 (black) to 1 (white)
+    Rect {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        gray: f32,
+    },
+    /// Picture scaled to fill the box
+    Image {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        picture: Arc<Picture>,
+    },
+}
+
+/// 8-bit RGB pixels, row by row
+#[derive(Debug, Clone, PartialEq)]
+pub struct Picture {
+    pub width: u32,
+    pub height: u32,
+    pub rgb: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page {
+    pub width: f32,
+    pub height: f32,
+    pub ops: Vec<Op>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Margins {
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub left: f32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Fragment {
+    text: String,
+    style: TextStyle,
+    width: f32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Line {
+    fragments: Vec<Fragment>,
+    width: f32,
+}
+
+impl Line {
+    fn push(&mut self, word: &str, style: TextStyle, space: bool, size: f32) {
+        let space = space && !self.fragments.is_empty();
+        let width = text_width(word, style, size)
+            + if space {
+                text_width(" ", style, size)
+            } else {
+                0.0
+            };
+
+        let fragment = match self.fragments.last_mut() {
+            Some(last) if last.style == style => last,
+            _ => {
+                self.fragments.push(Fragment {
+                    style,
+                    ..Default::default()
+                });
+                self.fragments.last_mut().unwrap()
+            }
+        };
+        if space {
+            fragment.text.push(' ');
+        }
+        fragment.text.push_str(word);
+        fragment.width += width;
+        self.width += width;
+    }
+}
+
+/// Break styled runs into lines no wider than `max_width`
+fn wrap(runs: &[Run], size: f32, max_width: f32) -> Vec<Line> {
+    let mut lines = vec![Line::default()];
+    let mut space = false;
+
+    for run in runs {
+        for (i, segment) in run.text.split('\n').enumerate() {
+            if i > 0 {
+                lines.push(Line::default());
+                space = false;
+            }
+            for (j, word) in segment.split(' ').enumerate() {
+                space |= j > 0;
+                if word.is_empty() {
+                    continue;
+                }
+                place_word(&mut lines, word, run.style, space, size, max_width);
+                space = false;
+            }
+        }
+    }
+
+    lines
+}
+
+fn place_word(
+    lines: &mut Vec<Line>,
+    word: &str,
+    style: TextStyle,
+    space: bool,
+    size: f32,
+    max_width: f32,
+) {
+    let width = text_width(word, style, size);
+    let line = lines.last_mut().unwrap();
+    let space_width = if space && !line.fragments.is_empty() {
+        text_width(" ", style, size)
+    } else {
+        0.0
+    };
+
+    if line.width + space_width + width <= max_width {
+        line.push(word, style, space, size);
+        return;
+    }
+    if !line.fragments.is_empty() {
+        lines.push(Line::default());
+    }
+    if width <= max_width {
+        lines.last_mut().unwrap().push(word, style, false, size);
+        return;
+    }
+
+    // Longer than a whole line: break between characters
+    let mut chunk = String::new();
+    for c in word.chars() {
+        chunk.push(c);
+        if chunk.chars().count() > 1 && text_width(&chunk, style, size) > max_width {
+            chunk.pop();
+            lines.last_mut().unwrap().push(&chunk, style, false, size);
+            lines.push(Line::default());
+            chunk = c.to_string();
+        }
+    }
+    lines.last_mut().unwrap().push(&chunk, style, false, size);
+}
+
+/// Header cells are always bold
+fn cell_runs(cell: &Cell) -> Vec<Run> {
+    let mut runs = cell.runs.clone();
+    if cell.header {
+        for run in &mut runs {
+            run.style.bold = true;
+        }
+    }
+    runs
+}
+
+/// Split the available width between columns
+///
+/// Columns get their natural (unwrapped) width, stretched to fill the line;
+/// when that doesn't fit, wide columns give way down to their longest word.
+fn column_widths(natural: &[f32], minimum: &[f32], available: f32) -> Vec<f32> {
+    let total_natural: f32 = natural.iter().sum();
+    let total_minimum: f32 = minimum.iter().sum();
+
+    if total_natural <= available {
+        natural
+            .iter()
+            .map(|w| w * available / total_natural)
+            .collect()
+    } else if total_minimum >= available {
+        minimum
+            .iter()
+            .map(|w| w * available / total_minimum)
+            .collect()
+    } else {
+        let share = (available - total_minimum) / (total_natural - total_minimum);
+        natural
+            .iter()
+            .zip(minimum)
+            .map(|(n, m)| m + (n - m) * share)
+            .collect()
+    }
+}
+
+/// Lays content out top to bottom, starting new pages as each one fills
+pub struct Flow {
+    width: f32,
+    /// `None` for a roll (receipt) that grows to fit its content
+    height: Option<f32>,
+    margins: Margins,
+    /// Body text size; headings and spacing scale from it
+    pub font_size: f32,
+    pages: Vec<Page>,
+    y: f32,
+}
+
+impl Flow {
+    pub fn new(width: f32, height: Option<f32>, margins: Margins, font_size: f32) -> Self {
+        let mut flow = Self {
+            width,
+            height,
+            margins,
+            font_size,
+            pages: Vec::new(),
+            y: 0.0,
+        };
+        flow.new_page();
+        flow
+    }
+
+    pub fn content_width(&self) -> f32 {
+        self.width - self.margins.left - self.margins.right
+    }
+
+    pub fn new_page(&mut self) {
+        self.pages.push(Page {
+            width: self.width,
+            height: self.height.unwrap_or(0.0),
+            ops: Vec::new(),
+        });
+        self.y = self.margins.top;
+    }
+
+    /// Start a new page unless `height` still fits on this one
+    fn ensure(&mut self, height: f32) {
+        if let Some(page_height) = self.height {
+            let at_top = self.y <= self.margins.top;
+            if !at_top && self.y + height > page_height - self.margins.bottom {
+                self.new_page();
+            }
+        }
+    }
+
+    fn push(&mut self, op: Op) {
+        self.pages.last_mut().unwrap().ops.push(op);
+    }
+
+    pub fn space(&mut self, height: f32) {
+        self.y += height;
+    }
+
+    fn draw_line(&mut self, line: &Line, x: f32, width: f32, size: f32, align: Align) {
+        let mut x = x + match align {
+            Align::Left => 0.0,
+            Align::Center => (width - line.width) / 2.0,
+            Align::Right => width - line.width,
+        };
+        let baseline = self.y + size * BASELINE;
+
+        for fragment in &line.fragments {
+            self.push(Op::Text {
+                x,
+                y: baseline,
+                size,
+                style: fragment.style,
+                text: fragment.text.clone(),
+            });
+            if fragment.style.underline {
+                let y = baseline + size * 0.12;
+                self.push(Op::Line {
+                    from: (x, y),
+                    to: (x + fragment.width, y),
+                    width: size * 0.06,
+                });
+            }
+            x += fragment.width;
+        }
+    }
+
+    pub fn paragraph(&mut self, runs: &[Run], size: f32, align: Align) {
+        let line_height = size * LINE_SPACING;
+        for line in wrap(runs, size, self.content_width()) {
+            self.ensure(line_height);
+            let x = self.margins.left;
+            let width = self.content_width();
+            self.draw_line(&line, x, width, size, align);
+            self.y += line_height;
+        }
+    }
+
+    /// Horizontal rule across the content width
+    pub fn rule(&mut self) {
+        self.ensure(self.font_size);
+        let y = self.y + self.font_size / 2.0;
+        self.push(Op::Line {
+            from: (self.margins.left, y),
+            to: (self.width - self.margins.right, y),
+            width: RULE_WIDTH,
+        });
+        self.y += self.font_size;
+    }
+
+    /// Bordered table; leading rows of header cells repeat on each page
+    pub fn table(&mut self, table: &Table) {
+        let size = self.font_size;
+        let columns = table.rows.iter().map(Vec::len).max().unwrap_or(0);
+        if columns == 0 {
+            return;
+        }
+        let padding = size * 0.4;
+
+        let mut natural = vec![0.0_f32; columns];
+        let mut minimum = vec![0.0_f32; columns];
+        for row in &table.rows {
+            for (column, cell) in row.iter().enumerate() {
+                let runs = cell_runs(cell);
+                let longest_line = wrap(&runs, size, f32::INFINITY)
+                    .iter()
+                    .map(|line| line.width)
+                    .fold(0.0, f32::max);
+                let longest_word = runs
+                    .iter()
+                    .flat_map(|run| {
+                        run.text
+                            .split([' ', '\n'])
+                            .map(|word| text_width(word, run.style, size))
+                    })
+                    .fold(0.0, f32::max);
+                natural[column] = natural[column].max(longest_line + 2.0 * padding);
+                minimum[column] = minimum[column].max(longest_word + 2.0 * padding);
+            }
+        }
+        let widths = column_widths(&natural, &minimum, self.content_width());
+
+        let header_rows = table
+            .rows
+            .iter()
+            .take_while(|row| row.iter().all(|cell| cell.header))
+            .count();
+
+        for (index, row) in table.rows.iter().enumerate() {
+            let (lines, height) = self.row_lines(row, &widths, padding);
+
+            let page_count = self.pages.len();
+            self.ensure(height);
+            if self.pages.len() > page_count && index >= header_rows {
+                for header in &table.rows[..header_rows] {
+                    let (lines, height) = self.row_lines(header, &widths, padding);
+                    self.draw_row(header, &lines, &widths, padding, height);
+                }
+            }
+
+            self.draw_row(row, &lines, &widths, padding, height);
+        }
+
+        self.y += size / 2.0;
+    }
+
+    /// Wrapped lines for each cell of a row, and the row's height
+    fn row_lines(&self, row: &[Cell], widths: &[f32], padding: f32) -> (Vec<Vec<Line>>, f32) {
+        let size = self.font_size;
+        let lines: Vec<Vec<Line>> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| wrap(&cell_runs(cell), size, width - 2.0 * padding))
+            .collect();
+        let height = lines.iter().map(Vec::len).max().unwrap_or(1) as f32 * size * LINE_SPACING
+            + 2.0 * padding;
+        (lines, height)
+    }
+
+    fn draw_row(
+        &mut self,
+        row: &[Cell],
+        lines: &[Vec<Line>],
+        widths: &[f32],
+        padding: f32,
+        height: f32,
+    ) {
+        let size = self.font_size;
+        let line_height = size * LINE_SPACING;
+        let left = self.margins.left;
+        let right = left + widths.iter().sum::<f32>();
+        let top = self.y;
+
+        if !row.is_empty() && row.iter().all(|cell| cell.header) {
+            self.push(Op::Rect {
+                x: left,
+                y: top,
+                width: right - left,
+                height,
+                gray: HEADER_FILL,
+            });
+        }
+
+        let mut x = left;
+        for ((cell, cell_lines), width) in row.iter().zip(lines).zip(widths) {
+            self.y = top + padding;
+            for line in cell_lines {
+                self.draw_line(line, x + padding, width - 2.0 * padding, size, cell.align);
+                self.y += line_height;
+            }
+            x += width;
+        }
+
+        for y in [top, top + height] {
+            self.push(Op::Line {
+                from: (left, y),
+                to: (right, y),
+                width: RULE_WIDTH,
+            });
+        }
+        let mut x = left;
+        for width in std::iter::once(&0.0).chain(widths) {
+            x += width;
+            self.push(Op::Line {
+                from: (x, top),
+                to: (x, top + height),
+                width: RULE_WIDTH,
+            });
+        }
+
+        self.y = top + height;
+    }
+
+    /// Lay out parsed HTML blocks
+    pub fn blocks(&mut self, blocks: &[Block]) {
+        for block in blocks {
+            match block {
+                Block::Paragraph(paragraph) => {
+                    let size = match paragraph.heading {
+                        Some(1) => self.font_size * 1.8,
+                        Some(2) => self.font_size * 1.4,
+                        Some(_) => self.font_size * 1.15,
+                        None => self.font_size,
+                    };
+                    if paragraph.heading.is_some() && self.y > self.margins.top {
+                        self.y += size / 2.0;
+                    }
+                    self.paragraph(&paragraph.runs, size, paragraph.align);
+                    self.y += self.font_size / 2.0;
+                }
+                Block::Table(table) => self.table(table),
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> Vec<Page> {
+        if self.height.is_none() {
+            if let Some(page) = self.pages.last_mut() {
+                page.height = self.y + self.margins.bottom;
+            }
+        }
+        self.pages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::html;
+
+    const MARGINS: Margins = Margins {
+        top: 20.0,
+        right: 20.0,
+        bottom: 20.0,
+        left: 20.0,
+    };
+
+    fn texts(page: &Page) -> Vec<&str> {
+        page.ops
+            .iter()
+            .filter_map(|op| match op {
+                Op::Text { text, .. } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_wrap_respects_width() {
+        let runs = [
+            Run::plain("Murakoze cyane ku bw'ubwizigame bwanyu"),
+            Run::bold(" RWF"),
+        ];
+        let lines = wrap(&runs, 10.0, 120.0);
+        assert!(lines.len() > 1);
+        assert!(lines.iter().all(|line| line.width <= 120.0));
+
+        let words: Vec<String> = lines
+            .iter()
+            .flat_map(|line| line.fragments.iter().map(|f| f.text.clone()))
+            .collect();
+        assert_eq!(words.last().unwrap(), "RWF");
+    }
+
+    #[test]
+    fn test_long_word_is_broken() {
+        let lines = wrap(&[Run::plain("x".repeat(200))], 10.0, 100.0);
+        assert!(lines.len() > 1);
+        assert!(lines.iter().all(|line| line.width <= 100.0));
+    }
+
+    #[test]
+    fn test_pages_break_and_receipt_grows() {
+        let blocks = html::parse(&"<p>Ledger entry</p>".repeat(80));
+
+        let mut flow = Flow::new(300.0, Some(400.0), MARGINS, 10.0);
+        flow.blocks(&blocks);
+        let pages = flow.finish();
+        assert!(pages.len() > 1);
+        assert!(pages.iter().all(|page| page.height == 400.0));
+
+        let mut roll = Flow::new(227.0, None, MARGINS, 10.0);
+        roll.blocks(&blocks);
+        let pages = roll.finish();
+        assert_eq!(pages.len(), 1);
+        assert!(pages[0].height > 400.0);
+    }
+
+    #[test]
+    fn test_table_header_repeats() {
+        let rows = "<tr><td>2025-03-01</td><td>RWF 5,000</td></tr>".repeat(40);
+        let blocks = html::parse(&format!(
+            "<table><tr><th>Date</th><th>Amount</th></tr>{}</table>",
+            rows
+        ));
+
+        let mut flow = Flow::new(300.0, Some(400.0), MARGINS, 10.0);
+        flow.blocks(&blocks);
+        let pages = flow.finish();
+        assert!(pages.len() > 1);
+        for page in &pages {
+            assert_eq!(&texts(page)[..2], ["Date", "Amount"]);
+        }
+        let dates = pages
+            .iter()
+            .flat_map(texts)
+            .filter(|text| *text == "2025-03-01")
+            .count();
+        assert_eq!(dates, 40);
+    }
+
+    #[test]
+    fn test_column_widths() {
+        assert_eq!(
+            column_widths(&[50.0, 50.0], &[10.0, 10.0], 200.0),
+            [100.0, 100.0]
+        );
+        // Wide column shrinks towards its longest word
+        let widths = column_widths(&[40.0, 300.0], &[40.0, 60.0], 200.0);
+        assert_eq!(widths[0], 40.0);
+        assert_eq!(widths[1], 160.0);
+    }
+}
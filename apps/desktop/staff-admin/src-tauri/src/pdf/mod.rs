@@ -0,0 +1,414 @@
+// PDF rendering without a browser
+//
+// Documents are laid out by `layout::Flow` into pages of positioned text,
+// lines and boxes, then written with printpdf. DejaVu Sans is embedded
+// (subset to the glyphs used) so French and Kinyarwanda text renders the same
+// on every machine and printer.
+
+pub mod html;
+pub mod layout;
+pub mod reports;
+
+use printpdf::{
+    Color, ColorBits, ColorSpace, Greyscale, Image, ImageTransform, ImageXObject, IndirectFontRef,
+    Line, Mm, PdfDocument, PdfLayerReference, Point, Pt, Px, Rect,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+use crate::error::{AppError, Result};
+use layout::{Flow, Margins, Op, Page, Picture, TextStyle};
+
+/// Body text size at 100% scale, in points
+pub const BASE_FONT_SIZE: f32 = 10.0;
+const PT_PER_MM: f32 = 72.0 / 25.4;
+
+/// Regular, bold, italic, bold italic; indexed by `font_index`
+static FONT_DATA: [&[u8]; 4] = [
+    include_bytes!("../../fonts/DejaVuSans.ttf"),
+    include_bytes!("../../fonts/DejaVuSans-Bold.ttf"),
+    include_bytes!("../../fonts/DejaVuSans-Oblique.ttf"),
+    include_bytes!("../../fonts/DejaVuSans-BoldOblique.ttf"),
+];
+
+fn font_index(style: TextStyle) -> usize {
+    style.bold as usize + 2 * style.italic as usize
+}
+
+fn faces() -> &'static [ttf_parser::Face<'static>] {
+    static FACES: OnceLock<Vec<ttf_parser::Face<'static>>> = OnceLock::new();
+    FACES.get_or_init(|| {
+        FONT_DATA
+            .iter()
+            .map(|data| ttf_parser::Face::parse(data, 0).expect("bundled font is valid"))
+            .collect()
+    })
+}
+
+/// Advance width of `text` in points
+pub fn text_width(text: &str, style: TextStyle, size: f32) -> f32 {
+    let face = &faces()[font_index(style)];
+    let units: u32 = text
+        .chars()
+        .filter_map(|c| face.glyph_index(c))
+        .filter_map(|glyph| face.glyph_hor_advance(glyph))
+        .map(u32::from)
+        .sum();
+    units as f32 * size / face.units_per_em() as f32
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PaperSize {
+    #[default]
+    A4,
+    A5,
+    Letter,
+    Legal,
+    /// 80mm roll, as long as the content
+    Receipt,
+}
+
+impl PaperSize {
+    /// Portrait width and height in millimetres
+    fn dimensions(self) -> (f32, Option<f32>) {
+        match self {
+            PaperSize::A4 => (210.0, Some(297.0)),
+            PaperSize::A5 => (148.0, Some(210.0)),
+            PaperSize::Letter => (215.9, Some(279.4)),
+            PaperSize::Legal => (215.9, Some(355.6)),
+            PaperSize::Receipt => (80.0, None),
+        }
+    }
+
+    /// IPP/CUPS `media` keyword
+    pub fn media(self) -> &'static str {
+        match self {
+            PaperSize::A4 => "iso_a4_210x297mm",
+            PaperSize::A5 => "iso_a5_148x210mm",
+            PaperSize::Letter => "na_letter_8.5x11in",
+            PaperSize::Legal => "na_legal_8.5x14in",
+            PaperSize::Receipt => "roll_max_80x3276.8mm",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Orientation {
+    #[default]
+    Portrait,
+    Landscape,
+}
+
+/// Page margins in millimetres
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct PageMargins {
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub left: f32,
+}
+
+impl Default for PageMargins {
+    fn default() -> Self {
+        Self {
+            top: 10.0,
+            right: 10.0,
+            bottom: 10.0,
+            left: 10.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageSetup {
+    pub paper: PaperSize,
+    /// Ignored for receipt rolls
+    pub orientation: Orientation,
+    pub margins: PageMargins,
+    /// Content scale; 1.0 is 100%
+    pub scale: f32,
+}
+
+impl Default for PageSetup {
+    fn default() -> Self {
+        Self {
+            paper: PaperSize::default(),
+            orientation: Orientation::default(),
+            margins: PageMargins::default(),
+            scale: 1.0,
+        }
+    }
+}
+
+impl PageSetup {
+    /// Empty flow for this page size, in points
+    pub fn flow(&self) -> Flow {
+        let (width, height) = self.paper.dimensions();
+        let (width, height) = match (self.orientation, height) {
+            (Orientation::Landscape, Some(height)) => (height, Some(width)),
+            _ => (width, height),
+        };
+
+        Flow::new(
+            width * PT_PER_MM,
+            height.map(|h| h * PT_PER_MM),
+            Margins {
+                top: self.margins.top * PT_PER_MM,
+                right: self.margins.right * PT_PER_MM,
+                bottom: self.margins.bottom * PT_PER_MM,
+                left: self.margins.left * PT_PER_MM,
+            },
+            BASE_FONT_SIZE * self.scale,
+        )
+    }
+}
+
+impl Picture {
+    /// Downscale to fit `max_size` pixels and flatten transparency onto white
+    pub fn from_image(image: &image::DynamicImage, max_size: u32) -> Self {
+        let image = if image.width() > max_size || image.height() > max_size {
+            image.thumbnail(max_size, max_size)
+        } else {
+            image.clone()
+        };
+        let rgba = image.to_rgba8();
+        let rgb = rgba
+            .pixels()
+            .flat_map(|pixel| {
+                let [r, g, b, a] = pixel.0;
+                let over_white =
+                    |c: u8| ((c as u32 * a as u32 + 255 * (255 - a as u32)) / 255) as u8;
+                [over_white(r), over_white(g), over_white(b)]
+            })
+            .collect();
+        Self {
+            width: rgba.width(),
+            height: rgba.height(),
+            rgb,
+        }
+    }
+}
+
+/// Lay out the print dialog's sanitized HTML
+pub fn html_pages(content: &str, setup: &PageSetup) -> Vec<Page> {
+    let mut flow = setup.flow();
+    flow.blocks(&html::parse(content));
+    flow.finish()
+}
+
+/// Page numbers selected by a range like "1-3, 5" (1-based, in order)
+pub fn parse_page_range(range: &str, page_count: usize) -> Result<Vec<usize>> {
+    let invalid = || AppError::invalid_input(format!("Invalid page range: {}", range));
+    let mut pages = Vec::new();
+
+    for part in range.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (start, end) = match part.split_once('-') {
+            Some((start, end)) => {
+                let start: usize = start.trim().parse().map_err(|_| invalid())?;
+                let end = match end.trim() {
+                    "" => page_count,
+                    end => end.parse().map_err(|_| invalid())?,
+                };
+                (start, end)
+            }
+            None => {
+                let page = part.parse().map_err(|_| invalid())?;
+                (page, page)
+            }
+        };
+        if start == 0 || end < start {
+            return Err(invalid());
+        }
+        for page in start..=end.min(page_count) {
+            if !pages.contains(&page) {
+                pages.push(page);
+            }
+        }
+    }
+
+    if pages.is_empty() {
+        return Err(AppError::invalid_input("The page range selects no pages"));
+    }
+    Ok(pages)
+}
+
+/// Keep only the pages a range selects
+pub fn select_pages(pages: Vec<Page>, range: Option<&str>) -> Result<Vec<Page>> {
+    let Some(range) = range.filter(|r| !r.trim().is_empty()) else {
+        return Ok(pages);
+    };
+    let selected = parse_page_range(range, pages.len())?;
+    let mut pages: Vec<Option<Page>> = pages.into_iter().map(Some).collect();
+    Ok(selected
+        .into_iter()
+        .filter_map(|number| pages[number - 1].take())
+        .collect())
+}
+
+fn pdf_error(e: printpdf::Error) -> AppError {
+    AppError::internal(format!("PDF generation failed: {}", e))
+}
+
+fn mm(points: f32) -> Mm {
+    Pt(points).into()
+}
+
+/// Write laid-out pages as a PDF document
+pub fn render(pages: &[Page], title: &str) -> Result<Vec<u8>> {
+    let first = pages
+        .first()
+        .ok_or_else(|| AppError::invalid_input("There is nothing to print"))?;
+    let (doc, first_page, first_layer) =
+        PdfDocument::new(title, mm(first.width), mm(first.height), "Content");
+
+    // Only embed the styles actually used
+    let mut fonts: [Option<IndirectFontRef>; 4] = Default::default();
+    for op in pages.iter().flat_map(|page| &page.ops) {
+        if let Op::Text { style, .. } = op {
+            let index = font_index(*style);
+            if fonts[index].is_none() {
+                fonts[index] = Some(doc.add_external_font(FONT_DATA[index]).map_err(pdf_error)?);
+            }
+        }
+    }
+
+    for (number, page) in pages.iter().enumerate() {
+        let layer = if number == 0 {
+            doc.get_page(first_page).get_layer(first_layer)
+        } else {
+            let (page_index, layer_index) =
+                doc.add_page(mm(page.width), mm(page.height), "Content");
+            doc.get_page(page_index).get_layer(layer_index)
+        };
+        draw_page(&layer, page, &fonts);
+    }
+
+    doc.save_to_bytes().map_err(pdf_error)
+}
+
+fn draw_page(layer: &PdfLayerReference, page: &Page, fonts: &[Option<IndirectFontRef>; 4]) {
+    // Layout measures from the top; PDF from the bottom
+    let point = |x: f32, y: f32| Point::new(mm(x), mm(page.height - y));
+
+    for op in &page.ops {
+        match op {
+            Op::Text {
+                x,
+                y,
+                size,
+                style,
+                text,
+            } => {
+                if let Some(font) = &fonts[font_index(*style)] {
+                    layer.use_text(text.as_str(), *size, mm(*x), mm(page.height - y), font);
+                }
+            }
+            Op::Line { from, to, width } => {
+                layer.set_outline_thickness(*width);
+                layer.add_line(Line {
+                    points: vec![(point(from.0, from.1), false), (point(to.0, to.1), false)],
+                    is_closed: false,
+                });
+            }
+            Op::Rect {
+                x,
+                y,
+                width,
+                height,
+                gray,
+            } => {
+                layer.set_fill_color(Color::Greyscale(Greyscale::new(*gray, None)));
+                layer.add_rect(Rect::new(
+                    mm(*x),
+                    mm(page.height - y - height),
+                    mm(x + width),
+                    mm(page.height - y),
+                ));
+                layer.set_fill_color(Color::Greyscale(Greyscale::new(0.0, None)));
+            }
+            Op::Image {
+                x,
+                y,
+                width,
+                height,
+                picture,
+            } => {
+                let image = Image::from(ImageXObject {
+                    width: Px(picture.width as usize),
+                    height: Px(picture.height as usize),
+                    color_space: ColorSpace::Rgb,
+                    bits_per_component: ColorBits::Bit8,
+                    interpolate: true,
+                    image_data: picture.rgb.clone(),
+                    image_filter: None,
+                    smask: None,
+                    clipping_bbox: None,
+                });
+                // At 72 dpi one pixel is one point before scaling
+                image.add_to_layer(
+                    layer.clone(),
+                    ImageTransform {
+                        translate_x: Some(mm(*x)),
+                        translate_y: Some(mm(page.height - y - height)),
+                        scale_x: Some(width / picture.width as f32),
+                        scale_y: Some(height / picture.height as f32),
+                        dpi: Some(72.0),
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_range() {
+        assert_eq!(parse_page_range("1-3, 5", 10).unwrap(), [1, 2, 3, 5]);
+        assert_eq!(parse_page_range("4-", 6).unwrap(), [4, 5, 6]);
+        assert_eq!(parse_page_range("2,2,9", 3).unwrap(), [2]);
+        assert!(parse_page_range("3-1", 5).is_err());
+        assert!(parse_page_range("a", 5).is_err());
+        assert!(parse_page_range("7", 5).is_err());
+    }
+
+    #[test]
+    fn test_landscape_swaps_dimensions() {
+        let setup = PageSetup {
+            orientation: Orientation::Landscape,
+            ..Default::default()
+        };
+        let pages = html_pages("<p>Raporo y'umunsi</p>", &setup);
+        assert!(pages[0].width > pages[0].height);
+        assert!((pages[0].width - 297.0 * PT_PER_MM).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_render_pdf() {
+        let setup = PageSetup::default();
+        let pages = html_pages(
+            "<h1>Statement</h1><p>Hélène <strong>Uwase</strong> <em>— RWF 5,000</em></p>",
+            &setup,
+        );
+        let pdf = render(&pages, "Statement").unwrap();
+        assert!(pdf.starts_with(b"%PDF-"));
+        // Subset fonts keep the file small
+        assert!(pdf.len() < 200_000, "PDF is {} bytes", pdf.len());
+    }
+
+    #[test]
+    fn test_text_width() {
+        let regular = text_width("Murakoze", TextStyle::default(), 10.0);
+        let bold = text_width("Murakoze", TextStyle::BOLD, 10.0);
+        assert!(regular > 30.0 && regular < 60.0);
+        assert!(bold > regular);
+        assert_eq!(
+            text_width("Murakoze", TextStyle::default(), 20.0),
+            regular * 2.0
+        );
+    }
+}
@@ -0,0 +1,677 @@
+// Member statements and SACCO reports from locally cached data
+//
+// The `export-statement` and `reports-export` edge functions need a
+// connection; these render the same figures on the device so staff in the
+// field can print them offline. The frontend passes rows from its offline
+// store; balances and totals are computed here. Every page carries the
+// SACCO's name and cached logo in the header and the report title, page
+// number and generation time in the footer.
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use super::html::{Block, Cell, Paragraph, Table};
+use super::layout::{Align, Op, Page, Picture, Run, TextStyle};
+use super::{text_width, PageSetup, PT_PER_MM};
+
+/// Space above the body for the SACCO header, in millimetres
+const HEADER_HEIGHT: f32 = 18.0;
+/// Space below the body for the footer, in millimetres
+const FOOTER_HEIGHT: f32 = 10.0;
+/// Largest logo edge kept, in pixels
+pub const LOGO_PIXELS: u32 = 240;
+const DATE_FORMAT: &str = "%d/%m/%Y";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Branding {
+    pub sacco_name: String,
+    /// SACCO whose cached logo is printed in the header
+    #[serde(default)]
+    pub sacco_id: Option<Uuid>,
+    #[serde(default)]
+    pub address: Option<String>,
+    #[serde(default)]
+    pub phone: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Report {
+    MemberStatement(MemberStatement),
+    GroupContributions(GroupContributions),
+    DailyCash(DailyCash),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MemberStatement {
+    pub member_name: String,
+    pub member_code: String,
+    #[serde(default)]
+    pub group_name: Option<String>,
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    /// Balance before `period_start`
+    pub opening_balance: f64,
+    pub entries: Vec<LedgerEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LedgerEntry {
+    pub occurred_at: DateTime<Utc>,
+    pub reference: String,
+    #[serde(default)]
+    pub description: String,
+    /// Positive for credits (deposits), negative for debits
+    pub amount: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GroupContributions {
+    pub group_name: String,
+    #[serde(default)]
+    pub group_code: Option<String>,
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    pub members: Vec<MemberContribution>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MemberContribution {
+    pub member_code: String,
+    pub member_name: String,
+    pub payments: u32,
+    pub total: f64,
+    /// Amount due over the period; arrears are shown when set
+    #[serde(default)]
+    pub expected: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DailyCash {
+    pub date: NaiveDate,
+    #[serde(default)]
+    pub teller: Option<String>,
+    pub opening_cash: f64,
+    pub transactions: Vec<CashTransaction>,
+    /// Cash counted at close; the variance is shown when set
+    #[serde(default)]
+    pub counted_cash: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CashTransaction {
+    pub occurred_at: DateTime<Utc>,
+    pub reference: String,
+    #[serde(default)]
+    pub member_name: Option<String>,
+    #[serde(default)]
+    pub description: String,
+    /// Positive for cash received, negative for cash paid out
+    pub amount: f64,
+}
+
+/// Whole francs with thousands separators, e.g. "-12,500"
+pub fn format_amount(amount: f64) -> String {
+    let rounded = amount.round();
+    let digits = format!("{:.0}", rounded.abs());
+    let mut grouped = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    if rounded < 0.0 {
+        format!("-{}", grouped)
+    } else {
+        grouped
+    }
+}
+
+fn local_date(at: &DateTime<Utc>) -> String {
+    at.with_timezone(&Local).format(DATE_FORMAT).to_string()
+}
+
+fn period(start: NaiveDate, end: NaiveDate) -> String {
+    format!(
+        "{} – {}",
+        start.format(DATE_FORMAT),
+        end.format(DATE_FORMAT)
+    )
+}
+
+fn heading(text: impl Into<String>) -> Block {
+    Block::Paragraph(Paragraph {
+        runs: vec![Run::bold(text)],
+        heading: Some(2),
+        align: Align::Left,
+    })
+}
+
+fn paragraph(runs: Vec<Run>) -> Block {
+    Block::Paragraph(Paragraph {
+        runs,
+        ..Default::default()
+    })
+}
+
+fn cell(text: impl Into<String>, align: Align) -> Cell {
+    Cell {
+        runs: vec![Run::plain(text)],
+        header: false,
+        align,
+    }
+}
+
+fn bold_cell(text: impl Into<String>, align: Align) -> Cell {
+    Cell {
+        runs: vec![Run::bold(text)],
+        header: false,
+        align,
+    }
+}
+
+fn amount_cell(amount: f64) -> Cell {
+    cell(format_amount(amount), Align::Right)
+}
+
+fn header_row(titles: &[(&str, Align)]) -> Vec<Cell> {
+    titles
+        .iter()
+        .map(|(title, align)| Cell {
+            runs: vec![Run::bold(*title)],
+            header: true,
+            align: *align,
+        })
+        .collect()
+}
+
+/// Label and value pairs, values right-aligned and bold
+fn summary(rows: &[(&str, String)]) -> Block {
+    Block::Table(Table {
+        rows: rows
+            .iter()
+            .map(|(label, value)| vec![cell(*label, Align::Left), bold_cell(value, Align::Right)])
+            .collect(),
+    })
+}
+
+impl Report {
+    pub fn title(&self) -> &'static str {
+        match self {
+            Report::MemberStatement(_) => "Member statement",
+            Report::GroupContributions(_) => "Group contribution summary",
+            Report::DailyCash(_) => "Daily cash report",
+        }
+    }
+
+    /// Who or what the report is about, for the heading and print history
+    pub fn subject(&self) -> String {
+        match self {
+            Report::MemberStatement(statement) => {
+                format!("{} ({})", statement.member_name, statement.member_code)
+            }
+            Report::GroupContributions(group) => match &group.group_code {
+                Some(code) => format!("{} ({})", group.group_name, code),
+                None => group.group_name.clone(),
+            },
+            Report::DailyCash(day) => day.date.format(DATE_FORMAT).to_string(),
+        }
+    }
+
+    fn blocks(&self) -> Vec<Block> {
+        let mut blocks = vec![heading(format!("{}: {}", self.title(), self.subject()))];
+        match self {
+            Report::MemberStatement(statement) => statement.blocks(&mut blocks),
+            Report::GroupContributions(group) => group.blocks(&mut blocks),
+            Report::DailyCash(day) => day.blocks(&mut blocks),
+        }
+        blocks
+    }
+}
+
+impl MemberStatement {
+    pub fn closing_balance(&self) -> f64 {
+        self.opening_balance + self.entries.iter().map(|entry| entry.amount).sum::<f64>()
+    }
+
+    fn blocks(&self, blocks: &mut Vec<Block>) {
+        let mut details = vec![
+            Run::bold("Period: "),
+            Run::plain(period(self.period_start, self.period_end)),
+        ];
+        if let Some(group) = &self.group_name {
+            details.extend([Run::bold("\nGroup: "), Run::plain(group)]);
+        }
+        blocks.push(paragraph(details));
+
+        let mut rows = vec![
+            header_row(&[
+                ("Date", Align::Left),
+                ("Reference", Align::Left),
+                ("Description", Align::Left),
+                ("Debit", Align::Right),
+                ("Credit", Align::Right),
+                ("Balance", Align::Right),
+            ]),
+            vec![
+                cell(
+                    self.period_start.format(DATE_FORMAT).to_string(),
+                    Align::Left,
+                ),
+                cell("", Align::Left),
+                cell("Opening balance", Align::Left),
+                cell("", Align::Right),
+                cell("", Align::Right),
+                amount_cell(self.opening_balance),
+            ],
+        ];
+
+        let mut entries: Vec<&LedgerEntry> = self.entries.iter().collect();
+        entries.sort_by_key(|entry| entry.occurred_at);
+        let mut balance = self.opening_balance;
+        let (mut debits, mut credits) = (0.0, 0.0);
+        for entry in entries {
+            balance += entry.amount;
+            let (debit, credit) = if entry.amount < 0.0 {
+                debits -= entry.amount;
+                (format_amount(-entry.amount), String::new())
+            } else {
+                credits += entry.amount;
+                (String::new(), format_amount(entry.amount))
+            };
+            rows.push(vec![
+                cell(local_date(&entry.occurred_at), Align::Left),
+                cell(&entry.reference, Align::Left),
+                cell(&entry.description, Align::Left),
+                cell(debit, Align::Right),
+                cell(credit, Align::Right),
+                amount_cell(balance),
+            ]);
+        }
+        blocks.push(Block::Table(Table { rows }));
+
+        blocks.push(summary(&[
+            ("Opening balance", format_amount(self.opening_balance)),
+            ("Total credits", format_amount(credits)),
+            ("Total debits", format_amount(debits)),
+            ("Closing balance (RWF)", format_amount(balance)),
+        ]));
+    }
+}
+
+impl GroupContributions {
+    fn blocks(&self, blocks: &mut Vec<Block>) {
+        blocks.push(paragraph(vec![
+            Run::bold("Period: "),
+            Run::plain(period(self.period_start, self.period_end)),
+        ]));
+
+        let with_expected = self.members.iter().any(|m| m.expected.is_some());
+        let mut titles = vec![
+            ("Code", Align::Left),
+            ("Member", Align::Left),
+            ("Payments", Align::Right),
+            ("Paid", Align::Right),
+        ];
+        if with_expected {
+            titles.extend([("Expected", Align::Right), ("Arrears", Align::Right)]);
+        }
+        let mut rows = vec![header_row(&titles)];
+
+        let (mut payments, mut paid, mut expected) = (0, 0.0, 0.0);
+        for member in &self.members {
+            payments += member.payments;
+            paid += member.total;
+            let mut row = vec![
+                cell(&member.member_code, Align::Left),
+                cell(&member.member_name, Align::Left),
+                cell(member.payments.to_string(), Align::Right),
+                amount_cell(member.total),
+            ];
+            if with_expected {
+                let due = member.expected.unwrap_or(0.0);
+                expected += due;
+                row.extend([amount_cell(due), amount_cell((due - member.total).max(0.0))]);
+            }
+            rows.push(row);
+        }
+
+        let mut totals = vec![
+            bold_cell("Total", Align::Left),
+            bold_cell(format!("{} members", self.members.len()), Align::Left),
+            bold_cell(payments.to_string(), Align::Right),
+            bold_cell(format_amount(paid), Align::Right),
+        ];
+        if with_expected {
+            let arrears: f64 = self
+                .members
+                .iter()
+                .map(|m| (m.expected.unwrap_or(0.0) - m.total).max(0.0))
+                .sum();
+            totals.extend([
+                bold_cell(format_amount(expected), Align::Right),
+                bold_cell(format_amount(arrears), Align::Right),
+            ]);
+        }
+        rows.push(totals);
+        blocks.push(Block::Table(Table { rows }));
+    }
+}
+
+impl DailyCash {
+    fn blocks(&self, blocks: &mut Vec<Block>) {
+        if let Some(teller) = &self.teller {
+            blocks.push(paragraph(vec![Run::bold("Teller: "), Run::plain(teller)]));
+        }
+
+        let mut rows = vec![header_row(&[
+            ("Time", Align::Left),
+            ("Reference", Align::Left),
+            ("Member", Align::Left),
+            ("Description", Align::Left),
+            ("In", Align::Right),
+            ("Out", Align::Right),
+        ])];
+
+        let mut transactions: Vec<&CashTransaction> = self.transactions.iter().collect();
+        transactions.sort_by_key(|txn| txn.occurred_at);
+        let (mut cash_in, mut cash_out) = (0.0, 0.0);
+        for txn in transactions {
+            let (received, paid) = if txn.amount < 0.0 {
+                cash_out -= txn.amount;
+                (String::new(), format_amount(-txn.amount))
+            } else {
+                cash_in += txn.amount;
+                (format_amount(txn.amount), String::new())
+            };
+            rows.push(vec![
+                cell(
+                    txn.occurred_at
+                        .with_timezone(&Local)
+                        .format("%H:%M")
+                        .to_string(),
+                    Align::Left,
+                ),
+                cell(&txn.reference, Align::Left),
+                cell(txn.member_name.as_deref().unwrap_or(""), Align::Left),
+                cell(&txn.description, Align::Left),
+                cell(received, Align::Right),
+                cell(paid, Align::Right),
+            ]);
+        }
+        blocks.push(Block::Table(Table { rows }));
+
+        let closing = self.opening_cash + cash_in - cash_out;
+        let mut lines = vec![
+            ("Opening cash", format_amount(self.opening_cash)),
+            ("Cash received", format_amount(cash_in)),
+            ("Cash paid out", format_amount(cash_out)),
+            ("Expected closing cash (RWF)", format_amount(closing)),
+        ];
+        if let Some(counted) = self.counted_cash {
+            lines.push(("Counted cash", format_amount(counted)));
+            lines.push(("Variance", format_amount(counted - closing)));
+        }
+        blocks.push(summary(&lines));
+    }
+}
+
+/// Lay out a report with the SACCO header and numbered footer on every page
+pub fn pages(
+    report: &Report,
+    branding: &Branding,
+    logo: Option<Arc<Picture>>,
+    setup: &PageSetup,
+    generated_at: NaiveDateTime,
+) -> Vec<Page> {
+    let mut body = *setup;
+    body.margins.top += HEADER_HEIGHT;
+    body.margins.bottom += FOOTER_HEIGHT;
+
+    let mut flow = body.flow();
+    flow.blocks(&report.blocks());
+    let font_size = flow.font_size;
+    let mut pages = flow.finish();
+
+    let generated = format!("Generated {}", generated_at.format("%d/%m/%Y %H:%M"));
+    let count = pages.len();
+    for (index, page) in pages.iter_mut().enumerate() {
+        let footer = Footer {
+            title: report.title(),
+            generated: &generated,
+            number: format!("Page {} of {}", index + 1, count),
+        };
+        decorate(page, setup, font_size, branding, logo.as_ref(), footer);
+    }
+    pages
+}
+
+struct Footer<'a> {
+    title: &'a str,
+    generated: &'a str,
+    number: String,
+}
+
+/// Draw the header and footer in the space reserved above and below the body
+fn decorate(
+    page: &mut Page,
+    setup: &PageSetup,
+    font_size: f32,
+    branding: &Branding,
+    logo: Option<&Arc<Picture>>,
+    footer: Footer,
+) {
+    let left = setup.margins.left * PT_PER_MM;
+    let right = page.width - setup.margins.right * PT_PER_MM;
+    let top = setup.margins.top * PT_PER_MM;
+    let header_bottom = top + (HEADER_HEIGHT - 4.0) * PT_PER_MM;
+    let small = font_size * 0.8;
+
+    let mut ops = Vec::new();
+    let mut x = left;
+    if let Some(logo) = logo {
+        let height = header_bottom - top - 2.0;
+        let width = height * logo.width as f32 / logo.height as f32;
+        ops.push(Op::Image {
+            x,
+            y: top,
+            width,
+            height,
+            picture: Arc::clone(logo),
+        });
+        x += width + font_size;
+    }
+
+    let name_size = font_size * 1.4;
+    ops.push(Op::Text {
+        x,
+        y: top + name_size,
+        size: name_size,
+        style: TextStyle::BOLD,
+        text: branding.sacco_name.clone(),
+    });
+    let contact: Vec<&str> = [&branding.address, &branding.phone]
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect();
+    if !contact.is_empty() {
+        ops.push(Op::Text {
+            x,
+            y: top + name_size + small * 1.6,
+            size: small,
+            style: TextStyle::default(),
+            text: contact.join(" · "),
+        });
+    }
+    ops.push(Op::Line {
+        from: (left, header_bottom),
+        to: (right, header_bottom),
+        width: 0.5,
+    });
+
+    let footer_top = page.height - (setup.margins.bottom + FOOTER_HEIGHT - 3.0) * PT_PER_MM;
+    ops.push(Op::Line {
+        from: (left, footer_top),
+        to: (right, footer_top),
+        width: 0.5,
+    });
+    ops.push(Op::Text {
+        x: left,
+        y: footer_top + small * 1.5,
+        size: small,
+        style: TextStyle::BOLD,
+        text: footer.title.to_string(),
+    });
+    ops.push(Op::Text {
+        x: right - text_width(&footer.number, TextStyle::default(), small),
+        y: footer_top + small * 1.5,
+        size: small,
+        style: TextStyle::default(),
+        text: footer.number,
+    });
+    ops.push(Op::Text {
+        x: left,
+        y: footer_top + small * 2.9,
+        size: small,
+        style: TextStyle::default(),
+        text: footer.generated.to_string(),
+    });
+
+    page.ops.extend(ops);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(page: &Page) -> Vec<&str> {
+        page.ops
+            .iter()
+            .filter_map(|op| match op {
+                Op::Text { text, .. } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn branding() -> Branding {
+        Branding {
+            sacco_name: "Twizigamire SACCO".to_string(),
+            sacco_id: None,
+            address: Some("Nyamirambo, Kigali".to_string()),
+            phone: None,
+        }
+    }
+
+    fn generated_at() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 10, 18)
+            .unwrap()
+            .and_hms_opt(9, 30, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_format_amount() {
+        assert_eq!(format_amount(0.0), "0");
+        assert_eq!(format_amount(999.0), "999");
+        assert_eq!(format_amount(1234567.4), "1,234,567");
+        assert_eq!(format_amount(-12500.0), "-12,500");
+    }
+
+    #[test]
+    fn test_member_statement_balances() {
+        let at = |day| {
+            NaiveDate::from_ymd_opt(2026, 10, day)
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap()
+                .and_utc()
+        };
+        let entry = |day, reference: &str, amount| LedgerEntry {
+            occurred_at: at(day),
+            reference: reference.to_string(),
+            description: String::new(),
+            amount,
+        };
+        let statement = MemberStatement {
+            member_name: "Uwase Hélène".to_string(),
+            member_code: "M-0012".to_string(),
+            group_name: Some("Abishyizehamwe".to_string()),
+            period_start: NaiveDate::from_ymd_opt(2026, 10, 1).unwrap(),
+            period_end: NaiveDate::from_ymd_opt(2026, 10, 31).unwrap(),
+            opening_balance: 10_000.0,
+            // Out of order; the ledger sorts by date
+            entries: vec![entry(9, "TX-2", -3_000.0), entry(2, "TX-1", 5_000.0)],
+        };
+        assert_eq!(statement.closing_balance(), 12_000.0);
+
+        let pages = pages(
+            &Report::MemberStatement(statement),
+            &branding(),
+            None,
+            &PageSetup::default(),
+            generated_at(),
+        );
+        let texts = texts(&pages[0]);
+        let tx1 = texts.iter().position(|t| *t == "TX-1").unwrap();
+        let tx2 = texts.iter().position(|t| *t == "TX-2").unwrap();
+        assert!(tx1 < tx2);
+        // Running balances after each entry
+        assert!(texts.contains(&"15,000"));
+        assert!(texts.contains(&"12,000"));
+        assert!(texts.contains(&"Twizigamire SACCO"));
+        assert!(texts.contains(&"Page 1 of 1"));
+        assert!(texts.contains(&"Generated 18/10/2026 09:30"));
+    }
+
+    #[test]
+    fn test_every_page_has_header_and_footer() {
+        let members = (1..=120)
+            .map(|n| MemberContribution {
+                member_code: format!("M-{:04}", n),
+                member_name: format!("Member {}", n),
+                payments: 4,
+                total: 8_000.0,
+                expected: Some(10_000.0),
+            })
+            .collect();
+        let report = Report::GroupContributions(GroupContributions {
+            group_name: "Abishyizehamwe".to_string(),
+            group_code: Some("G-07".to_string()),
+            period_start: NaiveDate::from_ymd_opt(2026, 9, 1).unwrap(),
+            period_end: NaiveDate::from_ymd_opt(2026, 9, 30).unwrap(),
+            members,
+        });
+        let logo = Arc::new(Picture {
+            width: 2,
+            height: 1,
+            rgb: vec![0; 6],
+        });
+
+        let pages = pages(
+            &report,
+            &branding(),
+            Some(logo),
+            &PageSetup::default(),
+            generated_at(),
+        );
+        assert!(pages.len() > 1);
+        for (index, page) in pages.iter().enumerate() {
+            let texts = texts(page);
+            assert!(texts.contains(&"Twizigamire SACCO"));
+            assert!(texts.contains(&format!("Page {} of {}", index + 1, pages.len()).as_str()));
+            assert!(page.ops.iter().any(|op| matches!(op, Op::Image { .. })));
+        }
+        // Totals: 120 x 8,000 paid, 120 x 2,000 in arrears
+        let last = texts(pages.last().unwrap());
+        assert!(last.contains(&"960,000"));
+        assert!(last.contains(&"240,000"));
+
+        let pdf = super::super::render(&pages, report.title()).unwrap();
+        assert!(pdf.starts_with(b"%PDF-"));
+    }
+}
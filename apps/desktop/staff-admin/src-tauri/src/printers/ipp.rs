@@ -0,0 +1,716 @@
+// Minimal IPP/1.1 client (RFC 8010/8011): Print-Job, Get-Job-Attributes and
+// Get-Printer-Attributes
+//
+// Only what receipt and document printing needs; every IPP Everywhere printer
+// and CUPS server accepts these operations.
+
+use std::time::Duration;
+
+use crate::error::{AppError, Context, ErrorCode, Result};
+
+pub const DEFAULT_DOCUMENT_FORMAT: &str = "application/octet-stream";
+const USER_NAME: &str = "sacco-plus";
+
+const OP_PRINT_JOB: u16 = 0x0002;
+const OP_GET_JOB_ATTRIBUTES: u16 = 0x0009;
+const OP_GET_PRINTER_ATTRIBUTES: u16 = 0x000B;
+
+const TAG_OPERATION: u8 = 0x01;
+const TAG_JOB: u8 = 0x02;
+const TAG_END: u8 = 0x03;
+const TAG_INTEGER: u8 = 0x21;
+const TAG_ENUM: u8 = 0x23;
+const TAG_NAME: u8 = 0x42;
+const TAG_KEYWORD: u8 = 0x44;
+const TAG_URI: u8 = 0x45;
+const TAG_CHARSET: u8 = 0x47;
+const TAG_LANGUAGE: u8 = 0x48;
+const TAG_MIME: u8 = 0x49;
+
+/// Job Template attributes (RFC 8011 section 5.2) for document jobs
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JobTemplate {
+    pub copies: Option<u32>,
+    /// e.g. "one-sided", "two-sided-long-edge"
+    pub sides: Option<&'static str>,
+    /// e.g. "iso_a4_210x297mm"
+    pub media: Option<&'static str>,
+    /// "color" or "monochrome"
+    pub print_color_mode: Option<&'static str>,
+}
+
+/// How long to follow a job after it has been accepted
+#[derive(Debug, Clone, Copy)]
+pub struct PollSettings {
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+pub const DEFAULT_POLL: PollSettings = PollSettings {
+    interval: Duration::from_secs(1),
+    timeout: Duration::from_secs(60),
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Pending,
+    Held,
+    Processing,
+    Stopped,
+    Canceled,
+    Aborted,
+    Completed,
+}
+
+impl JobState {
+    fn from_enum(value: i32) -> Option<Self> {
+        Some(match value {
+            3 => JobState::Pending,
+            4 => JobState::Held,
+            5 => JobState::Processing,
+            6 => JobState::Stopped,
+            7 => JobState::Canceled,
+            8 => JobState::Aborted,
+            9 => JobState::Completed,
+            _ => return None,
+        })
+    }
+}
+
+/// printer-state (RFC 8011 section 5.4.11)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IppPrinterState {
+    Idle,
+    Processing,
+    Stopped,
+}
+
+impl IppPrinterState {
+    fn from_enum(value: i32) -> Option<Self> {
+        Some(match value {
+            3 => IppPrinterState::Idle,
+            4 => IppPrinterState::Processing,
+            5 => IppPrinterState::Stopped,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IppPrinterStatus {
+    pub state: Option<IppPrinterState>,
+    /// printer-state-reasons keywords such as "media-empty-error"; the
+    /// placeholder "none" is left out
+    pub reasons: Vec<String>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IppJob {
+    pub job_id: i32,
+    pub state: Option<JobState>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Attribute {
+    name: String,
+    tag: u8,
+    value: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct IppResponse {
+    status: u16,
+    attributes: Vec<Attribute>,
+}
+
+impl IppResponse {
+    fn parse(bytes: &[u8]) -> Result<Self> {
+        let invalid = || {
+            AppError::from_code(ErrorCode::InvalidResponse).with_detail("Malformed IPP response")
+        };
+        if bytes.len() < 8 {
+            return Err(invalid());
+        }
+        let status = u16::from_be_bytes([bytes[2], bytes[3]]);
+
+        let mut attributes: Vec<Attribute> = Vec::new();
+        let mut pos = 8;
+        let read_u16 = |pos: usize| -> Option<usize> {
+            Some(u16::from_be_bytes([*bytes.get(pos)?, *bytes.get(pos + 1)?]) as usize)
+        };
+
+        while pos < bytes.len() {
+            let tag = bytes[pos];
+            pos += 1;
+            if tag == TAG_END {
+                break;
+            }
+            if tag < 0x10 {
+                // Group delimiter; attribute names are unique enough here
+                continue;
+            }
+
+            let name_len = read_u16(pos).ok_or_else(invalid)?;
+            let name = bytes.get(pos + 2..pos + 2 + name_len).ok_or_else(invalid)?;
+            pos += 2 + name_len;
+            let value_len = read_u16(pos).ok_or_else(invalid)?;
+            let value = bytes
+                .get(pos + 2..pos + 2 + value_len)
+                .ok_or_else(invalid)?;
+            pos += 2 + value_len;
+
+            // An empty name continues the previous attribute (1setOf values)
+            let name = if name.is_empty() {
+                attributes
+                    .last()
+                    .map(|a| a.name.clone())
+                    .unwrap_or_default()
+            } else {
+                String::from_utf8_lossy(name).to_string()
+            };
+            attributes.push(Attribute {
+                name,
+                tag,
+                value: value.to_vec(),
+            });
+        }
+
+        Ok(Self { status, attributes })
+    }
+
+    fn integer(&self, name: &str) -> Option<i32> {
+        self.attributes
+            .iter()
+            .find(|a| a.name == name && (a.tag == TAG_INTEGER || a.tag == TAG_ENUM))
+            .and_then(|a| a.value.as_slice().try_into().ok())
+            .map(i32::from_be_bytes)
+    }
+
+    fn text(&self, name: &str) -> Option<String> {
+        self.attributes
+            .iter()
+            .find(|a| a.name == name)
+            .map(|a| String::from_utf8_lossy(&a.value).to_string())
+    }
+
+    /// Every value of a 1setOf attribute
+    fn texts(&self, name: &str) -> Vec<String> {
+        self.attributes
+            .iter()
+            .filter(|a| a.name == name)
+            .map(|a| String::from_utf8_lossy(&a.value).to_string())
+            .collect()
+    }
+
+    fn check_status(&self) -> Result<()> {
+        if self.status < 0x0100 {
+            return Ok(());
+        }
+
+        let code = match self.status {
+            0x0406 => ErrorCode::PrinterNotFound,
+            // server-error-service-unavailable, -busy, -temporary-error
+            0x0502 | 0x0507 | 0x0505 => ErrorCode::PrinterOffline,
+            _ => ErrorCode::PrintFailed,
+        };
+        let detail = self
+            .text("status-message")
+            .unwrap_or_else(|| format!("IPP status 0x{:04x}", self.status));
+        Err(AppError::from_code(code).with_detail(detail))
+    }
+}
+
+struct RequestBuilder {
+    bytes: Vec<u8>,
+}
+
+impl RequestBuilder {
+    fn new(operation: u16, request_id: u32, printer_uri: &str) -> Self {
+        let mut request = Self {
+            bytes: Vec::with_capacity(256),
+        };
+        request.bytes.extend_from_slice(&[0x01, 0x01]); // IPP/1.1
+        request.bytes.extend_from_slice(&operation.to_be_bytes());
+        request.bytes.extend_from_slice(&request_id.to_be_bytes());
+        request.bytes.push(TAG_OPERATION);
+        request
+            .attribute(TAG_CHARSET, "attributes-charset", b"utf-8")
+            .attribute(TAG_LANGUAGE, "attributes-natural-language", b"en")
+            .attribute(TAG_URI, "printer-uri", printer_uri.as_bytes())
+            .attribute(TAG_NAME, "requesting-user-name", USER_NAME.as_bytes());
+        request
+    }
+
+    fn attribute(&mut self, tag: u8, name: &str, value: &[u8]) -> &mut Self {
+        self.bytes.push(tag);
+        self.bytes
+            .extend_from_slice(&(name.len() as u16).to_be_bytes());
+        self.bytes.extend_from_slice(name.as_bytes());
+        self.bytes
+            .extend_from_slice(&(value.len() as u16).to_be_bytes());
+        self.bytes.extend_from_slice(value);
+        self
+    }
+
+    /// Start a new attribute group, e.g. job attributes after operation ones
+    fn group(&mut self, tag: u8) -> &mut Self {
+        self.bytes.push(tag);
+        self
+    }
+
+    fn finish(mut self, document: &[u8]) -> Vec<u8> {
+        self.bytes.push(TAG_END);
+        self.bytes.extend_from_slice(document);
+        self.bytes
+    }
+}
+
+/// HTTP(S) URL an `ipp://` or `ipps://` printer URI is served from
+pub fn http_url(printer_uri: &str) -> Result<reqwest::Url> {
+    let parsed = reqwest::Url::parse(printer_uri)
+        .map_err(|e| AppError::invalid_input("Invalid printer URL").with_detail(e))?;
+    let scheme = match parsed.scheme() {
+        "ipp" | "http" => "http",
+        "ipps" | "https" => "https",
+        other => {
+            return Err(AppError::invalid_input(format!(
+                "Unsupported printer URL scheme: {}",
+                other
+            )))
+        }
+    };
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| AppError::invalid_input("Printer URL has no host"))?;
+    let port = parsed.port().unwrap_or(631);
+
+    let mut url = reqwest::Url::parse(&format!("{}://{}:{}{}", scheme, host, port, parsed.path()))
+        .map_err(|e| AppError::invalid_input("Invalid printer URL").with_detail(e))?;
+    url.set_query(parsed.query());
+    Ok(url)
+}
+
+pub struct IppClient {
+    printer_uri: String,
+    url: reqwest::Url,
+    client: reqwest::Client,
+}
+
+impl IppClient {
+    /// `certificate` is the printer's own PEM certificate, for `ipps://`
+    /// printers with a self-signed one. When given it is the only certificate
+    /// trusted, and isn't checked against the host name, which such
+    /// certificates rarely match; otherwise the system's trusted certificates
+    /// are used.
+    pub fn new(printer_uri: &str, certificate: Option<&str>) -> Result<Self> {
+        let mut builder = reqwest::Client::builder()
+            .user_agent("SACCO+ Staff Admin")
+            .timeout(Duration::from_secs(30));
+        if let Some(pem) = certificate {
+            let certificate = reqwest::Certificate::from_pem(pem.as_bytes()).map_err(|e| {
+                AppError::invalid_input("Invalid printer certificate").with_detail(e)
+            })?;
+            builder = builder
+                .tls_built_in_root_certs(false)
+                .add_root_certificate(certificate)
+                .danger_accept_invalid_hostnames(true);
+        }
+        let client = builder.build().context("Failed to create HTTP client")?;
+        Ok(Self {
+            printer_uri: printer_uri.to_string(),
+            url: http_url(printer_uri)?,
+            client,
+        })
+    }
+
+    async fn call(&self, body: Vec<u8>) -> Result<IppResponse> {
+        let response = self
+            .client
+            .post(self.url.clone())
+            .header(reqwest::header::CONTENT_TYPE, "application/ipp")
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| {
+                let error = AppError::from(e);
+                match error.category() {
+                    crate::error::ErrorCategory::Network => AppError::new(
+                        ErrorCode::PrinterOffline,
+                        format!("Printer {} is not reachable", self.printer_uri),
+                    )
+                    .with_detail(error),
+                    _ => error,
+                }
+            })?;
+
+        if !response.status().is_success() {
+            return Err(AppError::http_status(response.status()).with_detail(&self.printer_uri));
+        }
+        let bytes = response
+            .bytes()
+            .await
+            .context("Failed to read printer response")?;
+        let response = IppResponse::parse(&bytes)?;
+        response.check_status()?;
+        Ok(response)
+    }
+
+    pub async fn print_job(
+        &self,
+        job_name: &str,
+        document_format: &str,
+        template: &JobTemplate,
+        document: Vec<u8>,
+    ) -> Result<IppJob> {
+        let mut request = RequestBuilder::new(OP_PRINT_JOB, 1, &self.printer_uri);
+        request
+            .attribute(TAG_NAME, "job-name", job_name.as_bytes())
+            .attribute(TAG_MIME, "document-format", document_format.as_bytes());
+
+        if *template != JobTemplate::default() {
+            request.group(TAG_JOB);
+            if let Some(copies) = template.copies {
+                request.attribute(TAG_INTEGER, "copies", &(copies as i32).to_be_bytes());
+            }
+            for (name, value) in [
+                ("sides", template.sides),
+                ("media", template.media),
+                ("print-color-mode", template.print_color_mode),
+            ] {
+                if let Some(value) = value {
+                    request.attribute(TAG_KEYWORD, name, value.as_bytes());
+                }
+            }
+        }
+
+        let response = self.call(request.finish(&document)).await?;
+        let job_id = response.integer("job-id").ok_or_else(|| {
+            AppError::from_code(ErrorCode::InvalidResponse)
+                .with_detail("Print-Job response has no job-id")
+        })?;
+
+        Ok(IppJob {
+            job_id,
+            state: response.integer("job-state").and_then(JobState::from_enum),
+        })
+    }
+
+    pub async fn job_state(&self, job_id: i32) -> Result<Option<JobState>> {
+        let mut request = RequestBuilder::new(OP_GET_JOB_ATTRIBUTES, 2, &self.printer_uri);
+        request
+            .attribute(TAG_INTEGER, "job-id", &job_id.to_be_bytes())
+            .attribute(TAG_KEYWORD, "requested-attributes", b"job-state");
+
+        let response = self.call(request.finish(&[])).await?;
+        Ok(response.integer("job-state").and_then(JobState::from_enum))
+    }
+
+    pub async fn printer_status(&self) -> Result<IppPrinterStatus> {
+        let mut request = RequestBuilder::new(OP_GET_PRINTER_ATTRIBUTES, 3, &self.printer_uri);
+        request
+            .attribute(TAG_KEYWORD, "requested-attributes", b"printer-state")
+            .attribute(TAG_KEYWORD, "", b"printer-state-reasons")
+            .attribute(TAG_KEYWORD, "", b"printer-state-message");
+
+        let response = self.call(request.finish(&[])).await?;
+        Ok(IppPrinterStatus {
+            state: response
+                .integer("printer-state")
+                .and_then(IppPrinterState::from_enum),
+            reasons: response
+                .texts("printer-state-reasons")
+                .into_iter()
+                .filter(|reason| reason != "none")
+                .collect(),
+            message: response
+                .text("printer-state-message")
+                .filter(|message| !message.is_empty()),
+        })
+    }
+
+    /// Poll until the job completes
+    ///
+    /// A canceled or aborted job is `PrintFailed`. A job still pending, held
+    /// or stopped when `poll.timeout` passes, or one whose state can't be
+    /// read, is `PrintUnconfirmed`: the printer has it and may yet print it.
+    pub async fn wait_for_job(&self, job_id: i32, poll: PollSettings) -> Result<()> {
+        let deadline = tokio::time::Instant::now() + poll.timeout;
+        loop {
+            let state = self
+                .job_state(job_id)
+                .await
+                .map_err(|e| AppError::from_code(ErrorCode::PrintUnconfirmed).with_detail(e))?;
+            match state {
+                Some(JobState::Completed) => return Ok(()),
+                Some(JobState::Canceled | JobState::Aborted) => {
+                    return Err(AppError::new(
+                        ErrorCode::PrintFailed,
+                        format!("Print job {} was {:?}", job_id, state.unwrap()),
+                    )
+                    .with_retryable(false))
+                }
+                _ => {}
+            }
+
+            if tokio::time::Instant::now() + poll.interval > deadline {
+                let state = state.map_or("unknown".to_string(), |s| format!("{:?}", s));
+                return Err(AppError::from_code(ErrorCode::PrintUnconfirmed)
+                    .with_detail(format!("Print job {} is {}", job_id, state)));
+            }
+            tokio::time::sleep(poll.interval).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    fn response(status: u16, attributes: &[(u8, &str, Vec<u8>)]) -> Vec<u8> {
+        let mut bytes = vec![0x01, 0x01];
+        bytes.extend_from_slice(&status.to_be_bytes());
+        bytes.extend_from_slice(&1u32.to_be_bytes());
+        bytes.push(TAG_OPERATION);
+        for (tag, name, value) in attributes {
+            bytes.push(*tag);
+            bytes.extend_from_slice(&(name.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(name.as_bytes());
+            bytes.extend_from_slice(&(value.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(value);
+        }
+        bytes.push(TAG_END);
+        bytes
+    }
+
+    /// Reads one HTTP request and returns its body
+    fn read_request(socket: &mut std::net::TcpStream) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = socket.read(&mut buf).unwrap();
+            data.extend_from_slice(&buf[..n]);
+            if let Some(end) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+                let headers = String::from_utf8_lossy(&data[..end]).to_lowercase();
+                let length: usize = headers
+                    .lines()
+                    .find_map(|l| l.strip_prefix("content-length:"))
+                    .map(|v| v.trim().parse().unwrap())
+                    .unwrap_or(0);
+                while data.len() < end + 4 + length {
+                    let n = socket.read(&mut buf).unwrap();
+                    data.extend_from_slice(&buf[..n]);
+                }
+                return data[end + 4..end + 4 + length].to_vec();
+            }
+        }
+    }
+
+    /// Mock printer: accepts Print-Job as job 42, reports it processing once
+    /// and then completed
+    fn mock_printer() -> (u16, Arc<Mutex<Vec<Vec<u8>>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = requests.clone();
+
+        std::thread::spawn(move || {
+            let mut polls = 0;
+            for socket in listener.incoming() {
+                let mut socket = socket.unwrap();
+                let body = read_request(&mut socket);
+                let operation = u16::from_be_bytes([body[2], body[3]]);
+                seen.lock().unwrap().push(body);
+
+                let reply = match operation {
+                    OP_PRINT_JOB => response(
+                        0,
+                        &[
+                            (TAG_INTEGER, "job-id", 42i32.to_be_bytes().to_vec()),
+                            (TAG_ENUM, "job-state", 3i32.to_be_bytes().to_vec()),
+                        ],
+                    ),
+                    OP_GET_PRINTER_ATTRIBUTES => response(
+                        0,
+                        &[
+                            (TAG_ENUM, "printer-state", 5i32.to_be_bytes().to_vec()),
+                            (
+                                TAG_KEYWORD,
+                                "printer-state-reasons",
+                                b"media-empty-error".to_vec(),
+                            ),
+                            (TAG_KEYWORD, "", b"cover-open-report".to_vec()),
+                            (0x41, "printer-state-message", b"Load paper".to_vec()),
+                        ],
+                    ),
+                    _ => {
+                        polls += 1;
+                        let state: i32 = if polls == 1 { 5 } else { 9 };
+                        response(0, &[(TAG_ENUM, "job-state", state.to_be_bytes().to_vec())])
+                    }
+                };
+                let _ = write!(
+                    socket,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/ipp\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    reply.len()
+                );
+                let _ = socket.write_all(&reply);
+            }
+        });
+
+        (port, requests)
+    }
+
+    #[test]
+    fn test_http_url() {
+        assert_eq!(
+            http_url("ipp://printer.local/ipp/print").unwrap().as_str(),
+            "http://printer.local:631/ipp/print"
+        );
+        assert_eq!(
+            http_url("ipps://10.0.0.5:8443/printers/receipt")
+                .unwrap()
+                .as_str(),
+            "https://10.0.0.5:8443/printers/receipt"
+        );
+        // Some print servers pick the queue by query
+        assert_eq!(
+            http_url("ipp://print.branch.local/ipp/print?queue=counter")
+                .unwrap()
+                .as_str(),
+            "http://print.branch.local:631/ipp/print?queue=counter"
+        );
+        assert!(http_url("lpd://printer.local/queue").is_err());
+    }
+
+    #[test]
+    fn test_printer_certificate() {
+        let uri = "ipps://10.0.0.5/ipp/print";
+        assert!(IppClient::new(uri, None).is_ok());
+        let error = IppClient::new(uri, Some("not a certificate"))
+            .err()
+            .unwrap();
+        assert_eq!(error.code, ErrorCode::InvalidInput);
+    }
+
+    #[test]
+    fn test_error_status() {
+        let bytes = response(
+            0x0406,
+            &[(
+                0x41,
+                "status-message",
+                b"The printer does not exist".to_vec(),
+            )],
+        );
+        let error = IppResponse::parse(&bytes)
+            .unwrap()
+            .check_status()
+            .unwrap_err();
+        assert_eq!(error.code, ErrorCode::PrinterNotFound);
+        assert_eq!(error.detail.as_deref(), Some("The printer does not exist"));
+    }
+
+    #[tokio::test]
+    async fn test_print_job_and_poll() {
+        let (port, requests) = mock_printer();
+        let client = IppClient::new(&format!("ipp://127.0.0.1:{}/ipp/print", port), None).unwrap();
+
+        let job = client
+            .print_job(
+                "receipt",
+                DEFAULT_DOCUMENT_FORMAT,
+                &JobTemplate::default(),
+                b"\x1b@hello".to_vec(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            job,
+            IppJob {
+                job_id: 42,
+                state: Some(JobState::Pending)
+            }
+        );
+
+        let poll = PollSettings {
+            interval: Duration::from_millis(10),
+            timeout: Duration::from_secs(5),
+        };
+        client.wait_for_job(42, poll).await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(&requests[0][..4], &[0x01, 0x01, 0x00, 0x02]);
+        assert!(requests[0].ends_with(b"\x03\x1b@hello"));
+        assert_eq!(&requests[1][..4], &[0x01, 0x01, 0x00, 0x09]);
+    }
+
+    #[tokio::test]
+    async fn test_unfinished_job_is_unconfirmed() {
+        let (port, _) = mock_printer();
+        let client = IppClient::new(&format!("ipp://127.0.0.1:{}/ipp/print", port), None).unwrap();
+
+        // Still processing when the time is up
+        let poll = PollSettings {
+            interval: Duration::from_millis(50),
+            timeout: Duration::from_millis(10),
+        };
+        let error = client.wait_for_job(42, poll).await.unwrap_err();
+        assert_eq!(error.code, ErrorCode::PrintUnconfirmed);
+        assert!(!error.retryable);
+        assert_eq!(error.detail.as_deref(), Some("Print job 42 is Processing"));
+    }
+
+    #[tokio::test]
+    async fn test_printer_status() {
+        let (port, requests) = mock_printer();
+        let client = IppClient::new(&format!("ipp://127.0.0.1:{}/ipp/print", port), None).unwrap();
+
+        assert_eq!(
+            client.printer_status().await.unwrap(),
+            IppPrinterStatus {
+                state: Some(IppPrinterState::Stopped),
+                reasons: vec![
+                    "media-empty-error".to_string(),
+                    "cover-open-report".to_string()
+                ],
+                message: Some("Load paper".to_string()),
+            }
+        );
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(&requests[0][..4], &[0x01, 0x01, 0x00, 0x0B]);
+        // Additional requested-attributes values have an empty name
+        assert!(requests[0]
+            .windows(26)
+            .any(|w| w == b"\x44\x00\x00\x00\x15printer-state-reasons"));
+    }
+
+    #[tokio::test]
+    async fn test_job_template_attributes() {
+        let (port, requests) = mock_printer();
+        let client = IppClient::new(&format!("ipp://127.0.0.1:{}/ipp/print", port), None).unwrap();
+
+        let template = JobTemplate {
+            copies: Some(2),
+            sides: Some("two-sided-long-edge"),
+            ..Default::default()
+        };
+        client
+            .print_job("statement", "application/pdf", &template, b"%PDF-".to_vec())
+            .await
+            .unwrap();
+
+        let requests = requests.lock().unwrap();
+        let request = &requests[0];
+        // Job group delimiter followed by copies = 2
+        let copies = b"\x02\x21\x00\x06copies\x00\x04\x00\x00\x00\x02";
+        assert!(request.windows(copies.len()).any(|w| w == copies));
+        assert!(request.windows(19).any(|w| w == b"two-sided-long-edge"));
+        assert!(request.ends_with(b"\x03%PDF-"));
+    }
+}
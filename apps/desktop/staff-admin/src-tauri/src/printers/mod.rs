@@ -0,0 +1,525 @@
+// Printer backends
+//
+// Printers installed in the OS spooler are printed to through it. Ethernet
+// receipt printers that were never installed can be configured here instead
+// and are reached directly over raw TCP (JetDirect, port 9100) or IPP, and
+// USB or RS-232 receipt printers without a driver over their serial port.
+//
+// Jobs for the same physical printer are sent one at a time, so two tellers
+// printing at once can't interleave their receipts.
+//
+// `lp` returning only means CUPS accepted a job, so CUPS jobs are followed
+// over IPP on the local CUPS server, like jobs sent to IPP printers, until
+// the printer finishes them. Sending returns as soon as the job is accepted
+// and releases the printer; following it is up to the caller.
+
+pub mod ipp;
+pub mod raw_tcp;
+pub mod serial;
+pub mod status;
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tauri::Manager;
+use tokio::sync::OwnedMutexGuard;
+
+use crate::commands::print::{self, DocumentOptions};
+use crate::commands::serial::SerialDeviceProfile;
+use crate::error::{AppError, Context, ErrorCode, Result};
+
+const NETWORK_PRINTERS_FILE: &str = "network_printers.json";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PrinterBackend {
+    /// Installed in CUPS or the Windows spooler
+    #[default]
+    System,
+    RawTcp,
+    Ipp,
+    Serial,
+}
+
+/// What a manually configured printer takes, so status queries aren't sent
+/// to a printer that would print them
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PrinterLanguage {
+    #[default]
+    EscPos,
+    Zpl,
+    Tspl,
+}
+
+fn default_raw_port() -> u16 {
+    9100
+}
+
+fn default_timeout_ms() -> u64 {
+    5000
+}
+
+/// A job the printer or spooler has accepted
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Accepted {
+    /// Job id assigned by CUPS, or by the printer for IPP
+    pub job_id: Option<String>,
+    /// Where to follow the job, when the spooler can say whether it printed
+    pub tracking: Option<TrackedJob>,
+}
+
+/// An IPP job that can be asked for its state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackedJob {
+    pub printer_uri: String,
+    /// The printer's own certificate, see [`NetworkConnection::Ipp`]
+    pub certificate: Option<String>,
+    pub job_id: i32,
+}
+
+impl TrackedJob {
+    /// Wait until the job has printed; see [`ipp::IppClient::wait_for_job`]
+    pub async fn wait(self) -> Result<()> {
+        ipp::IppClient::new(&self.printer_uri, self.certificate.as_deref())?
+            .wait_for_job(self.job_id, ipp::DEFAULT_POLL)
+            .await
+    }
+}
+
+/// How to reach a manually configured network printer
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum NetworkConnection {
+    RawTcp {
+        host: String,
+        #[serde(default = "default_raw_port")]
+        port: u16,
+        #[serde(default = "default_timeout_ms")]
+        timeout_ms: u64,
+    },
+    Ipp {
+        /// `ipp://` or `ipps://` printer URI
+        url: String,
+        /// Sent as document-format; raw ESC/POS needs the printer's raw format
+        #[serde(default)]
+        document_format: Option<String>,
+        /// PEM certificate of an `ipps://` printer with a self-signed one;
+        /// without it only certificates the system trusts are accepted
+        #[serde(default)]
+        certificate: Option<String>,
+    },
+    /// USB-CDC or RS-232 receipt printer
+    Serial { profile: SerialDeviceProfile },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct NetworkPrinter {
+    pub name: String,
+    pub connection: NetworkConnection,
+    #[serde(default)]
+    pub language: PrinterLanguage,
+}
+
+impl NetworkPrinter {
+    pub fn backend(&self) -> PrinterBackend {
+        match self.connection {
+            NetworkConnection::RawTcp { .. } => PrinterBackend::RawTcp,
+            NetworkConnection::Ipp { .. } => PrinterBackend::Ipp,
+            NetworkConnection::Serial { .. } => PrinterBackend::Serial,
+        }
+    }
+
+    /// Send a finished job (ESC/POS bytes or a document) to the printer
+    pub async fn send(&self, data: Vec<u8>) -> Result<Accepted> {
+        match &self.connection {
+            NetworkConnection::RawTcp {
+                host,
+                port,
+                timeout_ms,
+            } => {
+                let (host, port) = (host.clone(), *port);
+                let timeout = std::time::Duration::from_millis(*timeout_ms);
+                tauri::async_runtime::spawn_blocking(move || {
+                    raw_tcp::send(&host, port, &data, timeout)
+                })
+                .await??;
+                Ok(Accepted::default())
+            }
+            NetworkConnection::Ipp {
+                url,
+                document_format,
+                certificate,
+            } => {
+                let format = document_format
+                    .as_deref()
+                    .unwrap_or(ipp::DEFAULT_DOCUMENT_FORMAT);
+                let client = ipp::IppClient::new(url, certificate.as_deref())?;
+                let job = client
+                    .print_job("SACCO+ receipt", format, &ipp::JobTemplate::default(), data)
+                    .await?;
+                Ok(ipp_accepted(url, certificate.clone(), job.job_id))
+            }
+            NetworkConnection::Serial { profile } => {
+                let profile = profile.clone();
+                tauri::async_runtime::spawn_blocking(move || serial::send(&profile, &data))
+                    .await??;
+                Ok(Accepted::default())
+            }
+        }
+    }
+}
+
+fn ipp_accepted(printer_uri: &str, certificate: Option<String>, job_id: i32) -> Accepted {
+    Accepted {
+        job_id: Some(job_id.to_string()),
+        tracking: Some(TrackedJob {
+            printer_uri: printer_uri.to_string(),
+            certificate,
+            job_id,
+        }),
+    }
+}
+
+/// One lock per physical printer, held while a job is being sent
+#[derive(Default)]
+pub struct PrinterLocks {
+    locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl PrinterLocks {
+    /// Wait until no other job is being sent to the device
+    pub async fn acquire(&self, device: String) -> Result<OwnedMutexGuard<()>> {
+        let lock = Arc::clone(self.locks.lock()?.entry(device).or_default());
+        Ok(lock.lock_owned().await)
+    }
+}
+
+/// Identifies the device behind a printer name; network printers configured
+/// under two names still share one lock
+fn device_key(printer_name: &str, network: Option<&NetworkPrinter>) -> String {
+    match network.map(|p| &p.connection) {
+        Some(NetworkConnection::RawTcp { host, port, .. }) => {
+            format!("tcp://{}:{}", host.to_lowercase(), port)
+        }
+        Some(NetworkConnection::Ipp { url, .. }) => url.to_lowercase(),
+        Some(NetworkConnection::Serial { profile }) => format!("serial:{}", profile.port),
+        None => format!("system:{}", printer_name),
+    }
+}
+
+/// Look up a printer by name and take its device lock
+async fn claim(
+    app_handle: &tauri::AppHandle,
+    printer_name: &str,
+) -> Result<(Option<NetworkPrinter>, OwnedMutexGuard<()>)> {
+    let network = load_network_printers(app_handle)?
+        .into_iter()
+        .find(|p| p.name == printer_name);
+    let guard = app_handle
+        .state::<PrinterLocks>()
+        .acquire(device_key(printer_name, network.as_ref()))
+        .await?;
+    Ok((network, guard))
+}
+
+fn config_path(app_handle: &tauri::AppHandle) -> Result<PathBuf> {
+    let dir = app_handle
+        .path()
+        .app_data_dir()
+        .context("Failed to resolve app data dir")?;
+    Ok(dir.join(NETWORK_PRINTERS_FILE))
+}
+
+fn load_from(path: &Path) -> Result<Vec<NetworkPrinter>> {
+    match std::fs::read_to_string(path) {
+        Ok(json) => serde_json::from_str(&json).context("Failed to parse network printers"),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e).context("Failed to read network printers"),
+    }
+}
+
+fn save_to(path: &Path, printers: &[NetworkPrinter]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).context("Failed to create app data dir")?;
+    }
+    let json =
+        serde_json::to_string_pretty(printers).context("Failed to serialize network printers")?;
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::write(&tmp_path, json).context("Failed to save network printers")?;
+    std::fs::rename(&tmp_path, path).context("Failed to save network printers")
+}
+
+pub fn load_network_printers(app_handle: &tauri::AppHandle) -> Result<Vec<NetworkPrinter>> {
+    load_from(&config_path(app_handle)?)
+}
+
+/// Add a network printer, replacing any existing one with the same name
+pub fn save_network_printer(app_handle: &tauri::AppHandle, printer: NetworkPrinter) -> Result<()> {
+    if printer.name.trim().is_empty() {
+        return Err(AppError::invalid_input("Printer name is required"));
+    }
+    if let NetworkConnection::Ipp {
+        url, certificate, ..
+    } = &printer.connection
+    {
+        ipp::IppClient::new(url, certificate.as_deref())?;
+    }
+
+    let path = config_path(app_handle)?;
+    let mut printers = load_from(&path)?;
+    printers.retain(|p| p.name != printer.name);
+    printers.push(printer);
+    save_to(&path, &printers)
+}
+
+pub fn remove_network_printer(app_handle: &tauri::AppHandle, name: &str) -> Result<()> {
+    let path = config_path(app_handle)?;
+    let mut printers = load_from(&path)?;
+    let before = printers.len();
+    printers.retain(|p| p.name != name);
+    if printers.len() == before {
+        return Err(AppError::new(
+            ErrorCode::PrinterNotFound,
+            format!("No network printer named {}", name),
+        ));
+    }
+    save_to(&path, &printers)
+}
+
+/// Send raw bytes to a printer by name, over the network if it is configured
+/// here, otherwise through the OS spooler
+pub async fn send(
+    app_handle: &tauri::AppHandle,
+    printer_name: &str,
+    data: Vec<u8>,
+) -> Result<Accepted> {
+    let (network, _guard) = claim(app_handle, printer_name).await?;
+
+    match network {
+        Some(printer) => printer.send(data).await,
+        None => {
+            let printer_name = printer_name.to_string();
+            let job_id =
+                tauri::async_runtime::spawn_blocking(move || print::send_raw(&printer_name, &data))
+                    .await??;
+            Ok(spooled(job_id))
+        }
+    }
+}
+
+/// Send a PDF document with the print dialog's copies, duplex and paper
+///
+/// Raw TCP and serial printers are receipt printers and can't take PDFs.
+pub async fn send_pdf(
+    app_handle: &tauri::AppHandle,
+    printer_name: &str,
+    pdf: Vec<u8>,
+    options: &DocumentOptions,
+) -> Result<Accepted> {
+    let (network, _guard) = claim(app_handle, printer_name).await?;
+
+    match network.map(|p| p.connection) {
+        Some(NetworkConnection::Ipp {
+            url, certificate, ..
+        }) => {
+            let template = ipp::JobTemplate {
+                copies: Some(options.copies),
+                sides: Some(options.sides()),
+                media: Some(options.paper_size.media()),
+                print_color_mode: Some(options.color_mode()),
+            };
+            let client = ipp::IppClient::new(&url, certificate.as_deref())?;
+            let job = client
+                .print_job("SACCO+ document", "application/pdf", &template, pdf)
+                .await?;
+            Ok(ipp_accepted(&url, certificate, job.job_id))
+        }
+        Some(NetworkConnection::RawTcp { .. }) => Err(AppError::new(
+            ErrorCode::Unsupported,
+            "Raw TCP printers can only print receipts; add this printer over IPP to print documents",
+        )),
+        Some(NetworkConnection::Serial { .. }) => Err(AppError::new(
+            ErrorCode::Unsupported,
+            "Serial printers can only print receipts",
+        )),
+        None => {
+            let (printer_name, options) = (printer_name.to_string(), options.clone());
+            let job_id = tauri::async_runtime::spawn_blocking(move || {
+                print::send_pdf(&printer_name, &pdf, &options)
+            })
+            .await??;
+            Ok(spooled(job_id))
+        }
+    }
+}
+
+/// Print HTML through the OS spooler
+pub async fn send_html(
+    app_handle: &tauri::AppHandle,
+    printer_name: &str,
+    html_content: String,
+) -> Result<Accepted> {
+    let (_, _guard) = claim(app_handle, printer_name).await?;
+    let printer_name = printer_name.to_string();
+    let job_id = tauri::async_runtime::spawn_blocking(move || {
+        print::send_html(&printer_name, &html_content)
+    })
+    .await??;
+    Ok(spooled(job_id))
+}
+
+/// Local CUPS printer URI and job number for a CUPS job id such as
+/// "Counter-42"
+fn cups_job(job_id: &str) -> Option<(String, i32)> {
+    let (queue, number) = job_id.rsplit_once('-')?;
+    let number = number.parse().ok()?;
+    Some((format!("ipp://localhost:631/printers/{}", queue), number))
+}
+
+/// A job handed to the OS spooler, followed when the spooler is CUPS and gave
+/// it an id
+fn spooled(job_id: Option<String>) -> Accepted {
+    let tracking = job_id
+        .as_deref()
+        .and_then(cups_job)
+        .map(|(printer_uri, job_id)| TrackedJob {
+            printer_uri,
+            certificate: None,
+            job_id,
+        });
+    Accepted { job_id, tracking }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_round_trip() {
+        let dir = std::env::temp_dir().join(format!("printers-test-{}", uuid::Uuid::new_v4()));
+        let path = dir.join(NETWORK_PRINTERS_FILE);
+        assert!(load_from(&path).unwrap().is_empty());
+
+        let printers = vec![
+            NetworkPrinter {
+                name: "Counter 1".to_string(),
+                connection: NetworkConnection::RawTcp {
+                    host: "192.168.1.50".to_string(),
+                    port: 9100,
+                    timeout_ms: 3000,
+                },
+                language: PrinterLanguage::EscPos,
+            },
+            NetworkPrinter {
+                name: "Back office".to_string(),
+                connection: NetworkConnection::Ipp {
+                    url: "ipps://printer.branch.local/ipp/print".to_string(),
+                    document_format: None,
+                    certificate: None,
+                },
+                language: PrinterLanguage::EscPos,
+            },
+        ];
+        save_to(&path, &printers).unwrap();
+        assert_eq!(load_from(&path).unwrap(), printers);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_connection_defaults() {
+        let printer: NetworkPrinter = serde_json::from_value(serde_json::json!({
+            "name": "Counter 2",
+            "connection": { "kind": "raw_tcp", "host": "10.0.0.9" }
+        }))
+        .unwrap();
+        assert_eq!(
+            printer.connection,
+            NetworkConnection::RawTcp {
+                host: "10.0.0.9".to_string(),
+                port: 9100,
+                timeout_ms: 5000,
+            }
+        );
+        assert_eq!(printer.backend(), PrinterBackend::RawTcp);
+        assert_eq!(printer.language, PrinterLanguage::EscPos);
+
+        let printer: NetworkPrinter = serde_json::from_value(serde_json::json!({
+            "name": "Labels",
+            "connection": { "kind": "raw_tcp", "host": "10.0.0.10" },
+            "language": "zpl"
+        }))
+        .unwrap();
+        assert_eq!(printer.language, PrinterLanguage::Zpl);
+    }
+
+    #[test]
+    fn test_device_key() {
+        let counter = |name: &str, host: &str| NetworkPrinter {
+            name: name.to_string(),
+            connection: NetworkConnection::RawTcp {
+                host: host.to_string(),
+                port: 9100,
+                timeout_ms: 3000,
+            },
+            language: PrinterLanguage::EscPos,
+        };
+        // Two names for the same printer share a lock
+        assert_eq!(
+            device_key("Counter 1", Some(&counter("Counter 1", "Printer-1.local"))),
+            device_key("Receipts", Some(&counter("Receipts", "printer-1.local")))
+        );
+        assert_ne!(device_key("Counter 1", None), device_key("Counter 2", None));
+    }
+
+    #[test]
+    fn test_cups_job() {
+        assert_eq!(
+            cups_job("Counter-42"),
+            Some(("ipp://localhost:631/printers/Counter".to_string(), 42))
+        );
+        assert_eq!(
+            cups_job("Front-Desk-7"),
+            Some(("ipp://localhost:631/printers/Front-Desk".to_string(), 7))
+        );
+        assert_eq!(cups_job("Counter"), None);
+        assert_eq!(cups_job("Counter-x"), None);
+
+        // Jobs CUPS named are followed, others only reported
+        assert_eq!(
+            spooled(Some("Counter-42".to_string())).tracking,
+            Some(TrackedJob {
+                printer_uri: "ipp://localhost:631/printers/Counter".to_string(),
+                certificate: None,
+                job_id: 42,
+            })
+        );
+        assert_eq!(spooled(Some("7".to_string())).tracking, None);
+    }
+
+    #[tokio::test]
+    async fn test_printer_locks_serialize_jobs() {
+        let locks = Arc::new(PrinterLocks::default());
+        let guard = locks
+            .acquire("tcp://10.0.0.9:9100".to_string())
+            .await
+            .unwrap();
+
+        let waiting = {
+            let locks = Arc::clone(&locks);
+            tokio::spawn(async move { locks.acquire("tcp://10.0.0.9:9100".to_string()).await })
+        };
+        // Another printer is not held up
+        locks
+            .acquire("system:Back office".to_string())
+            .await
+            .unwrap();
+
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+        drop(guard);
+        waiting.await.unwrap().unwrap();
+    }
+}
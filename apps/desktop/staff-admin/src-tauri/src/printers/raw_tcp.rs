@@ -0,0 +1,167 @@
+// Raw TCP (JetDirect / AppSocket) printing: open a socket, write the job,
+// close it. The same socket is bidirectional on ESC/POS printers, which
+// answer real-time status requests over it.
+
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::error::{AppError, ErrorCode, Result};
+use crate::escpos::status::{self, RealTimeStatus};
+
+fn connect(host: &str, port: u16, timeout: Duration) -> Result<TcpStream> {
+    let addrs: Vec<_> = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| {
+            AppError::new(
+                ErrorCode::PrinterNotFound,
+                format!("Cannot resolve printer {}", host),
+            )
+            .with_detail(e)
+        })?
+        .collect();
+
+    let offline = |e: std::io::Error| {
+        AppError::new(
+            ErrorCode::PrinterOffline,
+            format!("Printer {}:{} is not reachable", host, port),
+        )
+        .with_detail(e)
+    };
+
+    let mut last_error = None;
+    let mut stream = None;
+    for addr in &addrs {
+        match TcpStream::connect_timeout(addr, timeout) {
+            Ok(s) => {
+                stream = Some(s);
+                break;
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+    let stream = match stream {
+        Some(stream) => stream,
+        None => {
+            return Err(offline(last_error.unwrap_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::NotFound, "no addresses")
+            })))
+        }
+    };
+
+    stream.set_write_timeout(Some(timeout)).map_err(offline)?;
+    stream.set_read_timeout(Some(timeout)).map_err(offline)?;
+    Ok(stream)
+}
+
+pub fn send(host: &str, port: u16, data: &[u8], timeout: Duration) -> Result<()> {
+    let mut stream = connect(host, port, timeout)?;
+    let failed = |e: std::io::Error| {
+        AppError::new(ErrorCode::PrintFailed, "Failed to send job to printer").with_detail(e)
+    };
+    stream.write_all(data).map_err(failed)?;
+    stream.flush().map_err(failed)?;
+    // Half-close so the printer sees end of job; ignore printers that reset
+    let _ = stream.shutdown(Shutdown::Write);
+    Ok(())
+}
+
+/// Ask an ESC/POS printer for its sensor status with DLE EOT
+///
+/// Returns `None` if the printer accepts the connection but doesn't answer,
+/// as print servers that only take jobs do.
+pub fn real_time_status(
+    host: &str,
+    port: u16,
+    timeout: Duration,
+) -> Result<Option<RealTimeStatus>> {
+    let mut stream = connect(host, port, timeout)?;
+    let failed = |e: std::io::Error| {
+        AppError::new(ErrorCode::DeviceError, "Failed to read printer status").with_detail(e)
+    };
+
+    let mut replies = [0u8; status::REQUESTS.len()];
+    for (reply, n) in replies.iter_mut().zip(status::REQUESTS) {
+        stream.write_all(&status::request(n)).map_err(failed)?;
+        let mut byte = [0u8; 1];
+        match stream.read_exact(&mut byte) {
+            Ok(()) => *reply = byte[0],
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock
+                        | std::io::ErrorKind::TimedOut
+                        | std::io::ErrorKind::UnexpectedEof
+                ) =>
+            {
+                return Ok(None)
+            }
+            Err(e) => return Err(failed(e)),
+        }
+    }
+    let _ = stream.shutdown(Shutdown::Both);
+
+    RealTimeStatus::parse(replies[0], replies[1], replies[2]).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_send_to_listener() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut received = Vec::new();
+            socket.read_to_end(&mut received).unwrap();
+            received
+        });
+
+        send("127.0.0.1", port, b"\x1b@hello\n", Duration::from_secs(2)).unwrap();
+        assert_eq!(server.join().unwrap(), b"\x1b@hello\n");
+    }
+
+    #[test]
+    fn test_real_time_status() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            // A printer with the paper running low, then a print server that
+            // never answers
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut request = [0u8; 3];
+            for reply in [0x12, 0x12, 0x1E] {
+                socket.read_exact(&mut request).unwrap();
+                socket.write_all(&[reply]).unwrap();
+            }
+            let (mut silent, _) = listener.accept().unwrap();
+            let mut sink = Vec::new();
+            let _ = silent.read_to_end(&mut sink);
+        });
+
+        let status = real_time_status("127.0.0.1", port, Duration::from_secs(2))
+            .unwrap()
+            .unwrap();
+        assert!(status.paper_near_end && !status.paper_out && !status.offline);
+
+        let silent = real_time_status("127.0.0.1", port, Duration::from_millis(200)).unwrap();
+        assert_eq!(silent, None);
+    }
+
+    #[test]
+    fn test_unreachable_printer_is_offline() {
+        // Bind then drop to get a port nothing is listening on
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let error = send("127.0.0.1", port, b"x", Duration::from_millis(500)).unwrap_err();
+        assert_eq!(error.code, ErrorCode::PrinterOffline);
+        assert!(error.retryable);
+    }
+}
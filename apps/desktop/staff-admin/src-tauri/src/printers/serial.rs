@@ -0,0 +1,128 @@
+// Serial printing: ESC/POS receipt printers on a USB-CDC or RS-232 port. The
+// port is bidirectional, so the printer answers real-time status requests
+// over it as it does over raw TCP.
+
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use crate::commands::serial::SerialDeviceProfile;
+use crate::error::{AppError, ErrorCode, Result};
+use crate::escpos::status::{self, RealTimeStatus};
+
+/// How long to wait for each status byte
+const STATUS_TIMEOUT: Duration = Duration::from_secs(1);
+
+fn open(profile: &SerialDeviceProfile) -> Result<Box<dyn serialport::SerialPort>> {
+    profile.open().map_err(|e| {
+        AppError::new(
+            ErrorCode::PrinterOffline,
+            format!("Printer on {} is not reachable", profile.port),
+        )
+        .with_detail(e.detail.unwrap_or(e.message))
+    })
+}
+
+pub fn send(profile: &SerialDeviceProfile, data: &[u8]) -> Result<()> {
+    let mut port = open(profile)?;
+    port.write_all(data)
+        .and_then(|_| port.flush())
+        .map_err(|e| {
+            AppError::new(ErrorCode::PrintFailed, "Failed to send job to printer").with_detail(e)
+        })
+}
+
+/// Ask an ESC/POS printer for its sensor status with DLE EOT
+///
+/// Returns `None` if the port opens but nothing answers, as when the printer
+/// is switched off or the port belongs to something else.
+pub fn real_time_status(profile: &SerialDeviceProfile) -> Result<Option<RealTimeStatus>> {
+    let mut port = open(profile)?;
+    let failed = |e: std::io::Error| {
+        AppError::new(ErrorCode::DeviceError, "Failed to read printer status").with_detail(e)
+    };
+    port.set_timeout(STATUS_TIMEOUT)
+        .map_err(|e| failed(e.into()))?;
+    // Drop anything the printer sent before we asked
+    let _ = port.clear(serialport::ClearBuffer::Input);
+
+    match ask(&mut port).map_err(failed)? {
+        Some([printer, offline_cause, paper]) => {
+            RealTimeStatus::parse(printer, offline_cause, paper).map(Some)
+        }
+        None => Ok(None),
+    }
+}
+
+/// Send each DLE EOT request and read its one-byte reply, or `None` if one
+/// doesn't come
+fn ask<P: Read + Write + ?Sized>(
+    port: &mut P,
+) -> std::io::Result<Option<[u8; status::REQUESTS.len()]>> {
+    let mut replies = [0u8; status::REQUESTS.len()];
+    for (reply, n) in replies.iter_mut().zip(status::REQUESTS) {
+        port.write_all(&status::request(n))?;
+        let mut byte = [0u8; 1];
+        match port.read_exact(&mut byte) {
+            Ok(()) => *reply = byte[0],
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock
+                        | std::io::ErrorKind::TimedOut
+                        | std::io::ErrorKind::UnexpectedEof
+                ) =>
+            {
+                return Ok(None)
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(Some(replies))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Answers from a canned reply stream and records what was sent
+    struct FakePort {
+        sent: Vec<u8>,
+        replies: Cursor<Vec<u8>>,
+    }
+
+    impl Read for FakePort {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.replies.read(buf)
+        }
+    }
+
+    impl Write for FakePort {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.sent.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_ask_sends_dle_eot() {
+        let mut port = FakePort {
+            sent: Vec::new(),
+            replies: Cursor::new(vec![0x12, 0x16, 0x12]),
+        };
+        assert_eq!(ask(&mut port).unwrap(), Some([0x12, 0x16, 0x12]));
+        assert_eq!(port.sent, vec![0x10, 0x04, 1, 0x10, 0x04, 2, 0x10, 0x04, 4]);
+
+        // Stops asking once a reply doesn't come
+        let mut silent = FakePort {
+            sent: Vec::new(),
+            replies: Cursor::new(vec![0x12]),
+        };
+        assert_eq!(ask(&mut silent).unwrap(), None);
+        assert_eq!(silent.sent.len(), 6);
+    }
+}
@@ -0,0 +1,581 @@
+// Printer status
+//
+// Spooled and IPP printers report printer-state and printer-state-reasons;
+// thermal printers on raw TCP or a serial port are asked with DLE EOT, which
+// they answer even while offline. Label printers would print DLE EOT as
+// text, so they are never asked. The monitor polls every printer in the background and emits
+// `printer-status-changed` whenever one changes, so the print dialog can warn
+// a teller about an empty roll before the receipt goes missing.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{Emitter, Manager};
+
+use super::ipp::{IppClient, IppPrinterState, IppPrinterStatus};
+use super::{raw_tcp, serial, NetworkConnection, NetworkPrinter, PrinterLanguage};
+use crate::error::{ErrorCode, Result};
+use crate::escpos::status::RealTimeStatus;
+
+pub const STATUS_CHANGED_EVENT: &str = "printer-status-changed";
+
+const POLL_INTERVAL: Duration = Duration::from_secs(15);
+/// Local CUPS scheduler, for printers installed in the spooler
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+const CUPS_PRINTERS_URL: &str = "ipp://localhost:631/printers";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PrinterState {
+    Ready,
+    Busy,
+    Offline,
+    Error,
+    #[default]
+    Unknown,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum PrinterCondition {
+    PaperOut,
+    PaperNearEnd,
+    CoverOpen,
+    PaperJam,
+    Offline,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct PrinterStatus {
+    pub printer_name: String,
+    pub state: PrinterState,
+    pub conditions: Vec<PrinterCondition>,
+    /// The printer's own explanation, or why it could not be asked
+    pub message: Option<String>,
+    pub checked_at: DateTime<Utc>,
+}
+
+impl PrinterStatus {
+    fn new(printer_name: &str, state: PrinterState) -> Self {
+        Self {
+            printer_name: printer_name.to_string(),
+            state,
+            conditions: Vec::new(),
+            message: None,
+            checked_at: Utc::now(),
+        }
+    }
+
+    /// Whether anything a teller would notice differs, ignoring `checked_at`
+    fn differs_from(&self, other: &PrinterStatus) -> bool {
+        self.state != other.state
+            || self.conditions != other.conditions
+            || self.message != other.message
+    }
+}
+
+/// Condition named by an IPP printer-state-reasons keyword
+fn reason_condition(reason: &str) -> Option<PrinterCondition> {
+    let keyword = ["-error", "-warning", "-report"]
+        .iter()
+        .find_map(|suffix| reason.strip_suffix(suffix))
+        .unwrap_or(reason);
+    Some(match keyword {
+        "media-empty" | "media-needed" => PrinterCondition::PaperOut,
+        "media-low" => PrinterCondition::PaperNearEnd,
+        "cover-open" | "door-open" => PrinterCondition::CoverOpen,
+        "media-jam" => PrinterCondition::PaperJam,
+        "offline" | "shutdown" | "paused" => PrinterCondition::Offline,
+        _ => return None,
+    })
+}
+
+fn from_ipp(printer_name: &str, ipp: IppPrinterStatus) -> PrinterStatus {
+    let mut conditions: Vec<_> = ipp
+        .reasons
+        .iter()
+        .filter_map(|reason| reason_condition(reason))
+        .collect();
+    conditions.sort();
+    conditions.dedup();
+
+    let stopping = conditions.iter().any(|c| {
+        matches!(
+            c,
+            PrinterCondition::PaperOut | PrinterCondition::CoverOpen | PrinterCondition::PaperJam
+        )
+    });
+    let state = if conditions.contains(&PrinterCondition::Offline) {
+        PrinterState::Offline
+    } else if stopping || ipp.reasons.iter().any(|r| r.ends_with("-error")) {
+        PrinterState::Error
+    } else {
+        match ipp.state {
+            Some(IppPrinterState::Idle) => PrinterState::Ready,
+            Some(IppPrinterState::Processing) => PrinterState::Busy,
+            Some(IppPrinterState::Stopped) => PrinterState::Error,
+            None => PrinterState::Unknown,
+        }
+    };
+
+    PrinterStatus {
+        conditions,
+        message: ipp.message,
+        ..PrinterStatus::new(printer_name, state)
+    }
+}
+
+fn from_real_time(printer_name: &str, status: RealTimeStatus) -> PrinterStatus {
+    let mut conditions = Vec::new();
+    if status.paper_out {
+        conditions.push(PrinterCondition::PaperOut);
+    } else if status.paper_near_end {
+        conditions.push(PrinterCondition::PaperNearEnd);
+    }
+    if status.cover_open {
+        conditions.push(PrinterCondition::CoverOpen);
+    }
+    if status.offline {
+        conditions.push(PrinterCondition::Offline);
+    }
+    conditions.sort();
+
+    // An open cover or empty roll also takes the printer offline; report the
+    // cause rather than just "offline"
+    let state = if status.paper_out || status.cover_open || status.error {
+        PrinterState::Error
+    } else if status.offline {
+        PrinterState::Offline
+    } else {
+        PrinterState::Ready
+    };
+
+    PrinterStatus {
+        conditions,
+        ..PrinterStatus::new(printer_name, state)
+    }
+}
+
+async fn query_ipp(
+    printer_name: &str,
+    url: &str,
+    certificate: Option<&str>,
+) -> Result<PrinterStatus> {
+    match IppClient::new(url, certificate)?.printer_status().await {
+        Ok(ipp) => Ok(from_ipp(printer_name, ipp)),
+        Err(e) if e.code == ErrorCode::PrinterOffline => Ok(PrinterStatus {
+            conditions: vec![PrinterCondition::Offline],
+            message: Some(e.message),
+            ..PrinterStatus::new(printer_name, PrinterState::Offline)
+        }),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+async fn query_system(printer_name: &str) -> Result<PrinterStatus> {
+    // CUPS queue names can't contain spaces, slashes or '#'
+    let url = format!("{}/{}", CUPS_PRINTERS_URL, printer_name);
+    query_ipp(printer_name, &url, None).await
+}
+
+#[cfg(target_os = "windows")]
+async fn query_system(printer_name: &str) -> Result<PrinterStatus> {
+    let name = printer_name.to_string();
+    tauri::async_runtime::spawn_blocking(move || windows::query(&name)).await?
+}
+
+fn unreported(printer_name: &str) -> PrinterStatus {
+    PrinterStatus {
+        message: Some("Printer does not report its status".to_string()),
+        ..PrinterStatus::new(printer_name, PrinterState::Unknown)
+    }
+}
+
+fn from_reply(printer_name: &str, reply: Result<Option<RealTimeStatus>>) -> Result<PrinterStatus> {
+    match reply {
+        Ok(Some(status)) => Ok(from_real_time(printer_name, status)),
+        // Reachable, but doesn't speak DLE EOT
+        Ok(None) => Ok(unreported(printer_name)),
+        Err(e) if e.code == ErrorCode::PrinterOffline => Ok(PrinterStatus {
+            conditions: vec![PrinterCondition::Offline],
+            message: Some(e.message),
+            ..PrinterStatus::new(printer_name, PrinterState::Offline)
+        }),
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether the printer can be asked without anything being printed: IPP
+/// printers always can, raw TCP and serial printers only if they speak ESC/POS
+fn answers_status(printer: &NetworkPrinter) -> bool {
+    matches!(printer.connection, NetworkConnection::Ipp { .. })
+        || printer.language == PrinterLanguage::EscPos
+}
+
+/// Ask a printer for its current status
+///
+/// Raw TCP and serial printers are asked while holding the device lock so the
+/// question doesn't land in the middle of a receipt. Spoolers and IPP printers
+/// answer status requests while printing.
+pub async fn query(app_handle: &tauri::AppHandle, printer_name: &str) -> Result<PrinterStatus> {
+    let network = super::load_network_printers(app_handle)?
+        .into_iter()
+        .find(|p| p.name == printer_name);
+    let Some(printer) = network else {
+        return query_system(printer_name).await;
+    };
+    if !answers_status(&printer) {
+        return Ok(unreported(printer_name));
+    }
+
+    match printer.connection {
+        NetworkConnection::RawTcp {
+            host,
+            port,
+            timeout_ms,
+        } => {
+            let (_, _guard) = super::claim(app_handle, printer_name).await?;
+            let timeout = Duration::from_millis(timeout_ms);
+            let reply = tauri::async_runtime::spawn_blocking(move || {
+                raw_tcp::real_time_status(&host, port, timeout)
+            })
+            .await?;
+            from_reply(printer_name, reply)
+        }
+        NetworkConnection::Serial { profile } => {
+            let (_, _guard) = super::claim(app_handle, printer_name).await?;
+            let reply =
+                tauri::async_runtime::spawn_blocking(move || serial::real_time_status(&profile))
+                    .await?;
+            from_reply(printer_name, reply)
+        }
+        NetworkConnection::Ipp {
+            url, certificate, ..
+        } => query_ipp(printer_name, &url, certificate.as_deref()).await,
+    }
+}
+
+/// Last known status of each printer
+#[derive(Default)]
+pub struct PrinterMonitor {
+    statuses: Mutex<HashMap<String, PrinterStatus>>,
+}
+
+impl PrinterMonitor {
+    pub fn get(&self, printer_name: &str) -> Option<PrinterStatus> {
+        self.statuses.lock().ok()?.get(printer_name).cloned()
+    }
+
+    /// Record a status, returning whether it changed
+    pub fn update(&self, status: &PrinterStatus) -> Result<bool> {
+        let mut statuses = self.statuses.lock()?;
+        let changed = statuses
+            .get(&status.printer_name)
+            .is_none_or(|previous| status.differs_from(previous));
+        statuses.insert(status.printer_name.clone(), status.clone());
+        Ok(changed)
+    }
+
+    fn retain(&self, printer_names: &[String]) -> Result<()> {
+        self.statuses
+            .lock()?
+            .retain(|name, _| printer_names.contains(name));
+        Ok(())
+    }
+}
+
+/// Store a status and emit `printer-status-changed` if it changed
+pub fn record(app_handle: &tauri::AppHandle, status: &PrinterStatus) -> Result<()> {
+    if app_handle.state::<PrinterMonitor>().update(status)? {
+        let _ = app_handle.emit(STATUS_CHANGED_EVENT, status);
+    }
+    Ok(())
+}
+
+/// Query one printer, recording a failed query as an unknown status
+pub async fn refresh(app_handle: &tauri::AppHandle, printer_name: &str) -> Result<PrinterStatus> {
+    let status = match query(app_handle, printer_name).await {
+        Ok(status) => status,
+        Err(e) => PrinterStatus {
+            message: Some(e.message),
+            ..PrinterStatus::new(printer_name, PrinterState::Unknown)
+        },
+    };
+    record(app_handle, &status)?;
+    Ok(status)
+}
+
+/// Poll every printer that answers status requests until the app exits
+pub async fn run_monitor(app_handle: tauri::AppHandle) {
+    loop {
+        match crate::commands::print::list_printers(&app_handle).await {
+            Ok(printers) => {
+                // list_printers has just read the same file
+                let silent: Vec<String> = super::load_network_printers(&app_handle)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|p| !answers_status(p))
+                    .map(|p| p.name)
+                    .collect();
+                let names: Vec<String> = printers
+                    .into_iter()
+                    .map(|p| p.name)
+                    .filter(|name| !silent.contains(name))
+                    .collect();
+                let refreshes = names.iter().map(|name| refresh(&app_handle, name));
+                for result in futures::future::join_all(refreshes).await {
+                    if let Err(e) = result {
+                        eprintln!("Failed to record printer status: {}", e);
+                    }
+                }
+                if let Err(e) = app_handle.state::<PrinterMonitor>().retain(&names) {
+                    eprintln!("Failed to record printer status: {}", e);
+                }
+            }
+            Err(e) => eprintln!("Failed to list printers for status: {}", e),
+        }
+
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod windows {
+    use std::process::Command;
+
+    use super::{PrinterCondition, PrinterState, PrinterStatus};
+    use crate::error::{AppError, Context, ErrorCode, Result};
+
+    /// Query Win32_Printer, which reports what the port monitor knows
+    pub fn query(printer_name: &str) -> Result<PrinterStatus> {
+        let filter = format!(
+            "Name='{}'",
+            printer_name.replace('\\', "\\\\").replace('\'', "\\'")
+        );
+        let output = Command::new("wmic")
+            .args([
+                "printer",
+                "where",
+                &filter,
+                "get",
+                "DetectedErrorState,PrinterStatus,WorkOffline",
+                "/format:csv",
+            ])
+            .output()
+            .context("Failed to execute wmic")?;
+        if !output.status.success() {
+            return Err(
+                AppError::new(ErrorCode::DeviceError, "Failed to query printer status")
+                    .with_detail(String::from_utf8_lossy(&output.stderr).trim()),
+            );
+        }
+
+        let output = String::from_utf8_lossy(&output.stdout);
+        let mut lines = output.lines().filter(|line| !line.trim().is_empty());
+        let header: Vec<&str> = lines.next().unwrap_or("").split(',').collect();
+        let values: Vec<&str> = lines
+            .next()
+            .ok_or_else(|| {
+                AppError::new(
+                    ErrorCode::PrinterNotFound,
+                    format!("Printer {} not found", printer_name),
+                )
+            })?
+            .split(',')
+            .collect();
+        let field = |name: &str| {
+            header
+                .iter()
+                .position(|h| h.trim() == name)
+                .and_then(|i| values.get(i))
+                .map(|v| v.trim())
+                .unwrap_or("")
+        };
+
+        let mut conditions = Vec::new();
+        // DetectedErrorState: 3 low paper, 4 no paper, 7 door open, 8 jammed, 9 offline
+        match field("DetectedErrorState") {
+            "3" => conditions.push(PrinterCondition::PaperNearEnd),
+            "4" => conditions.push(PrinterCondition::PaperOut),
+            "7" => conditions.push(PrinterCondition::CoverOpen),
+            "8" => conditions.push(PrinterCondition::PaperJam),
+            "9" => conditions.push(PrinterCondition::Offline),
+            _ => {}
+        }
+        // PrinterStatus 7 is offline
+        if (field("WorkOffline").eq_ignore_ascii_case("TRUE") || field("PrinterStatus") == "7")
+            && !conditions.contains(&PrinterCondition::Offline)
+        {
+            conditions.push(PrinterCondition::Offline);
+        }
+
+        let state = if conditions.iter().any(|c| {
+            matches!(
+                c,
+                PrinterCondition::PaperOut
+                    | PrinterCondition::CoverOpen
+                    | PrinterCondition::PaperJam
+            )
+        }) {
+            PrinterState::Error
+        } else if conditions.contains(&PrinterCondition::Offline) {
+            PrinterState::Offline
+        } else {
+            match field("PrinterStatus") {
+                "3" => PrinterState::Ready,
+                "4" | "5" => PrinterState::Busy,
+                "6" => PrinterState::Error,
+                _ => PrinterState::Unknown,
+            }
+        };
+
+        Ok(PrinterStatus {
+            conditions,
+            ..PrinterStatus::new(printer_name, state)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ipp_status() {
+        let status = from_ipp(
+            "Back office",
+            IppPrinterStatus {
+                state: Some(IppPrinterState::Idle),
+                reasons: vec!["media-low-report".to_string()],
+                message: None,
+            },
+        );
+        assert_eq!(status.state, PrinterState::Ready);
+        assert_eq!(status.conditions, vec![PrinterCondition::PaperNearEnd]);
+
+        let status = from_ipp(
+            "Back office",
+            IppPrinterStatus {
+                state: Some(IppPrinterState::Stopped),
+                reasons: vec![
+                    "cover-open-error".to_string(),
+                    "media-empty-error".to_string(),
+                    "media-needed-warning".to_string(),
+                ],
+                message: Some("Close the cover".to_string()),
+            },
+        );
+        assert_eq!(status.state, PrinterState::Error);
+        assert_eq!(
+            status.conditions,
+            vec![PrinterCondition::PaperOut, PrinterCondition::CoverOpen]
+        );
+
+        // CUPS pauses a queue whose backend lost the printer
+        let status = from_ipp(
+            "Back office",
+            IppPrinterStatus {
+                state: Some(IppPrinterState::Stopped),
+                reasons: vec!["paused".to_string()],
+                message: None,
+            },
+        );
+        assert_eq!(status.state, PrinterState::Offline);
+    }
+
+    #[test]
+    fn test_real_time_status() {
+        let ready = from_real_time("Counter 1", RealTimeStatus::default());
+        assert_eq!(ready.state, PrinterState::Ready);
+        assert!(ready.conditions.is_empty());
+
+        let cover_open = from_real_time(
+            "Counter 1",
+            RealTimeStatus {
+                offline: true,
+                cover_open: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(cover_open.state, PrinterState::Error);
+        assert_eq!(
+            cover_open.conditions,
+            vec![PrinterCondition::CoverOpen, PrinterCondition::Offline]
+        );
+
+        let near_end = from_real_time(
+            "Counter 1",
+            RealTimeStatus {
+                paper_near_end: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(near_end.state, PrinterState::Ready);
+        assert_eq!(near_end.conditions, vec![PrinterCondition::PaperNearEnd]);
+    }
+
+    #[test]
+    fn test_label_printers_are_not_asked() {
+        let printer = |connection, language| NetworkPrinter {
+            name: "Labels".to_string(),
+            connection,
+            language,
+        };
+        let raw_tcp = NetworkConnection::RawTcp {
+            host: "10.0.0.10".to_string(),
+            port: 9100,
+            timeout_ms: 3000,
+        };
+        assert!(answers_status(&printer(
+            raw_tcp.clone(),
+            PrinterLanguage::EscPos
+        )));
+        assert!(!answers_status(&printer(
+            raw_tcp.clone(),
+            PrinterLanguage::Zpl
+        )));
+        assert!(!answers_status(&printer(raw_tcp, PrinterLanguage::Tspl)));
+
+        let serial = NetworkConnection::Serial {
+            profile: serde_json::from_value(serde_json::json!({ "port": "/dev/ttyUSB0" })).unwrap(),
+        };
+        assert!(answers_status(&printer(
+            serial.clone(),
+            PrinterLanguage::EscPos
+        )));
+        assert!(!answers_status(&printer(serial, PrinterLanguage::Tspl)));
+
+        // IPP status requests never reach the print engine
+        let ipp = NetworkConnection::Ipp {
+            url: "ipp://10.0.0.11/ipp/print".to_string(),
+            document_format: None,
+            certificate: None,
+        };
+        assert!(answers_status(&printer(ipp, PrinterLanguage::Zpl)));
+    }
+
+    #[test]
+    fn test_monitor_reports_changes_only() {
+        let monitor = PrinterMonitor::default();
+        let ready = PrinterStatus::new("Counter 1", PrinterState::Ready);
+        assert!(monitor.update(&ready).unwrap());
+
+        // Checked again later, nothing new
+        assert!(!monitor
+            .update(&PrinterStatus::new("Counter 1", PrinterState::Ready))
+            .unwrap());
+
+        let paper_out = PrinterStatus {
+            conditions: vec![PrinterCondition::PaperOut],
+            ..PrinterStatus::new("Counter 1", PrinterState::Error)
+        };
+        assert!(monitor.update(&paper_out).unwrap());
+        assert_eq!(monitor.get("Counter 1"), Some(paper_out));
+
+        monitor.retain(&[]).unwrap();
+        assert_eq!(monitor.get("Counter 1"), None);
+    }
+}
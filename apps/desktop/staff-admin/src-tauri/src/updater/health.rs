@@ -0,0 +1,358 @@
+// Launch health after updates
+//
+// Each launch is marked as started, and marked again once the window has
+// rendered. A launch still marked started when the next one begins crashed or
+// hung; after `MAX_FAILED_LAUNCHES` of those in a row on a version that never
+// started successfully, the app reinstalls the last version that did.
+//
+// Installers are kept under `installers/<version>/` once their version has
+// started successfully, for the current and the previous good version, so
+// there is always something to roll back to without a download.
+//
+// Not on macOS: installing there only mounts the disk image for staff to copy
+// the app from, so running an old installer wouldn't roll anything back.
+
+use chrono::{DateTime, Utc};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::error::{AppError, Context, Result};
+
+pub const MAX_FAILED_LAUNCHES: u32 = 3;
+pub const ROLLBACK_SUPPORTED: bool = cfg!(not(target_os = "macos"));
+const HEALTH_FILE: &str = "health.json";
+const INSTALLERS_DIR: &str = "installers";
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RollbackReport {
+    pub from_version: Version,
+    pub to_version: Version,
+    pub failed_launches: u32,
+    /// Rolled back at startup rather than by staff
+    pub automatic: bool,
+    pub rolled_back_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct LaunchHealth {
+    /// Last version that started successfully
+    pub good_version: Option<Version>,
+    /// The good version before `good_version`
+    pub previous_version: Option<Version>,
+    /// Version whose launch hasn't been confirmed yet
+    pub launching: Option<Version>,
+    /// Unconfirmed launches of `launching` in a row
+    pub failed_launches: u32,
+    /// `failed_launches` when the last launch was confirmed
+    #[serde(default)]
+    pub failed_before_confirm: u32,
+    /// Installer run by `install_update`, kept once its version starts
+    pub pending_installer: Option<PathBuf>,
+    /// Rollbacks not yet reported to the server
+    #[serde(default)]
+    pub unsent_reports: Vec<RollbackReport>,
+}
+
+impl LaunchHealth {
+    pub fn load(dir: &Path) -> Result<Self> {
+        let json = match std::fs::read_to_string(dir.join(HEALTH_FILE)) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e).context("Failed to read launch health"),
+        };
+        serde_json::from_str(&json).context("Failed to parse launch health")
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(dir).context("Failed to create update directory")?;
+        let json = serde_json::to_string(self).context("Failed to serialize launch health")?;
+        let path = dir.join(HEALTH_FILE);
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, json).context("Failed to write launch health")?;
+        std::fs::rename(&tmp_path, &path).context("Failed to write launch health")
+    }
+
+    /// Record the start of a launch, counting the last one as failed if it
+    /// was the same version and never confirmed
+    pub fn launch_started(&mut self, current: &Version) {
+        if self.launching.as_ref() == Some(current) {
+            self.failed_launches += 1;
+        } else {
+            self.failed_launches = 0;
+        }
+        self.launching = Some(current.clone());
+    }
+
+    /// Record a successful launch; returns the installer that installed it,
+    /// if any, to keep for later rollbacks
+    pub fn launch_confirmed(&mut self, current: &Version) -> Option<PathBuf> {
+        self.launching = None;
+        self.failed_before_confirm = std::mem::take(&mut self.failed_launches);
+        if self.good_version.as_ref() != Some(current) {
+            self.previous_version = self.good_version.replace(current.clone());
+        }
+        self.pending_installer.take()
+    }
+
+    /// The version a rollback would install: the last good one while the
+    /// current version has never started successfully, otherwise the one
+    /// before it
+    pub fn rollback_target(&self, current: &Version) -> Option<&Version> {
+        match &self.good_version {
+            Some(good) if good != current => Some(good),
+            _ => self.previous_version.as_ref(),
+        }
+    }
+
+    /// Failed launches of the current version before this one, whether or
+    /// not this launch has been confirmed yet
+    pub fn recent_failures(&self, current: &Version) -> u32 {
+        if self.launching.as_ref() == Some(current) {
+            self.failed_launches
+        } else {
+            self.failed_before_confirm
+        }
+    }
+
+    /// Whether the current version keeps failing to start and there is a
+    /// good version to go back to
+    pub fn should_roll_back(&self, current: &Version) -> bool {
+        ROLLBACK_SUPPORTED
+            && self.failed_launches >= MAX_FAILED_LAUNCHES
+            && self.launching.as_ref() == Some(current)
+            && self
+                .good_version
+                .as_ref()
+                .is_some_and(|good| good != current)
+    }
+
+    /// Record a rollback that is about to run; the installer of the version
+    /// being left is never kept
+    pub fn rolling_back(&mut self, report: RollbackReport) {
+        self.launching = None;
+        self.failed_launches = 0;
+        self.failed_before_confirm = 0;
+        self.pending_installer = None;
+        self.unsent_reports.push(report);
+    }
+
+    /// Versions whose installers are still needed
+    fn kept_versions(&self) -> Vec<&Version> {
+        self.good_version
+            .iter()
+            .chain(self.previous_version.iter())
+            .collect()
+    }
+}
+
+/// Record the start of a launch; returns the version to roll back to and its
+/// kept installer once this version has failed to start too often
+pub fn start_launch(dir: &Path, current: &Version) -> Result<Option<(Version, PathBuf)>> {
+    let mut health = LaunchHealth::load(dir)?;
+    health.launch_started(current);
+    health.save(dir)?;
+
+    if !health.should_roll_back(current) {
+        return Ok(None);
+    }
+    Ok(health
+        .rollback_target(current)
+        .and_then(|target| Some((target.clone(), kept_installer(dir, target)?))))
+}
+
+/// Record a successful launch, keeping the installer that installed it for
+/// later rollbacks
+pub fn confirm_launch(dir: &Path, current: &Version) -> Result<LaunchHealth> {
+    let mut health = LaunchHealth::load(dir)?;
+    let installer = health.launch_confirmed(current);
+    if let Some(installer) = installer.filter(|_| ROLLBACK_SUPPORTED) {
+        if let Err(e) = keep_installer(dir, current, &installer) {
+            eprintln!("Failed to keep installer for rollback: {}", e);
+        }
+    }
+    health.save(dir)?;
+    prune_installers(dir, &health)?;
+    Ok(health)
+}
+
+fn version_dir(dir: &Path, version: &Version) -> PathBuf {
+    dir.join(INSTALLERS_DIR).join(version.to_string())
+}
+
+/// Keep an installer and the signature and checksum beside it as the
+/// installer for `version`
+pub fn keep_installer(dir: &Path, version: &Version, installer: &Path) -> Result<()> {
+    let target = version_dir(dir, version);
+    std::fs::create_dir_all(&target).context("Failed to create installer store")?;
+    let file_name = installer
+        .file_name()
+        .ok_or_else(|| AppError::invalid_input("Installer has no file name"))?
+        .to_owned();
+    for suffix in ["", ".sig", ".sha256"] {
+        let mut from = installer.as_os_str().to_owned();
+        from.push(suffix);
+        let mut to = file_name.clone();
+        to.push(suffix);
+        std::fs::copy(&from, target.join(to)).context("Failed to keep installer")?;
+    }
+    Ok(())
+}
+
+/// The kept installer for `version`, if any
+pub fn kept_installer(dir: &Path, version: &Version) -> Option<PathBuf> {
+    std::fs::read_dir(version_dir(dir, version))
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .find(|path| {
+            let name = path.to_string_lossy();
+            !name.ends_with(".sig") && !name.ends_with(".sha256")
+        })
+}
+
+/// Delete kept installers for versions no longer needed
+pub fn prune_installers(dir: &Path, health: &LaunchHealth) -> Result<()> {
+    let entries = match std::fs::read_dir(dir.join(INSTALLERS_DIR)) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).context("Failed to read installer store"),
+    };
+    let keep = health.kept_versions();
+    for entry in entries {
+        let path = entry.context("Failed to read installer store")?.path();
+        let version = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| Version::parse(name).ok());
+        if !version.is_some_and(|version| keep.contains(&&version)) {
+            std::fs::remove_dir_all(&path).context("Failed to remove old installer")?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(version: &str) -> Version {
+        Version::parse(version).unwrap()
+    }
+
+    #[test]
+    #[cfg(not(target_os = "macos"))]
+    fn test_failed_launches_after_update() {
+        let mut health = LaunchHealth::default();
+        health.launch_started(&v("1.3.0"));
+        health.launch_confirmed(&v("1.3.0"));
+        assert_eq!(health.good_version, Some(v("1.3.0")));
+
+        // 1.4.0 installed; it crashes on every launch
+        health.pending_installer = Some(PathBuf::from("/tmp/staff-admin_1.4.0_x64.msi"));
+        for _ in 0..MAX_FAILED_LAUNCHES {
+            health.launch_started(&v("1.4.0"));
+            assert!(!health.should_roll_back(&v("1.4.0")));
+        }
+        health.launch_started(&v("1.4.0"));
+        assert!(health.should_roll_back(&v("1.4.0")));
+        assert_eq!(health.rollback_target(&v("1.4.0")), Some(&v("1.3.0")));
+
+        health.rolling_back(RollbackReport {
+            from_version: v("1.4.0"),
+            to_version: v("1.3.0"),
+            failed_launches: health.failed_launches,
+            automatic: true,
+            rolled_back_at: Utc::now(),
+        });
+
+        // Back on 1.3.0 everything is fine again, and 1.4.0 is never kept
+        health.launch_started(&v("1.3.0"));
+        assert!(!health.should_roll_back(&v("1.3.0")));
+        assert_eq!(health.launch_confirmed(&v("1.3.0")), None);
+        assert_eq!(health.good_version, Some(v("1.3.0")));
+        assert_eq!(health.previous_version, None);
+        assert_eq!(health.unsent_reports.len(), 1);
+    }
+
+    #[test]
+    fn test_successful_update_keeps_installers() {
+        let dir = std::env::temp_dir().join(format!("launch-health-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let installer = dir.join("staff-admin_1.4.0_x64.msi");
+        for suffix in ["", ".sig", ".sha256"] {
+            std::fs::write(format!("{}{}", installer.display(), suffix), suffix).unwrap();
+        }
+
+        let mut health = LaunchHealth {
+            good_version: Some(v("1.3.0")),
+            previous_version: Some(v("1.2.0")),
+            pending_installer: Some(installer.clone()),
+            ..LaunchHealth::default()
+        };
+        keep_installer(&dir, &v("1.2.0"), &installer).unwrap();
+        keep_installer(&dir, &v("1.3.0"), &installer).unwrap();
+
+        health.launch_started(&v("1.4.0"));
+        let kept = health.launch_confirmed(&v("1.4.0")).unwrap();
+        keep_installer(&dir, &v("1.4.0"), &kept).unwrap();
+        prune_installers(&dir, &health).unwrap();
+
+        // A working 1.4.0 can still be rolled back to 1.3.0 by hand
+        assert_eq!(health.rollback_target(&v("1.4.0")), Some(&v("1.3.0")));
+        assert!(kept_installer(&dir, &v("1.3.0")).is_some());
+        assert_eq!(
+            kept_installer(&dir, &v("1.4.0")).unwrap().file_name(),
+            installer.file_name()
+        );
+        assert!(kept_installer(&dir, &v("1.2.0")).is_none());
+
+        health.save(&dir).unwrap();
+        assert_eq!(LaunchHealth::load(&dir).unwrap(), health);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(not(target_os = "macos"))]
+    fn test_launches_on_disk() {
+        let dir = std::env::temp_dir().join(format!("launch-health-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let downloaded = |version: &str| {
+            let installer = dir.join(format!("staff-admin_{}_x64.msi", version));
+            for suffix in ["", ".sig", ".sha256"] {
+                std::fs::write(format!("{}{}", installer.display(), suffix), version).unwrap();
+            }
+            installer
+        };
+        let install = |installer: PathBuf| {
+            let mut health = LaunchHealth::load(&dir).unwrap();
+            health.pending_installer = Some(installer);
+            health.save(&dir).unwrap();
+        };
+
+        // 1.3.0 installed by the updater starts and is confirmed
+        install(downloaded("1.3.0"));
+        assert_eq!(start_launch(&dir, &v("1.3.0")).unwrap(), None);
+        let health = confirm_launch(&dir, &v("1.3.0")).unwrap();
+        assert_eq!(health.good_version, Some(v("1.3.0")));
+        let kept = kept_installer(&dir, &v("1.3.0")).unwrap();
+        assert_eq!(std::fs::read_to_string(&kept).unwrap(), "1.3.0");
+
+        // 1.4.0 never gets confirmed; the launch after the last allowed
+        // failure rolls back to the kept 1.3.0 installer
+        install(downloaded("1.4.0"));
+        for _ in 0..MAX_FAILED_LAUNCHES {
+            assert_eq!(start_launch(&dir, &v("1.4.0")).unwrap(), None);
+        }
+        assert_eq!(
+            start_launch(&dir, &v("1.4.0")).unwrap(),
+            Some((v("1.3.0"), kept))
+        );
+        assert!(kept_installer(&dir, &v("1.4.0")).is_none());
+
+        // Failures are still reported once a later launch is confirmed
+        let health = confirm_launch(&dir, &v("1.4.0")).unwrap();
+        assert_eq!(health.recent_failures(&v("1.4.0")), MAX_FAILED_LAUNCHES);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,306 @@
+// Update manifest
+//
+// `manifest.json` lists each channel's current release with one installer per
+// platform, the channels IT has assigned to particular devices and SACCOs,
+// and the oldest version still allowed to run. It is signed like the
+// installers, as `manifest.json.sig`, and pins each installer's size and
+// SHA-256 digest so only the installer it names can be installed. The signed
+// `published_at` and `expires` dates stop an old manifest being served again:
+// a device refuses one older than the newest it has used, or past its expiry.
+//
+// A release can be rolled out gradually: each device falls in a bucket from
+// 0 to 99, from a hash of its device id and the release version, and is
+// offered the release once the rollout percentage passes its bucket. Mixing
+// in the version means different devices go first for each release.
+
+use chrono::{DateTime, Utc};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateChannel {
+    #[default]
+    Stable,
+    /// Releases tried at a few branches before they go to stable
+    Pilot,
+    /// Pre-releases, for testers
+    Beta,
+}
+
+fn default_rollout_percent() -> u8 {
+    100
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PlatformAsset {
+    pub url: String,
+    /// Hex SHA-256 digest of the installer
+    pub sha256: String,
+    /// Installer size in bytes
+    pub size: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ChannelRelease {
+    pub version: Version,
+    #[serde(default)]
+    pub notes: String,
+    /// Share of devices offered the release, 0-100
+    #[serde(default = "default_rollout_percent")]
+    pub rollout_percent: u8,
+    /// Installers keyed by platform, e.g. "windows-x86_64"
+    pub platforms: HashMap<String, PlatformAsset>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ChannelAssignments {
+    /// By device id, as returned by `get_device_id`
+    #[serde(default)]
+    pub devices: HashMap<String, UpdateChannel>,
+    #[serde(default)]
+    pub saccos: HashMap<Uuid, UpdateChannel>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct UpdateManifest {
+    pub published_at: DateTime<Utc>,
+    /// Not used after this, so a copy that stops being updated can't hold
+    /// devices on its release
+    pub expires: DateTime<Utc>,
+    /// Older versions must update before they can be used
+    #[serde(default)]
+    pub minimum_version: Option<Version>,
+    pub channels: HashMap<UpdateChannel, ChannelRelease>,
+    #[serde(default)]
+    pub assignments: ChannelAssignments,
+}
+
+impl UpdateManifest {
+    /// The device's channel: its own assignment, then its SACCO's, then the
+    /// one chosen on the device
+    pub fn channel_for(
+        &self,
+        device_id: &str,
+        sacco_id: Option<Uuid>,
+        chosen: Option<UpdateChannel>,
+    ) -> UpdateChannel {
+        self.assignments
+            .devices
+            .get(device_id)
+            .or_else(|| sacco_id.and_then(|id| self.assignments.saccos.get(&id)))
+            .copied()
+            .or(chosen)
+            .unwrap_or_default()
+    }
+
+    /// The channel's release, or stable's when that is newer so pilot and
+    /// beta devices never fall behind; pre-releases are only offered on beta
+    pub fn release_for(&self, channel: UpdateChannel) -> Option<&ChannelRelease> {
+        let offered = |release: &&ChannelRelease| {
+            channel == UpdateChannel::Beta || release.version.pre.is_empty()
+        };
+        let own = self.channels.get(&channel).filter(offered);
+        let stable = self.channels.get(&UpdateChannel::Stable).filter(offered);
+        match (own, stable) {
+            (Some(own), Some(stable))
+                if stable.version.cmp_precedence(&own.version) == Ordering::Greater =>
+            {
+                Some(stable)
+            }
+            (Some(own), _) => Some(own),
+            (None, stable) => stable,
+        }
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        now >= self.expires
+    }
+
+    /// Whether `current` is older than the minimum supported version
+    pub fn requires_update(&self, current: &Version) -> bool {
+        self.minimum_version
+            .as_ref()
+            .is_some_and(|minimum| current.cmp_precedence(minimum) == Ordering::Less)
+    }
+}
+
+/// The device's rollout bucket for a release, 0-99
+pub fn rollout_bucket(device_id: &str, version: &Version) -> u8 {
+    let hash = Sha256::digest(format!("{}:{}", device_id, version));
+    let mut prefix = [0u8; 8];
+    prefix.copy_from_slice(&hash[..8]);
+    (u64::from_be_bytes(prefix) % 100) as u8
+}
+
+impl ChannelRelease {
+    /// Whether the staged rollout has reached this device
+    pub fn rolled_out_to(&self, device_id: &str) -> bool {
+        rollout_bucket(device_id, &self.version) < self.rollout_percent
+    }
+
+    pub fn asset(&self, platform: &str) -> Option<&PlatformAsset> {
+        self.platforms.get(platform)
+    }
+}
+
+/// This build's platform key in the manifest, named like the Tauri updater's
+/// targets
+pub fn platform_key() -> String {
+    let os = match std::env::consts::OS {
+        "macos" => "darwin",
+        os => os,
+    };
+    format!("{}-{}", os, std::env::consts::ARCH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn manifest() -> UpdateManifest {
+        serde_json::from_value(json!({
+            "published_at": "2026-10-01T08:00:00Z",
+            "expires": "2026-12-31T00:00:00Z",
+            "minimum_version": "1.2.0",
+            "channels": {
+                "stable": {
+                    "version": "1.3.2",
+                    "notes": "Faster receipt printing",
+                    "platforms": {
+                        "windows-x86_64": {
+                            "url": "https://releases.ibimina.rw/staff-admin/1.3.2/staff-admin_1.3.2_x64.msi",
+                            "sha256": "9f2c0e1d7b3a4c5d6e7f8091a2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5",
+                            "size": 104857600
+                        }
+                    }
+                },
+                "pilot": {
+                    "version": "1.4.0",
+                    "rollout_percent": 20,
+                    "platforms": {}
+                },
+                "beta": {
+                    "version": "1.3.0-beta.4",
+                    "platforms": {}
+                }
+            },
+            "assignments": {
+                "devices": { "device-a": "beta" },
+                "saccos": { "6f1c2d8e-4b1a-4d6b-9a3e-2f5c7d9e1a2b": "pilot" }
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_channel_assignment() {
+        let manifest = manifest();
+        let sacco = Uuid::parse_str("6f1c2d8e-4b1a-4d6b-9a3e-2f5c7d9e1a2b").unwrap();
+
+        // The device's own assignment wins over its SACCO's and its choice
+        assert_eq!(
+            manifest.channel_for("device-a", Some(sacco), Some(UpdateChannel::Stable)),
+            UpdateChannel::Beta
+        );
+        assert_eq!(
+            manifest.channel_for("device-b", Some(sacco), Some(UpdateChannel::Beta)),
+            UpdateChannel::Pilot
+        );
+        assert_eq!(
+            manifest.channel_for("device-b", None, Some(UpdateChannel::Beta)),
+            UpdateChannel::Beta
+        );
+        assert_eq!(
+            manifest.channel_for("device-b", None, None),
+            UpdateChannel::Stable
+        );
+
+        // Beta's 1.3.0 pre-release is older than stable, so beta gets stable
+        let beta = manifest.release_for(UpdateChannel::Beta).unwrap();
+        assert_eq!(beta.version.to_string(), "1.3.2");
+        let pilot = manifest.release_for(UpdateChannel::Pilot).unwrap();
+        assert_eq!(pilot.version.to_string(), "1.4.0");
+    }
+
+    #[test]
+    fn test_staged_rollout() {
+        let release = manifest().channels[&UpdateChannel::Pilot].clone();
+        let devices: Vec<String> = (0..1000).map(|i| format!("device-{}", i)).collect();
+
+        let reached = devices.iter().filter(|d| release.rolled_out_to(d)).count();
+        assert!((150..250).contains(&reached), "{} of 1000", reached);
+
+        // Stable per device, and widening the rollout keeps earlier devices
+        let wider = ChannelRelease {
+            rollout_percent: 50,
+            ..release.clone()
+        };
+        assert!(devices
+            .iter()
+            .filter(|d| release.rolled_out_to(d))
+            .all(|d| wider.rolled_out_to(d)));
+        let full = ChannelRelease {
+            rollout_percent: 100,
+            ..release.clone()
+        };
+        assert!(devices.iter().all(|d| full.rolled_out_to(d)));
+    }
+
+    #[test]
+    fn test_prerelease_only_on_beta() {
+        let mut manifest = manifest();
+        let rc = |version: &str| ChannelRelease {
+            version: Version::parse(version).unwrap(),
+            notes: String::new(),
+            rollout_percent: 100,
+            platforms: HashMap::new(),
+        };
+        manifest
+            .channels
+            .insert(UpdateChannel::Pilot, rc("1.4.0-rc.1"));
+        manifest
+            .channels
+            .insert(UpdateChannel::Beta, rc("1.4.0-rc.2"));
+
+        // Pilot skips its release candidate and gets stable
+        let pilot = manifest.release_for(UpdateChannel::Pilot).unwrap();
+        assert_eq!(pilot.version.to_string(), "1.3.2");
+        let beta = manifest.release_for(UpdateChannel::Beta).unwrap();
+        assert_eq!(beta.version.to_string(), "1.4.0-rc.2");
+
+        // Not even stable offers a pre-release published there by mistake
+        manifest
+            .channels
+            .insert(UpdateChannel::Stable, rc("1.3.3-rc.1"));
+        assert!(manifest.release_for(UpdateChannel::Stable).is_none());
+        assert_eq!(
+            manifest
+                .release_for(UpdateChannel::Beta)
+                .unwrap()
+                .version
+                .to_string(),
+            "1.4.0-rc.2"
+        );
+    }
+
+    #[test]
+    fn test_minimum_version() {
+        let manifest = manifest();
+        assert!(manifest.requires_update(&Version::parse("1.1.9").unwrap()));
+        assert!(manifest.requires_update(&Version::parse("1.2.0-rc.1").unwrap()));
+        assert!(!manifest.requires_update(&Version::parse("1.2.0").unwrap()));
+    }
+
+    #[test]
+    fn test_expiry() {
+        let manifest = manifest();
+        assert!(!manifest.is_expired("2026-12-30T23:59:59Z".parse().unwrap()));
+        assert!(manifest.is_expired("2026-12-31T00:00:00Z".parse().unwrap()));
+    }
+}
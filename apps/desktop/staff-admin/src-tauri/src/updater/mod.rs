@@ -0,0 +1,11 @@
+// Desktop updates from releases.ibimina.rw, or a copy of a release on the
+// branch network or a USB drive
+//
+// A signed manifest names the current release of each channel; installers are
+// published beside it with a detached signature and SHA-256 checksum. Both
+// are checked with the Tauri updater's minisign key before anything is used.
+
+pub mod health;
+pub mod manifest;
+pub mod sources;
+pub mod verify;
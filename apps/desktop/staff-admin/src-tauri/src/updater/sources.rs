@@ -0,0 +1,133 @@
+// Offline update sources
+//
+// Branches without a reliable connection can update from a copy of a release
+// on a USB drive, a network share or an HTTP server on the branch LAN. A copy
+// is the release server's `manifest.json` and `manifest.json.sig`, unchanged,
+// with each installer it lists and that installer's `.sig` and `.sha256`, all
+// in one folder. Installers are found in the copy by the file name in their
+// manifest URL.
+//
+// Sources are only a different place to fetch the same signed files from:
+// the manifest and installers are verified exactly as when downloaded from
+// the release server.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::error::{AppError, Result};
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum UpdateSource {
+    /// Folder on this computer, a USB drive or a mounted share
+    Folder { path: PathBuf },
+    /// HTTP server on the branch network, e.g. "http://192.168.1.10/staff-admin"
+    Mirror { url: String },
+}
+
+impl fmt::Display for UpdateSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpdateSource::Folder { path } => write!(f, "{}", path.display()),
+            UpdateSource::Mirror { url } => f.write_str(url),
+        }
+    }
+}
+
+impl UpdateSource {
+    /// URL of a file in the copy; `file:` URLs for folders
+    fn location(&self, file_name: &str) -> Result<String> {
+        match self {
+            UpdateSource::Folder { path } => reqwest::Url::from_file_path(path.join(file_name))
+                .map(String::from)
+                .map_err(|()| {
+                    AppError::invalid_input("Update folder must be an absolute path")
+                        .with_detail(path.display().to_string())
+                }),
+            UpdateSource::Mirror { url } => {
+                let base = reqwest::Url::parse(url).map_err(|e| {
+                    AppError::invalid_input("Invalid update mirror URL").with_detail(e)
+                })?;
+                if !matches!(base.scheme(), "http" | "https") {
+                    return Err(AppError::invalid_input("Update mirror must be an HTTP URL")
+                        .with_detail(url.clone()));
+                }
+                Ok(format!("{}/{}", url.trim_end_matches('/'), file_name))
+            }
+        }
+    }
+
+    pub fn manifest_url(&self) -> Result<String> {
+        self.location(MANIFEST_FILE)
+    }
+
+    /// Where the copy keeps the installer the manifest lists at `asset_url`
+    pub fn asset_url(&self, asset_url: &str) -> Result<String> {
+        let path = asset_url.split(['?', '#']).next().unwrap_or("");
+        match path.rsplit('/').next() {
+            Some(name)
+                if !name.is_empty()
+                    && !name.contains(['\\', ':'])
+                    && name != "."
+                    && name != ".." =>
+            {
+                self.location(name)
+            }
+            _ => Err(AppError::invalid_input("Installer URL has no file name")
+                .with_detail(asset_url.to_string())),
+        }
+    }
+}
+
+/// The file a `file:` URL names, for reading from folder sources
+pub fn local_path(url: &str) -> Option<PathBuf> {
+    let url = reqwest::Url::parse(url).ok()?;
+    if url.scheme() != "file" {
+        return None;
+    }
+    url.to_file_path().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_locations() {
+        let asset = "https://releases.ibimina.rw/staff-admin/1.3.2/staff-admin_1.3.2_x64.msi?dl=1";
+
+        let mirror = UpdateSource::Mirror {
+            url: "http://192.168.1.10/staff-admin/".to_string(),
+        };
+        assert_eq!(
+            mirror.manifest_url().unwrap(),
+            "http://192.168.1.10/staff-admin/manifest.json"
+        );
+        assert_eq!(
+            mirror.asset_url(asset).unwrap(),
+            "http://192.168.1.10/staff-admin/staff-admin_1.3.2_x64.msi"
+        );
+
+        let dir = std::env::temp_dir().join("staff admin bundle");
+        let folder = UpdateSource::Folder { path: dir.clone() };
+        let url = folder.asset_url(asset).unwrap();
+        assert!(url.starts_with("file://"));
+        assert_eq!(
+            local_path(&url),
+            Some(dir.join("staff-admin_1.3.2_x64.msi"))
+        );
+        assert_eq!(local_path(&mirror.manifest_url().unwrap()), None);
+
+        let relative = UpdateSource::Folder {
+            path: PathBuf::from("updates"),
+        };
+        assert!(relative.manifest_url().is_err());
+        let ftp = UpdateSource::Mirror {
+            url: "ftp://192.168.1.10/".to_string(),
+        };
+        assert!(ftp.manifest_url().is_err());
+    }
+}
@@ -0,0 +1,169 @@
+// Minisign signatures and SHA-256 checksums for release files
+//
+// Keys and signatures are the ones `tauri signer` writes: minisign text,
+// base64-encoded once more. Plain minisign text is accepted too.
+
+use base64::{engine::general_purpose, Engine as _};
+use minisign_verify::{PublicKey, Signature};
+use sha2::{Digest, Sha256};
+
+use crate::error::{AppError, ErrorCode, Result};
+
+/// Public key the release signatures are checked against; the same minisign
+/// key the Tauri updater uses, from `plugins.updater.pubkey` in tauri.conf.json
+///
+/// Builds without a key can't check updates, so they don't look for any.
+pub fn updater_public_key(app_handle: &tauri::AppHandle) -> Result<PublicKey> {
+    let encoded = configured_public_key(app_handle).ok_or_else(|| {
+        AppError::new(
+            ErrorCode::Unsupported,
+            "Updates are turned off in this build",
+        )
+        .with_detail("No update signing key: plugins.updater.pubkey is not set in tauri.conf.json")
+    })?;
+    decode_public_key(encoded)
+}
+
+fn configured_public_key(app_handle: &tauri::AppHandle) -> Option<&str> {
+    app_handle
+        .config()
+        .plugins
+        .0
+        .get("updater")
+        .and_then(|updater| updater["pubkey"].as_str())
+        .filter(|key| !key.trim().is_empty())
+}
+
+/// Whether this build has an update signing key to check updates with
+pub fn updates_enabled(app_handle: &tauri::AppHandle) -> bool {
+    configured_public_key(app_handle).is_some()
+}
+
+pub fn verification_failed(detail: impl std::fmt::Display) -> AppError {
+    AppError::from_code(ErrorCode::UpdateVerificationFailed).with_detail(detail)
+}
+
+fn minisign_text(encoded: &str) -> Result<String> {
+    let encoded = encoded.trim();
+    if encoded.starts_with("untrusted comment:") {
+        return Ok(encoded.to_string());
+    }
+    let bytes = general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(|e| verification_failed(format!("Invalid minisign encoding: {}", e)))?;
+    String::from_utf8(bytes).map_err(|_| verification_failed("Invalid minisign encoding"))
+}
+
+pub fn decode_public_key(encoded: &str) -> Result<PublicKey> {
+    PublicKey::decode(&minisign_text(encoded)?)
+        .map_err(|e| verification_failed(format!("Invalid update signing key: {}", e)))
+}
+
+/// SHA-256 checksum file: the hex digest, optionally followed by the file
+/// name as written by `sha256sum`
+fn parse_checksum(text: &str) -> Result<[u8; 32]> {
+    let digest = text.split_whitespace().next().unwrap_or("");
+    let mut checksum = [0u8; 32];
+    hex::decode_to_slice(digest, &mut checksum)
+        .map_err(|_| verification_failed("Invalid SHA-256 checksum file"))?;
+    Ok(checksum)
+}
+
+/// Check `data` against a detached signature
+pub fn verify_signature(public_key: &PublicKey, data: &[u8], signature: &str) -> Result<()> {
+    let signature = Signature::decode(&minisign_text(signature)?)
+        .map_err(|e| verification_failed(format!("Invalid signature file: {}", e)))?;
+    public_key
+        .verify(data, &signature, true)
+        .map_err(|e| verification_failed(format!("Bad signature: {}", e)))
+}
+
+/// Check an installer against its published checksum and detached signature
+pub fn verify_installer(
+    public_key: &PublicKey,
+    installer: &[u8],
+    signature: &str,
+    checksum: &str,
+) -> Result<()> {
+    let expected = parse_checksum(checksum)?;
+    let actual: [u8; 32] = Sha256::digest(installer).into();
+    if actual != expected {
+        return Err(verification_failed(format!(
+            "SHA-256 mismatch: expected {}, got {}",
+            hex::encode(expected),
+            hex::encode(actual)
+        )));
+    }
+    verify_signature(public_key, installer, signature)
+}
+
+/// Check an installer against the size and SHA-256 digest pinned for it in
+/// the signed manifest, so a mirror can't serve another validly signed
+/// installer, such as an older release, under the new version's name
+pub fn verify_pinned(installer: &[u8], sha256: &str, size: u64) -> Result<()> {
+    if installer.len() as u64 != size {
+        return Err(verification_failed(format!(
+            "Size mismatch: the manifest lists {} bytes, got {}",
+            size,
+            installer.len()
+        )));
+    }
+    let expected = parse_checksum(sha256)?;
+    let actual: [u8; 32] = Sha256::digest(installer).into();
+    if actual != expected {
+        return Err(verification_failed(format!(
+            "SHA-256 doesn't match the manifest: expected {}, got {}",
+            hex::encode(expected),
+            hex::encode(actual)
+        )));
+    }
+    Ok(())
+}
+
+/// Keys and signatures in the form `tauri signer` writes them
+#[cfg(test)]
+pub mod testing {
+    use base64::{engine::general_purpose, Engine as _};
+    use ed25519_dalek::{Signer, SigningKey};
+    use sha2::{Digest, Sha256};
+
+    const KEY_ID: [u8; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+
+    fn b64(bytes: &[u8]) -> String {
+        general_purpose::STANDARD.encode(bytes)
+    }
+
+    /// Public key as written to tauri.conf.json by `tauri signer generate`
+    pub fn tauri_public_key(key: &SigningKey) -> String {
+        let mut bin = b"Ed".to_vec();
+        bin.extend_from_slice(&KEY_ID);
+        bin.extend_from_slice(key.verifying_key().as_bytes());
+        b64(format!("untrusted comment: minisign public key\n{}\n", b64(&bin)).as_bytes())
+    }
+
+    /// Detached minisign signature, base64-encoded as Tauri writes `.sig` files
+    pub fn tauri_signature(key: &SigningKey, data: &[u8]) -> String {
+        let signature = key.sign(data).to_bytes();
+        let trusted_comment = "timestamp:1760000000\tfile:staff-admin.AppImage";
+        let mut global = signature.to_vec();
+        global.extend_from_slice(trusted_comment.as_bytes());
+
+        let mut bin = b"Ed".to_vec();
+        bin.extend_from_slice(&KEY_ID);
+        bin.extend_from_slice(&signature);
+        let text = format!(
+            "untrusted comment: signature from tauri secret key\n{}\ntrusted comment: {}\n{}\n",
+            b64(&bin),
+            trusted_comment,
+            b64(&key.sign(&global).to_bytes())
+        );
+        b64(text.as_bytes())
+    }
+
+    pub fn checksum(data: &[u8]) -> String {
+        format!(
+            "{}  staff-admin.AppImage\n",
+            hex::encode(Sha256::digest(data))
+        )
+    }
+}
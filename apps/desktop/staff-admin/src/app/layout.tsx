@@ -1,5 +1,6 @@
 import "./globals.css";
 import { AuthProvider } from "@/lib/auth";
+import { UpdateNotification } from "@/components/desktop";
 
 interface LayoutProps {
   children: React.ReactNode;
@@ -9,6 +10,7 @@ export function Layout({ children }: LayoutProps) {
   return (
     <AuthProvider>
       {children}
+      <UpdateNotification />
     </AuthProvider>
   );
 }
@@ -4,10 +4,15 @@ import { useEffect, useState } from 'react';
 import {
   UpdateInfo,
   DownloadProgress,
+  RollbackStatus,
   onUpdateAvailable,
   onDownloadProgress,
   downloadUpdate,
+  cancelUpdateDownload,
   installUpdate,
+  getRollbackStatus,
+  rollbackUpdate,
+  isAppError,
   isTauri,
 } from '@/lib/tauri';
 
@@ -17,11 +22,19 @@ export function UpdateNotification() {
   const [progress, setProgress] = useState<DownloadProgress | null>(null);
   const [installerPath, setInstallerPath] = useState<string | null>(null);
   const [error, setError] = useState<string | null>(null);
+  const [rollback, setRollback] = useState<RollbackStatus | null>(null);
 
   useEffect(() => {
     // Only run in Tauri environment
     if (!isTauri()) return;
 
+    // Offer to go back if this version failed to start before
+    getRollbackStatus()
+      .then((status) => {
+        if (status.failed_launches > 0 && status.rollback_version) setRollback(status);
+      })
+      .catch(() => {});
+
     // Listen for update notifications
     const unsubscribe = onUpdateAvailable((info) => {
       setUpdateInfo(info);
@@ -44,15 +57,25 @@ export function UpdateNotification() {
     try {
       setDownloading(true);
       setError(null);
-      const path = await downloadUpdate(updateInfo.download_url);
+      const path = await downloadUpdate();
       setInstallerPath(path);
     } catch (err) {
-      setError(err instanceof Error ? err.message : 'Download failed');
+      if (isAppError(err)) {
+        // Cancelling keeps the partial download to resume later
+        if (err.code !== 'CANCELLED') setError(err.message);
+      } else {
+        setError(err instanceof Error ? err.message : 'Download failed');
+      }
     } finally {
       setDownloading(false);
     }
   };
 
+  const handleCancel = async () => {
+    await cancelUpdateDownload();
+    setProgress(null);
+  };
+
   const handleInstall = async () => {
     if (!installerPath) return;
 
@@ -65,14 +88,63 @@ export function UpdateNotification() {
     }
   };
 
+  const handleRollback = async () => {
+    try {
+      setError(null);
+      await rollbackUpdate();
+      // App will restart automatically
+    } catch (err) {
+      setError(err instanceof Error ? err.message : 'Rollback failed');
+    }
+  };
+
   const handleDismiss = () => {
+    if (updateInfo?.required) return;
     setUpdateInfo(null);
     setProgress(null);
     setInstallerPath(null);
     setError(null);
   };
 
-  if (!updateInfo || !isTauri()) return null;
+  if (!isTauri()) return null;
+
+  if (rollback && !updateInfo) {
+    return (
+      <div className="fixed bottom-4 right-4 z-50 w-96 rounded-lg border border-gray-200 bg-white p-4 shadow-lg dark:border-gray-700 dark:bg-gray-800">
+        <h3 className="text-sm font-semibold text-gray-900 dark:text-gray-100">
+          Problems after updating?
+        </h3>
+        <p className="mt-1 text-sm text-gray-600 dark:text-gray-400">
+          Version {rollback.current_version} failed to start {rollback.failed_launches}{' '}
+          {rollback.failed_launches === 1 ? 'time' : 'times'}. You can go back to version{' '}
+          {rollback.rollback_version}.
+        </p>
+
+        {error && (
+          <div className="mt-3 rounded bg-red-50 p-2 text-sm text-red-800 dark:bg-red-900/20 dark:text-red-300">
+            {error}
+          </div>
+        )}
+
+        <div className="mt-4 flex gap-2">
+          <button
+            onClick={handleRollback}
+            className="flex-1 rounded bg-blue-600 px-4 py-2 text-sm font-medium text-white hover:bg-blue-700"
+          >
+            Roll Back & Restart
+          </button>
+          <button
+            onClick={() => setRollback(null)}
+            className="rounded border border-gray-300 px-4 py-2 text-sm font-medium text-gray-700 hover:bg-gray-50 dark:border-gray-600 dark:text-gray-300 dark:hover:bg-gray-700"
+          >
+            Keep
+          </button>
+        </div>
+      </div>
+    );
+  }
+
+  if (!updateInfo) return null;
 
   return (
     <div className="fixed bottom-4 right-4 z-50 w-96 rounded-lg border border-gray-200 bg-white p-4 shadow-lg dark:border-gray-700 dark:bg-gray-800">
@@ -84,6 +156,11 @@ export function UpdateNotification() {
           <p className="mt-1 text-sm text-gray-600 dark:text-gray-400">
             Version {updateInfo.latest_version} is now available.
           </p>
+          {updateInfo.required && (
+            <p className="mt-1 text-sm font-medium text-red-700 dark:text-red-300">
+              This version is no longer supported. Please update now.
+            </p>
+          )}
           {updateInfo.release_notes && (
             <div className="mt-2 max-h-32 overflow-y-auto rounded border border-gray-200 bg-gray-50 p-2 text-xs dark:border-gray-600 dark:bg-gray-900">
               <pre className="whitespace-pre-wrap font-mono text-gray-700 dark:text-gray-300">
@@ -92,25 +169,27 @@ export function UpdateNotification() {
             </div>
           )}
         </div>
-        <button
-          onClick={handleDismiss}
-          className="ml-2 text-gray-400 hover:text-gray-600 dark:hover:text-gray-200"
-          aria-label="Dismiss"
-        >
-          <svg
-            className="h-5 w-5"
-            fill="none"
-            stroke="currentColor"
-            viewBox="0 0 24 24"
+        {!updateInfo.required && (
+          <button
+            onClick={handleDismiss}
+            className="ml-2 text-gray-400 hover:text-gray-600 dark:hover:text-gray-200"
+            aria-label="Dismiss"
           >
-            <path
-              strokeLinecap="round"
-              strokeLinejoin="round"
-              strokeWidth={2}
-              d="M6 18L18 6M6 6l12 12"
-            />
-          </svg>
-        </button>
+            <svg
+              className="h-5 w-5"
+              fill="none"
+              stroke="currentColor"
+              viewBox="0 0 24 24"
+            >
+              <path
+                strokeLinecap="round"
+                strokeLinejoin="round"
+                strokeWidth={2}
+                d="M6 18L18 6M6 6l12 12"
+              />
+            </svg>
+          </button>
+        )}
       </div>
 
       {error && (
@@ -144,12 +223,14 @@ export function UpdateNotification() {
             >
               {downloading ? 'Downloading...' : 'Download Update'}
             </button>
-            <button
-              onClick={handleDismiss}
-              className="rounded border border-gray-300 px-4 py-2 text-sm font-medium text-gray-700 hover:bg-gray-50 dark:border-gray-600 dark:text-gray-300 dark:hover:bg-gray-700"
-            >
-              Later
-            </button>
+            {(downloading || !updateInfo.required) && (
+              <button
+                onClick={downloading ? handleCancel : handleDismiss}
+                className="rounded border border-gray-300 px-4 py-2 text-sm font-medium text-gray-700 hover:bg-gray-50 dark:border-gray-600 dark:text-gray-300 dark:hover:bg-gray-700"
+              >
+                {downloading ? 'Cancel' : 'Later'}
+              </button>
+            )}
           </>
         ) : (
           <>
@@ -159,12 +240,14 @@ export function UpdateNotification() {
             >
               Install & Restart
             </button>
-            <button
-              onClick={handleDismiss}
-              className="rounded border border-gray-300 px-4 py-2 text-sm font-medium text-gray-700 hover:bg-gray-50 dark:border-gray-600 dark:text-gray-300 dark:hover:bg-gray-700"
-            >
-              Later
-            </button>
+            {!updateInfo.required && (
+              <button
+                onClick={handleDismiss}
+                className="rounded border border-gray-300 px-4 py-2 text-sm font-medium text-gray-700 hover:bg-gray-50 dark:border-gray-600 dark:text-gray-300 dark:hover:bg-gray-700"
+              >
+                Later
+              </button>
+            )}
           </>
         )}
       </div>
@@ -8,18 +8,33 @@ import {
   AlertCircle,
 } from 'lucide-react';
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 import DOMPurify from 'dompurify';
 import { Dialog } from '@/components/ui/dialog';
 import { Select } from '@/components/ui/select';
 import { Switch } from '@/components/ui/switch';
 import { Button } from '@/components/ui/button';
 import { ErrorBoundary } from '@/components/ui/ErrorBoundary';
+import {
+  isAppError,
+  previewReceipt,
+  waitForPrintJob,
+  PRINTER_STATUS_CHANGED_EVENT,
+  type PrintJob,
+  type PrinterCondition,
+  type PrinterProfile,
+  type PrinterState,
+  type PrinterStatus,
+  type ReceiptData,
+  type ReceiptPreview,
+} from '@/lib/tauri/commands';
 
 interface PrinterInfo {
   id: string;
   name: string;
   isDefault: boolean;
-  status: 'ready' | 'busy' | 'offline' | 'error';
+  status: PrinterState;
+  conditions?: PrinterCondition[];
   type: 'laser' | 'inkjet' | 'thermal' | 'dot-matrix';
   capabilities: {
     color: boolean;
@@ -51,15 +66,40 @@ interface PrintDialogProps {
   documentType: 'receipt' | 'report' | 'statement' | 'custom';
   documentTitle: string;
   content: string | React.ReactNode;
+  /** Receipt to preview exactly as the thermal printer will print it */
+  receipt?: { data: ReceiptData; profile?: PrinterProfile };
   onPrint?: (options: PrintOptions) => Promise<void>;
 }
 
+/** Thermal paper is drawn at 203 dpi, 8 dots per millimetre */
+const DOTS_PER_MM = 8;
+
+/** Message for staff, with the error code when the backend gave one */
+function errorMessage(err: unknown, fallback: string): string {
+  if (isAppError(err)) return `${err.message} (${err.code})`;
+  return err instanceof Error ? err.message : fallback;
+}
+
+const CONDITION_LABELS: Record<PrinterCondition, string> = {
+  paper_out: 'Paper out',
+  paper_near_end: 'Paper low',
+  cover_open: 'Cover open',
+  paper_jam: 'Paper jam',
+  offline: 'Offline',
+};
+
+/** Busy printers queue the job, and unknown ones may still print */
+function canPrint(printer?: PrinterInfo): boolean {
+  return !!printer && printer.status !== 'offline' && printer.status !== 'error';
+}
+
 function PrintDialogContent({
   open,
   onClose,
   documentType,
   documentTitle,
   content,
+  receipt,
   onPrint,
 }: PrintDialogProps) {
   const [printers, setPrinters] = useState<PrinterInfo[]>([]);
@@ -68,6 +108,7 @@ function PrintDialogContent({
   const [isPrinting, setIsPrinting] = useState(false);
   const [showPreview, setShowPreview] = useState(true);
   const [error, setError] = useState<string | null>(null);
+  const [receiptPreview, setReceiptPreview] = useState<ReceiptPreview | null>(null);
 
   const [options, setOptions] = useState<PrintOptions>({
     printerId: '',
@@ -118,6 +159,45 @@ function PrintDialogContent({
     }
   }, [open]);
 
+  // Follow the printer monitor while the dialog is open
+  useEffect(() => {
+    if (!open) return;
+
+    const unlisten = listen<PrinterStatus>(PRINTER_STATUS_CHANGED_EVENT, ({ payload }) => {
+      setPrinters(prev =>
+        prev.map(p =>
+          p.id === payload.printer_name || p.name === payload.printer_name
+            ? { ...p, status: payload.state, conditions: payload.conditions }
+            : p
+        )
+      );
+    });
+    return () => {
+      unlisten.then(fn => fn());
+    };
+  }, [open]);
+
+  // Render receipts through the ESC/POS emulator
+  useEffect(() => {
+    if (!open || !receipt) {
+      setReceiptPreview(null);
+      return;
+    }
+
+    let cancelled = false;
+    previewReceipt(receipt.data, receipt.profile)
+      .then((preview) => {
+        if (!cancelled) setReceiptPreview(preview);
+      })
+      .catch((err) => {
+        console.error('Receipt preview error:', err);
+        if (!cancelled) setReceiptPreview(null);
+      });
+    return () => {
+      cancelled = true;
+    };
+  }, [open, receipt]);
+
   // Get selected printer details
   const currentPrinter = printers.find(p => p.id === selectedPrinter);
 
@@ -133,53 +213,48 @@ function PrintDialogContent({
     }
   }, [currentPrinter]);
 
-  // Handle print with retry logic
+  // Handle print; the print queue retries failed sends itself
   const handlePrint = useCallback(async () => {
     if (!selectedPrinter) {
       setError('Please select a printer');
       return;
     }
 
-    if (currentPrinter?.status !== 'ready') {
-      setError(`Printer is ${currentPrinter?.status}. Please wait or select another printer.`);
+    if (!canPrint(currentPrinter)) {
+      const problems = currentPrinter?.conditions?.map(c => CONDITION_LABELS[c]).join(', ');
+      setError(
+        `Printer is ${problems || currentPrinter?.status}. Please fix it or select another printer.`
+      );
       return;
     }
 
     setIsPrinting(true);
     setError(null);
 
-    let retries = 0;
-    const maxRetries = 3;
-
-    while (retries < maxRetries) {
-      try {
-        if (onPrint) {
-          await onPrint({ ...options, printerId: selectedPrinter });
-        } else {
-          // Default print behavior
-          await invoke('print_document', {
+    try {
+      if (onPrint) {
+        await onPrint({ ...options, printerId: selectedPrinter });
+      } else {
+        // Default print behavior
+        const job = await waitForPrintJob(
+          await invoke<PrintJob>('print_document', {
             printerId: selectedPrinter,
             content: typeof content === 'string' ? content : '',
             options,
-          });
-        }
-        onClose();
-        return;
-      } catch (err) {
-        retries++;
-        const errorMessage = err instanceof Error ? err.message : 'Print failed';
-        
-        if (retries >= maxRetries) {
-          setError(`${errorMessage} (after ${maxRetries} attempts)`);
-        } else {
-          // Wait before retry with exponential backoff
-          await new Promise(resolve => setTimeout(resolve, 1000 * Math.pow(2, retries - 1)));
+            title: documentTitle,
+          })
+        );
+        if (job.status !== 'printed') {
+          throw job.error ?? new Error('Print failed');
         }
       }
+      onClose();
+    } catch (err) {
+      setError(errorMessage(err, 'Print failed'));
+    } finally {
+      setIsPrinting(false);
     }
-
-    setIsPrinting(false);
-  }, [selectedPrinter, options, content, onPrint, onClose, currentPrinter]);
+  }, [selectedPrinter, options, content, documentTitle, onPrint, onClose, currentPrinter]);
 
   // Handle export to PDF
   const handleExportPDF = useCallback(async () => {
@@ -196,10 +271,11 @@ function PrintDialogContent({
           content: typeof content === 'string' ? content : '',
           filePath,
           options,
+          title: documentTitle,
         });
       }
     } catch (err) {
-      setError('Failed to export PDF: ' + (err instanceof Error ? err.message : 'Unknown error'));
+      setError('Failed to export PDF: ' + errorMessage(err, 'Unknown error'));
     }
   }, [content, documentTitle, options]);
 
@@ -227,7 +303,19 @@ function PrintDialogContent({
             </button>
           </div>
 
-          {showPreview && (
+          {showPreview && receiptPreview && (
+            <img
+              src={`data:image/png;base64,${receiptPreview.png}`}
+              alt={`Preview of ${documentTitle}`}
+              className="bg-white shadow-lg mx-auto"
+              style={{
+                width: `${receiptPreview.width / DOTS_PER_MM}mm`,
+                imageRendering: 'pixelated',
+              }}
+            />
+          )}
+
+          {showPreview && !receiptPreview && (
             <div 
               className="bg-white dark:bg-neutral-800 shadow-lg rounded-lg p-8 mx-auto"
               style={{
@@ -286,11 +374,17 @@ function PrintDialogContent({
                     className={`w-2 h-2 rounded-full ${
                       currentPrinter.status === 'ready' ? 'bg-green-500' :
                       currentPrinter.status === 'busy' ? 'bg-yellow-500' :
+                      currentPrinter.status === 'unknown' ? 'bg-gray-400' :
                       'bg-red-500'
                     }`}
                     aria-label={`Printer status: ${currentPrinter.status}`}
                   />
                   <span className="text-text-muted capitalize">{currentPrinter.status}</span>
+                  {currentPrinter.conditions?.map(c => (
+                    <span key={c} className="text-red-600 dark:text-red-400">
+                      {CONDITION_LABELS[c]}
+                    </span>
+                  ))}
                 </div>
               )}
             </div>
@@ -410,7 +504,7 @@ function PrintDialogContent({
             <div className="flex flex-col gap-2 pt-4 border-t border-border-default">
               <Button
                 onClick={handlePrint}
-                disabled={isPrinting || !selectedPrinter || currentPrinter?.status !== 'ready'}
+                disabled={isPrinting || !selectedPrinter || !canPrint(currentPrinter)}
                 className="w-full"
               >
                 {isPrinting ? (
@@ -5,6 +5,68 @@
 import { invoke } from '@tauri-apps/api/core';
 import { listen } from '@tauri-apps/api/event';
 
+// ============================================================================
+// Errors
+// ============================================================================
+
+export type ErrorCategory =
+  | 'auth'
+  | 'keychain'
+  | 'network'
+  | 'printer'
+  | 'device'
+  | 'storage'
+  | 'validation'
+  | 'internal'
+  /** Stopped at the user's request; not an error to show */
+  | 'cancelled';
+
+export type ErrorCode =
+  | 'NOT_SIGNED_IN'
+  | 'PERMISSION_DENIED'
+  | 'KEYCHAIN_LOCKED'
+  | 'KEYCHAIN_UNAVAILABLE'
+  | 'KEYCHAIN_ERROR'
+  | 'NETWORK_TIMEOUT'
+  | 'NETWORK_UNAVAILABLE'
+  | 'HTTP_ERROR'
+  | 'INVALID_RESPONSE'
+  | 'CANCELLED'
+  | 'PRINTER_NOT_FOUND'
+  | 'PRINTER_OFFLINE'
+  | 'PRINT_FAILED'
+  | 'PRINT_UNCONFIRMED'
+  | 'DEVICE_NOT_FOUND'
+  | 'DEVICE_BUSY'
+  | 'DEVICE_ERROR'
+  | 'UNSUPPORTED'
+  | 'FILE_NOT_FOUND'
+  | 'STORAGE_ERROR'
+  | 'RECEIPT_NUMBERS_EXHAUSTED'
+  | 'INVALID_INPUT'
+  | 'UPDATE_VERIFICATION_FAILED'
+  | 'DECODE_FAILED'
+  | 'INTERNAL';
+
+/** Error rejected by every command; `message` is safe to show to staff */
+export interface AppError {
+  code: ErrorCode;
+  category: ErrorCategory;
+  retryable: boolean;
+  message: string;
+  detail: string | null;
+}
+
+export function isAppError(error: unknown): error is AppError {
+  return (
+    typeof error === 'object' &&
+    error !== null &&
+    'code' in error &&
+    'category' in error &&
+    'message' in error
+  );
+}
+
 // ============================================================================
 // Auth Types & Commands
 // ============================================================================
@@ -31,14 +93,108 @@ export async function getDeviceId(): Promise<string> {
   return invoke<string>('get_device_id');
 }
 
+// ============================================================================
+// Member Card Types & Commands
+// ============================================================================
+
+export interface MemberCard {
+  key_id: string;
+  sacco_id: string;
+  member_ref: string;
+  issued_on: string;
+  card_serial: number;
+}
+
+export type CardStatus =
+  | 'valid'
+  | 'revoked'
+  | 'forged'
+  | 'unknown_key'
+  | 'invalid_key'
+  | 'malformed';
+
+export interface CardVerification {
+  status: CardStatus;
+  card: MemberCard | null;
+  detail: string | null;
+  revoked_at: string | null;
+  trust_store_synced_at: string | null;
+}
+
+export interface TrustStoreSummary {
+  key_count: number;
+  revocation_count: number;
+  keys_synced_at: string | null;
+  revocations_synced_at: string | null;
+}
+
+export async function verifyMemberCard(payload: string): Promise<CardVerification> {
+  return invoke<CardVerification>('verify_member_card', { payload });
+}
+
+export async function syncCardTrustStore(): Promise<TrustStoreSummary> {
+  return invoke<TrustStoreSummary>('sync_card_trust_store');
+}
+
+export async function getCardTrustStoreStatus(): Promise<TrustStoreSummary> {
+  return invoke<TrustStoreSummary>('get_card_trust_store_status');
+}
+
 // ============================================================================
 // Print Types & Commands
 // ============================================================================
 
+export type PrinterBackend = 'system' | 'raw_tcp' | 'ipp' | 'serial';
+
+export type PrinterState = 'ready' | 'busy' | 'offline' | 'error' | 'unknown';
+
+export type PrinterCondition =
+  | 'paper_out'
+  | 'paper_near_end'
+  | 'cover_open'
+  | 'paper_jam'
+  | 'offline';
+
 export interface PrinterInfo {
   name: string;
   is_default: boolean;
-  status: string;
+  status: PrinterState;
+  /** Paper, cover and connection problems the printer last reported */
+  conditions: PrinterCondition[];
+  backend: PrinterBackend;
+}
+
+export interface PrinterStatus {
+  printer_name: string;
+  state: PrinterState;
+  conditions: PrinterCondition[];
+  /** The printer's own explanation, or why it could not be asked */
+  message: string | null;
+  checked_at: string;
+}
+
+/** Event emitted with a PrinterStatus whenever a printer's status changes */
+export const PRINTER_STATUS_CHANGED_EVENT = 'printer-status-changed';
+
+export type NetworkConnection =
+  | { kind: 'raw_tcp'; host: string; port?: number; timeout_ms?: number }
+  | {
+      kind: 'ipp';
+      url: string;
+      document_format?: string;
+      /** PEM certificate of an ipps:// printer with a self-signed one */
+      certificate?: string;
+    }
+  | { kind: 'serial'; profile: SerialDeviceProfile };
+
+/** What the printer takes; label printers are never asked for their status */
+export type PrinterLanguage = 'esc_pos' | 'zpl' | 'tspl';
+
+export interface NetworkPrinter {
+  name: string;
+  connection: NetworkConnection;
+  /** Defaults to 'esc_pos' */
+  language?: PrinterLanguage;
 }
 
 export interface ReceiptItem {
@@ -46,39 +202,567 @@ export interface ReceiptItem {
   value: string;
 }
 
+export type PaperWidth = '58mm' | '80mm';
+
+export interface PrinterProfile {
+  paper_width?: PaperWidth;
+  /** Printer supports native QR commands; otherwise QR codes print as images */
+  native_qr?: boolean;
+  /** Defaults to cp858 */
+  code_page?: 'cp437' | 'cp850' | 'cp858' | 'wpc1252';
+}
+
+export interface ReceiptVerification {
+  payment_id: string;
+  reference_token: string;
+  /** Defaults to https://app.ibimina.rw/verify/receipt */
+  url?: string;
+}
+
 export interface ReceiptData {
   title: string;
   items: ReceiptItem[];
   total: string;
   footer: string;
+  verification?: ReceiptVerification;
+  receipt_number?: string;
+  /** Prints this SACCO's cached logo at the top of the receipt */
+  sacco_id?: string;
+  /** Set on reprints; prints a DUPLICATE banner */
+  duplicate?: boolean;
+  /** Extra values for the SACCO's receipt template, e.g. raw amounts and ISO dates */
+  fields?: Record<string, unknown>;
+}
+
+/**
+ * 'spooled' jobs were accepted and are being followed; 'unconfirmed' ones were
+ * accepted but never confirmed printed, and are not sent again
+ */
+export type PrintJobStatus =
+  | 'queued'
+  | 'sending'
+  | 'spooled'
+  | 'printed'
+  | 'failed'
+  | 'unconfirmed';
+
+export type PaperSize = 'A4' | 'A5' | 'Letter' | 'Legal' | 'Receipt';
+
+/** Print dialog settings; margins are in millimetres and scale in percent */
+export interface DocumentPrintOptions {
+  copies?: number;
+  color?: boolean;
+  duplex?: boolean;
+  paperSize?: PaperSize;
+  orientation?: 'portrait' | 'landscape';
+  scale?: number;
+  margins?: { top: number; right: number; bottom: number; left: number };
+  /** e.g. "1-3, 5"; all pages when omitted */
+  pageRange?: string;
+}
+
+/** SACCO shown in the header of every report page */
+export interface ReportBranding {
+  sacco_name: string;
+  /** SACCO whose cached logo is printed (see cacheSaccoLogo) */
+  sacco_id?: string;
+  address?: string;
+  phone?: string;
+}
+
+/** Positive amounts are credits, negative are debits; dates are ISO strings */
+export interface LedgerEntry {
+  occurred_at: string;
+  reference: string;
+  description?: string;
+  amount: number;
+}
+
+export interface MemberContribution {
+  member_code: string;
+  member_name: string;
+  payments: number;
+  total: number;
+  expected?: number;
+}
+
+/** Positive amounts are cash received, negative are cash paid out */
+export interface CashTransaction {
+  occurred_at: string;
+  reference: string;
+  member_name?: string;
+  description?: string;
+  amount: number;
+}
+
+/** Built from the offline store; balances and totals are computed when rendering */
+export type Report =
+  | {
+      kind: 'member_statement';
+      member_name: string;
+      member_code: string;
+      group_name?: string;
+      period_start: string;
+      period_end: string;
+      opening_balance: number;
+      entries: LedgerEntry[];
+    }
+  | {
+      kind: 'group_contributions';
+      group_name: string;
+      group_code?: string;
+      period_start: string;
+      period_end: string;
+      members: MemberContribution[];
+    }
+  | {
+      kind: 'daily_cash';
+      date: string;
+      teller?: string;
+      opening_cash: number;
+      transactions: CashTransaction[];
+      counted_cash?: number;
+    };
+
+export type LabelLanguage = 'zpl' | 'tspl';
+
+/** Positions and sizes in mm; text and data may use `{{ field }}` placeholders */
+export type LabelElement =
+  | { type: 'text'; x_mm: number; y_mm: number; text: string; height_mm?: number }
+  | {
+      type: 'barcode';
+      x_mm: number;
+      y_mm: number;
+      data: string;
+      height_mm: number;
+      module_mm?: number;
+      show_text?: boolean;
+    }
+  | { type: 'qr'; x_mm: number; y_mm: number; data: string; module_mm?: number }
+  | {
+      type: 'box';
+      x_mm: number;
+      y_mm: number;
+      width_mm: number;
+      height_mm: number;
+      thickness_mm?: number;
+    };
+
+export interface LabelDefinition {
+  width_mm: number;
+  height_mm: number;
+  /** Defaults to 203 */
+  dpi?: number;
+  /** Gap between labels on the roll; defaults to 2 mm */
+  gap_mm?: number;
+  elements: LabelElement[];
+}
+
+/** Member as printed on a label; extra API fields are kept for custom labels */
+export interface MemberLabel {
+  id: string;
+  member_ref: string;
+  full_name: string;
+  sacco_name: string;
+  group_name: string | null;
+  [field: string]: unknown;
+}
+
+export type PrintDocument =
+  | { kind: 'receipt'; receipt: ReceiptData; profile: PrinterProfile }
+  | { kind: 'html'; content: string }
+  | { kind: 'document'; content: string; title?: string; options: DocumentPrintOptions }
+  | { kind: 'report'; report: Report; branding: ReportBranding; options: DocumentPrintOptions }
+  | {
+      kind: 'labels';
+      label: LabelDefinition;
+      language: LabelLanguage;
+      members: MemberLabel[];
+      copies: number;
+    };
+
+export interface PrintJob {
+  id: string;
+  printer_name: string;
+  /** Null once printed, except for receipts, which are kept for reprints */
+  document: PrintDocument | null;
+  status: PrintJobStatus;
+  attempts: number;
+  /** Job id assigned by CUPS, or by the printer for IPP */
+  cups_job_id: string | null;
+  error: AppError | null;
+  /** Original job when this is a reprint */
+  duplicate_of: string | null;
+  created_at: string;
+  updated_at: string;
+}
+
+/** Event emitted with a PrintJob whenever its status changes */
+export const PRINT_JOB_UPDATED_EVENT = 'print-job-updated';
+
+export interface CachedLogo {
+  sacco_id: string;
+  logo_url: string;
+  fetched_at: string;
+}
+
+export async function cacheSaccoLogo(saccoId: string, logoUrl: string): Promise<CachedLogo> {
+  return invoke<CachedLogo>('cache_sacco_logo', { saccoId, logoUrl });
+}
+
+export interface ReceiptTemplate {
+  sacco_id: string;
+  source: string;
+  updated_at: string;
+}
+
+/** A template problem, with its 1-based line number */
+export interface TemplateError {
+  line: number;
+  message: string;
+}
+
+/** Result of syncing one SACCO's template; stored only if `errors` is empty */
+export interface TemplateSyncResult {
+  sacco_id: string;
+  updated_at: string;
+  errors: TemplateError[];
+}
+
+export interface TemplatePreviewLine {
+  text: string;
+  indent: number;
+  bold: boolean;
+  inverse: boolean;
+  /** Character magnification as [width, height] */
+  size: [number, number];
+}
+
+export interface TemplatePreview {
+  text: string;
+  lines: TemplatePreviewLine[];
+  /** PNG image, base64 encoded */
+  png: string;
+}
+
+export async function syncReceiptTemplates(): Promise<TemplateSyncResult[]> {
+  return invoke<TemplateSyncResult[]>('sync_receipt_templates');
+}
+
+export async function getReceiptTemplate(saccoId: string): Promise<ReceiptTemplate | null> {
+  return invoke<ReceiptTemplate | null>('get_receipt_template', { saccoId });
+}
+
+export async function getDefaultReceiptTemplate(): Promise<string> {
+  return invoke<string>('get_default_receipt_template');
+}
+
+export async function validateReceiptTemplate(source: string): Promise<TemplateError[]> {
+  return invoke<TemplateError[]>('validate_receipt_template', { source });
+}
+
+/** Render a template against `receiptData`, or sample data when omitted */
+export async function previewReceiptTemplate(
+  source: string,
+  receiptData?: ReceiptData,
+  profile?: PrinterProfile
+): Promise<TemplatePreview> {
+  return invoke<TemplatePreview>('preview_receipt_template', {
+    source,
+    receiptData: receiptData ?? null,
+    profile: profile ?? null,
+  });
 }
 
 export async function getPrinters(): Promise<PrinterInfo[]> {
   return invoke<PrinterInfo[]>('get_printers');
 }
 
-export async function printHtml(printerName: string, htmlContent: string): Promise<void> {
-  return invoke('print_html', { 
+export async function getPrinterStatus(printerName: string): Promise<PrinterStatus> {
+  return invoke<PrinterStatus>('get_printer_status', { printerName });
+}
+
+export async function getNetworkPrinters(): Promise<NetworkPrinter[]> {
+  return invoke<NetworkPrinter[]>('get_network_printers');
+}
+
+export async function saveNetworkPrinter(printer: NetworkPrinter): Promise<void> {
+  return invoke('save_network_printer', { printer });
+}
+
+export async function removeNetworkPrinter(name: string): Promise<void> {
+  return invoke('remove_network_printer', { name });
+}
+
+export async function printHtml(printerName: string, htmlContent: string): Promise<PrintJob> {
+  return invoke<PrintJob>('print_html', { 
     printerName, 
     htmlContent 
   });
 }
 
-export async function printReceipt(printerName: string, receiptData: ReceiptData): Promise<void> {
-  return invoke('print_receipt', { 
+export async function printReceipt(
+  printerName: string,
+  receiptData: ReceiptData,
+  profile?: PrinterProfile
+): Promise<PrintJob> {
+  return invoke<PrintJob>('print_receipt', { 
     printerName, 
-    receiptData 
+    receiptData,
+    profile
+  });
+}
+
+/** A receipt as the printer would print it; one image pixel per printer dot */
+export interface ReceiptPreview {
+  /** PNG image, base64 encoded */
+  png: string;
+  width: number;
+  height: number;
+  text: string;
+}
+
+export async function previewReceipt(
+  receiptData: ReceiptData,
+  profile?: PrinterProfile
+): Promise<ReceiptPreview> {
+  return invoke<ReceiptPreview>('preview_receipt', {
+    receiptData,
+    profile: profile ?? null,
+  });
+}
+
+/** Lay out sanitized HTML as a PDF and print it, without a browser */
+export async function printDocument(
+  printerId: string,
+  content: string,
+  options: DocumentPrintOptions,
+  title?: string
+): Promise<PrintJob> {
+  return invoke<PrintJob>('print_document', { printerId, content, options, title });
+}
+
+export async function exportToPdf(
+  content: string,
+  filePath: string,
+  options: DocumentPrintOptions,
+  title?: string
+): Promise<void> {
+  return invoke('export_to_pdf', { content, filePath, options, title });
+}
+
+/** Render a statement or report offline and save it as a PDF */
+export async function exportReport(
+  report: Report,
+  branding: ReportBranding,
+  filePath: string,
+  options: DocumentPrintOptions = {}
+): Promise<void> {
+  return invoke('export_report', { report, branding, filePath, options });
+}
+
+export async function printReport(
+  printerId: string,
+  report: Report,
+  branding: ReportBranding,
+  options: DocumentPrintOptions = {}
+): Promise<PrintJob> {
+  return invoke<PrintJob>('print_report', { printerId, report, branding, options });
+}
+
+/** Print one label per member, in order; uses the built-in member ID label by default */
+export async function printMemberLabels(
+  printerName: string,
+  memberIds: string[],
+  language: LabelLanguage,
+  label?: LabelDefinition,
+  copies?: number
+): Promise<PrintJob> {
+  return invoke<PrintJob>('print_member_labels', {
+    printerName,
+    memberIds,
+    language,
+    label: label ?? null,
+    copies: copies ?? null,
+  });
+}
+
+export async function getDefaultMemberLabel(): Promise<LabelDefinition> {
+  return invoke<LabelDefinition>('get_default_member_label');
+}
+
+/** Recent print jobs, newest first; `search` matches receipts and report titles and subjects */
+export async function getPrintJobs(limit?: number, search?: string): Promise<PrintJob[]> {
+  return invoke<PrintJob[]>('get_print_jobs', { limit, search });
+}
+
+/**
+ * Resolve once a submitted job has printed, failed for good or been left
+ * unconfirmed. Print commands return as soon as the job is queued; the queue
+ * retries it on its own.
+ */
+export async function waitForPrintJob(job: PrintJob): Promise<PrintJob> {
+  const done = (j: PrintJob) =>
+    j.status === 'printed' || j.status === 'failed' || j.status === 'unconfirmed';
+  if (done(job)) return job;
+
+  let finish: (j: PrintJob) => void = () => {};
+  const finished = new Promise<PrintJob>((resolve) => {
+    finish = resolve;
+  });
+  const unlisten = await listen<PrintJob>(PRINT_JOB_UPDATED_EVENT, (event) => {
+    if (event.payload.id === job.id && done(event.payload)) finish(event.payload);
   });
+  try {
+    // The job may have finished before the listener was registered
+    const current = (await getPrintJobs()).find((j) => j.id === job.id);
+    if (current && done(current)) return current;
+    return await finished;
+  } finally {
+    unlisten();
+  }
+}
+
+export async function retryPrintJob(jobId: string): Promise<PrintJob> {
+  return invoke<PrintJob>('retry_print_job', { jobId });
+}
+
+/** Print an earlier receipt again, marked DUPLICATE */
+export async function reprintReceipt(jobId: string, printerName?: string): Promise<PrintJob> {
+  return invoke<PrintJob>('reprint_receipt', { jobId, printerName });
+}
+
+/** Numbers `start..=end` of a branch's receipt sequence, reserved for this device */
+export interface NumberBlock {
+  id: string;
+  branch_id: string;
+  prefix: string;
+  start: number;
+  end: number;
+  reserved_at: string;
+}
+
+export interface IssuedNumber {
+  receipt_number: string;
+  block_id: string;
+  number: number;
+  /** Print job of the receipt the number was printed on */
+  print_job_id: string | null;
+  issued_at: string;
+}
+
+export interface VoidedNumber {
+  receipt_number: string;
+  reason: string;
+  voided_at: string;
+}
+
+/** Issued number whose receipt never printed; retry or reprint the job, or void the number */
+export interface PrintFailedNumber {
+  receipt_number: string;
+  print_job_id: string;
+  issued_at: string;
+  /** Why the last print failed, or that it was never confirmed */
+  reason: string;
+}
+
+export interface NumberRange {
+  first: string;
+  last: string;
+  count: number;
+}
+
+/** Every number in a block is exactly one of issued, voided or unused */
+export interface BlockReconciliation {
+  block: NumberBlock;
+  /** Issued and not voided, including `print_failed` */
+  issued: number;
+  print_failed: PrintFailedNumber[];
+  voided: VoidedNumber[];
+  unused: NumberRange | null;
+}
+
+export interface ReconciliationReport {
+  generated_at: string;
+  blocks: BlockReconciliation[];
+  issued: number;
+  print_failed: number;
+  voided: number;
+  unused: number;
+}
+
+/**
+ * Reserve a block of receipt numbers for a branch; once a device has a block,
+ * `printReceipt` numbers every receipt printed without a number
+ */
+export async function reserveReceiptNumbers(
+  branchId: string,
+  size?: number
+): Promise<NumberBlock> {
+  return invoke<NumberBlock>('reserve_receipt_numbers', { branchId, size: size ?? null });
+}
+
+export async function nextReceiptNumber(reference?: string): Promise<IssuedNumber> {
+  return invoke<IssuedNumber>('next_receipt_number', { reference: reference ?? null });
+}
+
+export async function voidReceiptNumber(receiptNumber: string, reason: string): Promise<void> {
+  return invoke('void_receipt_number', { receiptNumber, reason });
+}
+
+export async function getReceiptNumberReport(): Promise<ReconciliationReport> {
+  return invoke<ReconciliationReport>('get_receipt_number_report');
+}
+
+export interface DrawerKickOptions {
+  printer_name: string;
+  pin?: 'pin2' | 'pin5';
+  pulse_on_ms?: number;
+  pulse_off_ms?: number;
+}
+
+export type DisplayTarget =
+  | { kind: 'serial'; profile: SerialDeviceProfile }
+  | { kind: 'printer'; printer_name: string };
+
+export type DisplayMessage =
+  | { kind: 'amount_received'; amount: string; member_name: string | null }
+  | { kind: 'thank_you' }
+  | { kind: 'text'; line1: string; line2: string }
+  | { kind: 'clear' };
+
+export async function openCashDrawer(options: DrawerKickOptions, reason?: string): Promise<void> {
+  return invoke('open_cash_drawer', { options, reason: reason ?? null });
+}
+
+export async function showCustomerDisplay(
+  target: DisplayTarget,
+  message: DisplayMessage
+): Promise<void> {
+  return invoke('show_customer_display', { target, message });
 }
 
 // ============================================================================
 // Hardware Types & Commands
 // ============================================================================
 
+export interface MomoPayment {
+  merchant_code: string;
+  merchant_name: string | null;
+  amount: number | null;
+  currency: string;
+}
+
+export type ScanPayload =
+  | ({ kind: 'member_card' } & CardVerification)
+  | ({ kind: 'momo_payment' } & MomoPayment)
+  | { kind: 'text' };
+
 export interface ScanResult {
   data: string;
   scan_type: string;
   timestamp: number;
+  payload: ScanPayload;
 }
 
 export interface NFCData {
@@ -99,6 +783,56 @@ export async function stopBarcodeScan(): Promise<void> {
   return invoke('stop_barcode_scan');
 }
 
+export interface CameraFrame {
+  width: number;
+  height: number;
+  format: 'rgba' | 'rgb' | 'luma';
+  /** Base64-encoded pixel data */
+  data: string;
+}
+
+export async function decodeBarcodeImage(path: string): Promise<ScanResult[]> {
+  return invoke<ScanResult[]>('decode_barcode_image', { path });
+}
+
+export async function decodeCameraFrame(frame: CameraFrame): Promise<ScanResult[]> {
+  return invoke<ScanResult[]>('decode_camera_frame', { frame });
+}
+
+export interface SerialPortEntry {
+  name: string;
+  port_type: 'usb' | 'pci' | 'bluetooth' | 'unknown';
+  vid: number | null;
+  pid: number | null;
+  serial_number: string | null;
+  manufacturer: string | null;
+  product: string | null;
+}
+
+export interface SerialDeviceProfile {
+  port: string;
+  baud_rate?: number;
+  data_bits?: 5 | 6 | 7 | 8;
+  parity?: 'none' | 'odd' | 'even';
+  stop_bits?: 1 | 2;
+  flow_control?: 'none' | 'software' | 'hardware';
+  terminator?: 'cr' | 'lf' | 'crlf' | 'tab' | 'etx' | 'none';
+  idle_timeout_ms?: number;
+  scan_type?: string;
+}
+
+export async function listSerialPorts(): Promise<SerialPortEntry[]> {
+  return invoke<SerialPortEntry[]>('list_serial_ports');
+}
+
+export async function startSerialScanner(profile: SerialDeviceProfile): Promise<void> {
+  return invoke('start_serial_scanner', { profile });
+}
+
+export async function stopSerialScanner(port: string): Promise<void> {
+  return invoke('stop_serial_scanner', { port });
+}
+
 export async function isNfcAvailable(): Promise<boolean> {
   return invoke<boolean>('is_nfc_available');
 }
@@ -123,12 +857,47 @@ export async function authenticateBiometrics(reason: string): Promise<boolean> {
 // Update Types & Commands
 // ============================================================================
 
+/** Pilot gets releases before stable; beta gets pre-releases */
+export type UpdateChannel = 'stable' | 'pilot' | 'beta';
+
+/** A copy of a release: a folder (e.g. a USB drive) or an HTTP mirror on the branch network */
+export type UpdateSource =
+  | { kind: 'folder'; path: string }
+  | { kind: 'mirror'; url: string };
+
+export interface UpdatePreferences {
+  /** Used unless the update manifest assigns this device or its SACCO a channel */
+  channel: UpdateChannel | null;
+  sacco_id: string | null;
+  /** Checked before the release server; the newest release offered wins */
+  sources: UpdateSource[];
+}
+
 export interface UpdateInfo {
   current_version: string;
   latest_version: string;
   update_available: boolean;
+  /** Running an unsupported version; the update can't be put off */
+  required: boolean;
+  /** The device's channel, after any assignment in the update manifest */
+  channel: UpdateChannel;
+  /** Running a newer version than the channel's latest release */
+  ahead_of_latest: boolean;
+  /** The latest version is a pre-release */
+  prerelease: boolean;
   release_notes: string;
   download_url: string;
+  /** SHA-256 digest and size the signed manifest pins for the installer */
+  sha256: string;
+  size: number;
+}
+
+export interface RollbackStatus {
+  current_version: string;
+  /** Version a rollback would install, if its installer was kept */
+  rollback_version: string | null;
+  /** Launches of this version in a row that failed before this one */
+  failed_launches: number;
 }
 
 export interface DownloadProgress {
@@ -137,18 +906,42 @@ export interface DownloadProgress {
   percentage: number;
 }
 
-export async function checkForUpdates(): Promise<UpdateInfo> {
-  return invoke<UpdateInfo>('check_for_updates');
+export async function checkForUpdates(channel?: UpdateChannel): Promise<UpdateInfo> {
+  return invoke<UpdateInfo>('check_for_updates', { channel: channel ?? null });
+}
+
+export async function getUpdatePreferences(): Promise<UpdatePreferences> {
+  return invoke<UpdatePreferences>('get_update_preferences');
+}
+
+export async function setUpdatePreferences(preferences: UpdatePreferences): Promise<void> {
+  return invoke('set_update_preferences', { preferences });
+}
+
+/** Resolves to the verified installer's path */
+/** Downloads the update offered by the last `checkForUpdates` */
+export async function downloadUpdate(): Promise<string> {
+  return invoke<string>('download_update');
 }
 
-export async function downloadUpdate(downloadUrl: string): Promise<string> {
-  return invoke<string>('download_update', { downloadUrl });
+/** Resolves to false if no download was running */
+export async function cancelUpdateDownload(): Promise<boolean> {
+  return invoke<boolean>('cancel_update_download');
 }
 
 export async function installUpdate(installerPath: string): Promise<void> {
   return invoke('install_update', { installerPath });
 }
 
+export async function getRollbackStatus(): Promise<RollbackStatus> {
+  return invoke<RollbackStatus>('get_rollback_status');
+}
+
+/** Reinstalls the previous version and restarts the app; not available on macOS */
+export async function rollbackUpdate(): Promise<void> {
+  return invoke('rollback_update');
+}
+
 export async function getCurrentVersion(): Promise<string> {
   return invoke<string>('get_current_version');
 }